@@ -0,0 +1,209 @@
+// Copyright (c) Facebook, Inc. and its affiliates. All Rights Reserved
+//
+
+//! Benchmarks of the melder's core operations, on the bundled assets as well as on synthetic
+//! ones large enough for per-object costs to dominate.
+//!
+//! Run with `cargo bench`; Criterion keeps earlier results around, and reports any regressions.
+
+extern crate assets;
+extern crate criterion;
+extern crate gltf;
+extern crate gltf_variant_meld;
+extern crate serde_json;
+
+use std::fs;
+use std::path::Path;
+
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion};
+use serde_json::json;
+
+use gltf::json::Root;
+
+use gltf_variant_meld::meld_keys::{build_fingerprint, HasKeyForVariants};
+use gltf_variant_meld::{MeldOptions, Tag, WorkAsset};
+
+use assets::*;
+
+// the number of meshes in each synthetic asset
+const SYNTHETIC_MESH_COUNTS: &[usize] = &[100, 1000];
+
+fn load(path: &Path, tag: &str) -> WorkAsset {
+    WorkAsset::from_file(path, Some(&Tag::from(tag))).expect("glTF import failure")
+}
+
+// a glTF asset of `mesh_count` single-triangle meshes that use a handful of materials, which
+// are tinted by `tint` so that differently tinted assets meld into something variational
+fn synthetic_asset(mesh_count: usize, tint: f32) -> (Root, Vec<u8>) {
+    // every triangle shares the first accessor for its indices
+    let mut blob = vec![];
+    for index in 0..3u32 {
+        blob.extend_from_slice(&index.to_le_bytes());
+    }
+    let mut buffer_views = vec![json!({ "buffer": 0, "byteLength": blob.len() })];
+    let mut accessors = vec![json!({
+        "bufferView": 0,
+        "componentType": 5125,
+        "count": 3,
+        "type": "SCALAR",
+    })];
+    let mut meshes = vec![];
+    for ix in 0..mesh_count {
+        let offset = ix as f32;
+        let positions = [offset, 0.0, 0.0, offset + 1.0, 0.0, 0.0, offset, 1.0, 0.0];
+        buffer_views.push(json!({
+            "buffer": 0,
+            "byteOffset": blob.len(),
+            "byteLength": positions.len() * 4,
+        }));
+        for position in &positions {
+            blob.extend_from_slice(&position.to_le_bytes());
+        }
+        accessors.push(json!({
+            "bufferView": buffer_views.len() - 1,
+            "componentType": 5126,
+            "count": 3,
+            "type": "VEC3",
+            "min": [offset, 0.0, 0.0],
+            "max": [offset + 1.0, 1.0, 0.0],
+        }));
+        meshes.push(json!({
+            "name": format!("mesh_{}", ix),
+            "primitives": [ {
+                "attributes": { "POSITION": accessors.len() - 1 },
+                "indices": 0,
+                "material": ix % 8,
+            } ],
+        }));
+    }
+    let materials: Vec<_> = (0..8)
+        .map(|ix| {
+            json!({
+                "name": format!("material_{}", ix),
+                "pbrMetallicRoughness": { "baseColorFactor": [tint, ix as f32 / 8.0, 0.5, 1.0] },
+            })
+        })
+        .collect();
+    let root = json!({
+        "asset": { "version": "2.0" },
+        "accessors": accessors,
+        "bufferViews": buffer_views,
+        "buffers": [ { "byteLength": blob.len() } ],
+        "materials": materials,
+        "meshes": meshes,
+    });
+    let root = serde_json::from_value(root).expect("synthetic glTF is bad");
+    (root, blob)
+}
+
+fn synthetic(mesh_count: usize, tint: f32, tag: &str) -> WorkAsset {
+    let (root, blob) = synthetic_asset(mesh_count, tint);
+    let options = &MeldOptions::default();
+    WorkAsset::from_root(root, blob, Some(&Tag::from(tag)), None, options)
+        .expect("synthetic asset construction failure")
+}
+
+fn bench_construct(c: &mut Criterion) {
+    let bytes = fs::read(ASSET_TEAPOT_CAMO_PINK_BRONZE()).expect("Couldn't read asset file");
+    let base_dir = ASSET_TEAPOT_CAMO_PINK_BRONZE().parent();
+    let tag = Tag::from("camo");
+    c.bench_function("construct teapot", |b| {
+        b.iter(|| WorkAsset::from_slice(&bytes, Some(&tag), base_dir).unwrap())
+    });
+
+    // the trusted path skips most fingerprinting & validation
+    let exported = load(ASSET_TEAPOT_CAMO_PINK_BRONZE(), "camo")
+        .export()
+        .unwrap();
+    let options = &MeldOptions::default();
+    c.bench_function("construct exported teapot", |b| {
+        b.iter(|| WorkAsset::from_exported_slice(exported.glb(), &tag, options).unwrap())
+    });
+
+    // keys & fingerprints are the bulk of construction for large assets
+    for &mesh_count in SYNTHETIC_MESH_COUNTS {
+        let (root, blob) = synthetic_asset(mesh_count, 0.0);
+        c.bench_function(&format!("construct synthetic {}", mesh_count), |b| {
+            b.iter_batched(
+                || (root.clone(), blob.clone()),
+                |(root, blob)| WorkAsset::from_root(root, blob, Some(&tag), None, options).unwrap(),
+                BatchSize::LargeInput,
+            )
+        });
+    }
+}
+
+fn bench_keys(c: &mut Criterion) {
+    let teapot = load(ASSET_TEAPOT_CAMO_PINK_BRONZE(), "camo");
+    c.bench_function("key teapot images", |b| {
+        b.iter(|| {
+            for image in teapot.images() {
+                image.build_meld_key(&teapot).unwrap();
+            }
+        })
+    });
+    c.bench_function("key teapot materials", |b| {
+        b.iter(|| {
+            for material in teapot.materials() {
+                material.build_meld_key(&teapot).unwrap();
+            }
+        })
+    });
+
+    for &mesh_count in SYNTHETIC_MESH_COUNTS {
+        let asset = synthetic(mesh_count, 0.0, "base");
+        c.bench_function(&format!("fingerprint synthetic {}", mesh_count), |b| {
+            b.iter(|| {
+                for mesh in asset.meshes() {
+                    for primitive in &mesh.primitives {
+                        build_fingerprint(asset.root(), primitive, asset.blob_slice(), None)
+                            .unwrap();
+                    }
+                }
+            })
+        });
+    }
+}
+
+fn bench_meld(c: &mut Criterion) {
+    let base = load(ASSET_TEAPOT_CAMO_PINK_BRONZE(), "camo_pink_bronze");
+    let other = load(ASSET_TEAPOT_GREEN_PINK_SILVER(), "green_pink_silver");
+    c.bench_function("meld teapots", |b| {
+        b.iter(|| WorkAsset::meld(&base, &other).unwrap())
+    });
+
+    for &mesh_count in SYNTHETIC_MESH_COUNTS {
+        let base = synthetic(mesh_count, 0.0, "base");
+        let other = synthetic(mesh_count, 1.0, "other");
+        c.bench_function(&format!("meld synthetic {}", mesh_count), |b| {
+            b.iter(|| WorkAsset::meld(&base, &other).unwrap())
+        });
+    }
+}
+
+fn bench_export(c: &mut Criterion) {
+    let base = load(ASSET_TEAPOT_CAMO_PINK_BRONZE(), "camo_pink_bronze");
+    let other = load(ASSET_TEAPOT_GREEN_PINK_SILVER(), "green_pink_silver");
+    let (melded, _) = WorkAsset::meld(&base, &other).unwrap();
+    c.bench_function("export melded teapots", |b| {
+        b.iter(|| melded.export().unwrap())
+    });
+
+    for &mesh_count in SYNTHETIC_MESH_COUNTS {
+        let base = synthetic(mesh_count, 0.0, "base");
+        let other = synthetic(mesh_count, 1.0, "other");
+        let (melded, _) = WorkAsset::meld(&base, &other).unwrap();
+        c.bench_function(&format!("export melded synthetic {}", mesh_count), |b| {
+            b.iter(|| melded.export().unwrap())
+        });
+    }
+}
+
+criterion_group!(
+    benches,
+    bench_construct,
+    bench_keys,
+    bench_meld,
+    bench_export
+);
+criterion_main!(benches);
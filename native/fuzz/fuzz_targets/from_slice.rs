@@ -0,0 +1,15 @@
+// Copyright (c) Facebook, Inc. and its affiliates. All Rights Reserved
+//
+
+//! Arbitrary bytes, as glTF or GLB, must make `VariationalAsset::from_slice()` fail, not panic.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+use gltf_variant_meld::{Tag, VariationalAsset};
+
+fuzz_target!(|data: &[u8]| {
+    let _ = VariationalAsset::from_slice(data, Some(&Tag::from("fuzz")), None);
+    let _ = VariationalAsset::from_slice(data, None, None);
+});
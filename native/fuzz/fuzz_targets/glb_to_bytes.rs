@@ -0,0 +1,30 @@
+// Copyright (c) Facebook, Inc. and its affiliates. All Rights Reserved
+//
+
+//! Arbitrary chunk contents must make `GlbChunk::to_bytes()` fail, not panic, and whatever it
+//! writes must read back in again.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+use gltf_variant_meld::GlbChunk;
+
+fuzz_target!(|data: &[u8]| {
+    // the first byte picks where the JSON chunk ends and the BIN chunk begins
+    let (split, data) = match data.split_first() {
+        Some((&split, data)) => ((split as usize * data.len()) / 255, data),
+        None => return,
+    };
+    let (json, bin) = data.split_at(split);
+
+    let glb = match GlbChunk::to_bytes(GlbChunk::JSON(json), Some(GlbChunk::BIN(bin))) {
+        Ok(glb) => glb,
+        Err(_) => return,
+    };
+    if !json.is_empty() {
+        GlbChunk::from_bytes(&glb).expect("Couldn't read back our own GLB.");
+    }
+    let _ = GlbChunk::from_bytes_with_extras(&glb);
+    let _ = GlbChunk::from_bytes_with_extras(data);
+});
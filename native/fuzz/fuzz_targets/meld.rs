@@ -0,0 +1,37 @@
+// Copyright (c) Facebook, Inc. and its affiliates. All Rights Reserved
+//
+
+//! Arbitrary bytes, as glTF or GLB, must make `WorkAsset::meld()` fail, not panic, whichever
+//! side of the meld they're on.
+//!
+//! The other side is a real asset, so that whatever parses at all goes on to exercise the meld.
+
+#![no_main]
+
+use std::sync::OnceLock;
+
+use libfuzzer_sys::fuzz_target;
+
+use assets::ASSET_PINECONE_MATTE;
+use gltf_variant_meld::{Tag, WorkAsset};
+
+static BASE: OnceLock<WorkAsset> = OnceLock::new();
+
+fuzz_target!(|data: &[u8]| {
+    let other = match WorkAsset::from_slice(data, Some(&Tag::from("fuzz")), None) {
+        Ok(other) => other,
+        Err(_) => return,
+    };
+    let base = BASE.get_or_init(|| {
+        WorkAsset::from_file(ASSET_PINECONE_MATTE(), Some(&Tag::from("matte")))
+            .expect("Couldn't load the bundled matte pinecone.")
+    });
+
+    if let Ok((melded, _)) = WorkAsset::meld(base, &other) {
+        let _ = melded.export();
+    }
+    if let Ok((melded, _)) = WorkAsset::meld(&other, base) {
+        let _ = melded.export();
+    }
+    let _ = WorkAsset::meld(&other, &other);
+});
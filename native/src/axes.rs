@@ -0,0 +1,80 @@
+// Copyright (c) Facebook, Inc. and its affiliates. All Rights Reserved
+//
+
+//! Structured tags, for assets that vary along several independent axes at once.
+//!
+//! A product may come in a range of colours as well as a range of finishes. Rather than invent a
+//! name for each combination, a source asset can be tagged e.g. `color:red,finish:matte`, i.e.
+//! with a comma-separated list of `axis:value` pairs. Such a tag is still just a tag as far as
+//! `KHR_materials_variants` is concerned, but the melder keeps track of each axis separately in
+//! `Metadata::axes()`, so that clients can offer one picker per axis, and then look up the tag
+//! of the chosen combination with `Metadata::axis_tag()`.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::{Result, Tag};
+
+/// The character that separates an axis from its value in a structured tag.
+pub const AXIS_VALUE_SEPARATOR: char = ':';
+
+/// The character that separates the `axis:value` pairs of a structured tag.
+pub const AXIS_SEPARATOR: char = ',';
+
+/// The value along each axis of a structured tag.
+pub type AxisValues = BTreeMap<String, String>;
+
+/// Whether the given tag is structured, i.e. is made up of `axis:value` pairs.
+pub fn is_structured(tag: &str) -> bool {
+    tag.contains(AXIS_VALUE_SEPARATOR)
+}
+
+/// Splits a structured tag into its per-axis values, or returns `None` for a plain tag.
+///
+/// Each axis and value must be non-empty, and no axis may occur twice.
+pub fn parse_axis_tag(tag: &str) -> Result<Option<AxisValues>> {
+    if !is_structured(tag) {
+        return Ok(None);
+    }
+    let mut values = BTreeMap::new();
+    for pair in tag.split(AXIS_SEPARATOR) {
+        let mut split = pair.splitn(2, AXIS_VALUE_SEPARATOR);
+        let (axis, value) = match (split.next(), split.next()) {
+            (Some(axis), Some(value)) if !axis.is_empty() && !value.is_empty() => (axis, value),
+            _ => {
+                return Err(format!(
+                    "Tag {:?} has a malformed axis value {:?}; expected axis{}value.",
+                    tag, pair, AXIS_VALUE_SEPARATOR
+                ));
+            }
+        };
+        if values.insert(axis.to_owned(), value.to_owned()).is_some() {
+            return Err(format!("Tag {:?} has more than one {} value.", tag, axis));
+        }
+    }
+    Ok(Some(values))
+}
+
+/// Flattens per-axis values into a structured tag, in canonical form, i.e. with axes sorted.
+pub fn axis_tag(values: &AxisValues) -> Tag {
+    let pairs: Vec<String> = values
+        .iter()
+        .map(|(axis, value)| format!("{}{}{}", axis, AXIS_VALUE_SEPARATOR, value))
+        .collect();
+    pairs.join(&AXIS_SEPARATOR.to_string())
+}
+
+/// Gathers the values each axis takes across the given tags; plain tags are skipped.
+pub fn collect_axes<'a, I>(tags: I) -> Result<BTreeMap<String, BTreeSet<String>>>
+where
+    I: IntoIterator<Item = &'a Tag>,
+{
+    let mut axes: BTreeMap<String, BTreeSet<String>> = BTreeMap::new();
+    for tag in tags {
+        if let Some(values) = parse_axis_tag(tag)? {
+            for (axis, value) in values {
+                axes.entry(axis).or_default().insert(value);
+            }
+        }
+    }
+    Ok(axes)
+}
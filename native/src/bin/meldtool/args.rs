@@ -4,8 +4,17 @@
 use std::collections::HashMap;
 use std::fs;
 use std::path::PathBuf;
+use std::sync::Arc;
 
-use clap::{crate_authors, crate_version, App, Arg};
+use clap::{crate_authors, crate_version, App, AppSettings, Arg, SubCommand};
+
+use gltf_variant_meld::{
+    AssetInfoPolicy, BoundsPolicy, ColorPolicy, DracoSettings, ExtensionRevision,
+    ExtrasVariantExporter, ExtrasVariantImporter, FingerprintAlgorithm, FixedPrimitive,
+    MaterialFilterPolicy, MaterialOverrides, MeldOptions, MeshSelector, NameMergePolicy,
+    NodeTransformPolicy, SidecarVariantExporter, SwitchTableVariantExporter, TagCase,
+    TextureEncoding,
+};
 
 #[derive(Debug, PartialEq)]
 pub enum Verbosity {
@@ -14,10 +23,71 @@ pub enum Verbosity {
     Verbose,
 }
 
+#[derive(Debug)]
+pub enum Command {
+    Meld(WorkOrder),
+    ListTags(TagListing),
+    CompareMeta(MetaComparison),
+    Doctor(MeldDoctor),
+    AddVariant(VariantAddition),
+    PackReport(PackReport),
+}
+
+#[derive(Debug)]
+pub struct TagListing {
+    pub source_asset: SourceAsset,
+    pub json: bool,
+}
+
+#[derive(Debug)]
+pub struct MetaComparison {
+    pub earlier: PathBuf,
+    pub later: PathBuf,
+    pub max_growth: Option<usize>,
+    pub json: bool,
+}
+
+#[derive(Debug)]
+pub struct MeldDoctor {
+    pub base: SourceAsset,
+    pub other: SourceAsset,
+    pub meld_options: MeldOptions,
+    pub template_path: Option<PathBuf>,
+    pub json: bool,
+}
+
+#[derive(Debug)]
+pub struct VariantAddition {
+    pub source_asset: SourceAsset,
+    pub from: String,
+    pub tag: String,
+    pub overrides: MaterialOverrides,
+    pub texture_paths: Vec<PathBuf>,
+    pub output_path: PathBuf,
+}
+
+#[derive(Debug)]
+pub struct PackReport {
+    pub metadata_path: PathBuf,
+    pub stats_path: Option<PathBuf>,
+    pub title: Option<String>,
+    pub output_path: PathBuf,
+}
+
 #[derive(Debug)]
 pub struct WorkOrder {
     pub source_assets: SourceAssets,
     pub output_path: PathBuf,
+    pub metadata_path: Option<PathBuf>,
+    pub stats_path: Option<PathBuf>,
+    pub split_dir: Option<PathBuf>,
+    pub draco: Option<DracoSettings>,
+    pub meshopt: bool,
+    pub post_validate: Option<PostValidation>,
+    pub cache_dir: Option<PathBuf>,
+    pub read_jobs: usize,
+    pub correspondence_path: Option<PathBuf>,
+    pub meld_options: MeldOptions,
     pub verbosity: Verbosity,
 }
 
@@ -30,6 +100,12 @@ impl WorkOrder {
     }
 }
 
+#[derive(Debug)]
+pub enum PostValidation {
+    Embedded,
+    External(PathBuf),
+}
+
 #[derive(Debug)]
 pub struct SourceAssets {
     pub base: SourceAsset,
@@ -42,10 +118,213 @@ pub struct SourceAsset {
     pub tag: Option<String>,
 }
 
-pub fn parse_args() -> WorkOrder {
+pub fn parse_args() -> Command {
     let matches = App::new("glTFVariantMeld")
         .author(crate_authors!())
         .version(crate_version!())
+        .setting(AppSettings::SubcommandsNegateReqs)
+        .subcommand(
+            SubCommand::with_name("list-tags")
+                .about("print the variant tags of an asset, with display names & sizes")
+                .arg(
+                    Arg::with_name("file")
+                        .required(true)
+                        .value_name("FILE")
+                        .help("the asset whose tags to list"),
+                )
+                .arg(
+                    Arg::with_name("tag")
+                        .short("t")
+                        .long("tagged-as")
+                        .takes_value(true)
+                        .value_name("TAG")
+                        .help("the asset's default tag, if it isn't variational already"),
+                )
+                .arg(
+                    Arg::with_name("json")
+                        .long("json")
+                        .takes_value(false)
+                        .help("print JSON rather than a table"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("compare-meta")
+                .about("print how the metadata of one variational asset differs from another's")
+                .arg(
+                    Arg::with_name("earlier")
+                        .required(true)
+                        .value_name("EARLIER")
+                        .help("the asset to compare against, e.g. as previously published"),
+                )
+                .arg(
+                    Arg::with_name("later")
+                        .required(true)
+                        .value_name("LATER")
+                        .help("the asset to compare, e.g. a new meld"),
+                )
+                .arg(
+                    Arg::with_name("max_growth")
+                        .long("max-growth")
+                        .takes_value(true)
+                        .value_name("BYTES")
+                        .help("fail if any tag's texture data grew by more than this"),
+                )
+                .arg(
+                    Arg::with_name("json")
+                        .long("json")
+                        .takes_value(false)
+                        .help("print JSON rather than text"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("doctor")
+                .about("explain why one asset won't meld into another, and what might help")
+                .arg(
+                    Arg::with_name("base")
+                        .required(true)
+                        .value_name("BASE")
+                        .help("the asset to meld into"),
+                )
+                .arg(
+                    Arg::with_name("other")
+                        .required(true)
+                        .value_name("OTHER")
+                        .help("the asset that fails to meld"),
+                )
+                .arg(
+                    Arg::with_name("fingerprint")
+                        .long("fingerprint")
+                        .takes_value(true)
+                        .value_name("additive|quantized:GRID")
+                        .help("how to match up mesh primitives, as for melding"),
+                )
+                .arg(
+                    Arg::with_name("fingerprint_epsilon")
+                        .long("fingerprint-epsilon")
+                        .takes_value(true)
+                        .value_name("EPSILON")
+                        .help("how close primitive fingerprints must be to match, as for melding"),
+                )
+                .arg(
+                    Arg::with_name("id_key")
+                        .long("id-key")
+                        .takes_value(true)
+                        .value_name("KEY")
+                        .help("match up meshes by this 'extras' ID, as for melding"),
+                )
+                .arg(
+                    Arg::with_name("write_template")
+                        .long("write-template")
+                        .takes_value(true)
+                        .value_name("FILE")
+                        .help("write a primitive correspondence to edit & meld with, to this file"),
+                )
+                .arg(
+                    Arg::with_name("json")
+                        .long("json")
+                        .takes_value(false)
+                        .help("print JSON rather than text"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("add-variant")
+                .about("add a variant tag, copying another's materials with some values changed")
+                .arg(
+                    Arg::with_name("file")
+                        .required(true)
+                        .value_name("FILE")
+                        .help("the asset to add a variant to"),
+                )
+                .arg(
+                    Arg::with_name("from")
+                        .long("from")
+                        .required(true)
+                        .takes_value(true)
+                        .value_name("TAG")
+                        .help("the existing tag whose materials to copy"),
+                )
+                .arg(
+                    Arg::with_name("new_tag")
+                        .long("tag")
+                        .required(true)
+                        .takes_value(true)
+                        .value_name("TAG")
+                        .help("the new tag"),
+                )
+                .arg(
+                    Arg::with_name("set")
+                        .long("set")
+                        .takes_value(true)
+                        .multiple(true)
+                        .number_of_values(1)
+                        .value_name("PROPERTY=VALUE")
+                        .help("a value to change, e.g. roughnessFactor=0.8 or normalTexture=FILE"),
+                )
+                .arg(
+                    Arg::with_name("tag")
+                        .short("t")
+                        .long("tagged-as")
+                        .takes_value(true)
+                        .value_name("TAG")
+                        .help("the asset's default tag, if it isn't variational already"),
+                )
+                .arg(
+                    Arg::with_name("output")
+                        .short("o")
+                        .long("output")
+                        .required(true)
+                        .takes_value(true)
+                        .value_name("FILE")
+                        .help("the name of the output file"),
+                )
+                .arg(
+                    Arg::with_name("force")
+                        .short("f")
+                        .long("force")
+                        .takes_value(false)
+                        .help("overwrite output file if it exists"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("pack-report")
+                .about("write an HTML report of a meld's sizes, textures & warnings, for sharing")
+                .arg(
+                    Arg::with_name("metadata")
+                        .required(true)
+                        .value_name("FILE")
+                        .help("the metadata JSON written with --metadata-out, or the asset itself"),
+                )
+                .arg(
+                    Arg::with_name("stats")
+                        .long("stats")
+                        .takes_value(true)
+                        .value_name("FILE")
+                        .help("the statistics JSON written with --stats-out, for its warnings"),
+                )
+                .arg(
+                    Arg::with_name("title")
+                        .long("title")
+                        .takes_value(true)
+                        .value_name("TITLE")
+                        .help("the title of the report; by default, the name of the input file"),
+                )
+                .arg(
+                    Arg::with_name("output")
+                        .short("o")
+                        .long("output")
+                        .required(true)
+                        .takes_value(true)
+                        .value_name("FILE")
+                        .help("the name of the HTML file to write"),
+                )
+                .arg(
+                    Arg::with_name("force")
+                        .short("f")
+                        .long("force")
+                        .takes_value(false)
+                        .help("overwrite output file if it exists"),
+                ),
+        )
         .arg(
             Arg::with_name("base")
                 .short("b")
@@ -82,6 +361,46 @@ pub fn parse_args() -> WorkOrder {
                 .value_name("FILE")
                 .help("the name of the output file"),
         )
+        .arg(
+            Arg::with_name("metadata_out")
+                .long("metadata-out")
+                .takes_value(true)
+                .value_name("FILE")
+                .help("also write the output asset's metadata to this JSON file"),
+        )
+        .arg(
+            Arg::with_name("stats_out")
+                .long("stats-out")
+                .takes_value(true)
+                .value_name("FILE")
+                .help("write the statistics & phase timings of each meld to this JSON file"),
+        )
+        .arg(
+            Arg::with_name("also_split")
+                .long("also-split")
+                .takes_value(true)
+                .value_name("DIR")
+                .help("also write a plain GLB per variant tag into this directory, named after it"),
+        )
+        .arg(
+            Arg::with_name("draco")
+                .long("draco")
+                .help("compress the output's geometry with Draco; it can then no longer be melded"),
+        )
+        .arg(
+            Arg::with_name("meshopt")
+                .long("meshopt")
+                .conflicts_with("draco")
+                .help("compress the output's vertex data with meshopt; it can then not be melded"),
+        )
+        .arg(
+            Arg::with_name("post_validate")
+                .long("post-validate")
+                .takes_value(true)
+                .min_values(0)
+                .value_name("VALIDATOR")
+                .help("fail if the output has errors; checked by this gltf_validator, if given"),
+        )
         .arg(
             Arg::with_name("force")
                 .short("f")
@@ -89,6 +408,291 @@ pub fn parse_args() -> WorkOrder {
                 .takes_value(false)
                 .help("overwrite output file if it exists"),
         )
+        .arg(
+            Arg::with_name("cache")
+                .long("cache-dir")
+                .takes_value(true)
+                .value_name("DIR")
+                .help("cache meld keys & fingerprints of source assets in this directory"),
+        )
+        .arg(
+            Arg::with_name("read-jobs")
+                .short("j")
+                .long("read-jobs")
+                .takes_value(true)
+                .value_name("N")
+                .help("read & key the sources on up to this many threads; melds stay sequential"),
+        )
+        .arg(
+            Arg::with_name("correspondence")
+                .long("correspondence")
+                .takes_value(true)
+                .value_name("FILE")
+                .help("match up mesh primitives as this JSON file says; see doctor"),
+        )
+        .arg(
+            Arg::with_name("float_precision")
+                .long("float-precision")
+                .takes_value(true)
+                .value_name("DIGITS")
+                .help("decimal digits to compare material factors to, or 'exact'"),
+        )
+        .arg(
+            Arg::with_name("color_tolerance")
+                .long("color-tolerance")
+                .takes_value(true)
+                .value_name("DELTA")
+                .help("treat colour factors on the same grid of this spacing as equal, roughly"),
+        )
+        .arg(
+            Arg::with_name("color_policy")
+                .long("color-policy")
+                .takes_value(true)
+                .possible_values(&["base", "other"])
+                .value_name("POLICY")
+                .help("whose colours to keep when they're only tolerably equal"),
+        )
+        .arg(
+            Arg::with_name("embed_metadata")
+                .long("embed-metadata")
+                .takes_value(false)
+                .help("store metadata inside the melded output, for quick inspection later"),
+        )
+        .arg(
+            Arg::with_name("lenient")
+                .long("lenient")
+                .takes_value(false)
+                .help("pass through glTF we don't work on unparsed, e.g. for newer features"),
+        )
+        .arg(
+            Arg::with_name("texture_dir")
+                .long("texture-dir")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .value_name("DIR")
+                .help("look here for images & buffers that aren't where their asset says"),
+        )
+        .arg(
+            Arg::with_name("case_insensitive_uris")
+                .long("case-insensitive-uris")
+                .takes_value(false)
+                .help("find images & buffers whose files' names differ in case, with a warning"),
+        )
+        .arg(
+            Arg::with_name("drop_extra_chunks")
+                .long("drop-extra-chunks")
+                .takes_value(false)
+                .help("drop GLB chunks other than JSON & BIN, rather than carry them through"),
+        )
+        .arg(
+            Arg::with_name("asset_info_policy")
+                .long("asset-info-policy")
+                .takes_value(true)
+                .possible_values(&["merge", "base"])
+                .value_name("POLICY")
+                .help("merge the sources' copyrights & generators, or keep only the base's"),
+        )
+        .arg(
+            Arg::with_name("name_merge")
+                .long("name-merge")
+                .takes_value(true)
+                .possible_values(&["ignore", "keep-base", "keep-other"])
+                .value_name("POLICY")
+                .help("which name melded images & textures keep, recording the rest as aliases"),
+        )
+        .arg(
+            Arg::with_name("require_variants")
+                .long("require-variants")
+                .takes_value(false)
+                .help("list KHR_materials_variants as required, not merely used"),
+        )
+        .arg(
+            Arg::with_name("allow_unsupported_required")
+                .long("allow-unsupported-required")
+                .takes_value(false)
+                .help("pass through assets that require extensions we can't honor, e.g. Draco"),
+        )
+        .arg(
+            Arg::with_name("legacy")
+                .long("write-legacy-extension")
+                .takes_value(false)
+                .help("also write variants as FB_material_variants, for older viewers"),
+        )
+        .arg(
+            Arg::with_name("compact_mappings")
+                .long("compact-mappings")
+                .takes_value(false)
+                .help("leave out mappings to primitives' own materials, for smaller delivery"),
+        )
+        .arg(
+            Arg::with_name("extension_revision")
+                .long("extension-revision")
+                .takes_value(true)
+                .possible_values(&["draft", "ratified"])
+                .value_name("REVISION")
+                .help("the KHR_materials_variants revision to write; default is as in the base"),
+        )
+        .arg(
+            Arg::with_name("texture_encoding")
+                .long("texture-encoding")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .value_name("TAG=png|jpeg:QUALITY")
+                .help("re-encode the textures of the given tag's materials on export"),
+        )
+        .arg(
+            Arg::with_name("material_tag_separator")
+                .long("tags-from-material-names")
+                .takes_value(true)
+                .value_name("SEPARATOR")
+                .help("derive variants from material names, e.g. '_' for Body_red & Body_blue"),
+        )
+        .arg(
+            Arg::with_name("extras_tag_prefix")
+                .long("tags-from-extras")
+                .takes_value(true)
+                .value_name("PREFIX")
+                .help("import variants from primitive 'extras', e.g. 'variant_' for 'variant_red'"),
+        )
+        .arg(
+            Arg::with_name("extras_export_prefix")
+                .long("tags-to-extras")
+                .takes_value(true)
+                .value_name("PREFIX")
+                .help("also export variants into primitive 'extras', e.g. 'variant_red': 3"),
+        )
+        .arg(
+            Arg::with_name("variant_table")
+                .long("variant-table")
+                .takes_value(true)
+                .value_name("FILE_NAME")
+                .help("also write a JSON table of the variants next to the output file"),
+        )
+        .arg(
+            Arg::with_name("switch_table")
+                .long("switch-table")
+                .takes_value(true)
+                .value_name("FILE_NAME")
+                .help("also write a runtime switch table of per-node materials next to the output"),
+        )
+        .arg(
+            Arg::with_name("tag_case")
+                .long("tag-case")
+                .takes_value(true)
+                .possible_values(&["preserve", "lower", "upper"])
+                .value_name("CASE")
+                .help("normalize the case of all tags on the way in"),
+        )
+        .arg(
+            Arg::with_name("tag_punctuation")
+                .long("tag-punctuation")
+                .takes_value(true)
+                .value_name("CHARS")
+                .help("allow only ASCII letters, digits & these characters in tags"),
+        )
+        .arg(
+            Arg::with_name("tag_max_length")
+                .long("tag-max-length")
+                .takes_value(true)
+                .value_name("LENGTH")
+                .help("reject tags longer than this many characters"),
+        )
+        .arg(
+            Arg::with_name("reserved_tag")
+                .long("reserved-tag")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .value_name("TAG")
+                .help("reject this tag, e.g. because a runtime gives it special meaning"),
+        )
+        .arg(
+            Arg::with_name("memory_budget")
+                .long("memory-budget")
+                .takes_value(true)
+                .value_name("MEGABYTES")
+                .help("abort any meld that would hold more than about this much asset data"),
+        )
+        .arg(
+            Arg::with_name("position_bounds")
+                .long("position-bounds")
+                .takes_value(true)
+                .possible_values(&["keep", "verify", "recompute"])
+                .value_name("POLICY")
+                .help("what to do about vertex position min/max on export; default is keep"),
+        )
+        .arg(
+            Arg::with_name("fingerprint")
+                .long("fingerprint")
+                .takes_value(true)
+                .value_name("additive|quantized:GRID")
+                .help("how to match up mesh primitives, e.g. 'quantized:0.0001' to hash vertices"),
+        )
+        .arg(
+            Arg::with_name("fingerprint_epsilon")
+                .long("fingerprint-epsilon")
+                .takes_value(true)
+                .value_name("EPSILON")
+                .help("how close primitive fingerprints must be to match; errors suggest values"),
+        )
+        .arg(
+            Arg::with_name("node_transforms")
+                .long("node-transforms")
+                .takes_value(true)
+                .possible_values(&["ignore", "compare", "bake"])
+                .value_name("POLICY")
+                .help("whether to match meshes as their nodes place them; default is ignore"),
+        )
+        .arg(
+            Arg::with_name("id_key")
+                .long("id-key")
+                .takes_value(true)
+                .value_name("KEY")
+                .help("match up meshes by this mesh or node 'extras' ID, e.g. 'sourceId', if set"),
+        )
+        .arg(
+            Arg::with_name("node_scoped_meshes")
+                .long("node-scoped-meshes")
+                .takes_value(false)
+                .help("give each node a mesh of its own, named MESH@NODE, to vary independently"),
+        )
+        .arg(
+            Arg::with_name("fixed_primitive")
+                .long("fixed-primitive")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .value_name("MESH[:PRIMITIVE]")
+                .help("never vary this primitive, of a mesh given by name or as #INDEX"),
+        )
+        .arg(
+            Arg::with_name("variable_material")
+                .long("variable-material")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .value_name("PATTERN")
+                .help("let only materials whose names match vary, e.g. 'Body*'"),
+        )
+        .arg(
+            Arg::with_name("constant_material")
+                .long("constant-material")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .value_name("PATTERN")
+                .help("never let materials whose names match vary, e.g. 'Logo_?'"),
+        )
+        .arg(
+            Arg::with_name("constant_material_policy")
+                .long("constant-material-policy")
+                .takes_value(true)
+                .possible_values(&["error", "ignore"])
+                .value_name("POLICY")
+                .help("fail the meld when a constant material varies, or drop the variation"),
+        )
         .arg(
             Arg::with_name("verbose")
                 .short("v")
@@ -105,8 +709,247 @@ pub fn parse_args() -> WorkOrder {
         )
         .get_matches();
 
+    if let Some(matches) = matches.subcommand_matches("list-tags") {
+        return Command::ListTags(parse_tag_listing(matches));
+    }
+    if let Some(matches) = matches.subcommand_matches("compare-meta") {
+        return Command::CompareMeta(parse_meta_comparison(matches));
+    }
+    if let Some(matches) = matches.subcommand_matches("doctor") {
+        return Command::Doctor(parse_meld_doctor(matches));
+    }
+    if let Some(matches) = matches.subcommand_matches("add-variant") {
+        return Command::AddVariant(parse_variant_addition(matches));
+    }
+    if let Some(matches) = matches.subcommand_matches("pack-report") {
+        return Command::PackReport(parse_pack_report(matches));
+    }
+
     let source_assets = parse_source_assets(&matches);
 
+    let output_path = parse_output_path(&matches);
+
+    let metadata_path = matches.value_of("metadata_out").map(PathBuf::from);
+
+    let stats_path = matches.value_of("stats_out").map(PathBuf::from);
+
+    let split_dir = matches.value_of("also_split").map(PathBuf::from);
+    if let Some(split_dir) = &split_dir {
+        if split_dir.is_file() {
+            eprintln!("Error: Split path is a file: {}", split_dir.display());
+            std::process::exit(1);
+        }
+    }
+
+    let draco = if matches.is_present("draco") {
+        Some(DracoSettings::default())
+    } else {
+        None
+    };
+
+    let meshopt = matches.is_present("meshopt");
+
+    let post_validate = if matches.occurrences_of("post_validate") > 0 {
+        Some(match matches.value_of("post_validate") {
+            Some(validator) => PostValidation::External(PathBuf::from(validator)),
+            None => PostValidation::Embedded,
+        })
+    } else {
+        None
+    };
+
+    let cache_dir = matches.value_of("cache").map(PathBuf::from);
+
+    let read_jobs = matches
+        .value_of("read-jobs")
+        .map_or(1, |jobs| match jobs.parse() {
+            Ok(count) if count > 0 => count,
+            _ => {
+                eprintln!("Error: Bad read job count: {}", jobs);
+                std::process::exit(1);
+            }
+        });
+
+    let correspondence_path = matches.value_of("correspondence").map(PathBuf::from);
+    if correspondence_path.is_some() && source_assets.melds.len() != 1 {
+        eprintln!("Error: A correspondence file can only be used to meld a single asset.");
+        std::process::exit(1);
+    }
+
+    let meld_options = parse_meld_options(&matches);
+
+    let verbosity = if matches.occurrences_of("verbose") > 0 {
+        Verbosity::Verbose
+    } else if matches.occurrences_of("quiet") > 0 {
+        Verbosity::Quiet
+    } else {
+        Verbosity::Normal
+    };
+
+    Command::Meld(WorkOrder {
+        source_assets,
+        output_path,
+        metadata_path,
+        stats_path,
+        split_dir,
+        draco,
+        meshopt,
+        post_validate,
+        cache_dir,
+        read_jobs,
+        correspondence_path,
+        meld_options,
+        verbosity,
+    })
+}
+
+fn parse_tag_listing(matches: &clap::ArgMatches) -> TagListing {
+    let file = matches.value_of("file").unwrap();
+    let path = PathBuf::from(file);
+    if !path.exists() {
+        eprintln!("Error: Couldn't open file: {}", file);
+        std::process::exit(1);
+    }
+    TagListing {
+        source_asset: SourceAsset {
+            path,
+            tag: matches.value_of("tag").map(String::from),
+        },
+        json: matches.occurrences_of("json") > 0,
+    }
+}
+
+fn parse_meta_comparison(matches: &clap::ArgMatches) -> MetaComparison {
+    let existing_file = |arg| {
+        let file = matches.value_of(arg).unwrap();
+        let path = PathBuf::from(file);
+        if !path.exists() {
+            eprintln!("Error: Couldn't open file: {}", file);
+            std::process::exit(1);
+        }
+        path
+    };
+    let max_growth = matches.value_of("max_growth").map(|max_growth| {
+        max_growth.parse().unwrap_or_else(|_| {
+            eprintln!("Error: Bad max growth: {}", max_growth);
+            std::process::exit(1);
+        })
+    });
+    MetaComparison {
+        earlier: existing_file("earlier"),
+        later: existing_file("later"),
+        max_growth,
+        json: matches.occurrences_of("json") > 0,
+    }
+}
+
+fn parse_meld_doctor(matches: &clap::ArgMatches) -> MeldDoctor {
+    let existing_asset = |arg| {
+        let file = matches.value_of(arg).unwrap();
+        let path = PathBuf::from(file);
+        if !path.exists() {
+            eprintln!("Error: Couldn't open file: {}", file);
+            std::process::exit(1);
+        }
+        SourceAsset { path, tag: None }
+    };
+    MeldDoctor {
+        base: existing_asset("base"),
+        other: existing_asset("other"),
+        meld_options: parse_meld_options(matches),
+        template_path: matches.value_of("write_template").map(PathBuf::from),
+        json: matches.occurrences_of("json") > 0,
+    }
+}
+
+fn parse_variant_addition(matches: &clap::ArgMatches) -> VariantAddition {
+    let file = matches.value_of("file").unwrap();
+    let path = PathBuf::from(file);
+    if !path.exists() {
+        eprintln!("Error: Couldn't open file: {}", file);
+        std::process::exit(1);
+    }
+
+    // build the overrides as JSON, so that they're checked like any other
+    let mut json = serde_json::Map::new();
+    let mut texture_paths = vec![];
+    for setting in matches.values_of("set").into_iter().flatten() {
+        let (property, value) = match parse_setting(setting, &mut texture_paths) {
+            Some(parsed) => parsed,
+            None => {
+                eprintln!("Error: Bad material setting: {}", setting);
+                std::process::exit(1);
+            }
+        };
+        json.insert(property, value);
+    }
+    let overrides = MaterialOverrides::from_json(&serde_json::Value::Object(json).to_string())
+        .unwrap_or_else(|e| {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        });
+    for texture_path in &texture_paths {
+        if !texture_path.exists() {
+            eprintln!("Error: Couldn't open file: {}", texture_path.display());
+            std::process::exit(1);
+        }
+    }
+
+    VariantAddition {
+        source_asset: SourceAsset {
+            path,
+            tag: matches.value_of("tag").map(String::from),
+        },
+        from: matches.value_of("from").unwrap().to_owned(),
+        tag: matches.value_of("new_tag").unwrap().to_owned(),
+        overrides,
+        texture_paths,
+        output_path: parse_output_path(matches),
+    }
+}
+
+// a PROPERTY=VALUE setting as an overrides property & JSON value; textures are given by file,
+// which is added to the list of textures to read, and referenced by index
+fn parse_setting(
+    setting: &str,
+    texture_paths: &mut Vec<PathBuf>,
+) -> Option<(String, serde_json::Value)> {
+    let mut split = setting.splitn(2, '=');
+    let (property, value) = (split.next()?, split.next()?);
+    if property.ends_with("Texture") {
+        texture_paths.push(PathBuf::from(value));
+        return Some((property.to_owned(), (texture_paths.len() - 1).into()));
+    }
+    let numbers = value
+        .split(',')
+        .map(|number| number.trim().parse::<f64>().ok())
+        .collect::<Option<Vec<_>>>()?;
+    let value = match numbers.as_slice() {
+        [number] => (*number).into(),
+        _ => numbers.into(),
+    };
+    Some((property.to_owned(), value))
+}
+
+// the output file named by the -o argument, which mustn't exist unless -f is given
+fn parse_pack_report(matches: &clap::ArgMatches) -> PackReport {
+    let existing_file = |file: &str| {
+        let path = PathBuf::from(file);
+        if !path.exists() {
+            eprintln!("Error: Couldn't open file: {}", file);
+            std::process::exit(1);
+        }
+        path
+    };
+    PackReport {
+        metadata_path: existing_file(matches.value_of("metadata").unwrap()),
+        stats_path: matches.value_of("stats").map(existing_file),
+        title: matches.value_of("title").map(String::from),
+        output_path: parse_output_path(matches),
+    }
+}
+
+fn parse_output_path(matches: &clap::ArgMatches) -> PathBuf {
     let force = matches.occurrences_of("force") > 0;
     let output_path = &matches.value_of("output").unwrap();
     if let Ok(metadata) = fs::metadata(output_path) {
@@ -121,20 +964,212 @@ pub fn parse_args() -> WorkOrder {
             std::process::exit(1);
         }
     }
-    let output_path = PathBuf::from(output_path);
+    PathBuf::from(output_path)
+}
 
-    let verbosity = if matches.occurrences_of("verbose") > 0 {
-        Verbosity::Verbose
-    } else if matches.occurrences_of("quiet") > 0 {
-        Verbosity::Quiet
+fn parse_meld_options(matches: &clap::ArgMatches) -> MeldOptions {
+    let mut options = MeldOptions::default();
+    if let Some(precision) = matches.value_of("float_precision") {
+        options.float_precision = if precision == "exact" {
+            None
+        } else if let Ok(digits) = precision.parse() {
+            Some(digits)
+        } else {
+            eprintln!("Error: Bad float precision: {}", precision);
+            std::process::exit(1);
+        };
+    }
+    if let Some(tolerance) = matches.value_of("color_tolerance") {
+        if let Ok(tolerance) = tolerance.parse::<f32>() {
+            options.color_tolerance = Some(tolerance);
+        } else {
+            eprintln!("Error: Bad color tolerance: {}", tolerance);
+            std::process::exit(1);
+        }
+    }
+    if matches.value_of("color_policy") == Some("other") {
+        options.color_policy = ColorPolicy::KeepOther;
+    }
+    options.embed_metadata = matches.occurrences_of("embed_metadata") > 0;
+    options.lenient = matches.occurrences_of("lenient") > 0;
+    options.case_insensitive_uris = matches.occurrences_of("case_insensitive_uris") > 0;
+    options.drop_extra_chunks = matches.occurrences_of("drop_extra_chunks") > 0;
+    if matches.value_of("asset_info_policy") == Some("base") {
+        options.asset_info_policy = AssetInfoPolicy::KeepBase;
+    }
+    options.name_merge_policy = match matches.value_of("name_merge") {
+        Some("keep-base") => NameMergePolicy::KeepBase,
+        Some("keep-other") => NameMergePolicy::KeepOther,
+        _ => NameMergePolicy::Ignore,
+    };
+    options.require_variants_extension = matches.occurrences_of("require_variants") > 0;
+    options.allow_unsupported_required = matches.occurrences_of("allow_unsupported_required") > 0;
+    options.write_legacy_extension = matches.occurrences_of("legacy") > 0;
+    options.compact_variant_mappings = matches.occurrences_of("compact_mappings") > 0;
+    if let Some(encodings) = matches.values_of("texture_encoding") {
+        for encoding in encodings {
+            if let Some((tag, encoding)) = parse_texture_encoding(encoding) {
+                options.texture_encodings.insert(tag, encoding);
+            } else {
+                eprintln!("Error: Bad texture encoding: {}", encoding);
+                std::process::exit(1);
+            }
+        }
+    }
+    if let Some(dirs) = matches.values_of("texture_dir") {
+        options.texture_search_paths = dirs.map(PathBuf::from).collect();
+    }
+    options.material_tag_separator = matches.value_of("material_tag_separator").map(String::from);
+    if let Some(prefix) = matches.value_of("extras_tag_prefix") {
+        let importer = ExtrasVariantImporter {
+            prefix: String::from(prefix),
+        };
+        options.variant_importers.0.push(Arc::new(importer));
+    }
+    if let Some(prefix) = matches.value_of("extras_export_prefix") {
+        let exporter = ExtrasVariantExporter {
+            prefix: String::from(prefix),
+        };
+        options.variant_exporters.0.push(Arc::new(exporter));
+    }
+    if let Some(file_name) = matches.value_of("variant_table") {
+        let exporter = SidecarVariantExporter {
+            file_name: String::from(file_name),
+        };
+        options.variant_exporters.0.push(Arc::new(exporter));
+    }
+    if let Some(file_name) = matches.value_of("switch_table") {
+        let exporter = SwitchTableVariantExporter {
+            file_name: String::from(file_name),
+        };
+        options.variant_exporters.0.push(Arc::new(exporter));
+    }
+    options.mesh_id_key = matches.value_of("id_key").map(String::from);
+    options.node_scoped_meshes = matches.occurrences_of("node_scoped_meshes") > 0;
+    if let Some(fixed) = matches.values_of("fixed_primitive") {
+        for fixed in fixed {
+            if let Some(fixed) = parse_fixed_primitive(fixed) {
+                options.fixed_primitives.push(fixed);
+            } else {
+                eprintln!("Error: Bad fixed primitive: {}", fixed);
+                std::process::exit(1);
+            }
+        }
+    }
+    if let Some(patterns) = matches.values_of("variable_material") {
+        options.material_filter.allow = patterns.map(String::from).collect();
+    }
+    if let Some(patterns) = matches.values_of("constant_material") {
+        options.material_filter.deny = patterns.map(String::from).collect();
+    }
+    if matches.value_of("constant_material_policy") == Some("ignore") {
+        options.material_filter.policy = MaterialFilterPolicy::Ignore;
+    }
+    options.tag_policy.case = match matches.value_of("tag_case") {
+        Some("lower") => TagCase::Lower,
+        Some("upper") => TagCase::Upper,
+        _ => TagCase::Preserve,
+    };
+    options.tag_policy.allowed_punctuation = matches.value_of("tag_punctuation").map(String::from);
+    if let Some(max_length) = matches.value_of("tag_max_length") {
+        if let Ok(max_length) = max_length.parse() {
+            options.tag_policy.max_length = Some(max_length);
+        } else {
+            eprintln!("Error: Bad tag max length: {}", max_length);
+            std::process::exit(1);
+        }
+    }
+    if let Some(reserved) = matches.values_of("reserved_tag") {
+        options.tag_policy.reserved = reserved.map(String::from).collect();
+    }
+    if let Some(budget) = matches.value_of("memory_budget") {
+        if let Ok(megabytes) = budget.parse::<usize>() {
+            options.memory_budget = Some(megabytes * 1000000);
+        } else {
+            eprintln!("Error: Bad memory budget: {}", budget);
+            std::process::exit(1);
+        }
+    }
+    options.extension_revision = match matches.value_of("extension_revision") {
+        Some("draft") => Some(ExtensionRevision::Draft),
+        Some("ratified") => Some(ExtensionRevision::Ratified),
+        _ => None,
+    };
+    if let Some(fingerprint) = matches.value_of("fingerprint") {
+        if let Some(algorithm) = parse_fingerprint_algorithm(fingerprint) {
+            options.fingerprint_algorithm = algorithm;
+        } else {
+            eprintln!("Error: Bad fingerprint algorithm: {}", fingerprint);
+            std::process::exit(1);
+        }
+    }
+    if let Some(epsilon) = matches.value_of("fingerprint_epsilon") {
+        match epsilon.parse::<f64>() {
+            Ok(parsed) if parsed > 0.0 => options.fingerprint_epsilon = parsed,
+            _ => {
+                eprintln!("Error: Bad fingerprint epsilon: {}", epsilon);
+                std::process::exit(1);
+            }
+        }
+    }
+    options.position_bounds = match matches.value_of("position_bounds") {
+        Some("verify") => BoundsPolicy::Verify,
+        Some("recompute") => BoundsPolicy::Recompute,
+        _ => BoundsPolicy::Keep,
+    };
+    options.node_transforms = match matches.value_of("node_transforms") {
+        Some("compare") => NodeTransformPolicy::Compare,
+        Some("bake") => NodeTransformPolicy::Bake,
+        _ => NodeTransformPolicy::Ignore,
+    };
+    options
+}
+
+fn parse_texture_encoding(arg: &str) -> Option<(String, TextureEncoding)> {
+    let mut split = arg.splitn(2, '=');
+    let (tag, encoding) = (split.next()?, split.next()?);
+    let encoding = if encoding == "png" {
+        TextureEncoding::Png
+    } else if encoding.starts_with("jpeg:") {
+        match encoding["jpeg:".len()..].parse() {
+            Ok(quality) if quality >= 1 && quality <= 100 => TextureEncoding::Jpeg(quality),
+            _ => return None,
+        }
     } else {
-        Verbosity::Normal
+        return None;
     };
+    Some((tag.to_owned(), encoding))
+}
 
-    WorkOrder {
-        source_assets,
-        output_path,
-        verbosity,
+// a mesh name or #INDEX, optionally followed by :PRIMITIVE
+fn parse_fixed_primitive(arg: &str) -> Option<FixedPrimitive> {
+    let (mesh, primitive) = match arg.rfind(':') {
+        Some(colon) => match arg[colon + 1..].parse() {
+            Ok(primitive) => (&arg[..colon], Some(primitive)),
+            Err(_) => (arg, None),
+        },
+        None => (arg, None),
+    };
+    let mesh = if mesh.starts_with('#') {
+        MeshSelector::Index(mesh[1..].parse().ok()?)
+    } else if !mesh.is_empty() {
+        MeshSelector::Name(mesh.to_owned())
+    } else {
+        return None;
+    };
+    Some(FixedPrimitive { mesh, primitive })
+}
+
+fn parse_fingerprint_algorithm(arg: &str) -> Option<FingerprintAlgorithm> {
+    if arg == "additive" {
+        Some(FingerprintAlgorithm::Additive)
+    } else if arg.starts_with("quantized:") {
+        match arg["quantized:".len()..].parse() {
+            Ok(grid) if grid > 0.0 => Some(FingerprintAlgorithm::Quantized(grid)),
+            _ => None,
+        }
+    } else {
+        None
     }
 }
 
@@ -6,56 +6,513 @@ extern crate clap;
 extern crate gltf_variant_meld;
 
 use std::fs;
+use std::path::Path;
+use std::thread;
 
-use gltf_variant_meld::{Result, VariationalAsset};
+use serde_json::{json, Value};
+
+use gltf_variant_meld::{
+    validate_glb, MeldCache, MeldCounts, MeldDiagnosis, MeldOptions, MeldStats, MeshMismatch,
+    Metadata, MetadataDelta, PrimitiveCorrespondence, Result, Tag, VariationalAsset,
+};
 
 mod args;
 use args::parse_args;
-pub use args::{SourceAsset, SourceAssets, WorkOrder};
+pub use args::{
+    Command, MeldDoctor, MetaComparison, PackReport, PostValidation, SourceAsset, SourceAssets,
+    TagListing, VariantAddition, WorkOrder,
+};
+
+mod report;
+use report::render_report;
 
 fn main() {
-    let work_order = parse_args();
+    let result = match parse_args() {
+        Command::Meld(work_order) => process(work_order),
+        Command::ListTags(listing) => list_tags(listing),
+        Command::CompareMeta(comparison) => compare_meta(comparison),
+        Command::Doctor(doctor) => doctor_meld(doctor),
+        Command::AddVariant(addition) => add_variant(addition),
+        Command::PackReport(report) => pack_report(report),
+    };
 
-    if let Err(err) = process(work_order) {
+    if let Err(err) = result {
         eprintln!("Error: {}", err);
+        std::process::exit(1);
+    }
+}
+
+fn list_tags(listing: TagListing) -> Result<()> {
+    let asset = read_asset(&listing.source_asset, None, &MeldOptions::default())?;
+    let metadata = asset.metadata();
+
+    let rows: Vec<_> = metadata
+        .tags()
+        .iter()
+        .map(|tag| {
+            let name = metadata.tag_display(tag).and_then(|d| d.name.as_ref());
+            let exclusive = metadata
+                .tag_exclusive_sizes(tag)
+                .map_or(0, |sizes| sizes.texture_bytes);
+            (tag, name, exclusive)
+        })
+        .collect();
+
+    if listing.json {
+        let rows: Vec<_> = rows
+            .iter()
+            .map(|(tag, name, exclusive)| {
+                json!({ "tag": tag, "name": name, "exclusiveTextureBytes": exclusive })
+            })
+            .collect();
+        let json = serde_json::to_string_pretty(&rows)
+            .map_err(|e| format!("Couldn't serialize tags: {}", e))?;
+        println!("{}", json);
+    } else {
+        for (tag, name, exclusive) in rows {
+            let name = name.map(String::as_str).unwrap_or("-");
+            println!("{:<24} {:<32} {:>12}", tag, name, exclusive);
+        }
+    }
+    Ok(())
+}
+
+fn compare_meta(comparison: MetaComparison) -> Result<()> {
+    // these are variational assets already, so any embedded metadata is used as it is
+    let load = |path: &Path| -> Result<VariationalAsset> {
+        let glb = fs::read(path)
+            .map_err(|e| format!("Couldn't read asset file {}: {}", path.display(), e))?;
+        VariationalAsset::from_slice(&glb, None, path.parent())
+    };
+    let earlier = load(&comparison.earlier)?;
+    let later = load(&comparison.later)?;
+    let delta = later.metadata().diff(earlier.metadata());
+
+    let flagged: Vec<&Tag> = match comparison.max_growth {
+        Some(max_growth) => delta
+            .per_tag_texture_bytes
+            .iter()
+            .filter(|(_, bytes)| **bytes > max_growth as i64)
+            .map(|(tag, _)| tag)
+            .collect(),
+        None => vec![],
+    };
+
+    if comparison.json {
+        let json = serde_json::to_string_pretty(&json!({ "delta": delta, "flagged": flagged }))
+            .map_err(|e| format!("Couldn't serialize metadata delta: {}", e))?;
+        println!("{}", json);
+    } else {
+        println!("{:>28}: {}", "Tags added", delta.tags_added.join(", "));
+        describe_delta(&delta);
+    }
+
+    if !flagged.is_empty() {
+        let flagged: Vec<&str> = flagged.iter().map(|tag| tag.as_str()).collect();
+        return Err(format!(
+            "Texture data grew by more than {} bytes for tags: {}",
+            comparison.max_growth.unwrap_or(0),
+            flagged.join(", ")
+        ));
+    }
+    Ok(())
+}
+
+fn doctor_meld(doctor: MeldDoctor) -> Result<()> {
+    let options = &doctor.meld_options;
+    let base = read_asset(&doctor.base, None, options)?;
+    let other = read_asset(&doctor.other, None, options)?;
+    let diagnosis = VariationalAsset::diagnose_meld(&base, &other, options)?;
+
+    if let Some(template_path) = &doctor.template_path {
+        let template = VariationalAsset::correspondence_template(&base, &other, options)?;
+        fs::write(template_path, template.to_json()?)
+            .map_err(|e| format!("Couldn't write correspondence file: {}", e))?;
+    }
+
+    if doctor.json {
+        let json = serde_json::to_string_pretty(&diagnosis)
+            .map_err(|e| format!("Couldn't serialize diagnosis: {}", e))?;
+        println!("{}", json);
+    } else if diagnosis.is_empty() {
+        println!("No problems found; these assets should meld as they are.");
+    } else {
+        describe_diagnosis(&diagnosis, options);
+    }
+    Ok(())
+}
+
+fn add_variant(addition: VariantAddition) -> Result<()> {
+    let asset = read_asset(&addition.source_asset, None, &MeldOptions::default())?;
+    let textures = addition
+        .texture_paths
+        .iter()
+        .map(|path| {
+            fs::read(path)
+                .map_err(|e| format!("Couldn't read texture file {}: {}", path.display(), e))
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let (from, tag) = (Tag::from(addition.from), Tag::from(addition.tag));
+    let result = asset.derive_variant(&from, &tag, &addition.overrides, &textures)?;
+    fs::write(&addition.output_path, result.glb())
+        .map_err(|e| format!("Couldn't write output file: {}", e))?;
+
+    println!(
+        "Added variant {} to '{}'; {} bytes written.",
+        tag,
+        addition.output_path.display(),
+        result.glb().len()
+    );
+    Ok(())
+}
+
+fn pack_report(report: PackReport) -> Result<()> {
+    // the metadata JSON of a meld, or failing that, a variational asset to take it from
+    let path = &report.metadata_path;
+    let bytes = fs::read(path)
+        .map_err(|e| format!("Couldn't read metadata file {}: {}", path.display(), e))?;
+    let metadata = match serde_json::from_slice::<Metadata>(&bytes) {
+        Ok(metadata) => metadata,
+        Err(_) => VariationalAsset::from_slice(&bytes, None, path.parent())?
+            .metadata()
+            .clone(),
+    };
+    let stats: Vec<MeldStats> = match &report.stats_path {
+        Some(stats_path) => {
+            let json = fs::read_to_string(stats_path)
+                .map_err(|e| format!("Couldn't read statistics file: {}", e))?;
+            serde_json::from_str(&json)
+                .map_err(|e| format!("Couldn't parse statistics file: {}", e))?
+        }
+        None => vec![],
+    };
+
+    let title = match &report.title {
+        Some(title) => title.to_owned(),
+        None => format!("Variants of {}", path.display()),
+    };
+    let html = render_report(&title, &metadata, &stats);
+    fs::write(&report.output_path, &html)
+        .map_err(|e| format!("Couldn't write output file: {}", e))?;
+
+    println!(
+        "Wrote report to '{}'; {} bytes written.",
+        report.output_path.display(),
+        html.len()
+    );
+    Ok(())
+}
+
+fn describe_diagnosis(diagnosis: &MeldDiagnosis, options: &MeldOptions) {
+    let mesh_name = |name: &Option<String>| name.clone().unwrap_or_else(|| "<unnamed>".into());
+    for problem in &diagnosis.problems {
+        let name = mesh_name(&problem.name);
+        match &problem.mismatch {
+            MeshMismatch::NoSuchName => {
+                println!(
+                    "Mesh #{} ({}): no base mesh of that name.",
+                    problem.other_mesh, name
+                );
+                for candidate in problem.candidates.iter().take(3) {
+                    let distance = candidate
+                        .fingerprint_distance
+                        .map_or("primitive count differs".into(), |d| {
+                            format!("fingerprint distance {:.6}", d)
+                        });
+                    println!(
+                        "    candidate: base mesh #{} ({}), {}",
+                        candidate.base_mesh,
+                        mesh_name(&candidate.name),
+                        distance
+                    );
+                }
+            }
+            MeshMismatch::PrimitiveCount { base, other } => println!(
+                "Mesh #{} ({}): {} primitives, but the corresponding base mesh has {}.",
+                problem.other_mesh, name, other, base
+            ),
+            MeshMismatch::Fingerprint {
+                primitive,
+                matches,
+                closest_distance,
+            } => println!(
+                "Mesh #{} ({}): base primitive {} has {} matches within epsilon {}{}.",
+                problem.other_mesh,
+                name,
+                primitive,
+                matches,
+                options.fingerprint_epsilon,
+                closest_distance.map_or(String::new(), |d| format!(", closest at {:.6}", d))
+            ),
+        }
+    }
+
+    let renames = diagnosis.problems.iter().any(|problem| {
+        problem
+            .candidates
+            .iter()
+            .any(|c| c.fingerprint_distance.is_some())
+    });
+    if renames {
+        println!(
+            "Suggestion: rename meshes to match their candidates; meshes are matched by name."
+        );
+    }
+    if let Some(epsilon) = diagnosis.suggested_epsilon {
+        println!("Suggestion: try --fingerprint-epsilon {:.6}", epsilon);
+    }
+    if diagnosis.ambiguous_fingerprints {
+        println!("Suggestion: some primitives look alike; try --fingerprint quantized:0.0001");
+        println!("Suggestion: or match them up by hand; see --write-template & --correspondence");
     }
 }
 
 fn process(work_order: WorkOrder) -> Result<()> {
-    let base = read_asset(&work_order.source_assets.base)?;
+    let cache = match &work_order.cache_dir {
+        Some(dir) => Some(MeldCache::new(dir)?),
+        None => None,
+    };
+
+    // with several read jobs, all sources are read up front, in parallel; otherwise, as they're
+    // needed; either way, the melds themselves then happen one at a time, in order
+    let mut preloaded = read_sources_in_parallel(cache.as_ref(), &work_order)?.into_iter();
+    let mut read = |source| match preloaded.next() {
+        Some(asset) => Ok(asset),
+        None => read_source(source, cache.as_ref(), &work_order),
+    };
+
+    let base = read(&work_order.source_assets.base)?;
     if work_order.verbose() {
         println!("Base asset:");
         describe_asset(&base);
     }
 
+    let correspondence = match &work_order.correspondence_path {
+        Some(path) => {
+            let json = fs::read_to_string(path)
+                .map_err(|e| format!("Couldn't read correspondence file: {}", e))?;
+            PrimitiveCorrespondence::from_json(&json)?
+        }
+        None => PrimitiveCorrespondence::default(),
+    };
+
     let mut result = base;
-    for meld in &work_order.source_assets.melds {
-        let meld = read_asset(meld)?;
-        result = VariationalAsset::meld(&result, &meld)?;
+    let mut all_stats = vec![];
+    for source in &work_order.source_assets.melds {
+        let meld = read(source)?;
+        let options = &work_order.meld_options;
+        let (melded, stats) =
+            VariationalAsset::meld_with_correspondence(&result, &meld, options, &correspondence)
+                .map_err(|e| format!("Couldn't meld in {}: {}", source.path.display(), e))?;
+        if !work_order.quiet() {
+            for warning in &stats.warnings {
+                eprintln!("Warning: {}: {}", source.path.display(), warning);
+            }
+        }
         if work_order.verbose() {
             println!("New melded result:");
-            describe_asset(&result);
+            describe_asset(&melded);
+            describe_meld(&stats);
+            describe_delta(&melded.metadata().diff(result.metadata()));
         }
+        result = melded;
+        all_stats.push(stats);
     }
 
-    fs::write(&work_order.output_path, result.glb())
+    let compressed = match &work_order.draco {
+        Some(settings) => Some(result.export_draco(&work_order.meld_options, settings)?),
+        None if work_order.meshopt => Some(result.export_meshopt(&work_order.meld_options)?),
+        None => None,
+    };
+    let output = compressed.as_deref().unwrap_or_else(|| result.glb());
+    fs::write(&work_order.output_path, output)
         .map_err(|e| format!("Couldn't write output file: {}", e))?;
 
+    if let Some(validation) = &work_order.post_validate {
+        post_validate(validation, output, &work_order)?;
+    }
+
+    for (file_name, bytes) in result.sidecars() {
+        fs::write(work_order.output_path.with_file_name(file_name), bytes)
+            .map_err(|e| format!("Couldn't write sidecar file {}: {}", file_name, e))?;
+    }
+
+    if let Some(metadata_path) = &work_order.metadata_path {
+        let json = serde_json::to_string_pretty(result.metadata())
+            .map_err(|e| format!("Couldn't serialize metadata: {}", e))?;
+        fs::write(metadata_path, json)
+            .map_err(|e| format!("Couldn't write metadata file: {}", e))?;
+    }
+
+    if let Some(stats_path) = &work_order.stats_path {
+        let json = serde_json::to_string_pretty(&all_stats)
+            .map_err(|e| format!("Couldn't serialize meld statistics: {}", e))?;
+        fs::write(stats_path, json)
+            .map_err(|e| format!("Couldn't write statistics file: {}", e))?;
+    }
+
     if !work_order.quiet() {
         println!(
             "Success! {} bytes written to '{}'.",
-            result.glb().len(),
+            output.len(),
             work_order.output_path.to_str().unwrap_or("<error>"),
         );
     }
+
+    if let Some(split_dir) = &work_order.split_dir {
+        write_split(&result, split_dir, &work_order)?;
+    }
+    Ok(())
+}
+
+fn write_split(asset: &VariationalAsset, split_dir: &Path, work_order: &WorkOrder) -> Result<()> {
+    // stream each variant to its file, rather than holding them all in memory at once
+    let split = asset.split_to_dir(split_dir, &work_order.meld_options)?;
+    let count = split.len();
+
+    if work_order.verbose() {
+        for (tag, written) in split {
+            println!("{:>28}: {}", tag, size(written));
+        }
+    }
+    if !work_order.quiet() {
+        println!(
+            "Split into {} variants in '{}'.",
+            count,
+            split_dir.display()
+        );
+    }
+    Ok(())
+}
+
+fn post_validate(validation: &PostValidation, glb: &[u8], work_order: &WorkOrder) -> Result<()> {
+    let output_path = &work_order.output_path;
+    let errors = match validation {
+        PostValidation::Embedded => validate_glb(glb),
+        PostValidation::External(validator) => run_validator(validator, output_path)?,
+    };
+    if !errors.is_empty() {
+        return Err(format!(
+            "Output '{}' failed validation:\n  {}",
+            output_path.display(),
+            errors.join("\n  ")
+        ));
+    }
+    if work_order.verbose() {
+        println!("Output passed validation.");
+    }
     Ok(())
 }
 
-fn read_asset(asset: &SourceAsset) -> Result<VariationalAsset> {
-    Ok(VariationalAsset::from_file(
-        &asset.path,
-        asset.tag.as_ref(),
-    )?)
+// run the official glTF-Validator on the given file, returning the errors it reports
+fn run_validator(validator: &Path, glb_path: &Path) -> Result<Vec<String>> {
+    let output = std::process::Command::new(validator)
+        .arg("--stdout")
+        .arg(glb_path)
+        .output()
+        .map_err(|e| format!("Couldn't run validator {}: {}", validator.display(), e))?;
+    let report: Value = serde_json::from_slice(&output.stdout).map_err(|e| {
+        format!(
+            "Couldn't read validator report ({}): {}",
+            e,
+            String::from_utf8_lossy(&output.stderr).trim()
+        )
+    })?;
+
+    // severity 0 means error; warnings, infos & hints don't fail the run
+    let messages = report.pointer("/issues/messages").and_then(Value::as_array);
+    let mut errors: Vec<String> = messages
+        .into_iter()
+        .flatten()
+        .filter(|message| message.get("severity").and_then(Value::as_u64) == Some(0))
+        .map(|message| {
+            let field = |name: &str| message.get(name).and_then(Value::as_str).unwrap_or("?");
+            let (pointer, code) = (field("pointer"), field("code"));
+            format!("{} ({}): {}", pointer, code, field("message"))
+        })
+        .collect();
+    // the validator truncates long lists of messages, but not its count of errors
+    let error_count = report.pointer("/issues/numErrors").and_then(Value::as_u64);
+    if errors.is_empty() && error_count.unwrap_or(0) > 0 {
+        errors.push(format!("{} errors.", error_count.unwrap_or(0)));
+    }
+    Ok(errors)
+}
+
+fn read_asset(
+    asset: &SourceAsset,
+    cache: Option<&MeldCache>,
+    options: &MeldOptions,
+) -> Result<VariationalAsset> {
+    VariationalAsset::from_file_with_options(&asset.path, asset.tag.as_ref(), cache, options)
+}
+
+// read a source asset to meld, warning of any files found only by ignoring case, and of any
+// extensions it requires that we pass through blindly
+fn read_source(
+    source: &SourceAsset,
+    cache: Option<&MeldCache>,
+    work_order: &WorkOrder,
+) -> Result<VariationalAsset> {
+    let asset = read_asset(source, cache, &work_order.meld_options)?;
+    if !work_order.quiet() {
+        for warning in asset.load_warnings() {
+            eprintln!("Warning: {}: {}", source.path.display(), warning);
+        }
+    }
+    let unsupported = asset.unsupported_required_extensions()?;
+    if !unsupported.is_empty() && !work_order.quiet() {
+        eprintln!(
+            "Warning: {} requires unsupported extensions, passed through untouched: {}",
+            source.path.display(),
+            unsupported.join(", ")
+        );
+    }
+    Ok(asset)
+}
+
+// read the base & all the assets to meld into it, in order, on as many threads as the work order
+// allows; with just the one, nothing is read
+fn read_sources_in_parallel(
+    cache: Option<&MeldCache>,
+    work_order: &WorkOrder,
+) -> Result<Vec<VariationalAsset>> {
+    let sources = &work_order.source_assets;
+    let sources: Vec<&SourceAsset> = std::iter::once(&sources.base)
+        .chain(&sources.melds)
+        .collect();
+    let jobs = work_order.read_jobs.min(sources.len());
+    if jobs <= 1 {
+        return Ok(vec![]);
+    }
+
+    let sources = &sources;
+    let mut read: Vec<(usize, Result<VariationalAsset>)> = thread::scope(|scope| {
+        let handles: Vec<_> = (0..jobs)
+            .map(|job| {
+                scope.spawn(move || {
+                    sources
+                        .iter()
+                        .enumerate()
+                        .skip(job)
+                        .step_by(jobs)
+                        .map(|(ix, source)| (ix, read_source(source, cache, work_order)))
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect();
+        handles
+            .into_iter()
+            .flat_map(|handle| {
+                handle
+                    .join()
+                    .unwrap_or_else(|panic| std::panic::resume_unwind(panic))
+            })
+            .collect()
+    });
+    read.sort_by_key(|(ix, _)| *ix);
+    read.into_iter().map(|(_, asset)| asset).collect()
 }
 
 fn describe_asset(asset: &VariationalAsset) {
@@ -64,12 +521,100 @@ fn describe_asset(asset: &VariationalAsset) {
     let variational = asset.metadata().variational_sizes().texture_bytes;
     println!("          Total texture data: {}", size(total));
     println!("  Of which is depends on tag: {}", size(variational));
+    let saved = asset.metadata().blob_bytes_saved();
+    println!("    Saved by blob compaction: {}", size(saved));
+    let dedupe = asset.metadata().dedupe();
+    let (shared, exclusive) = (dedupe.shared_bytes, dedupe.exclusive_bytes);
+    println!("      Shared by several tags: {}", size(shared));
+    println!("        Exclusive to one tag: {}", size(exclusive));
+    let duplicated = dedupe.duplicated_bytes;
+    println!("       Stored more than once: {}", size(duplicated));
+    let counts = asset.metadata().counts();
+    println!(
+        "                   Materials: {} ({} depend on tag)",
+        counts.materials, counts.variational_materials
+    );
+    println!("                    Textures: {}", counts.textures);
+    println!("                      Images: {}", counts.images);
+    println!("                    Samplers: {}", counts.samplers);
+    let images = asset.metadata().images();
+    if !images.is_empty() {
+        let npot = images
+            .iter()
+            .filter(|image| !image.is_power_of_two())
+            .count();
+        println!("     Not power-of-two images: {}", npot);
+        let largest = images
+            .iter()
+            .map(|image| image.width.max(image.height))
+            .max();
+        println!("     Largest image dimension: {} px", largest.unwrap_or(0));
+    }
+    for (axis, values) in asset.metadata().axes() {
+        let values: Vec<&str> = values.iter().map(String::as_str).collect();
+        println!("{:>28}: {}", format!("Axis {}", axis), values.join(", "));
+    }
+}
+
+fn describe_meld(stats: &MeldStats) {
+    let counts =
+        |counts: &MeldCounts| format!("{} reused, {} copied", counts.reused, counts.copied);
+    println!("{:>28}: {}", "Materials", counts(&stats.materials));
+    println!("{:>28}: {}", "Textures", counts(&stats.textures));
+    println!("{:>28}: {}", "Images", counts(&stats.images));
+    println!("{:>28}: {}", "Samplers", counts(&stats.samplers));
+    println!(
+        "{:>28}: {}",
+        "Blob bytes appended",
+        size(stats.blob_bytes_appended)
+    );
+    println!("{:>28}: {}", "Tags added", stats.tags_added.join(", "));
+    println!("{:>28}: {}", "Primitives touched", stats.primitives_touched);
+    println!(
+        "{:>28}: {} bytes root, {} bytes mappings",
+        "Variants JSON added", stats.variants_root_json_added, stats.variants_mapping_json_added
+    );
+    println!("{:>28}: {}", "Peak memory", size(stats.peak_memory));
+    let timings = &stats.timings;
+    if !timings.is_empty() {
+        println!("{:>28}: {:.3?}", "Construct", timings.construct);
+        println!("{:>28}: {:.3?}", "... transform", timings.transform);
+        println!("{:>28}: {:.3?}", "... keys", timings.keys);
+        println!("{:>28}: {:.3?}", "... fingerprints", timings.fingerprints);
+        println!("{:>28}: {:.3?}", "Meld", timings.meld);
+        println!("{:>28}: {:.3?}", "Export", timings.export);
+    }
+}
+
+fn describe_delta(delta: &MetadataDelta) {
+    println!(
+        "{:>28}: {}",
+        "Texture data change",
+        signed_size(delta.texture_bytes)
+    );
+    for (tag, bytes) in &delta.per_tag_texture_bytes {
+        if *bytes != 0 {
+            println!(
+                "{:>28}: {}",
+                format!("... tag {}", tag),
+                signed_size(*bytes)
+            );
+        }
+    }
+    if !delta.tags_removed.is_empty() {
+        println!("{:>28}: {}", "Tags removed", delta.tags_removed.join(", "));
+    }
+}
+
+fn signed_size(byte_delta: i64) -> String {
+    let sign = if byte_delta < 0 { "-" } else { "+" };
+    format!("{}{}", sign, size(byte_delta.abs() as usize))
 }
 
 fn size(byte_count: usize) -> String {
     if byte_count < 1000000 {
-        format!("{:.01} kB", byte_count / 1000)
+        format!("{:.01} kB", byte_count as f64 / 1000.0)
     } else {
-        format!("{:.01} MB", byte_count / 1000000)
+        format!("{:.01} MB", byte_count as f64 / 1000000.0)
     }
 }
@@ -0,0 +1,195 @@
+// Copyright (c) Facebook, Inc. and its affiliates. All Rights Reserved
+//
+
+//! Renders the metadata & statistics of a meld as a self-contained HTML page, for sharing with
+//! people who'd rather not read JSON.
+
+use gltf_variant_meld::{MeldStats, Metadata};
+
+use super::size;
+
+// the page we fill in; it has no external references, so the report is a single file
+const TEMPLATE: &str = include_str!("report.html");
+
+/// Renders the report, given the output asset's metadata and the statistics of each meld that
+/// went into it, if known.
+pub fn render_report(title: &str, metadata: &Metadata, stats: &[MeldStats]) -> String {
+    let subtitle = format!(
+        "{} variants; {} of texture data, of which {} depends on the variant.",
+        metadata.tags().len(),
+        size(metadata.total_sizes().texture_bytes),
+        size(metadata.variational_sizes().texture_bytes)
+    );
+    fill(
+        TEMPLATE,
+        &[
+            ("TITLE", escape(title)),
+            ("SUBTITLE", escape(&subtitle)),
+            ("SUMMARY", render_summary(metadata, stats)),
+            ("TAGS", render_tags(metadata)),
+            ("IMAGES", render_images(metadata)),
+            ("WARNINGS", render_warnings(stats)),
+        ],
+    )
+}
+
+// substitutes each {{NAME}} in a single pass over the template, so that text which came from
+// the asset itself (a tag, say) is never mistaken for a placeholder by a later substitution
+fn fill(template: &str, values: &[(&str, String)]) -> String {
+    let mut filled = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find("{{") {
+        filled.push_str(&rest[..start]);
+        let after = &rest[start + 2..];
+        let value = after.find("}}").and_then(|end| {
+            let name = &after[..end];
+            let value = values.iter().find(|(key, _)| *key == name);
+            value.map(|(_, value)| (value, end))
+        });
+        match value {
+            Some((value, end)) => {
+                filled.push_str(value);
+                rest = &after[end + 2..];
+            }
+            None => {
+                filled.push_str("{{");
+                rest = after;
+            }
+        }
+    }
+    filled.push_str(rest);
+    filled
+}
+
+fn render_summary(metadata: &Metadata, stats: &[MeldStats]) -> String {
+    let (total, variational) = (metadata.total_sizes(), metadata.variational_sizes());
+    let saved = metadata.blob_bytes_saved();
+    let dedupe = metadata.dedupe();
+    let counts = metadata.counts();
+    let materials = format!(
+        "{} ({} depend on variant)",
+        counts.materials, counts.variational_materials
+    );
+    let mut rows = vec![
+        ("Total texture data", size(total.texture_bytes)),
+        ("Depends on variant", size(variational.texture_bytes)),
+        ("Shared by several variants", size(dedupe.shared_bytes)),
+        ("Exclusive to one variant", size(dedupe.exclusive_bytes)),
+        ("Stored more than once", size(dedupe.duplicated_bytes)),
+        ("Saved by blob compaction", size(saved)),
+        ("Materials", materials),
+        ("Textures", counts.textures.to_string()),
+        ("Images", counts.images.to_string()),
+    ];
+    if !stats.is_empty() {
+        let reused: usize = stats
+            .iter()
+            .map(|stats| stats.materials.reused + stats.textures.reused + stats.images.reused)
+            .sum();
+        let appended: usize = stats.iter().map(|stats| stats.blob_bytes_appended).sum();
+        rows.push(("Melds", stats.len().to_string()));
+        rows.push(("Objects reused rather than copied", reused.to_string()));
+        rows.push(("Blob bytes appended by melds", size(appended)));
+    }
+
+    let mut html = String::from("<table>\n");
+    for (label, value) in rows {
+        html += &format!(
+            "<tr><th>{}</th><td class=\"num\">{}</td></tr>\n",
+            escape(label),
+            escape(&value)
+        );
+    }
+    html + "</table>"
+}
+
+fn render_tags(metadata: &Metadata) -> String {
+    if metadata.tags().is_empty() {
+        return String::from("<p class=\"note\">The asset has no variants.</p>");
+    }
+    let mut html = String::from(
+        "<table>\n<tr><th>Variant</th><th>Name</th><th class=\"num\">Texture data</th>\
+         <th class=\"num\">Exclusive</th><th class=\"num\">Exclusive images</th>\
+         <th class=\"num\">Exclusive materials</th></tr>\n",
+    );
+    for tag in metadata.tags() {
+        let name = metadata.tag_display(tag).and_then(|d| d.name.as_ref());
+        let bytes = metadata.tag_sizes(tag).map_or(0, |s| s.texture_bytes);
+        let exclusive = metadata
+            .tag_exclusive_sizes(tag)
+            .map_or(0, |s| s.texture_bytes);
+        let images = metadata.exclusive_images(tag).map_or(0, <[usize]>::len);
+        let materials = metadata.exclusive_materials(tag).map_or(0, <[usize]>::len);
+        html += &format!(
+            "<tr><td>{}</td><td>{}</td><td class=\"num\">{}</td><td class=\"num\">{}</td>\
+             <td class=\"num\">{}</td><td class=\"num\">{}</td></tr>\n",
+            escape(tag),
+            escape(name.map(String::as_str).unwrap_or("-")),
+            size(bytes),
+            size(exclusive),
+            images,
+            materials
+        );
+    }
+    html + "</table>"
+}
+
+fn render_images(metadata: &Metadata) -> String {
+    if metadata.images().is_empty() {
+        return String::from(
+            "<p class=\"note\">No image details; the melder was built without the image \
+             feature, or the asset has no textures.</p>",
+        );
+    }
+    let mut html = String::from(
+        "<table>\n<tr><th class=\"num\">Image</th><th>Format</th><th class=\"num\">Size</th>\
+         <th class=\"num\">Bytes</th><th>Variants</th></tr>\n",
+    );
+    for image in metadata.images() {
+        let npot = if image.is_power_of_two() {
+            ""
+        } else {
+            " (not power of two)"
+        };
+        html += &format!(
+            "<tr><td class=\"num\">{}</td><td>{}</td><td class=\"num\">{} &times; {}{}</td>\
+             <td class=\"num\">{}</td><td>{}</td></tr>\n",
+            image.image,
+            escape(&image.format),
+            image.width,
+            image.height,
+            npot,
+            size(image.bytes),
+            escape(&image.tags.join(", "))
+        );
+    }
+    html + "</table>"
+}
+
+fn render_warnings(stats: &[MeldStats]) -> String {
+    let warnings: Vec<&String> = stats.iter().flat_map(|stats| &stats.warnings).collect();
+    if warnings.is_empty() {
+        return String::from("<p class=\"note\">None.</p>");
+    }
+    let mut html = String::from("<ul class=\"warnings\">\n");
+    for warning in warnings {
+        html += &format!("<li>{}</li>\n", escape(warning));
+    }
+    html + "</ul>"
+}
+
+// make text safe to place in HTML element content & attribute values
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
@@ -0,0 +1,143 @@
+// Copyright (c) Facebook, Inc. and its affiliates. All Rights Reserved
+//
+
+//! A self-contained facade over the rest of this module, for tools that want to read & write
+//! `KHR_materials_variants` without melding anything.
+
+use std::collections::{HashMap, HashSet};
+
+use gltf::json::Root;
+
+use super::{detect_revision, extract_variant_map, get_variant_lookup, install, uninstall};
+use super::{get_variant_displays, write_variant_displays};
+use super::{write_root_variant_lookup_map, write_variant_map, ExtensionRevision};
+use crate::{Result, Tag, TagDisplay};
+
+/// Everything `KHR_materials_variants` says about an asset, but for binary payloads: its
+/// variants, how to present them, and which material each variant selects on each primitive.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct VariantDocument {
+    /// The variant tags, in the order of the root extension's `variants` array.
+    pub variants: Vec<Tag>,
+    /// How each tag is to be presented to end users, for those tags it's been specified for.
+    pub displays: HashMap<Tag, TagDisplay>,
+    /// For each primitive of each mesh, the index of the material each tag selects; a primitive
+    /// the extension says nothing about has an empty mapping.
+    pub mappings: Vec<Vec<HashMap<Tag, usize>>>,
+    /// The revision of the extension to write, or that was read, if it could be told.
+    pub revision: Option<ExtensionRevision>,
+}
+
+/// Reads the `KHR_materials_variants` data of the given asset.
+///
+/// An asset without the extension yields a document without variants. The older, legacy
+/// `FB_material_variants` extension is not read; see `upgrade_legacy()` for that.
+pub fn read(root: &Root) -> Result<VariantDocument> {
+    let revision = detect_revision(root)?;
+    let lookup = get_variant_lookup(root)?;
+    let variants: Vec<Tag> = (0..lookup.len()).map(|ix| lookup[&ix].to_owned()).collect();
+
+    let mut displays = HashMap::new();
+    for (ix, display) in get_variant_displays(root)? {
+        if let Some(tag) = lookup.get(&ix) {
+            displays.insert(tag.to_owned(), display);
+        }
+    }
+
+    let mut mappings = vec![];
+    for (m_ix, mesh) in root.meshes.iter().enumerate() {
+        let mut mesh_mappings = vec![];
+        for (p_ix, primitive) in mesh.primitives.iter().enumerate() {
+            let mapping = extract_variant_map(primitive, &lookup)
+                .map_err(|e| format!("Mesh {} primitive {}: {}", m_ix, p_ix, e))?;
+            mesh_mappings.push(mapping);
+        }
+        mappings.push(mesh_mappings);
+    }
+
+    Ok(VariantDocument {
+        variants,
+        displays,
+        mappings,
+        revision,
+    })
+}
+
+/// Writes the given `KHR_materials_variants` data into the given asset, replacing any that was
+/// there, and declaring the extension used.
+///
+/// The document must have a mapping for each primitive of each mesh of the asset, every tag it
+/// maps must be one of its variants, and every material index must exist. A document without
+/// variants removes the extension altogether. If the document names no revision, the ratified
+/// one is written.
+pub fn write(root: &mut Root, document: &VariantDocument) -> Result<()> {
+    check_document(root, document)?;
+    if document.variants.is_empty() {
+        uninstall(root);
+        return Ok(());
+    }
+    install(root, false);
+    write_root_variant_lookup_map(root, &document.variants)?;
+    write_variant_displays(root, &document.displays)?;
+
+    let lookup: HashMap<usize, Tag> = document.variants.iter().cloned().enumerate().collect();
+    let revision = document.revision.unwrap_or_default();
+    for (mesh, mesh_mappings) in root.meshes.iter_mut().zip(&document.mappings) {
+        for (primitive, mapping) in mesh.primitives.iter_mut().zip(mesh_mappings) {
+            write_variant_map(primitive, mapping, &lookup, revision)?;
+        }
+    }
+    Ok(())
+}
+
+// make sure the document fits the asset, and makes sense in itself
+fn check_document(root: &Root, document: &VariantDocument) -> Result<()> {
+    let mut seen = HashSet::new();
+    for tag in &document.variants {
+        if tag.is_empty() {
+            return Err(format!("Variant tags can't be empty."));
+        }
+        if !seen.insert(tag) {
+            return Err(format!("Variant tag {} is listed more than once.", tag));
+        }
+    }
+
+    if document.mappings.len() != root.meshes.len() {
+        return Err(format!(
+            "The document maps {} meshes, but the asset has {}.",
+            document.mappings.len(),
+            root.meshes.len()
+        ));
+    }
+    for (m_ix, (mesh, mesh_mappings)) in root.meshes.iter().zip(&document.mappings).enumerate() {
+        if mesh_mappings.len() != mesh.primitives.len() {
+            return Err(format!(
+                "The document maps {} primitives of mesh {}, but it has {}.",
+                mesh_mappings.len(),
+                m_ix,
+                mesh.primitives.len()
+            ));
+        }
+        for (p_ix, mapping) in mesh_mappings.iter().enumerate() {
+            for (tag, &material_ix) in mapping {
+                if !seen.contains(tag) {
+                    return Err(format!(
+                        "Mesh {} primitive {} maps tag {}, which isn't a variant.",
+                        m_ix, p_ix, tag
+                    ));
+                }
+                if material_ix >= root.materials.len() {
+                    return Err(format!(
+                        "Mesh {} primitive {} maps tag {} to material {}, but there are only {}.",
+                        m_ix,
+                        p_ix,
+                        tag,
+                        material_ix,
+                        root.materials.len()
+                    ));
+                }
+            }
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,181 @@
+// Copyright (c) Facebook, Inc. and its affiliates. All Rights Reserved
+//
+
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+use serde_derive::{Deserialize, Serialize};
+
+use gltf::json::Root;
+
+use super::{extract_variant_map, get_variant_lookup};
+use super::{install, write_root_variant_lookup_map, write_variant_map};
+use super::{ExtensionRevision, FB_MATERIAL_VARIANTS};
+use crate::{Result, Tag};
+
+/// The root extension of the earlier `FB_material_variants` draft, naming the default tag.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct FBLegacyRootExtension {
+    pub default_tag: Tag,
+}
+
+/// The primitive extension of the earlier `FB_material_variants` draft.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct FBLegacyPrimitiveExtension {
+    #[serde(default)]
+    pub mapping: Vec<FBLegacyPrimitiveEntry>,
+}
+
+/// A material and the tags that select it, in the `FB_material_variants` draft.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct FBLegacyPrimitiveEntry {
+    pub material: u32,
+    #[serde(default)]
+    pub tags: Vec<Tag>,
+}
+
+/// Converts any `FB_material_variants` data in the asset to `KHR_materials_variants` form.
+///
+/// The draft extension named its tags directly on each primitive, and kept a default tag in the
+/// root; the tags are gathered into a root variant lookup table, the primitive mappings are
+/// rewritten to reference it, and all traces of the old extension are removed.
+///
+/// Returns the default tag of the legacy asset, or `None` if it wasn't one.
+pub fn upgrade_legacy(root: &mut Root) -> Result<Option<Tag>> {
+    let revision = ExtensionRevision::default();
+    let boxed = match &mut root.extensions {
+        Some(extensions) => extensions.others.remove(FB_MATERIAL_VARIANTS),
+        None => None,
+    };
+    let root_extension: FBLegacyRootExtension = match boxed {
+        Some(boxed) => parse_extension(boxed.to_string())?,
+        None => return Ok(None),
+    };
+
+    // first pass: parse every primitive mapping, gathering up all the tags
+    let mut tags = BTreeSet::new();
+    let mut mappings = vec![];
+    for mesh in root.meshes.iter_mut() {
+        let mut mesh_mappings = vec![];
+        for primitive in mesh.primitives.iter_mut() {
+            let boxed = match &mut primitive.extensions {
+                Some(extensions) => extensions.others.remove(FB_MATERIAL_VARIANTS),
+                None => None,
+            };
+            let extension: FBLegacyPrimitiveExtension = match boxed {
+                Some(boxed) => parse_extension(boxed.to_string())?,
+                None => Default::default(),
+            };
+            let mut tag_to_ix = HashMap::new();
+            for entry in extension.mapping {
+                for tag in entry.tags {
+                    tags.insert(tag.clone());
+                    tag_to_ix.insert(tag, entry.material as usize);
+                }
+            }
+            mesh_mappings.push(tag_to_ix);
+        }
+        mappings.push(mesh_mappings);
+    }
+
+    // then write it all back out in `KHR_materials_variants` form
+    let tags: Vec<Tag> = tags.into_iter().collect();
+    write_root_variant_lookup_map(root, &tags)?;
+    let variant_ix_lookup: HashMap<usize, Tag> = tags.into_iter().enumerate().collect();
+    for (mesh, mesh_mappings) in root.meshes.iter_mut().zip(mappings) {
+        for (primitive, tag_to_ix) in mesh.primitives.iter_mut().zip(mesh_mappings) {
+            write_variant_map(primitive, &tag_to_ix, &variant_ix_lookup, revision)?;
+        }
+    }
+
+    let required = root
+        .extensions_required
+        .contains(&FB_MATERIAL_VARIANTS.to_owned());
+    root.extensions_used
+        .retain(|used| used != FB_MATERIAL_VARIANTS);
+    install(root, required);
+    root.extensions_required
+        .retain(|required| required != FB_MATERIAL_VARIANTS);
+
+    Ok(Some(root_extension.default_tag))
+}
+
+/// Writes the asset's `KHR_materials_variants` data also in `FB_material_variants` form.
+///
+/// This is for the benefit of older viewers that only understand the earlier draft. The output is
+/// deterministically ordered, and is read back (and discarded) by `upgrade_legacy()`.
+pub fn write_legacy(root: &mut Root, default_tag: &Tag) -> Result<()> {
+    let variant_ix_lookup = get_variant_lookup(root)?;
+
+    for mesh in root.meshes.iter_mut() {
+        for primitive in mesh.primitives.iter_mut() {
+            let tag_to_ix = extract_variant_map(primitive, &variant_ix_lookup)?;
+            if tag_to_ix.is_empty() {
+                continue;
+            }
+            // invert the mapping tag->ix to an ordered ix->tags one
+            let mut ix_to_tags = BTreeMap::new();
+            for (tag, ix) in tag_to_ix {
+                ix_to_tags
+                    .entry(ix)
+                    .or_insert_with(BTreeSet::new)
+                    .insert(tag);
+            }
+            let extension = FBLegacyPrimitiveExtension {
+                mapping: ix_to_tags
+                    .into_iter()
+                    .map(|(ix, tags)| FBLegacyPrimitiveEntry {
+                        material: ix as u32,
+                        tags: tags.into_iter().collect(),
+                    })
+                    .collect(),
+            };
+            let value = serde_json::to_string(&extension)
+                .and_then(|s| serde_json::from_str(&s))
+                .map_err(|e| {
+                    format!(
+                        "Failed to transform legacy primitive extension {:#?}, with error: {}",
+                        extension, e,
+                    )
+                })?;
+            primitive
+                .extensions
+                .get_or_insert(Default::default())
+                .others
+                .insert(FB_MATERIAL_VARIANTS.to_owned(), value);
+        }
+    }
+
+    let extension = FBLegacyRootExtension {
+        default_tag: default_tag.to_owned(),
+    };
+    let value = serde_json::to_string(&extension)
+        .and_then(|s| serde_json::from_str(&s))
+        .map_err(|e| {
+            format!(
+                "Failed to transform legacy root extension {:#?}, with error: {}",
+                extension, e,
+            )
+        })?;
+    root.extensions
+        .get_or_insert(Default::default())
+        .others
+        .insert(FB_MATERIAL_VARIANTS.to_owned(), value);
+
+    if !root
+        .extensions_used
+        .contains(&FB_MATERIAL_VARIANTS.to_owned())
+    {
+        root.extensions_used.push(FB_MATERIAL_VARIANTS.to_owned());
+    }
+    Ok(())
+}
+
+fn parse_extension<T: serde::de::DeserializeOwned>(json_string: String) -> Result<T> {
+    serde_json::from_str(&json_string).map_err(|e| {
+        format!(
+            "Bad JSON in FB_material_variants extension: {}; json = {}",
+            e.to_string(),
+            json_string,
+        )
+    })
+}
@@ -8,22 +8,147 @@
 //! We're specifically concerned with reading and writing values that are meaningful from
 //! the point of view of i.e. `WorkAsset` into a glTF format, and especially the abstraction
 //! we get from the `gltf` crates.
+//!
+//! Other tools that just want the extension's data can use `read()` and `write()`, which deal
+//! in a whole `VariantDocument` at a time; the finer-grained functions are what they're made of.
 
 use gltf::json::Root;
 
+use serde_derive::{Deserialize, Serialize};
+use serde_json::Value;
+
 const KHR_MATERIALS_VARIANTS: &str = "KHR_materials_variants";
+const FB_MATERIAL_VARIANTS: &str = "FB_material_variants";
+
+// the extensions an asset may require of us: those whose data we work on, or can carry through
+// a meld untouched; anything else, e.g. Draco-compressed geometry, we'd get subtly wrong
+const HONORED_EXTENSIONS: &[&str] = &[
+    FB_MATERIAL_VARIANTS,
+    "KHR_lights_punctual",
+    "KHR_materials_pbrSpecularGlossiness",
+    "KHR_materials_unlit",
+    KHR_MATERIALS_VARIANTS,
+    "KHR_texture_transform",
+];
+
+mod document;
+pub use document::{read, write, VariantDocument};
 
 mod on_root;
-pub use on_root::{write_root_variant_lookup_map, get_variant_lookup};
+pub use on_root::{get_variant_displays, write_variant_displays};
+pub use on_root::{get_variant_lookup, write_root_variant_lookup_map};
+pub use on_root::{get_variant_payloads, write_variant_payloads};
+pub use on_root::{has_compact_mappings_marker, write_compact_mappings_marker};
 
 mod on_primitive;
 pub use on_primitive::{extract_variant_map, write_variant_map};
 
-/// Updates the `extensions_used` glTF property with the name of our extension.
+mod revision;
+pub use revision::detect_revision;
+
+mod legacy;
+pub use legacy::{upgrade_legacy, write_legacy};
+
+/// The revisions of `KHR_materials_variants` we can read & write.
 ///
-pub fn install(root: &mut Root) {
+/// The draft this tool originally targeted named each primitive's list of material mappings
+/// `mapping`; the ratified extension calls it `mappings`. The two are otherwise identical.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub enum ExtensionRevision {
+    /// The draft revision, as linked from this crate's documentation.
+    Draft,
+    /// The ratified revision, as published by Khronos.
+    Ratified,
+}
+
+impl Default for ExtensionRevision {
+    fn default() -> Self {
+        ExtensionRevision::Ratified
+    }
+}
+
+/// Updates the `extensions_used` glTF property with the name of our extension, and also the
+/// `extensions_required` property, if `required` is set.
+///
+pub fn install(root: &mut Root, required: bool) {
     let used = &mut root.extensions_used;
     if !used.contains(&String::from(KHR_MATERIALS_VARIANTS)) {
         used.push(String::from(KHR_MATERIALS_VARIANTS));
     }
+    let required_list = &mut root.extensions_required;
+    if required && !required_list.contains(&String::from(KHR_MATERIALS_VARIANTS)) {
+        required_list.push(String::from(KHR_MATERIALS_VARIANTS));
+    }
+}
+
+/// Lists those of the asset's required extensions that we can't honor; see
+/// `MeldOptions::allow_unsupported_required`.
+///
+pub fn unsupported_required(root: &Root) -> Vec<String> {
+    root.extensions_required
+        .iter()
+        .filter(|required| !HONORED_EXTENSIONS.contains(&required.as_str()))
+        .cloned()
+        .collect()
+}
+
+/// Adds any extensions that *other* uses or requires to the respective lists of *root*, so that
+/// objects melded in from *other* remain properly declared.
+///
+pub fn merge_declarations(root: &mut Root, other: &Root) {
+    for used in &other.extensions_used {
+        if !root.extensions_used.contains(used) {
+            root.extensions_used.push(used.to_owned());
+        }
+    }
+    for required in &other.extensions_required {
+        if !root.extensions_required.contains(required) {
+            root.extensions_required.push(required.to_owned());
+        }
+    }
+}
+
+/// How many bytes of compact JSON our extension takes up in the asset, counting its name as
+/// well as its value: in the root, which lists the variants, and in all primitives' mappings.
+///
+pub fn json_bytes(root: &Root) -> (usize, usize) {
+    let size = |value: Option<&Value>| {
+        value.map_or(0, |value| {
+            KHR_MATERIALS_VARIANTS.len() + 3 + value.to_string().len()
+        })
+    };
+    let root_bytes = size(
+        root.extensions
+            .as_ref()
+            .and_then(|extensions| extensions.others.get(KHR_MATERIALS_VARIANTS)),
+    );
+    let mut primitive_bytes = 0;
+    for primitive in root.meshes.iter().flat_map(|mesh| &mesh.primitives) {
+        primitive_bytes += size(
+            primitive
+                .extensions
+                .as_ref()
+                .and_then(|extensions| extensions.others.get(KHR_MATERIALS_VARIANTS)),
+        );
+    }
+    (root_bytes, primitive_bytes)
+}
+
+/// Removes every trace of our extension from the asset, leaving plain glTF.
+///
+pub fn uninstall(root: &mut Root) {
+    root.extensions_used
+        .retain(|used| used != KHR_MATERIALS_VARIANTS);
+    root.extensions_required
+        .retain(|required| required != KHR_MATERIALS_VARIANTS);
+    if let Some(extensions) = &mut root.extensions {
+        extensions.others.remove(KHR_MATERIALS_VARIANTS);
+    }
+    for mesh in &mut root.meshes {
+        for primitive in &mut mesh.primitives {
+            if let Some(extensions) = &mut primitive.extensions {
+                extensions.others.remove(KHR_MATERIALS_VARIANTS);
+            }
+        }
+    }
 }
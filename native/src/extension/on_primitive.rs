@@ -7,15 +7,22 @@ use serde_derive::{Deserialize, Serialize};
 
 use gltf::json::mesh::Primitive;
 
-use super::KHR_MATERIALS_VARIANTS;
+use super::{ExtensionRevision, KHR_MATERIALS_VARIANTS};
 use crate::{Result, Tag};
 
 #[derive(Clone, Debug, Default, Deserialize, Serialize)]
 pub struct FBMaterialVariantPrimitiveExtension {
-    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    #[serde(default, alias = "mapping", skip_serializing_if = "Vec::is_empty")]
     pub mappings: Vec<FBMaterialVariantPrimitiveEntry>,
 }
 
+/// The primitive extension as written for `ExtensionRevision::Draft`.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct FBMaterialVariantDraftPrimitiveExtension {
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub mapping: Vec<FBMaterialVariantPrimitiveEntry>,
+}
+
 #[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Debug, Default, Deserialize, Serialize)]
 pub struct FBMaterialVariantPrimitiveEntry {
     #[serde(default)]
@@ -25,14 +32,20 @@ pub struct FBMaterialVariantPrimitiveEntry {
     pub variants: Vec<u32>,
 }
 
-/// Write the `tag_to_ix` mapping to the `Primitive' in `KHR_materials_variants` form.
+/// Write the `tag_to_ix` mapping to the `Primitive' in `KHR_materials_variants` form, as it
+/// looks in the given revision of the extension.
 ///
 /// This method guarantees a deterministic ordering of the output.
 ///
 /// Please see [the `KHR_materials_variants`
 /// spec](https://github.com/zellski/glTF/blob/ext/zell-fb-asset-variants/extensions/2.0/Khronos/KHR_materials_variants/README.md)
 /// for further details.
-pub fn write_variant_map(primitive: &mut Primitive, tag_to_ix: &HashMap<Tag, usize>, variant_ix_lookup: &HashMap<usize, Tag>) -> Result<()> {
+pub fn write_variant_map(
+    primitive: &mut Primitive,
+    tag_to_ix: &HashMap<Tag, usize>,
+    variant_ix_lookup: &HashMap<usize, Tag>,
+    revision: ExtensionRevision,
+) -> Result<()> {
     if tag_to_ix.is_empty() {
         if let Some(extensions) = &mut primitive.extensions {
             extensions.others.remove(KHR_MATERIALS_VARIANTS);
@@ -76,14 +89,19 @@ pub fn write_variant_map(primitive: &mut Primitive, tag_to_ix: &HashMap<Tag, usi
         mappings: mapping_entries,
     };
     // serialise to JSON string
-    let value = serde_json::to_string(&new_extension)
-        .and_then(|s| serde_json::from_str(&s))
-        .map_err(|e| {
-            format!(
-                "Failed to transform primitive extension {:#?}, with error: {}",
-                new_extension, e,
-            )
-        })?;
+    let json = match revision {
+        ExtensionRevision::Ratified => serde_json::to_string(&new_extension),
+        ExtensionRevision::Draft => {
+            let mapping = new_extension.mappings.clone();
+            serde_json::to_string(&FBMaterialVariantDraftPrimitiveExtension { mapping })
+        }
+    };
+    let value = json.and_then(|s| serde_json::from_str(&s)).map_err(|e| {
+        format!(
+            "Failed to transform primitive extension {:#?}, with error: {}",
+            new_extension, e,
+        )
+    })?;
 
     // and done
     primitive
@@ -96,6 +114,9 @@ pub fn write_variant_map(primitive: &mut Primitive, tag_to_ix: &HashMap<Tag, usi
 
 /// Parses and returns the `KHR_materials_variants` data on a primitive, if any.
 ///
+/// Mappings must reference only variants in the given lookup table, and may map each variant
+/// only once; violations are reported with the index of the offending mapping.
+///
 /// Please see [the `KHR_materials_variants`
 /// spec](https://github.com/zellski/glTF/blob/ext/zell-fb-asset-variants/extensions/2.0/Khronos/KHR_materials_variants/README.md)
 /// for further details
@@ -108,11 +129,29 @@ pub fn extract_variant_map(primitive: &Primitive, variant_ix_lookup: &HashMap<us
             return match parse {
                 Ok(parse) => {
                     let mut result = HashMap::new();
-                    for entry in parse.mappings {
+                    for (entry_ix, entry) in parse.mappings.into_iter().enumerate() {
+                        if entry.variants.is_empty() {
+                            return Err(format!("Mapping {} has no variants.", entry_ix));
+                        }
                         for variant_ix in entry.variants {
                             let key = variant_ix as usize;
-                            let variant_tag = &variant_ix_lookup[&key];
-                            result.insert(variant_tag.to_owned(), entry.material as usize);
+                            let variant_tag = variant_ix_lookup.get(&key).ok_or_else(|| {
+                                format!(
+                                    "Mapping {} references variant {}, but there are only {}.",
+                                    entry_ix,
+                                    variant_ix,
+                                    variant_ix_lookup.len(),
+                                )
+                            })?;
+                            let material_ix = entry.material as usize;
+                            if let Some(previous) =
+                                result.insert(variant_tag.to_owned(), material_ix)
+                            {
+                                return Err(format!(
+                                    "Mapping {} maps variant {} ({}) to material {}, but it's already mapped to {}.",
+                                    entry_ix, variant_ix, variant_tag, material_ix, previous,
+                                ));
+                            }
                         }
                     }
                     Ok(result)
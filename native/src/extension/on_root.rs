@@ -1,12 +1,22 @@
 // Copyright (c) Facebook, Inc. and its affiliates. All Rights Reserved
 //
-use std::collections::{HashMap};
+use std::collections::{BTreeMap, HashMap};
 use serde_derive::{Deserialize, Serialize};
 
 use gltf::json::Root;
 
+use serde_json::{json, Map, Value};
+
 use super::KHR_MATERIALS_VARIANTS;
-use crate::{Result, Tag};
+use crate::gltfext::{add_buffer_view_from_slice, get_slice_from_buffer_view, set_root_buffer};
+use crate::{Result, Tag, TagDisplay, TagPayload};
+
+// the property of a variant entry's `extras` that holds its payloads, if any
+const PAYLOADS_KEY: &str = "payloads";
+
+// the property of the root extension's `extras` that says its primitive mappings leave out the
+// variants that select the primitive's own material
+const COMPACT_MAPPINGS_KEY: &str = "compactMappings";
 
 #[derive(Clone, Debug, Default, Deserialize, Serialize)]
 pub struct FBMaterialVariantRootExtension {
@@ -60,6 +70,8 @@ pub fn write_root_variant_lookup_map(root: &mut Root, tags_in_use: &Vec<Tag>) ->
 /// Extracts the variant lookup object from the root of the glTF file. This lookup is used to
 /// translate Tags with indicies located on mesh primitives.
 ///
+/// Variant names must be non-empty and unique.
+///
 /// Please see [the `KHR_materials_variants`
 /// spec](https://github.com/zellski/glTF/blob/ext/zell-fb-asset-variants/extensions/2.0/Khronos/KHR_materials_variants/README.md)
 /// for further details.
@@ -67,7 +79,17 @@ pub fn get_variant_lookup(root: &Root) -> Result<HashMap<usize, Tag>> {
     match get_root_extension(&root)? {
         Some(extension) => {
             let mut lookup = HashMap::new();
+            let mut seen = HashMap::new();
             for (ix, variant) in extension.variants.iter().enumerate() {
+                if variant.name.is_empty() {
+                    return Err(format!("Variant {} has an empty name.", ix));
+                }
+                if let Some(previous_ix) = seen.insert(&variant.name, ix) {
+                    return Err(format!(
+                        "Variant {} has the same name ({}) as variant {}.",
+                        ix, variant.name, previous_ix,
+                    ));
+                }
                 lookup.insert(ix, variant.name.to_owned());
             }
             Ok(lookup)
@@ -78,6 +100,227 @@ pub fn get_variant_lookup(root: &Root) -> Result<HashMap<usize, Tag>> {
     }
 }
 
+/// Extracts the display metadata of each variant from the `extras` of its entry in the root
+/// extension, keyed by variant index.
+///
+/// Entries with no `extras`, or `extras` we don't understand, are skipped rather than rejected,
+/// since other tools may well use them for their own purposes.
+pub fn get_variant_displays(root: &Root) -> Result<HashMap<usize, TagDisplay>> {
+    let mut displays = HashMap::new();
+    for (ix, extras) in get_variant_extras(root)? {
+        let display = serde_json::from_value::<TagDisplay>(Value::Object(extras))
+            .ok()
+            .filter(|display| !display.is_empty());
+        if let Some(display) = display {
+            displays.insert(ix, display);
+        }
+    }
+    Ok(displays)
+}
+
+/// Writes display metadata into the `extras` of the variant entries of the root extension,
+/// which must already have been written by `write_root_variant_lookup_map()`.
+///
+/// Displays for tags that have no variant entry are ignored.
+pub fn write_variant_displays(root: &mut Root, displays: &HashMap<Tag, TagDisplay>) -> Result<()> {
+    update_variant_extras(root, |tag, extras| {
+        if let Some(display) = displays.get(tag).filter(|display| !display.is_empty()) {
+            let display = serde_json::to_value(display)
+                .map_err(|e| format!("Failed to transform tag display: {}", e))?;
+            if let Value::Object(display) = display {
+                extras.extend(display);
+            }
+        }
+        Ok(())
+    })
+}
+
+/// Extracts the payloads of each variant from the `extras` of its entry in the root extension,
+/// keyed by variant index, reading binary payloads from the given blob.
+pub fn get_variant_payloads(
+    root: &Root,
+    blob: &Vec<u8>,
+) -> Result<HashMap<usize, BTreeMap<String, TagPayload>>> {
+    let mut result = HashMap::new();
+    for (ix, mut extras) in get_variant_extras(root)? {
+        let payloads = match extras.remove(PAYLOADS_KEY) {
+            Some(Value::Object(payloads)) => payloads,
+            _ => continue,
+        };
+        let mut variant_payloads = BTreeMap::new();
+        for (key, payload) in payloads {
+            let bad_payload = || format!("Variant {} has a malformed payload {:?}.", ix, key);
+            let payload = if let Some(json) = payload.get("json") {
+                TagPayload::Json(json.to_owned())
+            } else {
+                let view_ix = payload
+                    .get("bufferView")
+                    .and_then(Value::as_u64)
+                    .ok_or_else(bad_payload)?;
+                let view = root
+                    .buffer_views
+                    .get(view_ix as usize)
+                    .ok_or_else(bad_payload)?;
+                TagPayload::Binary {
+                    mime_type: payload
+                        .get("mimeType")
+                        .and_then(Value::as_str)
+                        .map(String::from),
+                    bytes: get_slice_from_buffer_view(view, blob)?.to_vec(),
+                }
+            };
+            variant_payloads.insert(key, payload);
+        }
+        result.insert(ix, variant_payloads);
+    }
+    Ok(result)
+}
+
+/// Writes payloads into the `extras` of the variant entries of the root extension, which must
+/// already have been written by `write_root_variant_lookup_map()`. Binary payloads are appended
+/// to the blob, each in a buffer view of its own.
+///
+/// Payloads for tags that have no variant entry are ignored.
+pub fn write_variant_payloads(
+    root: &mut Root,
+    blob: &mut Vec<u8>,
+    payloads: &HashMap<Tag, BTreeMap<String, TagPayload>>,
+) -> Result<()> {
+    let mut buffer_views = std::mem::replace(&mut root.buffer_views, vec![]);
+    update_variant_extras(root, |tag, extras| {
+        if let Some(variant_payloads) = payloads.get(tag).filter(|p| !p.is_empty()) {
+            let mut json = Map::new();
+            for (key, payload) in variant_payloads {
+                let payload = match payload {
+                    TagPayload::Json(value) => json!({ "json": value }),
+                    TagPayload::Binary { mime_type, bytes } => {
+                        let view = add_buffer_view_from_slice(bytes, &mut buffer_views, blob)
+                            .map_err(|e| format!("Payload {} of tag {}: {}", key, tag, e))?;
+                        match mime_type {
+                            Some(mime_type) => {
+                                json!({ "bufferView": view.value(), "mimeType": mime_type })
+                            }
+                            None => json!({ "bufferView": view.value() }),
+                        }
+                    }
+                };
+                json.insert(key.to_owned(), payload);
+            }
+            extras.insert(PAYLOADS_KEY.to_owned(), Value::Object(json));
+        }
+        Ok(())
+    })?;
+    root.buffer_views = buffer_views;
+    set_root_buffer(blob, &mut root.buffers)?;
+    Ok(())
+}
+
+// the `extras` object of each variant entry of the root extension that has one
+fn get_variant_extras(root: &Root) -> Result<Vec<(usize, Map<String, Value>)>> {
+    let extension = get_raw_root_extension(root)?;
+    let variants = extension
+        .as_ref()
+        .and_then(|extension| extension.get("variants"))
+        .and_then(Value::as_array);
+    let mut result = vec![];
+    if let Some(variants) = variants {
+        for (ix, variant) in variants.iter().enumerate() {
+            if let Some(Value::Object(extras)) = variant.get("extras") {
+                result.push((ix, extras.to_owned()));
+            }
+        }
+    }
+    Ok(result)
+}
+
+/// Notes in the `extras` of the root extension, which must already have been written by
+/// `write_root_variant_lookup_map()`, that the primitive mappings leave out the variants that
+/// select the primitive's own material; see `MeldOptions::compact_variant_mappings`.
+pub fn write_compact_mappings_marker(root: &mut Root) -> Result<()> {
+    let mut extension = match get_raw_root_extension(root)? {
+        Some(Value::Object(extension)) => extension,
+        _ => return Ok(()),
+    };
+    let mut extras = match extension.remove("extras") {
+        Some(Value::Object(extras)) => extras,
+        _ => Map::new(),
+    };
+    extras.insert(COMPACT_MAPPINGS_KEY.to_owned(), Value::Bool(true));
+    extension.insert("extras".to_owned(), Value::Object(extras));
+
+    let value = serde_json::to_string(&extension)
+        .and_then(|s| serde_json::from_str(&s))
+        .map_err(|e| format!("Failed to transform root extension: {}", e))?;
+    root.extensions
+        .get_or_insert(Default::default())
+        .others
+        .insert(KHR_MATERIALS_VARIANTS.to_owned(), value);
+    Ok(())
+}
+
+/// Whether the root extension says its primitive mappings were compacted, as written by
+/// `write_compact_mappings_marker()`.
+pub fn has_compact_mappings_marker(root: &Root) -> Result<bool> {
+    Ok(get_raw_root_extension(root)?
+        .as_ref()
+        .and_then(|extension| extension.get("extras"))
+        .and_then(|extras| extras.get(COMPACT_MAPPINGS_KEY))
+        .and_then(Value::as_bool)
+        .unwrap_or(false))
+}
+
+// let the caller add to the `extras` object of each variant entry of the root extension, by tag
+fn update_variant_extras<F>(root: &mut Root, mut update: F) -> Result<()>
+where
+    F: FnMut(&str, &mut Map<String, Value>) -> Result<()>,
+{
+    let mut extension = match get_raw_root_extension(root)? {
+        Some(extension) => extension,
+        None => return Ok(()),
+    };
+    if let Some(variants) = extension.get_mut("variants").and_then(Value::as_array_mut) {
+        for variant in variants.iter_mut() {
+            let variant = match variant {
+                Value::Object(variant) => variant,
+                _ => continue,
+            };
+            let tag = match variant.get("name").and_then(Value::as_str) {
+                Some(tag) => tag.to_owned(),
+                None => continue,
+            };
+            let mut extras = match variant.remove("extras") {
+                Some(Value::Object(extras)) => extras,
+                _ => Map::new(),
+            };
+            update(&tag, &mut extras)?;
+            if !extras.is_empty() {
+                variant.insert("extras".to_owned(), Value::Object(extras));
+            }
+        }
+    }
+
+    let value = serde_json::to_string(&extension)
+        .and_then(|s| serde_json::from_str(&s))
+        .map_err(|e| format!("Failed to transform root extension: {}", e))?;
+    root.extensions
+        .get_or_insert(Default::default())
+        .others
+        .insert(KHR_MATERIALS_VARIANTS.to_owned(), value);
+    Ok(())
+}
+
+fn get_raw_root_extension(root: &Root) -> Result<Option<Value>> {
+    if let Some(extensions) = &root.extensions {
+        if let Some(ref boxed) = extensions.others.get(KHR_MATERIALS_VARIANTS) {
+            let json_string = boxed.to_string();
+            return serde_json::from_str(&json_string)
+                .map(Some)
+                .map_err(|e| format!("Bad JSON in KHR_materials_variants extension: {}", e));
+        }
+    }
+    Ok(None)
+}
+
 fn get_root_extension(root: &Root) -> Result<Option<FBMaterialVariantRootExtension>> {
     if let Some(extensions) = &root.extensions {
         if let Some(ref boxed) = extensions.others.get(KHR_MATERIALS_VARIANTS) {
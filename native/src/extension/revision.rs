@@ -0,0 +1,41 @@
+// Copyright (c) Facebook, Inc. and its affiliates. All Rights Reserved
+//
+
+use gltf::json::Root;
+
+use super::{ExtensionRevision, KHR_MATERIALS_VARIANTS};
+use crate::Result;
+
+/// Works out which revision of `KHR_materials_variants` the asset was written with, if any.
+///
+/// It's an error for an asset to mix revisions, but an asset with no variational primitives
+/// gives us no clue at all, and so yields `None`.
+pub fn detect_revision(root: &Root) -> Result<Option<ExtensionRevision>> {
+    let mut detected = None;
+    for (m_ix, mesh) in root.meshes.iter().enumerate() {
+        for (p_ix, primitive) in mesh.primitives.iter().enumerate() {
+            let boxed = match &primitive.extensions {
+                Some(extensions) => extensions.others.get(KHR_MATERIALS_VARIANTS),
+                None => None,
+            };
+            if let Some(boxed) = boxed {
+                let json_string = boxed.to_string();
+                let value: serde_json::Value = serde_json::from_str(&json_string)
+                    .map_err(|e| format!("Bad JSON in KHR_materials_variants extension: {}", e))?;
+                let revision = if value.get("mapping").is_some() {
+                    ExtensionRevision::Draft
+                } else {
+                    ExtensionRevision::Ratified
+                };
+                if detected.map_or(false, |detected| detected != revision) {
+                    return Err(format!(
+                        "Mesh {} primitive {} uses {:?} KHR_materials_variants, unlike the rest.",
+                        m_ix, p_ix, revision
+                    ));
+                }
+                detected = Some(revision);
+            }
+        }
+    }
+    Ok(detected)
+}
@@ -1,16 +1,19 @@
 // Copyright (c) Facebook, Inc. and its affiliates. All Rights Reserved
 //
 
-//! Utilities for building binary glTF (GLB) files.
+//! Utilities for building and reading binary glTF (GLB) files.
+
+use std::io::Write;
 
 use crate::Result;
 
-use GlbChunk::{BIN, JSON};
+use GlbChunk::{Other, BIN, JSON};
 
 const GLB_VERSION: u32 = 2;
 const GLB_MAGIC: [u8; 4] = [b'g', b'l', b'T', b'F'];
 
-/// GLB 2.0 holds one JSON chunk followed by an optional BIN chunk.
+/// GLB 2.0 holds one JSON chunk followed by an optional BIN chunk, and then any number of chunks
+/// of other types, which readers that don't know them are required to skip.
 pub enum GlbChunk<'a> {
     /// A byte slice of valid JSON conforming to the [glTF schema].
     ///
@@ -18,6 +21,8 @@ pub enum GlbChunk<'a> {
     JSON(&'a [u8]),
     /// An binary blob, destined to become the data underlying a glTF buffer.
     BIN(&'a [u8]),
+    /// A chunk of some other type, identified by its magic number, e.g. vendor-specific data.
+    Other(u32, &'a [u8]),
 }
 
 impl<'a> GlbChunk<'a> {
@@ -25,52 +30,226 @@ impl<'a> GlbChunk<'a> {
         match *self {
             JSON(_) => 0x4E4F534A,
             BIN(_) => 0x004E4942,
+            Other(magic, _) => magic,
+        }
+    }
+    fn name(&self) -> &'static str {
+        match *self {
+            JSON(_) => "JSON chunk",
+            BIN(_) => "BIN chunk",
+            Other(_, _) => "extra chunk",
         }
     }
     fn bytes(&self) -> &[u8] {
         match *self {
             JSON(bytes) => bytes,
             BIN(bytes) => bytes,
+            Other(_, bytes) => bytes,
         }
     }
 
     /// Serialised JSON & optional BIN chunks binary glTF, i.e. GLB 2.0.
     pub fn to_bytes(json_chunk: Self, bin_chunk: Option<Self>) -> Result<Vec<u8>> {
+        Self::to_bytes_with_extras(json_chunk, bin_chunk, vec![])
+    }
+
+    /// Serialises JSON, optional BIN, and any extra chunks into binary glTF, i.e. GLB 2.0.
+    ///
+    /// The extra chunks follow the others in the given order; they must all be `Other` chunks,
+    /// and their magic numbers must not be those of JSON or BIN.
+    pub fn to_bytes_with_extras(
+        json_chunk: Self,
+        bin_chunk: Option<Self>,
+        extra_chunks: Vec<Self>,
+    ) -> Result<Vec<u8>> {
         // create the initial header
         let mut glb_bytes = vec![];
         glb_bytes.extend_from_slice(&GLB_MAGIC);
         glb_bytes.extend_from_slice(&(GLB_VERSION as u32).to_le_bytes());
         glb_bytes.extend_from_slice(&(0 as u32).to_le_bytes()); // fill in later
 
-        let mut append_chunk = |chunk: Self| {
+        let mut append_chunk = |chunk: Self| -> Result<()> {
             let mut chunk_bytes = chunk.bytes().to_vec();
             if chunk_bytes.len() > 0 {
                 while (chunk_bytes.len() % 4) != 0 {
                     chunk_bytes.push(if let JSON(_) = chunk { b' ' } else { 0x00 });
                 }
-                glb_bytes.extend_from_slice(&(chunk_bytes.len() as u32).to_le_bytes());
+                let chunk_len = checked_length(chunk_bytes.len(), chunk.name())?;
+                glb_bytes.extend_from_slice(&chunk_len.to_le_bytes());
                 glb_bytes.extend_from_slice(&(chunk.magic() as u32).to_le_bytes());
                 glb_bytes.extend_from_slice(&chunk_bytes);
             }
+            Ok(())
         };
 
         if let JSON(_) = json_chunk {
-            append_chunk(json_chunk);
+            append_chunk(json_chunk)?;
         } else {
             return Err(format!("First GLB chunk must be of type JSON."));
         }
         if let Some(bin_chunk) = bin_chunk {
             if let BIN(_) = bin_chunk {
-                append_chunk(bin_chunk);
+                append_chunk(bin_chunk)?;
             } else {
                 return Err(format!("Second GLB chunk must be of type BIN, or None."));
             }
         }
-
-        let glb_len_bytes = &(glb_bytes.len() as u32).to_le_bytes();
-        for i in 0..3 {
-            glb_bytes[0x08 + i] = glb_len_bytes[i];
+        for extra_chunk in extra_chunks {
+            match extra_chunk {
+                Other(magic, _) if !is_known_magic(magic) => append_chunk(extra_chunk)?,
+                _ => return Err(format!("Extra GLB chunks can't be of type JSON or BIN.")),
+            }
         }
+
+        let glb_len_bytes = &checked_length(glb_bytes.len(), "file")?.to_le_bytes();
+        glb_bytes[0x08..0x0C].copy_from_slice(glb_len_bytes);
         Ok(glb_bytes)
     }
+
+    /// Writes JSON, a BIN chunk made of the given pieces, and any extra chunks as binary glTF,
+    /// i.e. GLB 2.0, to `writer`, returning the number of bytes written.
+    ///
+    /// Each piece of the BIN chunk is padded with zeros to a multiple of 4 bytes, so that the
+    /// chunk need never be put together in memory; with no pieces, there's no BIN chunk. The
+    /// extra chunks are as for `to_bytes_with_extras()`.
+    pub fn write_with_extras<W: Write>(
+        writer: &mut W,
+        json_chunk: Self,
+        bin_pieces: &[&[u8]],
+        extra_chunks: Vec<Self>,
+    ) -> Result<usize> {
+        let json = match json_chunk {
+            JSON(json) => json,
+            _ => return Err(format!("First GLB chunk must be of type JSON.")),
+        };
+        for extra_chunk in &extra_chunks {
+            match *extra_chunk {
+                Other(magic, _) if !is_known_magic(magic) => (),
+                _ => return Err(format!("Extra GLB chunks can't be of type JSON or BIN.")),
+            }
+        }
+        let padded = |len: usize| (len + 3) & !3;
+        let bin_len: usize = bin_pieces.iter().map(|piece| padded(piece.len())).sum();
+
+        // every non-empty chunk comes with 8 bytes of header
+        let chunk_lens = std::iter::once(json.len())
+            .chain(std::iter::once(bin_len))
+            .chain(extra_chunks.iter().map(|chunk| chunk.bytes().len()))
+            .filter(|&len| len > 0)
+            .map(|len| 8 + padded(len));
+        let glb_len = checked_length(12 + chunk_lens.sum::<usize>(), "file")?;
+
+        let mut written = 0;
+        let mut write = |bytes: &[u8]| -> Result<()> {
+            written += bytes.len();
+            writer
+                .write_all(bytes)
+                .map_err(|e| format!("Couldn't write GLB: {}", e))
+        };
+        write(&GLB_MAGIC)?;
+        write(&GLB_VERSION.to_le_bytes())?;
+        write(&glb_len.to_le_bytes())?;
+
+        let mut write_chunk = |magic: u32, name: &str, pieces: &[&[u8]], pad: u8| -> Result<()> {
+            let chunk_len: usize = pieces.iter().map(|piece| padded(piece.len())).sum();
+            if chunk_len > 0 {
+                write(&checked_length(chunk_len, name)?.to_le_bytes())?;
+                write(&magic.to_le_bytes())?;
+                for piece in pieces {
+                    write(piece)?;
+                    write(&vec![pad; padded(piece.len()) - piece.len()])?;
+                }
+            }
+            Ok(())
+        };
+        write_chunk(JSON(json).magic(), JSON(json).name(), &[json], b' ')?;
+        write_chunk(BIN(&[]).magic(), BIN(&[]).name(), bin_pieces, 0x00)?;
+        for extra_chunk in &extra_chunks {
+            let bytes = extra_chunk.bytes();
+            write_chunk(extra_chunk.magic(), extra_chunk.name(), &[bytes], 0x00)?;
+        }
+        Ok(written)
+    }
+
+    /// Splits GLB 2.0 bytes into their JSON & optional BIN chunks, or `None` if not GLB at all.
+    ///
+    /// Any chunks beyond those are ignored; see `from_bytes_with_extras()`.
+    pub fn from_bytes(glb_bytes: &'a [u8]) -> Result<Option<(Self, Option<Self>)>> {
+        Ok(Self::from_bytes_with_extras(glb_bytes)?.map(|(json, bin, _)| (json, bin)))
+    }
+
+    /// Splits GLB 2.0 bytes into their JSON, optional BIN, and any extra chunks, or `None` if not
+    /// GLB at all. The extra chunks are all `Other` chunks, in the order they appear in the file.
+    pub fn from_bytes_with_extras(
+        glb_bytes: &'a [u8],
+    ) -> Result<Option<(Self, Option<Self>, Vec<Self>)>> {
+        if glb_bytes.len() < 12 || glb_bytes[0..4] != GLB_MAGIC {
+            return Ok(None);
+        }
+        let mut version = [0u8; 4];
+        version.copy_from_slice(&glb_bytes[4..8]);
+        let version = u32::from_le_bytes(version);
+        if version != GLB_VERSION {
+            return Err(format!(
+                "Unsupported GLB version {}; only glTF 2.0 is supported.",
+                version
+            ));
+        }
+        // returns the (magic, bytes) of the chunk at the given offset, if there is one
+        let read_chunk = |offset: usize| -> Result<Option<(u32, &'a [u8])>> {
+            if glb_bytes.len() < offset + 8 {
+                return Ok(None);
+            }
+            let read_u32 = |offset: usize| {
+                let mut bytes = [0u8; 4];
+                bytes.copy_from_slice(&glb_bytes[offset..offset + 4]);
+                u32::from_le_bytes(bytes)
+            };
+            let (len, magic) = (read_u32(offset) as usize, read_u32(offset + 4));
+            // a huge length could wrap around on 32-bit targets, e.g. WebAssembly
+            let end = match (offset + 8).checked_add(len) {
+                Some(end) if end <= glb_bytes.len() => end,
+                _ => return Err(format!("GLB chunk at offset {} overruns the file.", offset)),
+            };
+            Ok(Some((magic, &glb_bytes[offset + 8..end])))
+        };
+
+        let json = match read_chunk(12)? {
+            Some((magic, bytes)) if magic == JSON(bytes).magic() => bytes,
+            _ => return Err(format!("First GLB chunk must be of type JSON.")),
+        };
+        let mut offset = 20 + json.len();
+        let mut bin = None;
+        let mut extras = vec![];
+        while let Some((magic, bytes)) = read_chunk(offset)? {
+            if magic == JSON(bytes).magic() {
+                return Err(format!("GLB holds more than one JSON chunk."));
+            } else if magic == BIN(bytes).magic() {
+                if bin.is_some() || !extras.is_empty() {
+                    return Err(format!("GLB BIN chunk must follow the JSON chunk."));
+                }
+                bin = Some(BIN(bytes));
+            } else {
+                extras.push(Other(magic, bytes));
+            }
+            offset += 8 + bytes.len();
+        }
+        Ok(Some((JSON(json), bin, extras)))
+    }
+}
+
+// whether a chunk type is one that GLB 2.0 itself defines
+fn is_known_magic(magic: u32) -> bool {
+    magic == JSON(&[]).magic() || magic == BIN(&[]).magic()
+}
+
+// GLB lengths are 32-bit; fail rather than silently wrap
+fn checked_length(len: usize, what: &str) -> Result<u32> {
+    if len as u64 > u32::max_value() as u64 {
+        return Err(format!(
+            "GLB {} of {} bytes exceeds the format's 32-bit length limit.",
+            what, len
+        ));
+    }
+    Ok(len as u32)
 }
@@ -3,14 +3,28 @@
 
 //! Utility functions that extend the functionality of the `gltf` crate(s) for our needs.
 
-use gltf::json::{buffer::View, Buffer, Index};
+use std::collections::{BTreeSet, HashMap};
+
+use serde_json::{json, Value};
+use sha1::Sha1;
+
+use gltf::json::accessor::{Accessor, ComponentType, GenericComponentType, Type};
+use gltf::json::mesh::Semantic;
+use gltf::json::validation::Checked;
+use gltf::json::{buffer::View, Buffer, Index, Root};
 
 use crate::Result;
 
+// glTF byte offsets & lengths are 32-bit; we use 64 bits internally, and check against this
+const MAX_BUFFER_BYTES: u64 = u32::max_value() as u64;
+
+// exporters round accessor bounds when they write them; this much relative error is tolerated
+const BOUNDS_TOLERANCE: f64 = 1e-5;
+
 /// Returns the underlying byte slice of the given buffer view.
 pub fn get_slice_from_buffer_view<'a>(view: &'a View, blob: &'a Vec<u8>) -> Result<&'a [u8]> {
     let start = view.byte_offset.unwrap_or(0) as usize;
-    let end = start + view.byte_length as usize;
+    let end = start.saturating_add(view.byte_length as usize);
     (&blob.get(start..end)).ok_or_else(|| {
         format!(
             "Slice [{}..{}] out of range for buffer view of length {}.",
@@ -21,21 +35,30 @@ pub fn get_slice_from_buffer_view<'a>(view: &'a View, blob: &'a Vec<u8>) -> Resu
 
 /// Adds a byte slice to the given blob, creates & pushes a buffer view onto the given vector.
 ///
-/// This method ensures the byte slice ends up at a 4-byte-aligned position in the blob.
+/// This method ensures the byte slice ends up at a 4-byte-aligned position in the blob. It fails,
+/// leaving blob and views untouched, if the new view would reach beyond what glTF can address.
 pub fn add_buffer_view_from_slice(
     bytes: &[u8],
     buffer_views: &mut Vec<View>,
     blob: &mut Vec<u8>,
-) -> Index<View> {
+) -> Result<Index<View>> {
+    let view_ix = buffer_views.len();
+    let start = (blob.len() as u64 + 3) & !3;
+    let end = start + bytes.len() as u64;
+    if end > MAX_BUFFER_BYTES {
+        return Err(format!(
+            "Buffer view {} would span bytes [{}..{}] of the blob, beyond glTF's 32-bit limit.",
+            view_ix, start, end
+        ));
+    }
+
     while (blob.len() % 4) != 0 {
         blob.push(0x00);
     }
-
-    let view_ix = buffer_views.len();
     let view = View {
         buffer: Index::new(0),
         byte_length: bytes.len() as u32,
-        byte_offset: Some(blob.len() as u32),
+        byte_offset: Some(start as u32),
         byte_stride: None,
         name: None,
         target: None,
@@ -46,19 +69,548 @@ pub fn add_buffer_view_from_slice(
 
     blob.extend_from_slice(bytes);
 
-    Index::new(view_ix as u32)
+    Ok(Index::new(view_ix as u32))
+}
+
+/// Adds a byte slice to the given blob in a buffer view of its own, and creates & pushes onto the
+/// given vector an accessor that presents it as *count* tightly packed elements of the given
+/// component type and type.
+///
+/// The byte slice must hold exactly that many elements; matrices whose columns glTF would pad,
+/// i.e. `MAT2` and `MAT3` of 1- or 2-byte components, aren't supported. The accessor has no
+/// `min` or `max`; callers that need them should fill them in. On failure, blob, views and
+/// accessors are all left untouched.
+pub fn add_accessor_from_slice(
+    bytes: &[u8],
+    component_type: ComponentType,
+    type_: Type,
+    count: usize,
+    buffer_views: &mut Vec<View>,
+    accessors: &mut Vec<Accessor>,
+    blob: &mut Vec<u8>,
+) -> Result<Index<Accessor>> {
+    let accessor_ix = accessors.len();
+    let padded = match type_ {
+        Type::Mat2 | Type::Mat3 => component_type.size() < 4,
+        _ => false,
+    };
+    if padded {
+        return Err(format!(
+            "Accessor {} would need padded {:?} columns of {:?}, which aren't supported.",
+            accessor_ix, type_, component_type
+        ));
+    }
+    let expected = count * component_type.size() * type_.multiplicity();
+    if bytes.len() != expected {
+        return Err(format!(
+            "Accessor {} of {} {:?} {:?} elements needs {} bytes, not {}.",
+            accessor_ix,
+            count,
+            type_,
+            component_type,
+            expected,
+            bytes.len()
+        ));
+    }
+    if count > u32::max_value() as usize {
+        return Err(format!(
+            "Accessor {} of {} elements is beyond glTF's 32-bit limit.",
+            accessor_ix, count
+        ));
+    }
+
+    let view_ix = add_buffer_view_from_slice(bytes, buffer_views, blob)?;
+    accessors.push(Accessor {
+        buffer_view: Some(view_ix),
+        byte_offset: 0,
+        count: count as u32,
+        component_type: Checked::Valid(GenericComponentType(component_type)),
+        extensions: None,
+        extras: Default::default(),
+        type_: Checked::Valid(type_),
+        min: None,
+        max: None,
+        name: None,
+        normalized: false,
+        sparse: None,
+    });
+    Ok(Index::new(accessor_ix as u32))
 }
 
 /// Replaces any contents of the provided buffer vector with a single one, holding the given blob.
-pub fn set_root_buffer(blob: &[u8], buffers: &mut Vec<Buffer>) {
+///
+/// Fails if the blob is too large for a glTF buffer.
+pub fn set_root_buffer(blob: &[u8], buffers: &mut Vec<Buffer>) -> Result<()> {
+    set_root_buffer_length(blob.len(), buffers)
+}
+
+/// Does what `set_root_buffer()` does, for a blob of the given length.
+pub fn set_root_buffer_length(blob_len: usize, buffers: &mut Vec<Buffer>) -> Result<()> {
+    if blob_len as u64 > MAX_BUFFER_BYTES {
+        return Err(format!(
+            "The blob of {} bytes is too large for a glTF buffer, which is limited to {}.",
+            blob_len, MAX_BUFFER_BYTES
+        ));
+    }
     buffers.clear();
-    if !blob.is_empty() {
+    if blob_len > 0 {
         buffers.push(Buffer {
-            byte_length: blob.len() as u32,
+            byte_length: blob_len as u32,
             uri: None,
             name: None,
             extensions: None,
             extras: None,
         });
     }
+    Ok(())
+}
+
+/// Checks that every buffer view lies within the blob, and every reference to one is valid.
+///
+/// This is a sanity check of our own output rather than a full validation of glTF; accessors
+/// are checked only to start within their buffer view.
+pub fn check_buffer_view_bounds(root: &Root, blob: &[u8]) -> Result<()> {
+    for (ix, view) in root.buffer_views.iter().enumerate() {
+        let end =
+            (view.byte_offset.unwrap_or(0) as usize).saturating_add(view.byte_length as usize);
+        if end > blob.len() {
+            return Err(format!(
+                "Buffer view {} ends at byte {}, beyond the blob of length {}.",
+                ix,
+                end,
+                blob.len()
+            ));
+        }
+    }
+    let check_view = |view_ix: Index<View>, referrer: String| -> Result<&View> {
+        root.buffer_views.get(view_ix.value()).ok_or_else(|| {
+            format!(
+                "{} references non-existent buffer view {}.",
+                referrer, view_ix
+            )
+        })
+    };
+    for (ix, accessor) in root.accessors.iter().enumerate() {
+        if let Some(view_ix) = accessor.buffer_view {
+            let view = check_view(view_ix, format!("Accessor {}", ix))?;
+            if accessor.byte_offset >= view.byte_length {
+                return Err(format!(
+                    "Accessor {} starts at byte {} of buffer view {}, of length {}.",
+                    ix, accessor.byte_offset, view_ix, view.byte_length
+                ));
+            }
+        }
+        if let Some(sparse) = &accessor.sparse {
+            check_view(sparse.indices.buffer_view, format!("Accessor {}", ix))?;
+            check_view(sparse.values.buffer_view, format!("Accessor {}", ix))?;
+        }
+    }
+    for (ix, image) in root.images.iter().enumerate() {
+        if let Some(view_ix) = image.buffer_view {
+            check_view(view_ix, format!("Image {}", ix))?;
+        }
+    }
+    Ok(())
+}
+
+/// Checks the `min` & `max` of every accessor used for vertex positions against the data it
+/// actually holds, overwriting them with the true bounds if `fix` is set.
+///
+/// Returns the indices of the accessors whose bounds were missing or wrong. Both primitive and
+/// morph target positions are checked; sparse accessors are skipped.
+pub fn check_position_bounds(root: &mut Root, blob: &Vec<u8>, fix: bool) -> Result<Vec<usize>> {
+    let mut position_ixs = BTreeSet::new();
+    for mesh in &root.meshes {
+        for primitive in &mesh.primitives {
+            let positions = primitive
+                .attributes
+                .get(&Checked::Valid(Semantic::Positions));
+            if let Some(accessor_ix) = positions {
+                position_ixs.insert(accessor_ix.value());
+            }
+            for target in primitive.targets.iter().flatten() {
+                if let Some(accessor_ix) = target.positions {
+                    position_ixs.insert(accessor_ix.value());
+                }
+            }
+        }
+    }
+
+    let mut wrong = vec![];
+    for accessor_ix in position_ixs {
+        let (min, max) = match position_bounds(root, accessor_ix, blob)? {
+            Some(bounds) => bounds,
+            None => continue,
+        };
+        let accessor = &mut root.accessors[accessor_ix];
+        if bounds_match(&accessor.min, &min) && bounds_match(&accessor.max, &max) {
+            continue;
+        }
+        wrong.push(accessor_ix);
+        if fix {
+            accessor.min = Some(json!(min));
+            accessor.max = Some(json!(max));
+        }
+    }
+    Ok(wrong)
+}
+
+// the actual per-component bounds of a VEC3 float accessor, or None if it has no elements
+fn position_bounds(
+    root: &Root,
+    accessor_ix: usize,
+    blob: &Vec<u8>,
+) -> Result<Option<([f32; 3], [f32; 3])>> {
+    let accessor = root.accessors.get(accessor_ix).ok_or_else(|| {
+        format!(
+            "Primitive references non-existent accessor {}.",
+            accessor_ix
+        )
+    })?;
+    match (&accessor.component_type, &accessor.type_) {
+        (Checked::Valid(GenericComponentType(ComponentType::F32)), Checked::Valid(Type::Vec3)) => {}
+        _ => {
+            return Err(format!(
+                "Accessor {} holds positions, but not as VEC3 floats.",
+                accessor_ix
+            ))
+        }
+    }
+    let view = match accessor.buffer_view {
+        Some(view_ix) if accessor.sparse.is_none() && accessor.count > 0 => root
+            .buffer_views
+            .get(view_ix.value())
+            .ok_or_else(|| format!("Accessor {} lacks a valid buffer view.", accessor_ix))?,
+        _ => return Ok(None),
+    };
+    let bytes = get_slice_from_buffer_view(view, blob)?;
+    let stride = view.byte_stride.map_or(12, |stride| stride as usize);
+
+    let mut min = [f32::INFINITY; 3];
+    let mut max = [f32::NEG_INFINITY; 3];
+    for element in 0..accessor.count as usize {
+        let start = accessor.byte_offset as usize + element * stride;
+        let element = bytes
+            .get(start..start + 12)
+            .ok_or_else(|| format!("Accessor {} reaches beyond its buffer view.", accessor_ix))?;
+        for component in 0..3 {
+            let b = &element[4 * component..4 * component + 4];
+            let value = f32::from_le_bytes([b[0], b[1], b[2], b[3]]);
+            min[component] = min[component].min(value);
+            max[component] = max[component].max(value);
+        }
+    }
+    Ok(Some((min, max)))
+}
+
+// whether the given JSON bounds are, within tolerance, the actual ones
+fn bounds_match(bounds: &Option<Value>, actual: &[f32; 3]) -> bool {
+    let bounds = match bounds.as_ref().and_then(Value::as_array) {
+        Some(bounds) if bounds.len() == 3 => bounds,
+        _ => return false,
+    };
+    bounds.iter().zip(actual).all(|(bound, &actual)| {
+        let actual = actual as f64;
+        bound.as_f64().map_or(false, |bound| {
+            (bound - actual).abs() <= BOUNDS_TOLERANCE * actual.abs().max(1.0)
+        })
+    })
+}
+
+/// Rebuilds the blob from only those bytes that are covered by the root's buffer views.
+///
+/// Buffer views that are referenced by neither accessors nor images are removed, and remaining
+/// references re-indexed, unless `keep_unreferenced` is set; callers should set it whenever the
+/// asset may reference buffer views in ways we can't see, e.g. through unknown extensions.
+///
+/// Views are re-based into the new blob in their original order. Views that overlap continue to
+/// share their bytes, and every view keeps its offset modulo 4, so alignment is preserved.
+pub fn compact_blob(root: &mut Root, blob: &[u8], keep_unreferenced: bool) -> Result<Vec<u8>> {
+    let spans = compact_blob_spans(root, blob.len(), keep_unreferenced)?;
+    let mut new_blob = vec![];
+    for (start, end) in spans {
+        new_blob.extend_from_slice(&blob[start..end.min(blob.len())]);
+        while (new_blob.len() % 4) != 0 {
+            new_blob.push(0x00);
+        }
+    }
+    Ok(new_blob)
+}
+
+/// Does what `compact_blob()` does to the root, for a blob of the given length, but leaves the
+/// bytes to the caller: returns the spans of the old blob that, in order and each padded with
+/// zeros to a multiple of 4 bytes, make up the new one. Spans may reach up to 3 bytes past the
+/// end of the old blob, which should be taken as padding too.
+///
+/// This lets e.g. a GLB writer stream the bytes straight from the old blob.
+pub fn compact_blob_spans(
+    root: &mut Root,
+    blob_len: usize,
+    keep_unreferenced: bool,
+) -> Result<Vec<(usize, usize)>> {
+    let view_count = root.buffer_views.len();
+    let mut referenced = vec![keep_unreferenced; view_count];
+    {
+        let mut reference = |view: Index<View>| -> Result<()> {
+            *referenced.get_mut(view.value()).ok_or_else(|| {
+                format!("Reference to non-existent buffer view {}.", view.value())
+            })? = true;
+            Ok(())
+        };
+        for accessor in &root.accessors {
+            if let Some(view) = accessor.buffer_view {
+                reference(view)?;
+            }
+            if let Some(sparse) = &accessor.sparse {
+                reference(sparse.indices.buffer_view)?;
+                reference(sparse.values.buffer_view)?;
+            }
+        }
+        for image in &root.images {
+            if let Some(view) = image.buffer_view {
+                reference(view)?;
+            }
+        }
+    }
+
+    // drop unreferenced views, and build a mapping from old view index to new
+    let mut new_ix = vec![None; view_count];
+    let mut new_views = vec![];
+    for (ix, view) in root.buffer_views.drain(..).enumerate() {
+        if referenced[ix] {
+            new_ix[ix] = Some(Index::new(new_views.len() as u32));
+            new_views.push(view);
+        }
+    }
+    remap_buffer_view_references(root, |view| new_ix[view.value()].unwrap());
+
+    // merge the 4-aligned byte ranges of the remaining views into disjoint spans
+    let mut ranges: Vec<(usize, usize)> = new_views
+        .iter()
+        .map(|view| {
+            let start = view.byte_offset.unwrap_or(0) as usize;
+            let end = start.saturating_add(view.byte_length as usize);
+            if end > blob_len {
+                return Err(format!(
+                    "Buffer view [{}..{}] out of range for blob of length {}.",
+                    start, end, blob_len
+                ));
+            }
+            Ok((start & !3, (end + 3) & !3))
+        })
+        .collect::<Result<_>>()?;
+    ranges.sort_unstable();
+    let mut spans: Vec<(usize, usize)> = vec![];
+    for (start, end) in ranges {
+        match spans.last_mut() {
+            Some(last) if start <= last.1 => last.1 = last.1.max(end),
+            _ => spans.push((start, end)),
+        }
+    }
+
+    // lay out each span in the new blob, recording where it ends up
+    let mut new_len = 0;
+    let mut span_offsets = vec![];
+    for &(start, end) in &spans {
+        span_offsets.push(new_len);
+        new_len += (end.min(blob_len) - start + 3) & !3;
+    }
+
+    // and finally re-base every view
+    for view in &mut new_views {
+        let start = view.byte_offset.unwrap_or(0) as usize;
+        // spans are sorted & disjoint, so the last one that starts at or before the view holds it
+        let span_ix = spans
+            .iter()
+            .rposition(|&(span_start, _)| span_start <= start)
+            .unwrap();
+        let new_start = span_offsets[span_ix] + (start - spans[span_ix].0);
+        view.byte_offset = Some(new_start as u32);
+    }
+    root.buffer_views = new_views;
+    set_root_buffer_length(new_len, &mut root.buffers)?;
+
+    Ok(spans)
+}
+
+/// Rebuilds the blob with the buffer views in the order they're first referenced, by accessors
+/// and then by images, so that the same objects in the same order always make the same blob.
+///
+/// Views referenced by neither are dropped, so this is only for assets we fully understand.
+/// Each view gets its own copy of its bytes, even if it used to overlap another, and keeps its
+/// offset modulo 4, so alignment is preserved.
+pub fn reorder_blob(root: &mut Root, blob: &[u8]) -> Result<Vec<u8>> {
+    let mut order = vec![];
+    {
+        let mut seen = vec![false; root.buffer_views.len()];
+        let mut reference = |view: Index<View>| -> Result<()> {
+            let seen = seen.get_mut(view.value()).ok_or_else(|| {
+                format!("Reference to non-existent buffer view {}.", view.value())
+            })?;
+            if !*seen {
+                *seen = true;
+                order.push(view.value());
+            }
+            Ok(())
+        };
+        for accessor in &root.accessors {
+            if let Some(view) = accessor.buffer_view {
+                reference(view)?;
+            }
+            if let Some(sparse) = &accessor.sparse {
+                reference(sparse.indices.buffer_view)?;
+                reference(sparse.values.buffer_view)?;
+            }
+        }
+        for image in &root.images {
+            if let Some(view) = image.buffer_view {
+                reference(view)?;
+            }
+        }
+    }
+
+    let mut new_ix = vec![None; root.buffer_views.len()];
+    let mut new_views = vec![];
+    let mut new_blob = vec![];
+    for ix in order {
+        let mut view = root.buffer_views[ix].clone();
+        let start = view.byte_offset.unwrap_or(0) as usize;
+        let end = start.saturating_add(view.byte_length as usize);
+        let bytes = blob.get(start..end).ok_or_else(|| {
+            format!(
+                "Buffer view [{}..{}] out of range for blob of length {}.",
+                start,
+                end,
+                blob.len()
+            )
+        })?;
+        while (new_blob.len() % 4) != (start % 4) {
+            new_blob.push(0x00);
+        }
+        view.byte_offset = Some(new_blob.len() as u32);
+        new_blob.extend_from_slice(bytes);
+        new_ix[ix] = Some(Index::new(new_views.len() as u32));
+        new_views.push(view);
+    }
+    while (new_blob.len() % 4) != 0 {
+        new_blob.push(0x00);
+    }
+    root.buffer_views = new_views;
+    remap_buffer_view_references(root, |view| new_ix[view.value()].unwrap());
+    set_root_buffer(&new_blob, &mut root.buffers)?;
+
+    Ok(new_blob)
+}
+
+/// Redirects accessor & image references from buffer views to identical earlier ones.
+///
+/// Views are identical if they hold the same bytes and have the same stride and target. The
+/// redundant views are left in place, unreferenced; see `compact_blob()` for cleaning those up.
+/// Returns the number of views that were made redundant.
+pub fn dedupe_buffer_views(root: &mut Root, blob: &Vec<u8>) -> Result<usize> {
+    let mut first_ix: HashMap<String, usize> = HashMap::new();
+    let mut redirect = vec![];
+    for (ix, view) in root.buffer_views.iter().enumerate() {
+        let bytes = get_slice_from_buffer_view(view, blob)?;
+        let key = format!(
+            "{}:{:?}:{:?}",
+            Sha1::from(bytes).digest().to_string(),
+            view.byte_stride,
+            view.target
+        );
+        redirect.push(*first_ix.entry(key).or_insert(ix));
+    }
+    let redundant = redirect
+        .iter()
+        .enumerate()
+        .filter(|&(ix, &first)| ix != first)
+        .count();
+    if redundant > 0 {
+        remap_buffer_view_references(root, |view| Index::new(redirect[view.value()] as u32));
+    }
+    Ok(redundant)
+}
+
+/// Replaces the bytes of the given buffer view with new ones, of any length, and returns the
+/// repacked blob.
+///
+/// The view keeps its index, stride and target; the bytes it used to cover are dropped from the
+/// blob unless some other view overlaps them, and every view is re-based accordingly. Accessors
+/// that read from the view are left alone; callers must fix up their counts and bounds.
+pub fn replace_buffer_view_bytes(
+    root: &mut Root,
+    blob: &[u8],
+    view_ix: Index<View>,
+    bytes: &[u8],
+) -> Result<Vec<u8>> {
+    if view_ix.value() >= root.buffer_views.len() {
+        return Err(format!(
+            "Can't replace non-existent buffer view {}.",
+            view_ix.value()
+        ));
+    }
+    // append the new bytes in a scratch view, then point the real one at them
+    let mut new_blob = blob.to_vec();
+    let mut scratch = vec![];
+    add_buffer_view_from_slice(bytes, &mut scratch, &mut new_blob)?;
+
+    let view = &mut root.buffer_views[view_ix.value()];
+    view.byte_offset = scratch[0].byte_offset;
+    view.byte_length = scratch[0].byte_length;
+
+    compact_blob(root, &new_blob, true)
+}
+
+/// Removes the given buffer view, and its bytes, and returns the repacked blob.
+///
+/// Fails if an accessor or image still references the view. References to later views are shifted
+/// down by one. Views referenced in ways we can't see, e.g. through unknown extensions, are not
+/// remapped, so callers should only remove views from assets they fully understand.
+pub fn remove_buffer_view(root: &mut Root, blob: &[u8], view_ix: Index<View>) -> Result<Vec<u8>> {
+    let ix = view_ix.value();
+    if ix >= root.buffer_views.len() {
+        return Err(format!("Can't remove non-existent buffer view {}.", ix));
+    }
+    let is_view = |view: Option<Index<View>>| view.map_or(false, |view| view.value() == ix);
+    let referenced_by_accessor = root.accessors.iter().any(|accessor| {
+        is_view(accessor.buffer_view)
+            || accessor.sparse.as_ref().map_or(false, |sparse| {
+                is_view(Some(sparse.indices.buffer_view))
+                    || is_view(Some(sparse.values.buffer_view))
+            })
+    });
+    let referenced_by_image = root.images.iter().any(|image| is_view(image.buffer_view));
+    if referenced_by_accessor || referenced_by_image {
+        return Err(format!(
+            "Can't remove buffer view {}; it's still referenced.",
+            ix
+        ));
+    }
+
+    root.buffer_views.remove(ix);
+    remap_buffer_view_references(root, |view| {
+        if view.value() > ix {
+            Index::new(view.value() as u32 - 1)
+        } else {
+            view
+        }
+    });
+    compact_blob(root, blob, true)
+}
+
+// apply the given mapping to every accessor & image reference to a buffer view
+fn remap_buffer_view_references<F>(root: &mut Root, remap: F)
+where
+    F: Fn(Index<View>) -> Index<View>,
+{
+    for accessor in &mut root.accessors {
+        accessor.buffer_view = accessor.buffer_view.map(&remap);
+        if let Some(sparse) = &mut accessor.sparse {
+            sparse.indices.buffer_view = remap(sparse.indices.buffer_view);
+            sparse.values.buffer_view = remap(sparse.values.buffer_view);
+        }
+    }
+    for image in &mut root.images {
+        image.buffer_view = image.buffer_view.map(&remap);
+    }
 }
@@ -39,13 +39,32 @@
 //!
 //! Each source asset brought into the tool is identified by a *tag*, a short string, and it's
 //! these same tags that are later used to trigger different runtime apperances.
+//!
+//! ### Thread Safety
+//!
+//! All the library's core types are `Send + Sync`, so that distinct assets may be melded on as
+//! many threads at once as desired, e.g. from a thread pool. A `VariationalAsset` is immutable,
+//! and may be shared freely, as may a `MeldCache`, even between processes.
+//!
+//! The library never spawns threads of its own: every operation runs to completion on the
+//! thread that calls it. How much work happens in parallel is therefore entirely up to the
+//! caller, e.g. through the size of whatever thread pool it melds from, which is as it should be
+//! for servers that share their cores with other work. Should the library one day parallelize
+//! internally, it will honour a pool chosen by the caller, rather than start one of its own.
+//!
+//! ### Untrusted Input
+//!
+//! No public function panics on malformed input: arbitrary bytes passed to e.g.
+//! `VariationalAsset::from_slice()`, `WorkAsset::meld()` or `GlbChunk::from_bytes()` yield an
+//! `Err`, never a crash of the host process or WebAssembly instance. Fuzz targets in `fuzz/`
+//! hold us to this. Note however that buffers and images may reference files by URI, which are
+//! then read from disk; a service that melds uploads should turn away assets that do so.
 
 extern crate gltf;
 extern crate serde;
 extern crate serde_derive;
 extern crate serde_json;
 extern crate sha1;
-extern crate spectral;
 
 /// Tags are short identifiers used to switch between different mesh primitive materials.
 pub type Tag = String;
@@ -55,16 +74,33 @@ pub type Error = String;
 /// Convenience type for a Result using our Error.
 pub type Result<T> = ::std::result::Result<T, crate::Error>;
 
+/// Structured tags, made up of values along independent axes.
+pub mod axes;
+pub use axes::AxisValues;
+
 /// The JSON/Serde implementation of `KHR_materials_variants`.
 pub mod extension;
+pub use extension::{ExtensionRevision, VariantDocument};
 
 /// The VarationalAsset struct and associated functionality.
 pub mod variational_asset;
-pub use variational_asset::{AssetSizes, Metadata, VariationalAsset};
+pub use variational_asset::{
+    AssetSizes, DedupeSummary, ImageInfo, Metadata, MetadataDelta, ObjectCounts, TagDisplay,
+    TagPayload, VariantMaterial, VariantTable, VariationalAsset,
+};
 
 /// The internal workhorse WorkAsset struct & functionality.
 pub mod work_asset;
-pub use work_asset::WorkAsset;
+pub use work_asset::{
+    tag_file_stem, AssetInfoPolicy, BoundsPolicy, ColorPolicy, DracoSettings,
+    ExtrasVariantExporter, ExtrasVariantImporter, FingerprintAlgorithm, FixedPrimitive,
+    InputLimits, MaterialFilter, MaterialFilterPolicy, MaterialOverrides, MeldCache, MeldCounts,
+    MeldDiagnosis, MeldEstimate, MeldOptions, MeldStats, MeshCandidate, MeshMismatch, MeshProblem,
+    MeshSelector, NameMergePolicy, NearDuplicate, NodeTransformPolicy, PhaseTimings,
+    PrimitiveCorrespondence, PrimitivePair, PrimitiveRef, SharingAnalysis, SidecarVariantExporter,
+    SwitchTableVariantExporter, TagCase, TagPolicy, TextureEncoding, VariantExporter,
+    VariantExporters, VariantImporter, VariantImporters, WorkAsset, INPUT_LIMIT_EXCEEDED,
+};
 
 pub mod glb;
 pub use glb::GlbChunk;
@@ -72,6 +108,24 @@ pub use glb::GlbChunk;
 pub mod gltfext;
 pub use gltfext::*;
 
+/// Checking exported GLB bytes for errors that would make viewers reject them.
+pub mod validate;
+pub use validate::validate_glb;
+
 /// Mapping glTF objects to unique keys for melding purposes.
 pub mod meld_keys;
-pub use meld_keys::{Fingerprint, MeldKey};
+pub use meld_keys::{Fingerprint, MeldKey, MESH_ID_EXTRAS_KEY};
+
+// fails to compile should any of the core types stop being safe to share between threads
+#[allow(dead_code)]
+fn assert_send_sync() {
+    fn is_send_sync<T: Send + Sync>() {}
+    is_send_sync::<VariationalAsset>();
+    is_send_sync::<WorkAsset>();
+    is_send_sync::<Metadata>();
+    is_send_sync::<MeldOptions>();
+    is_send_sync::<MeldCache>();
+    is_send_sync::<MeldStats>();
+    is_send_sync::<PrimitiveCorrespondence>();
+    is_send_sync::<MaterialOverrides>();
+}
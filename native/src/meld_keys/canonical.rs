@@ -0,0 +1,43 @@
+// Copyright (c) Facebook, Inc. and its affiliates. All Rights Reserved
+//
+
+use gltf::json::material::{AlphaCutoff, AlphaMode};
+use gltf::json::validation::Checked;
+use gltf::json::Material;
+
+/// Returns a copy of `material` in canonical form, suitable for building a `MeldKey` from.
+///
+/// Values that are omitted in the JSON are already filled in with their spec defaults during
+/// parsing, so an explicit `"metallicFactor": 1.0` and an omitted one look identical by the time
+/// we see them. What remains is to erase state that the spec says has no effect, and which
+/// exporters nonetheless write out inconsistently:
+/// - `alphaCutoff` is ignored unless `alphaMode` is `MASK`, so it's reset to its default.
+/// - negative zeros are made positive, so that e.g. `-0.0` and `0.0` format the same.
+pub fn canonical_material(material: &Material) -> Material {
+    let mut material = material.clone();
+
+    if material.alpha_mode != Checked::Valid(AlphaMode::Mask) {
+        material.alpha_cutoff = AlphaCutoff::default();
+    }
+
+    let pbr = &mut material.pbr_metallic_roughness;
+    pbr.base_color_factor.0.iter_mut().for_each(unsign_zero);
+    unsign_zero(&mut pbr.metallic_factor.0);
+    unsign_zero(&mut pbr.roughness_factor.0);
+    material.emissive_factor.0.iter_mut().for_each(unsign_zero);
+    unsign_zero(&mut material.alpha_cutoff.0);
+    if let Some(normal) = &mut material.normal_texture {
+        unsign_zero(&mut normal.scale);
+    }
+    if let Some(occlusion) = &mut material.occlusion_texture {
+        unsign_zero(&mut occlusion.strength.0);
+    }
+
+    material
+}
+
+fn unsign_zero(value: &mut f32) {
+    if *value == 0.0 {
+        *value = 0.0;
+    }
+}
@@ -1,10 +1,12 @@
 // Copyright (c) Facebook, Inc. and its affiliates. All Rights Reserved
 //
 
-use gltf::{mesh::Primitive, Buffer};
-
-use spectral::prelude::*;
+use gltf::json::accessor::{Accessor, ComponentType, GenericComponentType, Type};
+use gltf::json::mesh::{Primitive, Semantic};
+use gltf::json::validation::Checked;
+use gltf::json::{Index, Root};
 
+use crate::work_asset::transforms::{transform_point, Transform};
 use crate::{Fingerprint, Result};
 
 /// Computes a `Fingerprint` from a `Primitive`.
@@ -17,47 +19,62 @@ use crate::{Fingerprint, Result};
 /// We look at vertex positions and vertex colours, and simply add them up, with an added
 /// skew to the Y and Z dimensions, to break symmetries.
 ///
+/// The vertex data is read straight from the `Root`'s accessors and the blob, both borrowed,
+/// so that fingerprinting a large asset doesn't require a `gltf::Document` copy of it. If a
+/// `transform` is given, positions are transformed by it before they're added up, e.g. to
+/// compare a mesh as its node places it; see `MeldOptions::node_transforms`.
+///
 /// More complexity could be added here, if warranted.
-pub fn build_fingerprint(primitive: &Primitive, blob: &[u8]) -> Result<Fingerprint> {
-    let buf_to_blob = |buf: Buffer| {
-        assert_that(&buf.index()).is_equal_to(0);
-        if blob.is_empty() {
-            None
-        } else {
-            Some(blob)
-        }
-    };
-
-    let reader = primitive.reader(buf_to_blob);
+pub fn build_fingerprint(
+    root: &Root,
+    primitive: &Primitive,
+    blob: &[u8],
+    transform: Option<&Transform>,
+) -> Result<Fingerprint> {
+    let attribute = |semantic| primitive.attributes.get(&Checked::Valid(semantic));
 
-    let positions: Vec<[f32; 3]> = reader
-        .read_positions()
-        .ok_or(format!("Primitive lacks position data!"))?
-        .collect();
+    let positions = attribute(Semantic::Positions)
+        .ok_or(format!("Primitive lacks position data!"))
+        .and_then(|&ix| AccessorData::new(root, ix, blob))?;
+    if positions.component_type != ComponentType::F32 || positions.components != 3 {
+        return Err(format!("Primitive position data isn't VEC3 floats!"));
+    }
 
-    let indices: Vec<u32> = reader
-        .read_indices()
-        .ok_or(format!("Primitive lacks indices!"))?
-        .into_u32()
-        .collect();
+    let indices = primitive
+        .indices
+        .ok_or(format!("Primitive lacks indices!"))
+        .and_then(|ix| AccessorData::new(root, ix, blob))?;
+    let indices: Vec<usize> = (0..indices.count)
+        .map(|ix| indices.read_index(ix))
+        .collect::<Result<_>>()?;
 
     let count = indices.len() as f64;
 
     let mut cumulative_fingerprint = {
         let mut print: f64 = 0.0;
         for &ix in &indices {
-            print += vec3_to_print(positions[ix as usize]) / count;
+            print += vec3_to_print(read_position(&positions, ix, transform)?) / count;
         }
         print
     };
 
-    if let Some(colors) = reader.read_colors(0) {
-        let colors: Vec<[f32; 4]> = colors.into_rgba_f32().collect();
+    if let Some(&colors) = attribute(Semantic::Colors(0)) {
+        let colors = AccessorData::new(root, colors, blob)?;
+        let alpha = |ix| match colors.components {
+            3 => Ok(1.0),
+            _ => colors.read(ix, 3),
+        };
 
         cumulative_fingerprint += {
             let mut print: f64 = 0.0;
             for &ix in &indices {
-                print += vec4_to_print(colors[ix as usize]) / count;
+                let color = [
+                    colors.read(ix, 0)?,
+                    colors.read(ix, 1)?,
+                    colors.read(ix, 2)?,
+                    alpha(ix)?,
+                ];
+                print += vec4_to_print(color) / count;
             }
             print
         }
@@ -66,6 +83,102 @@ pub fn build_fingerprint(primitive: &Primitive, blob: &[u8]) -> Result<Fingerpri
     Ok(cumulative_fingerprint)
 }
 
+/// Computes a `Fingerprint` from a `Primitive` by hashing its vertices, quantized to a grid.
+///
+/// Every corner of every triangle contributes the hash of its vertex: the position, and the
+/// first colour & texture coordinates if there are any, with each component rounded to the
+/// nearest multiple of `grid`. The hashes are summed, which makes the result independent of
+/// triangle and vertex order, and even of whether triangles share vertices. Unlike with
+/// `build_fingerprint()`, two primitives only match if all their quantized vertices do, though
+/// a value that lies close to halfway between grid points may of course round either way.
+///
+/// Positions are transformed by `transform` before they're quantized, if it's given.
+///
+/// The hash is returned as an integral `Fingerprint`, so that distinct hashes are never taken
+/// to be almost equal.
+pub fn build_quantized_fingerprint(
+    root: &Root,
+    primitive: &Primitive,
+    blob: &[u8],
+    transform: Option<&Transform>,
+    grid: f32,
+) -> Result<Fingerprint> {
+    if grid.is_nan() || grid <= 0.0 {
+        return Err(format!("Bad fingerprint grid spacing: {}", grid));
+    }
+    let attribute = |semantic| primitive.attributes.get(&Checked::Valid(semantic));
+
+    let positions = attribute(Semantic::Positions)
+        .ok_or(format!("Primitive lacks position data!"))
+        .and_then(|&ix| AccessorData::new(root, ix, blob))?;
+    if positions.component_type != ComponentType::F32 || positions.components != 3 {
+        return Err(format!("Primitive position data isn't VEC3 floats!"));
+    }
+    let mut attributes = vec![];
+    for semantic in vec![Semantic::Colors(0), Semantic::TexCoords(0)] {
+        if let Some(&ix) = attribute(semantic) {
+            attributes.push(AccessorData::new(root, ix, blob)?);
+        }
+    }
+
+    let indices = primitive
+        .indices
+        .ok_or(format!("Primitive lacks indices!"))
+        .and_then(|ix| AccessorData::new(root, ix, blob))?;
+
+    let mut print: u64 = 0;
+    for element in 0..indices.count {
+        let ix = indices.read_index(element)?;
+        let mut hash = FNV_OFFSET_BASIS;
+        let mut quantize = |value: f32| {
+            let value = value as f64 / grid as f64;
+            for &byte in &(value.round() as i64).to_le_bytes() {
+                hash = (hash ^ byte as u64).wrapping_mul(FNV_PRIME);
+            }
+        };
+        for &value in &read_position(&positions, ix, transform)? {
+            quantize(value);
+        }
+        for data in &attributes {
+            for component in 0..data.components {
+                quantize(data.read(ix, component)?);
+            }
+        }
+        print = print.wrapping_add(mix(hash));
+    }
+    // keep the 52 bits an f64 can hold exactly
+    Ok((print >> 12) as f64)
+}
+
+// the parameters of 64-bit FNV-1a, which we hash quantized vertices with
+const FNV_OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+// scramble the bits of a hash, so that summing many of them doesn't cancel out structure; this
+// is the finalizer of SplitMix64
+fn mix(mut hash: u64) -> u64 {
+    hash = (hash ^ (hash >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    hash = (hash ^ (hash >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    hash ^ (hash >> 31)
+}
+
+// the position of a vertex, transformed if there's a transform to apply
+fn read_position(
+    positions: &AccessorData,
+    ix: usize,
+    transform: Option<&Transform>,
+) -> Result<[f32; 3]> {
+    let position = [
+        positions.read(ix, 0)?,
+        positions.read(ix, 1)?,
+        positions.read(ix, 2)?,
+    ];
+    Ok(match transform {
+        Some(transform) => transform_point(transform, position),
+        None => position,
+    })
+}
+
 fn vec3_to_print(vec: [f32; 3]) -> f64 {
     // arbitrary symmetry-breaking shear
     (vec[0] + 1.3 * vec[1] + 1.7 * vec[2]) as f64
@@ -75,3 +188,109 @@ fn vec4_to_print(vec: [f32; 4]) -> f64 {
     // arbitrary symmetry-breaking shear
     (vec[0] + 1.1 * vec[1] + 1.3 * vec[2] + 1.5 * vec[3]) as f64
 }
+
+// the elements of an accessor, as laid out in a borrowed slice of the blob
+struct AccessorData<'a> {
+    ix: usize,
+    bytes: &'a [u8],
+    count: usize,
+    stride: usize,
+    components: usize,
+    component_type: ComponentType,
+}
+
+impl<'a> AccessorData<'a> {
+    fn new(root: &Root, ix: Index<Accessor>, blob: &'a [u8]) -> Result<AccessorData<'a>> {
+        let accessor = root
+            .accessors
+            .get(ix.value())
+            .ok_or_else(|| format!("Primitive references non-existent accessor {}.", ix))?;
+        let (component_type, type_) = match (&accessor.component_type, &accessor.type_) {
+            (Checked::Valid(GenericComponentType(component_type)), Checked::Valid(type_)) => {
+                (*component_type, *type_)
+            }
+            _ => return Err(format!("Accessor {} has an invalid type.", ix)),
+        };
+        let components = match type_ {
+            Type::Scalar | Type::Vec2 | Type::Vec3 | Type::Vec4 => type_.multiplicity(),
+            _ => return Err(format!("Accessor {} holds matrices, not vectors.", ix)),
+        };
+
+        let view = accessor
+            .buffer_view
+            .and_then(|view_ix| root.buffer_views.get(view_ix.value()))
+            .ok_or_else(|| format!("Accessor {} lacks a valid buffer view.", ix))?;
+        if view.buffer.value() != 0 {
+            return Err(format!("Accessor {} is not backed by the blob.", ix));
+        }
+        let start = view.byte_offset.unwrap_or(0) as usize + accessor.byte_offset as usize;
+        let end = view.byte_offset.unwrap_or(0) as usize + view.byte_length as usize;
+
+        let element_size = components * component_type.size();
+        let stride = view
+            .byte_stride
+            .map_or(element_size, |stride| stride as usize);
+        let count = accessor.count as usize;
+        let needed = match count {
+            0 => 0,
+            _ => (count - 1) * stride + element_size,
+        };
+        let bytes = blob
+            .get(start..end)
+            .filter(|bytes| bytes.len() >= needed)
+            .ok_or_else(|| format!("Accessor {} reaches beyond its buffer view.", ix))?;
+
+        Ok(AccessorData {
+            ix: ix.value(),
+            bytes,
+            count,
+            stride,
+            components,
+            component_type,
+        })
+    }
+
+    // the raw bytes of one component of one element
+    fn component_bytes(&self, element: usize, component: usize) -> Result<&'a [u8]> {
+        if element >= self.count || component >= self.components {
+            return Err(format!(
+                "Element {} is out of range for accessor {}.",
+                element, self.ix
+            ));
+        }
+        let start = element * self.stride + component * self.component_type.size();
+        Ok(&self.bytes[start..start + self.component_type.size()])
+    }
+
+    // one component of one element, as a float; integers are assumed to be normalized
+    fn read(&self, element: usize, component: usize) -> Result<f32> {
+        let b = self.component_bytes(element, component)?;
+        Ok(match self.component_type {
+            ComponentType::F32 => f32::from_le_bytes([b[0], b[1], b[2], b[3]]),
+            ComponentType::U8 => b[0] as f32 / 255.0,
+            ComponentType::U16 => u16::from_le_bytes([b[0], b[1]]) as f32 / 65535.0,
+            _ => {
+                return Err(format!(
+                    "Accessor {} has unsupported component type {:?}.",
+                    self.ix, self.component_type
+                ))
+            }
+        })
+    }
+
+    // one element of a scalar accessor of vertex indices
+    fn read_index(&self, element: usize) -> Result<usize> {
+        let b = self.component_bytes(element, 0)?;
+        Ok(match self.component_type {
+            ComponentType::U8 => b[0] as usize,
+            ComponentType::U16 => u16::from_le_bytes([b[0], b[1]]) as usize,
+            ComponentType::U32 => u32::from_le_bytes([b[0], b[1], b[2], b[3]]) as usize,
+            _ => {
+                return Err(format!(
+                    "Accessor {} has unsupported index type {:?}.",
+                    self.ix, self.component_type
+                ))
+            }
+        })
+    }
+}
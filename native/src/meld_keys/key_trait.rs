@@ -1,6 +1,7 @@
 // Copyright (c) Facebook, Inc. and its affiliates. All Rights Reserved
 //
 
+use serde_json::Value;
 use sha1::Sha1;
 
 use gltf::json::texture;
@@ -8,6 +9,7 @@ use gltf::json::Mesh;
 use gltf::json::{material::NormalTexture, material::OcclusionTexture};
 use gltf::json::{texture::Sampler, Image, Index, Material, Texture};
 
+use crate::meld_keys::canonical_material;
 use crate::{MeldKey, Result, WorkAsset};
 
 /// A trait implemented on glTF objects for which we need a `MeldKey`.
@@ -26,18 +28,25 @@ impl HasKeyForVariants for Image {
     /// Example: "`daf12297c5c549fa199b85adbe77d626edc93184`"
     fn build_meld_key(&self, work_asset: &WorkAsset) -> Result<MeldKey> {
         let image_bytes = work_asset.read_image_bytes(self)?;
-        Ok(Sha1::from(image_bytes).digest().to_string())
+        Ok(Sha1::from(&image_bytes[..]).digest().to_string())
     }
 }
 
 impl HasKeyForVariants for Texture {
     /// The `MeldKey` of an `Texture` combines a `Sampler` and an `Image` `MeldKey`.
     ///
-    /// Example: "`[sampler=,source=daf12297c5c549fa199b85adbe77d626edc93184]`"
+    /// A texture without a sampler is keyed as if it used the default sampler, which is what
+    /// the glTF spec says it means.
+    ///
+    /// Example: "`[sampler=[mag_filter=None,min_filter=None,wrap_s=Repeat,wrap_t=Repeat],source=daf12297c5c549fa199b85adbe77d626edc93184]`"
     fn build_meld_key(&self, work_asset: &WorkAsset) -> Result<MeldKey> {
+        let sampler_key = match self.sampler {
+            Some(ix) => key(work_asset.sampler_keys(), ix),
+            None => sampler_key(&Sampler::default()),
+        };
         Ok(format!(
             "[sampler={},source={}]",
-            key_or_empty(work_asset.sampler_keys(), self.sampler),
+            sampler_key,
             key(work_asset.image_keys(), self.source),
         ))
     }
@@ -48,46 +57,66 @@ impl HasKeyForVariants for Sampler {
     ///
     /// Example: "`[mag_filter=None,min_filter=None,wrap_s=Repeat,wrap_t=Repeat]`"
     fn build_meld_key(&self, _work_asset: &WorkAsset) -> Result<MeldKey> {
-        Ok(format!(
-            "[mag_filter={:?},min_filter={:?},wrap_s={:?},wrap_t={:?}]",
-            self.mag_filter, self.min_filter, self.wrap_s, self.wrap_t
-        ))
+        Ok(sampler_key(self))
     }
 }
 
+/// The `MeldKey` of a `Sampler`, which (unlike other keys) does not depend on any other object.
+pub fn sampler_key(sampler: &Sampler) -> MeldKey {
+    format!(
+        "[mag_filter={:?},min_filter={:?},wrap_s={:?},wrap_t={:?}]",
+        sampler.mag_filter, sampler.min_filter, sampler.wrap_s, sampler.wrap_t
+    )
+}
+
 impl HasKeyForVariants for Material {
     /// The `MeldKey` of a `Material` combines `Texture` keys with its own many JSON attributes.
     ///
-    /// Example: "`[[pbr=[bcf=[1.0, 1.0, 1.0, 1.0], bct=[tc=0,src=[sampler=,source=49ff16b74ed7beabc95d49ef8a0f7615db949851]], mf=0.4, rf=0.6, mrt=[]], nt=[], ot=[], et=[], ef=[0.0, 0.0, 0.0], am=Opaque, ac=0.5, ds=false]`"
+    /// Example: "`[[pbr=[bcf=[1.00000, 1.00000, 1.00000, 1.00000], bct=[tc=0,src=[sampler=[mag_filter=None,min_filter=None,wrap_s=Valid(Repeat),wrap_t=Valid(Repeat)],source=49ff16b74ed7beabc95d49ef8a0f7615db949851]], mf=0.40000, rf=0.60000, mrt=[]], nt=[], ot=[], et=[], ef=[0.00000, 0.00000, 0.00000], am=Valid(Opaque), ac=0.50000, ds=false]`"
+    ///
+    /// The material is first brought into canonical form; see `canonical_material()`. Its
+    /// floating-point factors are then rounded according to `MeldOptions::float_precision`, or
+    /// for colour factors, possibly `MeldOptions::color_tolerance`.
     fn build_meld_key(&self, work_asset: &WorkAsset) -> Result<MeldKey> {
-        let pbr = &self.pbr_metallic_roughness;
+        let material = canonical_material(self);
+        let pbr = &material.pbr_metallic_roughness;
         Ok(format!(
-            "[[pbr=[bcf={:?}, bct={}, mf={:?}, rf={:?}, mrt={}], nt={}, ot={}, et={}, ef={:?}, am={:?}, ac={:?}, ds={}]",
-            pbr.base_color_factor,
+            "[[pbr=[bcf={}, bct={}, mf={}, rf={}, mrt={}], nt={}, ot={}, et={}, ef={}, am={:?}, ac={}, ds={}]",
+            colors_key(work_asset, &pbr.base_color_factor.0),
             key_for_texinfo(work_asset, &pbr.base_color_texture),
-            pbr.metallic_factor,
-            pbr.roughness_factor,
+            float_key(work_asset, pbr.metallic_factor.0),
+            float_key(work_asset, pbr.roughness_factor.0),
             key_for_texinfo(work_asset, &pbr.metallic_roughness_texture),
-            key_for_normal_texinfo(work_asset, &self.normal_texture),
-            key_for_occlusion_texinfo(work_asset, &self.occlusion_texture),
-            key_for_texinfo(work_asset, &self.emissive_texture),
-            self.emissive_factor,
-            self.alpha_mode,
-            self.alpha_cutoff,
-            self.double_sided,
+            key_for_normal_texinfo(work_asset, &material.normal_texture),
+            key_for_occlusion_texinfo(work_asset, &material.occlusion_texture),
+            key_for_texinfo(work_asset, &material.emissive_texture),
+            colors_key(work_asset, &material.emissive_factor.0),
+            material.alpha_mode,
+            float_key(work_asset, material.alpha_cutoff.0),
+            material.double_sided,
         ))
     }
 }
 
+/// The property of a mesh's `extras` in which export stamps the mesh's `MeldKey`.
+pub const MESH_ID_EXTRAS_KEY: &str = "meldMeshId";
+
 impl HasKeyForVariants for Mesh {
     /// The `MeldKey` of a `Mesh` is simply its name. This is probably a temporary solution.
     ///
     /// Example: "`polySurface12`"
     ///
+    /// Meshes of an asset we exported carry their key in `extras` (see `MESH_ID_EXTRAS_KEY`),
+    /// which takes precedence over the name; so a melded asset whose meshes are later renamed
+    /// still melds with new sources that use the original names.
+    ///
     /// Note: It'd be very, very convenient if we can match up meshes by name, because comparing
     /// them numerically is kind of a nightmare of fuzzy computational geometry. The question is if
     /// the tool can require users to control the glTF level name to the extend necessary.
     fn build_meld_key(&self, _work_asset: &WorkAsset) -> Result<MeldKey> {
+        if let Some(id) = stamped_mesh_id(self) {
+            return Ok(id);
+        }
         self.name
             .as_ref()
             .map(String::from)
@@ -95,6 +124,15 @@ impl HasKeyForVariants for Mesh {
     }
 }
 
+// the key stamped into a mesh's extras on export, if any
+fn stamped_mesh_id(mesh: &Mesh) -> Option<MeldKey> {
+    let extras: Value = serde_json::from_str(mesh.extras.as_ref()?.get()).ok()?;
+    extras
+        .get(MESH_ID_EXTRAS_KEY)
+        .and_then(Value::as_str)
+        .map(String::from)
+}
+
 fn key_for_texinfo(work_asset: &WorkAsset, texinfo: &Option<texture::Info>) -> MeldKey {
     if let Some(texinfo) = &texinfo {
         format!(
@@ -111,7 +149,7 @@ fn key_for_normal_texinfo(work_asset: &WorkAsset, texinfo: &Option<NormalTexture
     if let Some(texinfo) = &texinfo {
         format!(
             "[s={},tc={},src={}]",
-            texinfo.scale,
+            float_key(work_asset, texinfo.scale),
             texinfo.tex_coord,
             key(work_asset.texture_keys(), texinfo.index),
         )
@@ -126,8 +164,8 @@ fn key_for_occlusion_texinfo(
 ) -> MeldKey {
     if let Some(texinfo) = &texinfo {
         format!(
-            "[s={:?},tc={},src={}]",
-            texinfo.strength,
+            "[s={},tc={},src={}]",
+            float_key(work_asset, texinfo.strength.0),
             texinfo.tex_coord,
             key(work_asset.texture_keys(), texinfo.index),
         )
@@ -136,10 +174,36 @@ fn key_for_occlusion_texinfo(
     }
 }
 
-fn key_or_empty<T>(keys: &Vec<MeldKey>, ix: Option<Index<T>>) -> MeldKey {
-    match ix {
-        Some(ix) => key(keys, ix),
-        None => String::new(),
+// format a float rounded to the configured precision, or exactly if there is none
+fn float_key(work_asset: &WorkAsset, value: f32) -> MeldKey {
+    match work_asset.options().float_precision {
+        Some(precision) => {
+            let scale = 10f64.powi(precision as i32);
+            let rounded = (value as f64 * scale).round() / scale;
+            // adding positive zero turns any negative zero the rounding produced positive
+            format!("{:.*}", precision, rounded + 0.0)
+        }
+        None => format!("{:?}", value),
+    }
+}
+
+fn floats_key(work_asset: &WorkAsset, values: &[f32]) -> MeldKey {
+    let keys: Vec<MeldKey> = values.iter().map(|&v| float_key(work_asset, v)).collect();
+    format!("[{}]", keys.join(", "))
+}
+
+// format colour channels on the configured tolerance grid, if any, else like other floats; as
+// keys only ever compare equal or not, values either side of a cell boundary never match
+fn colors_key(work_asset: &WorkAsset, values: &[f32]) -> MeldKey {
+    match work_asset.options().color_tolerance {
+        Some(tolerance) => {
+            let keys: Vec<MeldKey> = values
+                .iter()
+                .map(|&v| format!("~{}", (v / tolerance).round() as i64))
+                .collect();
+            format!("[{}]", keys.join(", "))
+        }
+        None => floats_key(work_asset, values),
     }
 }
 
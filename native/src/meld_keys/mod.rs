@@ -2,10 +2,13 @@
 //
 
 mod key_trait;
-pub use key_trait::HasKeyForVariants;
+pub use key_trait::{sampler_key, HasKeyForVariants, MESH_ID_EXTRAS_KEY};
+
+mod canonical;
+pub use canonical::canonical_material;
 
 mod fingerprints;
-pub use fingerprints::build_fingerprint;
+pub use fingerprints::{build_fingerprint, build_quantized_fingerprint};
 
 /// A short string that uniquely identifies all glTF objects other than `Mesh` `Primitives`.
 pub type MeldKey = String;
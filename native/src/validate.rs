@@ -0,0 +1,187 @@
+// Copyright (c) Facebook, Inc. and its affiliates. All Rights Reserved
+//
+
+//! A post-export check of GLB bytes, an embedded subset of what the official
+//! [glTF-Validator](https://github.com/KhronosGroup/glTF-Validator) reports as errors.
+//!
+//! This is no replacement for the real validator; it looks only for the kind of problem that
+//! makes viewers reject an asset outright: a broken GLB container, invalid indices, buffers and
+//! buffer views that don't fit the binary chunk, accessors that overrun their buffer views, and
+//! malformed `KHR_materials_variants` data.
+
+use gltf::json::validation::{Checked, Validate};
+use gltf::json::{Path, Root};
+
+use crate::extension::{extract_variant_map, get_variant_lookup};
+use crate::gltfext::check_buffer_view_bounds;
+use crate::work_asset::describe_object;
+use crate::{GlbChunk, Result};
+
+/// Checks the given GLB bytes for errors, returning a description of each one found.
+///
+/// An empty result means the asset passed. Some errors, e.g. a corrupt GLB container or JSON
+/// that doesn't parse, make further checks pointless; they're then the only one reported.
+pub fn validate_glb(glb: &[u8]) -> Vec<String> {
+    let mut errors = vec![];
+    if let Err(e) = check_glb(glb, &mut errors) {
+        errors.push(e);
+    }
+    errors
+}
+
+// collect every error we can find, returning early only on those that stop us in our tracks
+fn check_glb(glb: &[u8], errors: &mut Vec<String>) -> Result<()> {
+    let (json, blob) = match GlbChunk::from_bytes(glb)? {
+        Some((GlbChunk::JSON(json), Some(GlbChunk::BIN(bin)))) => (json, bin),
+        Some((GlbChunk::JSON(json), _)) => (json, &[][..]),
+        _ => return Err(format!("Not a GLB file.")),
+    };
+    let root: Root =
+        serde_json::from_slice(json).map_err(|e| format!("Bad glTF JSON in GLB: {}", e))?;
+
+    root.validate(&root, Path::new, &mut |path, error| {
+        errors.push(format!("{}: {}", path(), error))
+    });
+    if !errors.is_empty() {
+        // the checks below assume valid indices
+        return Ok(());
+    }
+
+    check_buffers(&root, blob, errors);
+    if let Err(e) = check_buffer_view_bounds(&root, blob) {
+        errors.push(e);
+    }
+    check_accessors(&root, errors);
+    check_images(&root, errors);
+    check_extensions(&root, errors);
+    check_variants(&root, errors);
+    Ok(())
+}
+
+// the first buffer, and only the first, may be backed by the BIN chunk, which it must fit
+fn check_buffers(root: &Root, blob: &[u8], errors: &mut Vec<String>) {
+    for (ix, buffer) in root.buffers.iter().enumerate() {
+        if buffer.uri.is_some() {
+            continue;
+        }
+        if ix > 0 {
+            errors.push(format!(
+                "Buffer {} has no URI, but isn't the first buffer.",
+                ix
+            ));
+            continue;
+        }
+        let length = buffer.byte_length as usize;
+        // the chunk may hold up to 3 bytes of padding beyond the buffer
+        if length > blob.len() || blob.len() - length > 3 {
+            errors.push(format!(
+                "Buffer 0 is {} bytes long, but the BIN chunk holds {}.",
+                length,
+                blob.len()
+            ));
+        }
+    }
+}
+
+// every accessor's elements must lie within its buffer view
+fn check_accessors(root: &Root, errors: &mut Vec<String>) {
+    for (ix, accessor) in root.accessors.iter().enumerate() {
+        let (view_ix, component_type, type_) = match (
+            accessor.buffer_view,
+            &accessor.component_type,
+            &accessor.type_,
+        ) {
+            (Some(view_ix), Checked::Valid(component_type), Checked::Valid(type_)) => {
+                (view_ix, component_type.0, type_)
+            }
+            _ => continue,
+        };
+        if accessor.count == 0 {
+            continue;
+        }
+        let view = &root.buffer_views[view_ix.value()];
+        // we ignore the column padding of small matrices, so this is if anything an underestimate
+        let element_size = component_type.size() * type_.multiplicity();
+        let stride = view
+            .byte_stride
+            .map_or(element_size, |stride| stride as usize);
+        // a hostile count or offset may not fit in a usize, least of all on wasm32
+        let end = stride
+            .checked_mul(accessor.count as usize - 1)
+            .and_then(|span| span.checked_add(accessor.byte_offset as usize))
+            .and_then(|span| span.checked_add(element_size));
+        let end = match end {
+            Some(end) => end,
+            None => {
+                errors.push(format!(
+                    "Accessor {} ends past any addressable byte of buffer view {}.",
+                    ix, view_ix
+                ));
+                continue;
+            }
+        };
+        if end > view.byte_length as usize {
+            errors.push(format!(
+                "Accessor {} ends at byte {} of buffer view {}, of length {}.",
+                ix, end, view_ix, view.byte_length
+            ));
+        }
+    }
+}
+
+// an image stored in a buffer view must say what kind of image it is
+fn check_images(root: &Root, errors: &mut Vec<String>) {
+    for (ix, image) in root.images.iter().enumerate() {
+        if image.buffer_view.is_some() && image.mime_type.is_none() {
+            errors.push(format!(
+                "{} has a buffer view, but no MIME type.",
+                describe_object("Image", ix, image.name.as_ref())
+            ));
+        }
+    }
+}
+
+// every required extension must also be declared as used
+fn check_extensions(root: &Root, errors: &mut Vec<String>) {
+    for extension in &root.extensions_required {
+        if !root.extensions_used.contains(extension) {
+            errors.push(format!(
+                "Extension {} is required, but not declared as used.",
+                extension
+            ));
+        }
+    }
+}
+
+// variant mappings must reference existing variants and materials
+fn check_variants(root: &Root, errors: &mut Vec<String>) {
+    let lookup = match get_variant_lookup(root) {
+        Ok(lookup) => lookup,
+        Err(e) => {
+            errors.push(e);
+            return;
+        }
+    };
+    for (m_ix, mesh) in root.meshes.iter().enumerate() {
+        let mesh_name = describe_object("Mesh", m_ix, mesh.name.as_ref());
+        for (p_ix, primitive) in mesh.primitives.iter().enumerate() {
+            let map = match extract_variant_map(primitive, &lookup) {
+                Ok(map) => map,
+                Err(e) => {
+                    errors.push(format!("{}, primitive {}: {}", mesh_name, p_ix, e));
+                    continue;
+                }
+            };
+            let mut map: Vec<_> = map.into_iter().collect();
+            map.sort_unstable();
+            for (tag, material_ix) in map {
+                if material_ix >= root.materials.len() {
+                    errors.push(format!(
+                        "{}, primitive {}: variant {} maps to non-existent material {}.",
+                        mesh_name, p_ix, tag, material_ix
+                    ));
+                }
+            }
+        }
+    }
+}
@@ -1,28 +1,94 @@
 // Copyright (c) Facebook, Inc. and its affiliates. All Rights Reserved
 //
 
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, BTreeSet};
 
 extern crate wasm_bindgen;
 use wasm_bindgen::prelude::*;
 
 use serde_derive::{Deserialize, Serialize};
-use serde_json::json;
+use serde_json::{json, value::RawValue, Map, Value};
 
-use crate::{AssetSizes, Tag};
+use gltf::json::Root;
+use gltf::Document;
+
+use crate::axes::{self, AxisValues};
+use crate::extension;
+use crate::glb::GlbChunk;
+use crate::{AssetSizes, DedupeSummary, ImageInfo, ObjectCounts, Result, Tag, TagDisplay};
+
+// the property of the glTF root `extras` object that holds embedded metadata
+const EMBEDDED_METADATA_KEY: &str = "glTFVariantMeld";
 
 /// All the metadata generated for a variational asset.
+///
+/// Its collections are ordered, so that it serializes to the same JSON every time.
 #[wasm_bindgen]
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Metadata {
     /// The set of variational tags in this asset.
-    pub(crate) tags: HashSet<Tag>,
+    pub(crate) tags: BTreeSet<Tag>,
+    /// For structured tags, the set of values that occur along each axis.
+    #[serde(default)]
+    pub(crate) axes: BTreeMap<String, BTreeSet<String>>,
+    /// How to present tags to end users, for those tags it's been specified for.
+    #[serde(default)]
+    pub(crate) tag_displays: BTreeMap<Tag, TagDisplay>,
     /// The sum byte size of **every** referenced texture in this asset.
     pub(crate) total_sizes: AssetSizes,
     /// The sum byte size of textures that are referenced depending on active variant tag.
     pub(crate) variational_sizes: AssetSizes,
     // The sum byte size of textures active under each variant tag specifically.
-    pub(crate) per_tag_sizes: HashMap<Tag, AssetSizes>,
+    pub(crate) per_tag_sizes: BTreeMap<Tag, AssetSizes>,
+    /// The sum byte size of textures that are active under each variant tag, and no other.
+    #[serde(default)]
+    pub(crate) per_tag_exclusive_sizes: BTreeMap<Tag, AssetSizes>,
+    /// The indices of the images that are used by each variant tag's materials, and no other's.
+    #[serde(default)]
+    pub(crate) per_tag_exclusive_images: BTreeMap<Tag, Vec<usize>>,
+    /// The indices of the materials that are referenced by each variant tag, and no other.
+    #[serde(default)]
+    pub(crate) per_tag_exclusive_materials: BTreeMap<Tag, Vec<usize>>,
+    /// The number of dead bytes dropped from the binary blob by compaction during export.
+    pub(crate) blob_bytes_saved: usize,
+    /// The number of materials, textures, images and samplers in this asset.
+    pub(crate) counts: ObjectCounts,
+    /// How well texture image bytes are shared between tags.
+    #[serde(default)]
+    pub(crate) dedupe: DedupeSummary,
+    /// The dimensions & format of each referenced image; requires the `image` feature.
+    #[serde(default)]
+    pub(crate) images: Vec<ImageInfo>,
+}
+
+/// How one asset's metadata differs from another's, e.g. a new meld from the previously published
+/// asset; see `Metadata::diff()`.
+///
+/// Byte deltas are signed: positive where the newer asset is the larger.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct MetadataDelta {
+    /// The tags the newer asset has, and the older one doesn't, in order.
+    pub tags_added: Vec<Tag>,
+    /// The tags the older asset has, and the newer one doesn't, in order.
+    pub tags_removed: Vec<Tag>,
+    /// The change in the byte size of **every** referenced texture.
+    pub texture_bytes: i64,
+    /// The change in the byte size of textures that are referenced depending on variant tag.
+    pub variational_texture_bytes: i64,
+    /// The change in the texture byte size of each tag of either asset; a tag that is missing
+    /// from one of them counts as zero bytes there.
+    pub per_tag_texture_bytes: BTreeMap<Tag, i64>,
+}
+
+impl MetadataDelta {
+    /// Whether the two assets' tags & texture sizes are all the same.
+    pub fn is_empty(&self) -> bool {
+        self.tags_added.is_empty()
+            && self.tags_removed.is_empty()
+            && self.texture_bytes == 0
+            && self.variational_texture_bytes == 0
+            && self.per_tag_texture_bytes.values().all(|&delta| delta == 0)
+    }
 }
 
 // methods that are already happily wasm_bind compliant
@@ -37,19 +103,107 @@ impl Metadata {
     pub fn variational_sizes(&self) -> AssetSizes {
         self.variational_sizes
     }
+
+    /// The number of dead bytes dropped from the binary blob by compaction during export.
+    pub fn blob_bytes_saved(&self) -> usize {
+        self.blob_bytes_saved
+    }
+
+    /// The number of materials, textures, images and samplers in this asset.
+    pub fn counts(&self) -> ObjectCounts {
+        self.counts
+    }
 }
 
 // methods that wasm_bindgen can't cope with in their preferred form
 impl Metadata {
     /// The set of variational tags in this asset.
-    pub fn tags(&self) -> &HashSet<Tag> {
+    pub fn tags(&self) -> &BTreeSet<Tag> {
         &self.tags
     }
 
+    /// For each axis of the asset's structured tags, the values that occur along it.
+    ///
+    /// Plain tags don't contribute; see the `axes` module for details.
+    pub fn axes(&self) -> &BTreeMap<String, BTreeSet<String>> {
+        &self.axes
+    }
+
+    /// The structured tag for the given combination of axis values, if the asset has it.
+    pub fn axis_tag(&self, values: &AxisValues) -> Option<&Tag> {
+        self.tags.get(&axes::axis_tag(values))
+    }
+
+    /// How the given tag is to be presented to end users, if that's been specified.
+    pub fn tag_display(&self, tag: &Tag) -> Option<&TagDisplay> {
+        self.tag_displays.get(tag)
+    }
+
     /// The asset sizes associated with the given tag, if any.
     pub fn tag_sizes(&self, tag: &Tag) -> Option<&AssetSizes> {
         self.per_tag_sizes.get(tag)
     }
+
+    /// The asset sizes exclusive to the given tag, if any; i.e. what it'd save to drop the tag.
+    pub fn tag_exclusive_sizes(&self, tag: &Tag) -> Option<&AssetSizes> {
+        self.per_tag_exclusive_sizes.get(tag)
+    }
+
+    /// The indices of the images only the given tag's materials use, if it's a tag of ours.
+    ///
+    /// These are what dropping the tag would save; see `tag_exclusive_sizes()` for their sum.
+    pub fn exclusive_images(&self, tag: &Tag) -> Option<&[usize]> {
+        self.per_tag_exclusive_images.get(tag).map(Vec::as_slice)
+    }
+
+    /// The indices of the materials only the given tag references, if it's a tag of ours.
+    pub fn exclusive_materials(&self, tag: &Tag) -> Option<&[usize]> {
+        self.per_tag_exclusive_materials.get(tag).map(Vec::as_slice)
+    }
+
+    /// How well texture image bytes are shared between tags, or fail to be.
+    pub fn dedupe(&self) -> &DedupeSummary {
+        &self.dedupe
+    }
+
+    /// The dimensions & format of each image referenced by a material, in order of index.
+    ///
+    /// This is read from the image headers, without decoding any pixels, and requires building
+    /// with the `image` feature; without it, or for formats other than PNG & JPEG, the image is
+    /// left out.
+    pub fn images(&self) -> &[ImageInfo] {
+        &self.images
+    }
+
+    /// How this metadata differs from that of some *earlier* asset, e.g. what a meld changed.
+    pub fn diff(&self, earlier: &Metadata) -> MetadataDelta {
+        let delta = |now: usize, then: usize| now as i64 - then as i64;
+        let tag_bytes = |metadata: &Metadata, tag: &Tag| {
+            metadata
+                .tag_sizes(tag)
+                .map_or(0, |sizes| sizes.texture_bytes)
+        };
+        MetadataDelta {
+            tags_added: self.tags.difference(&earlier.tags).cloned().collect(),
+            tags_removed: earlier.tags.difference(&self.tags).cloned().collect(),
+            texture_bytes: delta(
+                self.total_sizes.texture_bytes,
+                earlier.total_sizes.texture_bytes,
+            ),
+            variational_texture_bytes: delta(
+                self.variational_sizes.texture_bytes,
+                earlier.variational_sizes.texture_bytes,
+            ),
+            per_tag_texture_bytes: self
+                .tags
+                .union(&earlier.tags)
+                .map(|tag| {
+                    let bytes = delta(tag_bytes(self, tag), tag_bytes(earlier, tag));
+                    (tag.to_owned(), bytes)
+                })
+                .collect(),
+        }
+    }
 }
 
 #[wasm_bindgen]
@@ -59,8 +213,167 @@ impl Metadata {
         json!(self.tags).to_string()
     }
 
+    /// WASM-friendly version of `axes()`; returns a JSON-encoded map of axes to value arrays.
+    pub fn wasm_axes(&self) -> String {
+        json!(self.axes).to_string()
+    }
+
+    /// WASM-friendly version of `tag_exclusive_sizes()`; returns a JSON-encoded map.
+    pub fn wasm_tag_exclusive_sizes(&self) -> String {
+        json!(self.per_tag_exclusive_sizes).to_string()
+    }
+
+    /// WASM-friendly version of `exclusive_images()`; returns a JSON-encoded map of tags to
+    /// image indices.
+    pub fn wasm_exclusive_images(&self) -> String {
+        json!(self.per_tag_exclusive_images).to_string()
+    }
+
+    /// WASM-friendly version of `exclusive_materials()`; returns a JSON-encoded map of tags to
+    /// material indices.
+    pub fn wasm_exclusive_materials(&self) -> String {
+        json!(self.per_tag_exclusive_materials).to_string()
+    }
+
+    /// WASM-friendly version of `tag_display()`; returns a JSON-encoded map of tags to displays.
+    pub fn wasm_tag_displays(&self) -> String {
+        json!(self.tag_displays).to_string()
+    }
+
+    /// WASM-friendly version of `images()`; returns a JSON-encoded array of objects.
+    pub fn wasm_images(&self) -> String {
+        json!(self.images).to_string()
+    }
+
+    /// WASM-friendly version of `dedupe()`; returns a JSON-encoded object.
+    pub fn wasm_dedupe(&self) -> String {
+        json!(self.dedupe).to_string()
+    }
+
     /// WASM-friendly version of `tags()`; returns a JSON-encoded map of tags to sizes.
     pub fn wasm_tag_sizes(&self) -> String {
         json!(self.per_tag_sizes).to_string()
     }
 }
+
+// embedding of metadata into the exported glTF itself
+impl Metadata {
+    /// Writes this metadata, along with the asset's default tag, into the root `extras`.
+    ///
+    /// Any other properties in `extras` are left alone.
+    pub(crate) fn embed(&self, root: &mut Root, default_tag: &Tag) -> Result<()> {
+        let mut extras = root_extras(root)?;
+        extras.insert(
+            EMBEDDED_METADATA_KEY.to_owned(),
+            json!({ "defaultTag": default_tag, "metadata": self }),
+        );
+        set_root_extras(root, extras)
+    }
+
+    /// Removes any embedded metadata from the root `extras`, since it'd go stale if edited.
+    pub(crate) fn strip_embedded(root: &mut Root) -> Result<()> {
+        let mut extras = root_extras(root)?;
+        if extras.remove(EMBEDDED_METADATA_KEY).is_some() {
+            set_root_extras(root, extras)?;
+        }
+        Ok(())
+    }
+
+    /// Reads back the default tag and metadata embedded in GLB bytes by `embed()`, if any.
+    ///
+    /// Other tools may well edit an asset but keep its `extras`, so the metadata is only
+    /// returned if the GLB is sound, and still agrees with its glTF; otherwise, it's as if
+    /// there were none.
+    pub(crate) fn read_embedded(glb: &[u8]) -> Result<Option<(Tag, Metadata)>> {
+        let (json, bin) = match GlbChunk::from_bytes(glb) {
+            Ok(Some((GlbChunk::JSON(json), bin))) => (json, bin),
+            _ => return Ok(None),
+        };
+        // the header's length must be that of the whole file
+        let mut declared = [0u8; 4];
+        declared.copy_from_slice(&glb[8..12]);
+        if u32::from_le_bytes(declared) as usize != glb.len() {
+            return Ok(None);
+        }
+        let root: Value = serde_json::from_slice(json)
+            .map_err(|e| format!("Bad JSON in GLB: {}", e.to_string()))?;
+        let (tag, metadata) = match root
+            .get("extras")
+            .and_then(|e| e.get(EMBEDDED_METADATA_KEY))
+        {
+            Some(embedded) => {
+                let tag = embedded.get("defaultTag").and_then(Value::as_str);
+                let metadata = embedded
+                    .get("metadata")
+                    .cloned()
+                    .map(serde_json::from_value);
+                match (tag, metadata) {
+                    (Some(tag), Some(Ok(metadata))) => (tag.to_owned(), metadata),
+                    _ => return Err(format!("Bad embedded metadata: {}", embedded)),
+                }
+            }
+            None => return Ok(None),
+        };
+
+        let root: Root = match serde_json::from_value(root) {
+            Ok(root) => root,
+            Err(_) => return Ok(None),
+        };
+        let root = match Document::from_json(root) {
+            Ok(document) => document.into_json(),
+            Err(_) => return Ok(None),
+        };
+        let bin_len = match bin {
+            Some(GlbChunk::BIN(bytes)) => bytes.len(),
+            _ => 0,
+        };
+        if !metadata.agrees_with(&root, bin_len, &tag) {
+            return Ok(None);
+        }
+        Ok(Some((tag, metadata)))
+    }
+
+    // whether the metadata could have been computed from the given glTF, with a BIN chunk of the
+    // given length; what we export is self-contained, so external buffers & images mean edits
+    fn agrees_with(&self, root: &Root, bin_len: usize, default_tag: &Tag) -> bool {
+        let self_contained = root.buffers.len() == 1
+            && root.buffers[0].uri.is_none()
+            && root.buffers[0].byte_length as usize <= bin_len
+            && root.images.iter().all(|image| image.uri.is_none());
+        let counts = &self.counts;
+        let same_counts = counts.materials == root.materials.len()
+            && counts.textures == root.textures.len()
+            && counts.images == root.images.len()
+            && counts.samplers == root.samplers.len();
+        // the default tag is only listed among the variants if anything varies
+        let variants: BTreeSet<Tag> = match extension::get_variant_lookup(root) {
+            Ok(lookup) => lookup.into_iter().map(|(_, tag)| tag).collect(),
+            Err(_) => return false,
+        };
+        let same_tags = self
+            .tags
+            .iter()
+            .all(|tag| tag == default_tag || variants.contains(tag));
+        self_contained && same_counts && same_tags
+    }
+}
+
+fn root_extras(root: &Root) -> Result<Map<String, Value>> {
+    match &root.extras {
+        Some(raw) => match serde_json::from_str(raw.get()) {
+            Ok(Value::Object(map)) => Ok(map),
+            _ => Err(format!("Root extras is not a JSON object: {}", raw.get())),
+        },
+        None => Ok(Map::new()),
+    }
+}
+
+fn set_root_extras(root: &mut Root, extras: Map<String, Value>) -> Result<()> {
+    root.extras = if extras.is_empty() {
+        None
+    } else {
+        let json = Value::Object(extras).to_string();
+        Some(RawValue::from_string(json).map_err(|e| format!("Bad root extras: {}", e))?)
+    };
+    Ok(())
+}
@@ -1,18 +1,44 @@
 // Copyright (c) Facebook, Inc. and its affiliates. All Rights Reserved
 //
 
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap};
 use std::path::Path;
+use std::sync::Arc;
 
 extern crate wasm_bindgen;
 use wasm_bindgen::prelude::*;
 
 use serde_derive::{Deserialize, Serialize};
+use serde_json::Value;
 
-use crate::{Error, Tag, WorkAsset};
+use gltf::json::{Material, Root};
+use gltf::Gltf;
+
+use crate::extension;
+use crate::glb::GlbChunk;
+use crate::work_asset::memory::MemoryBudget;
+use crate::work_asset::timings::PhaseTimer;
+use crate::{
+    DracoSettings, Error, MaterialOverrides, MeldCache, MeldDiagnosis, MeldEstimate, MeldOptions,
+    MeldStats, PrimitiveCorrespondence, SharingAnalysis, Tag, TagDisplay, TagPayload, WorkAsset,
+};
 
 /// The Metadata struct & accessor methods
 pub mod metadata;
-pub use metadata::Metadata;
+pub use metadata::{Metadata, MetadataDelta};
+
+/// The VariantTable type, a resolved view of per-primitive variant mappings
+pub mod variant_table;
+pub use variant_table::{VariantMaterial, VariantTable};
+
+/// The TagDisplay struct, describing how to present a tag to end users
+pub mod tag_display;
+pub use tag_display::TagDisplay;
+
+/// The TagPayload type, small pieces of caller data attached to a tag
+pub mod tag_payload;
+pub use tag_payload::TagPayload;
 
 /// Compatibility methods for the WebAssembly build
 pub mod wasm;
@@ -40,11 +66,13 @@ pub mod wasm;
 ///      Some(&shiny_tag),
 ///   ).expect("Eek! Couldn't create shiny pinecone VariationalAsset.");
 ///
-///   let result = VariationalAsset::meld(
+///   let (result, stats) = VariationalAsset::meld(
 ///     &pinecone_matte,
 ///     &pinecone_shiny
 ///   ).expect("Erk. Failed to meld two pinecones.");
 ///
+///   assert_eq!(stats.tags_added, vec![shiny_tag.clone()]);
+///
 ///   assert!(result.metadata().tags().contains(&matte_tag));
 ///   assert!(result.metadata().tags().contains(&shiny_tag));
 ///   assert_eq!(result.metadata().tags().len(), 2);
@@ -61,6 +89,15 @@ pub struct VariationalAsset {
 
     /// All the metadata generated for this asset.
     pub(crate) metadata: Metadata,
+
+    /// Any files the `MeldOptions::variant_exporters` wrote alongside the glTF, by file name.
+    pub(crate) sidecars: BTreeMap<String, Vec<u8>>,
+
+    /// Warnings about how the files of the source asset were found; see `load_warnings()`.
+    pub(crate) load_warnings: Vec<String>,
+
+    /// The `WorkAsset` this asset was exported from, if it was kept; see `into_exported()`.
+    pub(crate) work_asset: Option<Arc<WorkAsset>>,
 }
 
 /// A summary of a mesh primitive's byte size requirements; currently textures only.
@@ -71,6 +108,72 @@ pub struct AssetSizes {
     pub texture_bytes: usize,
 }
 
+/// The dimensions & format of a texture image, as read from its header; see `Metadata::images()`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ImageInfo {
+    /// The index of the image in the exported asset.
+    pub image: usize,
+    /// The width of the image, in pixels.
+    pub width: u32,
+    /// The height of the image, in pixels.
+    pub height: u32,
+    /// The number of colour channels, e.g. 4 for RGBA.
+    pub channels: u8,
+    /// The format the image is encoded in, e.g. "png" or "jpeg".
+    pub format: String,
+    /// The byte size of the image, in its encoded form.
+    pub bytes: usize,
+    /// The tags whose materials use the image, in order.
+    pub tags: Vec<Tag>,
+}
+
+impl ImageInfo {
+    /// Whether both dimensions are powers of two, which some runtimes need e.g. for mipmapping.
+    pub fn is_power_of_two(&self) -> bool {
+        self.width.is_power_of_two() && self.height.is_power_of_two()
+    }
+
+    /// Whether either dimension exceeds the given number of pixels.
+    pub fn exceeds(&self, max_dimension: u32) -> bool {
+        self.width > max_dimension || self.height > max_dimension
+    }
+}
+
+/// How well an asset's texture image bytes are shared between its variant tags.
+///
+/// Every referenced image counts towards exactly one of `shared_bytes` and `exclusive_bytes`,
+/// or neither, if no tag uses it. An asset whose bytes are mostly exclusive may be exactly as
+/// intended, e.g. for colour variants; but it may also mean that the source assets differ in
+/// ways that defeat the melder.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct DedupeSummary {
+    /// For each number of tags, the byte size of the images used by exactly that many tags.
+    pub bytes_by_tag_count: BTreeMap<usize, usize>,
+    /// The byte size of images that are used by more than one tag.
+    pub shared_bytes: usize,
+    /// The byte size of images that are used by just one tag.
+    pub exclusive_bytes: usize,
+    /// The byte size of extra copies of images: those whose bytes are identical to another
+    /// image's, yet are stored separately, because the objects that use them never melded.
+    pub duplicated_bytes: usize,
+}
+
+/// A count of the glTF objects in an asset, to track its complexity beyond raw byte sizes.
+#[wasm_bindgen]
+#[derive(Debug, Copy, Clone, Default, Serialize, Deserialize)]
+pub struct ObjectCounts {
+    /// The number of materials in the asset.
+    pub materials: usize,
+    /// The number of materials that are referenced depending on active variant tag.
+    pub variational_materials: usize,
+    /// The number of textures in the asset.
+    pub textures: usize,
+    /// The number of images in the asset.
+    pub images: usize,
+    /// The number of samplers in the asset.
+    pub samplers: usize,
+}
+
 // methods that wasm_bindgen can't cope with in their preferred form
 impl VariationalAsset {
     /// Generates a new `VariationalAsset` from a glTF file.
@@ -83,7 +186,35 @@ impl VariationalAsset {
     /// what's in the asset, or else be empty.
     pub fn from_file(file: &Path, default_tag: Option<&Tag>) -> Result<VariationalAsset, Error> {
         let loaded = WorkAsset::from_file(file, default_tag)?;
-        loaded.export()
+        loaded.into_exported()
+    }
+
+    /// Generates a new `VariationalAsset` from a glTF file, consulting the given `MeldCache`.
+    ///
+    /// Behaves exactly like `from_file()`, but skips recomputing meld keys and fingerprints
+    /// for source content the cache has seen before.
+    pub fn from_file_with_cache(
+        file: &Path,
+        default_tag: Option<&Tag>,
+        cache: &MeldCache,
+    ) -> Result<VariationalAsset, Error> {
+        let loaded = WorkAsset::from_file_with_cache(file, default_tag, Some(cache))?;
+        loaded.into_exported()
+    }
+
+    /// Generates a new `VariationalAsset` from a glTF file, with the given options, consulting
+    /// the `MeldCache` if one is given.
+    ///
+    /// Given `MeldOptions::keep_work_assets`, a later meld with the same options uses the asset
+    /// as it's loaded here. See `from_file()` for details.
+    pub fn from_file_with_options(
+        file: &Path,
+        default_tag: Option<&Tag>,
+        cache: Option<&MeldCache>,
+        options: &MeldOptions,
+    ) -> Result<VariationalAsset, Error> {
+        let loaded = WorkAsset::from_file_with_options(file, default_tag, cache, options)?;
+        loaded.into_exported()
     }
 
     /// Generates a new `VariationalAsset` from a byte slice of glTF.
@@ -94,13 +225,62 @@ impl VariationalAsset {
     /// If the asset doesn't implement `KHR_materials_variants`, then the argument
     /// `default_tag` must be non-empty. If it does, then `default_tag` must either match
     /// what's in the asset, or else be empty.
+    ///
+    /// If the glTF is the GLB of a previous export with `MeldOptions::embed_metadata` set, and
+    /// its embedded default tag agrees with `default_tag`, it's used as-is, with no need to
+    /// recompute its metadata; that is, unless the GLB looks to have been edited since, e.g. if
+    /// its object counts no longer match those of the metadata.
     pub fn from_slice(
         gltf: &[u8],
         default_tag: Option<&Tag>,
         base_dir: Option<&Path>,
     ) -> Result<VariationalAsset, Error> {
-        let loaded = WorkAsset::from_slice(gltf, default_tag, base_dir)?;
-        loaded.export()
+        Self::from_slice_with_options(gltf, default_tag, base_dir, &MeldOptions::default())
+    }
+
+    /// Generates a new `VariationalAsset` from a byte slice of glTF, with the given options.
+    ///
+    /// This is how to apply `MeldOptions::input_limits` to glTF from an untrusted source; an
+    /// asset that exceeds them yields an error that begins with `INPUT_LIMIT_EXCEEDED`. Embedded
+    /// metadata is only taken on trust with the default options, as any others could make for a
+    /// different asset. See `from_slice()` for details.
+    pub fn from_slice_with_options(
+        gltf: &[u8],
+        default_tag: Option<&Tag>,
+        base_dir: Option<&Path>,
+        options: &MeldOptions,
+    ) -> Result<VariationalAsset, Error> {
+        // any other options could well make for a different asset than the one that was embedded
+        if *options == MeldOptions::default() {
+            if let Some((embedded_tag, metadata)) = Metadata::read_embedded(gltf)? {
+                if default_tag.map_or(true, |tag| *tag == embedded_tag) {
+                    return Ok(VariationalAsset {
+                        glb: gltf.to_vec(),
+                        default_tag: embedded_tag,
+                        metadata,
+                        sidecars: BTreeMap::new(),
+                        load_warnings: vec![],
+                        work_asset: None,
+                    });
+                }
+            }
+        }
+        let loaded = WorkAsset::from_slice_with_options(gltf, default_tag, base_dir, options)?;
+        loaded.into_exported()
+    }
+
+    /// Generates a new `VariationalAsset` from a glTF already parsed with the `gltf` crate.
+    ///
+    /// This spares applications that hold a `Gltf` anyway, e.g. for rendering a preview, the
+    /// cost of a second parse. The arguments otherwise work as for `from_slice()`.
+    pub fn from_gltf(
+        gltf: Gltf,
+        default_tag: Option<&Tag>,
+        base_dir: Option<&Path>,
+    ) -> Result<VariationalAsset, Error> {
+        let options = &MeldOptions::default();
+        let loaded = WorkAsset::from_gltf(gltf, default_tag, base_dir, options)?;
+        loaded.into_exported()
     }
 
     /// The generated glTF for this asset. Will always implement `KHR_materials_variants`
@@ -119,6 +299,129 @@ impl VariationalAsset {
         &self.metadata
     }
 
+    /// The files that `MeldOptions::variant_exporters` wrote alongside the glTF, by file name;
+    /// e.g. a switch table of `SidecarVariantExporter`. These aren't part of the asset, and an
+    /// asset loaded from glTF has none.
+    pub fn sidecars(&self) -> &BTreeMap<String, Vec<u8>> {
+        &self.sidecars
+    }
+
+    /// Warnings about how the files of the source asset's buffers & images were found, if this
+    /// asset was just loaded from glTF that references them; see `WorkAsset::load_warnings()`.
+    pub fn load_warnings(&self) -> &[String] {
+        &self.load_warnings
+    }
+
+    /// The extensions this asset requires that we can't honor, and whose data was passed through
+    /// untouched; see `MeldOptions::allow_unsupported_required`.
+    pub fn unsupported_required_extensions(&self) -> Result<Vec<String>, Error> {
+        let json = match GlbChunk::from_bytes(self.glb())? {
+            Some((GlbChunk::JSON(json), _)) => json,
+            _ => return Ok(vec![]),
+        };
+        let root: Root = serde_json::from_slice(json)
+            .map_err(|e| format!("Bad JSON in GLB: {}", e.to_string()))?;
+        Ok(extension::unsupported_required(&root))
+    }
+
+    /// For each primitive of each mesh, the mapping of tag to the material it selects.
+    ///
+    /// This includes the default tag for every primitive that has a material, so runtimes can
+    /// precompute their switch tables without parsing `KHR_materials_variants` themselves.
+    pub fn variant_table(&self) -> Result<VariantTable, Error> {
+        self.work_asset(&MeldOptions::default())?.variant_table()
+    }
+
+    /// Looks for near-duplicate images used by different tags, which would better be shared.
+    ///
+    /// See `WorkAsset::analyze_sharing()` for details.
+    pub fn analyze_sharing(&self, max_distance: u32) -> Result<SharingAnalysis, Error> {
+        self.work_asset(&MeldOptions::default())?
+            .analyze_sharing(max_distance)
+    }
+
+    /// Splits this asset into one plain, non-variational GLB per variant tag.
+    ///
+    /// This is for viewers that don't understand `KHR_materials_variants`: each GLB shows its
+    /// tag's materials, and holds only what that variant needs.
+    pub fn split(&self) -> Result<BTreeMap<Tag, Vec<u8>>, Error> {
+        self.split_with_options(&MeldOptions::default())
+    }
+
+    /// Splits this asset as `split()` does, re-encoding textures per the given `MeldOptions`.
+    pub fn split_with_options(
+        &self,
+        options: &MeldOptions,
+    ) -> Result<BTreeMap<Tag, Vec<u8>>, Error> {
+        self.work_asset(options)?.split()
+    }
+
+    /// Splits this asset as `split_with_options()` does, but streams each GLB to a file in the
+    /// given directory rather than building it in memory; see `WorkAsset::split_to_dir()`.
+    /// Returns the number of bytes written for each tag.
+    pub fn split_to_dir(
+        &self,
+        dir: &Path,
+        options: &MeldOptions,
+    ) -> Result<BTreeMap<Tag, usize>, Error> {
+        self.work_asset(options)?.split_to_dir(dir)
+    }
+
+    /// This asset as GLB with its geometry compressed with Draco, for delivery; see
+    /// `WorkAsset::export_draco()`. The result can't be melded further.
+    pub fn export_draco(
+        &self,
+        options: &MeldOptions,
+        settings: &DracoSettings,
+    ) -> Result<Vec<u8>, Error> {
+        self.work_asset(options)?.export_draco(settings)
+    }
+
+    /// This asset as GLB with its vertex data compressed with meshopt, for delivery; see
+    /// `WorkAsset::export_meshopt()`. The result can't be melded further.
+    pub fn export_meshopt(&self, options: &MeldOptions) -> Result<Vec<u8>, Error> {
+        self.work_asset(options)?.export_meshopt()
+    }
+
+    /// This asset in canonical form, re-exported; see `WorkAsset::canonicalize()`.
+    ///
+    /// Assets with the same meshes, materials & variants have the same canonical form, however
+    /// they were put together, e.g. by melding the same sources in different orders.
+    pub fn canonicalize(&self) -> Result<VariationalAsset, Error> {
+        let mut asset = self.work_asset(&MeldOptions::default())?.into_owned();
+        asset.canonicalize()?;
+        asset.into_exported()
+    }
+
+    /// Whether two assets hold the same meshes, materials & variants, with the same default tag.
+    ///
+    /// The assets are compared in canonical form, so the order in which their contents were put
+    /// together is of no consequence; nor is their `asset` property, which only credits sources.
+    /// This is for tests & CI to check that different ways of melding agree.
+    pub fn semantically_equal(a: &VariationalAsset, b: &VariationalAsset) -> Result<bool, Error> {
+        if a.default_tag != b.default_tag {
+            return Ok(false);
+        }
+        let a = canonical_contents(&a.canonicalize()?)?;
+        let b = canonical_contents(&b.canonicalize()?)?;
+        Ok(a == b)
+    }
+
+    // the `WorkAsset` this asset was exported from, if it was kept and keyed with the given
+    // options, or else a fresh one parsed from our GLB
+    fn work_asset(&self, options: &MeldOptions) -> Result<Cow<WorkAsset>, Error> {
+        match &self.work_asset {
+            Some(asset) if asset.options() == options => Ok(Cow::Borrowed(asset.as_ref())),
+            _ => WorkAsset::from_slice_with_options(
+                self.glb(),
+                Some(self.default_tag()),
+                None,
+                options,
+            )
+            .map(Cow::Owned),
+        }
+    }
+
     /// Melds one variational asset into another, combining material-switching tags
     /// on a per-mesh, per-primitive basis.
     ///
@@ -130,16 +433,294 @@ impl VariationalAsset {
     /// Further, the whole point of this tool is to identify shared pieces of data
     /// between the two assets, keep only one, and redirect all references to it.
     ///
+    /// The melded asset is returned along with `MeldStats` describing what was shared.
     pub fn meld<'a>(
         base: &'a VariationalAsset,
         other: &'a VariationalAsset,
+    ) -> Result<(VariationalAsset, MeldStats), Error> {
+        Self::meld_with_options(base, other, &MeldOptions::default())
+    }
+
+    /// Melds one variational asset into another, as `meld()`, using the given `MeldOptions`.
+    ///
+    /// Assets that were themselves produced by this library, with the same options, are melded
+    /// without being parsed or keyed again; so a long chain of melds only ever keys each new
+    /// source asset once.
+    pub fn meld_with_options<'a>(
+        base: &'a VariationalAsset,
+        other: &'a VariationalAsset,
+        options: &MeldOptions,
+    ) -> Result<(VariationalAsset, MeldStats), Error> {
+        let correspondence = &PrimitiveCorrespondence::default();
+        Self::meld_with_correspondence(base, other, options, correspondence)
+    }
+
+    /// Melds one variational asset into another, as `meld_with_options()`, matching up the mesh
+    /// primitives listed in *correspondence* explicitly.
+    ///
+    /// See `WorkAsset::meld_with_correspondence()` for details.
+    pub fn meld_with_correspondence<'a>(
+        base: &'a VariationalAsset,
+        other: &'a VariationalAsset,
+        options: &MeldOptions,
+        correspondence: &PrimitiveCorrespondence,
+    ) -> Result<(VariationalAsset, MeldStats), Error> {
+        let (base_root_json, base_mapping_json) = variants_json_bytes(base)?;
+        let base = base.work_asset(options)?;
+        let other = other.work_asset(options)?;
+
+        let (meld, mut stats) = WorkAsset::meld_with_correspondence(&base, &other, correspondence)?;
+
+        // nothing is let go during a meld, so what it held at its peak, it holds still; and
+        // exporting then holds a compacted copy of the blob, and the GLB built from that
+        let mut memory = MemoryBudget::resume(options.memory_budget, stats.peak_memory);
+        memory.hold(2 * meld.blob_slice().len(), "exporting the result")?;
+        stats.peak_memory = memory.peak();
+
+        let timer = PhaseTimer::start();
+        let meld = meld.into_exported()?;
+        stats.timings.export = timer.elapsed();
+
+        let (root_json, mapping_json) = variants_json_bytes(&meld)?;
+        stats.variants_root_json_added = root_json.saturating_sub(base_root_json);
+        stats.variants_mapping_json_added = mapping_json.saturating_sub(base_mapping_json);
+        Ok((meld, stats))
+    }
+
+    /// Predicts the outcome of melding *other* into *base*, without performing the meld.
+    ///
+    /// This compares meld keys only, and is cheap enough to run interactively before deciding
+    /// to meld; see `WorkAsset::estimate_meld()` for what it does and doesn't account for.
+    pub fn estimate_meld_size(
+        base: &VariationalAsset,
+        other: &VariationalAsset,
+    ) -> Result<MeldEstimate, Error> {
+        let options = &MeldOptions::default();
+        WorkAsset::estimate_meld(&base.work_asset(options)?, &other.work_asset(options)?)
+    }
+
+    /// Explains why melding *other* into *base* with the given `MeldOptions` fails, or would.
+    ///
+    /// See `WorkAsset::diagnose_meld()` for what's reported.
+    pub fn diagnose_meld(
+        base: &VariationalAsset,
+        other: &VariationalAsset,
+        options: &MeldOptions,
+    ) -> Result<MeldDiagnosis, Error> {
+        WorkAsset::diagnose_meld(&base.work_asset(options)?, &other.work_asset(options)?)
+    }
+
+    /// A `PrimitiveCorrespondence` to start from, for melding *other* into *base* with the given
+    /// `MeldOptions`; see `WorkAsset::correspondence_template()`.
+    pub fn correspondence_template(
+        base: &VariationalAsset,
+        other: &VariationalAsset,
+        options: &MeldOptions,
+    ) -> Result<PrimitiveCorrespondence, Error> {
+        WorkAsset::correspondence_template(&base.work_asset(options)?, &other.work_asset(options)?)
+    }
+
+    /// Composes assets that each vary along a single, different axis into one asset that offers
+    /// every combination of their values, e.g. every body colour with every trim finish.
+    ///
+    /// See `WorkAsset::meld_axes()` for the details.
+    pub fn meld_axes(assets: &[&VariationalAsset]) -> Result<VariationalAsset, Error> {
+        let mut loaded = vec![];
+        for asset in assets {
+            loaded.push(asset.work_asset(&MeldOptions::default())?);
+        }
+        let loaded: Vec<&WorkAsset> = loaded.iter().map(|asset| asset.as_ref()).collect();
+        WorkAsset::meld_axes(&loaded)?.into_exported()
+    }
+
+    /// Melds a new source asset into this one, which is presumed to be large and already variational.
+    ///
+    /// This is equivalent to `meld_with_options(self, new_source, options)`, except that this asset
+    /// is trusted to be the result of a previous export: unless it still holds its work asset from
+    /// a load with the same options, it's reloaded without the uniqueness checks, and without
+    /// fingerprinting meshes that have only one primitive. Its images and materials are keyed
+    /// again regardless, so this is a saving only for assets with many single-primitive meshes.
+    pub fn append(
+        &self,
+        new_source: &VariationalAsset,
+        options: &MeldOptions,
+    ) -> Result<VariationalAsset, Error> {
+        let base = match &self.work_asset {
+            Some(asset) if asset.options() == options => Cow::Borrowed(asset.as_ref()),
+            _ => Cow::Owned(WorkAsset::from_exported_slice(
+                self.glb(),
+                self.default_tag(),
+                options,
+            )?),
+        };
+        let other = new_source.work_asset(options)?;
+
+        let (meld, _stats) = WorkAsset::meld(&base, &other)?;
+        meld.into_exported()
+    }
+
+    /// Specifies how `tag` is to be presented to end users, returning the re-exported result.
+    ///
+    /// The display is stored inside the asset itself, and is surfaced again through
+    /// `Metadata::tag_display()`.
+    pub fn with_tag_display(
+        &self,
+        tag: &Tag,
+        display: TagDisplay,
+    ) -> Result<VariationalAsset, Error> {
+        if !self.metadata.tags().contains(tag) {
+            return Err(format!("No tag {} in asset.", tag));
+        }
+        let mut asset = self.work_asset(&MeldOptions::default())?.into_owned();
+        asset.set_tag_display(tag, display)?;
+        asset.into_exported()
+    }
+
+    /// Attaches a payload to `tag` under the given key, returning the re-exported result.
+    ///
+    /// Any earlier payload of the same key is replaced. See `TagPayload` for how it's stored.
+    pub fn with_tag_payload(
+        &self,
+        tag: &Tag,
+        key: &str,
+        payload: TagPayload,
+    ) -> Result<VariationalAsset, Error> {
+        if !self.metadata.tags().contains(tag) {
+            return Err(format!("No tag {} in asset.", tag));
+        }
+        let mut asset = self.work_asset(&MeldOptions::default())?.into_owned();
+        asset.set_tag_payload(tag, key, payload)?;
+        asset.into_exported()
+    }
+
+    /// All the payloads attached to `tag`, keyed by name.
+    pub fn tag_payloads(&self, tag: &Tag) -> Result<BTreeMap<String, TagPayload>, Error> {
+        let asset = self.work_asset(&MeldOptions::default())?;
+        Ok(asset.tag_payloads(tag).cloned().unwrap_or_default())
+    }
+
+    /// Maps `tag` to the material named `material_name` on every primitive of the mesh named
+    /// `mesh_name`, returning the re-exported result.
+    ///
+    /// This allows for simple scripted authoring of variants on top of an existing asset,
+    /// without requiring a second source file to meld in.
+    pub fn assign_variant(
+        &self,
+        tag: &Tag,
+        mesh_name: &str,
+        material_name: &str,
+    ) -> Result<VariationalAsset, Error> {
+        let mut asset = self.work_asset(&MeldOptions::default())?.into_owned();
+        let mesh_ix = mesh_ix_named(&asset, mesh_name)?;
+        let material_key = asset.unique_material_key(material_name)?;
+
+        for primitive_ix in 0..asset.meshes()[mesh_ix].primitives.len() {
+            asset.set_variant(mesh_ix, primitive_ix, tag, &material_key)?;
+        }
+        asset.into_exported()
+    }
+
+    /// Redirects every use of each material named as a key of *names* to the material named as
+    /// its value, returning the re-exported result; see `WorkAsset::remap_materials()`.
+    pub fn remap_materials(
+        &self,
+        names: &HashMap<String, String>,
     ) -> Result<VariationalAsset, Error> {
-        let base = &WorkAsset::from_slice(base.glb(), Some(base.default_tag()), None)?;
-        let other = &WorkAsset::from_slice(other.glb(), Some(other.default_tag()), None)?;
+        let mut asset = self.work_asset(&MeldOptions::default())?.into_owned();
+        asset.remap_materials(names)?;
+        asset.into_exported()
+    }
 
-        let meld = WorkAsset::meld(base, other)?;
-        meld.export()
+    /// Maps `tag` on every primitive of the mesh named `mesh_name` to a new material, given as
+    /// glTF JSON, returning the re-exported result; see `WorkAsset::replace_material()`.
+    ///
+    /// This allows for fixing up a variant, e.g. tweaking the roughness of a "matte" variant,
+    /// without re-exporting and re-melding its source. Any texture references of the material
+    /// index into `textures`, the bytes of PNG or JPEG files.
+    pub fn replace_material(
+        &self,
+        tag: &Tag,
+        mesh_name: &str,
+        material_json: &str,
+        textures: &[Vec<u8>],
+    ) -> Result<VariationalAsset, Error> {
+        if !self.metadata.tags().contains(tag) {
+            return Err(format!("No tag {} in asset.", tag));
+        }
+        let material: Material =
+            serde_json::from_str(material_json).map_err(|e| format!("Bad material JSON: {}", e))?;
+        let mut asset = self.work_asset(&MeldOptions::default())?.into_owned();
+        let mesh_ix = mesh_ix_named(&asset, mesh_name)?;
+        asset.replace_material(tag, mesh_ix, material, textures)?;
+        asset.into_exported()
     }
+
+    /// Adds `tag` as a copy of the existing tag `from`, with `overrides` applied to its
+    /// materials, returning the re-exported result; see `WorkAsset::derive_variant()`.
+    ///
+    /// Any texture overrides index into `textures`, the bytes of PNG or JPEG files.
+    pub fn derive_variant(
+        &self,
+        from: &Tag,
+        tag: &Tag,
+        overrides: &MaterialOverrides,
+        textures: &[Vec<u8>],
+    ) -> Result<VariationalAsset, Error> {
+        if !self.metadata.tags().contains(from) {
+            return Err(format!("No tag {} in asset.", from));
+        }
+        let mut asset = self.work_asset(&MeldOptions::default())?.into_owned();
+        asset.derive_variant(from, tag, overrides, textures)?;
+        asset.into_exported()
+    }
+}
+
+// the JSON of the given asset's GLB without its `asset` property, its BIN chunk, and the magic
+// numbers & bytes of any extra chunks
+fn canonical_contents(
+    asset: &VariationalAsset,
+) -> Result<(Value, Vec<u8>, Vec<(u32, Vec<u8>)>), Error> {
+    let (json, bin, extras) = match GlbChunk::from_bytes_with_extras(asset.glb())? {
+        Some((GlbChunk::JSON(json), bin, extras)) => (json, bin, extras),
+        _ => return Err(format!("Internal error: Exported asset isn't GLB.")),
+    };
+    let mut json: Value =
+        serde_json::from_slice(json).map_err(|e| format!("Bad JSON in GLB: {}", e))?;
+    if let Value::Object(map) = &mut json {
+        map.remove("asset");
+    }
+    let bin = match bin {
+        Some(GlbChunk::BIN(bytes)) => bytes.to_vec(),
+        _ => vec![],
+    };
+    let extras = extras
+        .into_iter()
+        .filter_map(|chunk| match chunk {
+            GlbChunk::Other(magic, bytes) => Some((magic, bytes.to_vec())),
+            _ => None,
+        })
+        .collect();
+    Ok((json, bin, extras))
+}
+
+// the bytes of JSON `KHR_materials_variants` takes up in the given asset's root & primitives
+fn variants_json_bytes(asset: &VariationalAsset) -> Result<(usize, usize), Error> {
+    let json = match GlbChunk::from_bytes(asset.glb())? {
+        Some((GlbChunk::JSON(json), _)) => json,
+        _ => return Err(format!("Internal error: Exported asset isn't GLB.")),
+    };
+    let root: Root = serde_json::from_slice(json).map_err(|e| format!("Bad JSON in GLB: {}", e))?;
+    Ok(extension::json_bytes(&root))
+}
+
+// meshes are keyed by the name they were first melded under; this looks them up by the name they
+// have now
+fn mesh_ix_named(asset: &WorkAsset, mesh_name: &str) -> Result<usize, Error> {
+    asset
+        .meshes()
+        .iter()
+        .position(|mesh| mesh.name.as_ref().map(String::as_str) == Some(mesh_name))
+        .ok_or_else(|| format!("No mesh named '{}'.", mesh_name))
 }
 
 impl AssetSizes {
@@ -0,0 +1,46 @@
+// Copyright (c) Facebook, Inc. and its affiliates. All Rights Reserved
+//
+
+use std::collections::BTreeMap;
+
+use serde_derive::{Deserialize, Serialize};
+
+/// How to present a tag to end users, e.g. in a storefront's variant picker.
+///
+/// This travels inside the asset, in the `extras` of the tag's variant entry in the root
+/// `KHR_materials_variants` extension, so that clients need no external mapping table.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TagDisplay {
+    /// A human-readable name for the tag, e.g. "Midnight Blue" for `midnight_blue`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    /// A longer description of the variant the tag selects.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// Translations of `name`, keyed by BCP 47 locale, e.g. `fr-CA`.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub localized_names: BTreeMap<String, String>,
+    /// Translations of `description`, keyed by BCP 47 locale.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub localized_descriptions: BTreeMap<String, String>,
+}
+
+impl TagDisplay {
+    /// Whether there's nothing here to display.
+    pub fn is_empty(&self) -> bool {
+        *self == TagDisplay::default()
+    }
+
+    /// The name to show for the given locale, falling back to the untranslated name.
+    pub fn localized_name(&self, locale: &str) -> Option<&String> {
+        self.localized_names.get(locale).or(self.name.as_ref())
+    }
+
+    /// The description to show for the given locale, falling back to the untranslated one.
+    pub fn localized_description(&self, locale: &str) -> Option<&String> {
+        self.localized_descriptions
+            .get(locale)
+            .or(self.description.as_ref())
+    }
+}
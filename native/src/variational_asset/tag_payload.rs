@@ -0,0 +1,23 @@
+// Copyright (c) Facebook, Inc. and its affiliates. All Rights Reserved
+//
+
+use serde_json::Value;
+
+/// A small piece of caller data attached to a tag, e.g. a SKU code or a thumbnail image.
+///
+/// Payloads travel inside the asset, keyed by name in the `extras` of the tag's variant entry in
+/// the root `KHR_materials_variants` extension: JSON payloads as they are, binary ones as a
+/// reference to a buffer view in the GLB's binary chunk. They are meant for bookkeeping, not
+/// bulk data; every payload is copied whenever the asset is loaded or exported.
+#[derive(Debug, Clone, PartialEq)]
+pub enum TagPayload {
+    /// Arbitrary JSON.
+    Json(Value),
+    /// Raw bytes, optionally labelled with their MIME type.
+    Binary {
+        /// The MIME type of the bytes, e.g. `image/png`, if known.
+        mime_type: Option<String>,
+        /// The payload itself.
+        bytes: Vec<u8>,
+    },
+}
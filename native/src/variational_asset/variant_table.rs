@@ -0,0 +1,20 @@
+// Copyright (c) Facebook, Inc. and its affiliates. All Rights Reserved
+//
+
+use std::collections::HashMap;
+
+use serde_derive::{Deserialize, Serialize};
+
+use crate::Tag;
+
+/// For each mesh, for each of its primitives, the mapping of `Tag` to the material it selects.
+pub type VariantTable = Vec<Vec<HashMap<Tag, VariantMaterial>>>;
+
+/// A reference to the glTF material a variant tag selects on some mesh primitive.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VariantMaterial {
+    /// The index of the material in the glTF `materials` array.
+    pub index: usize,
+    /// The material's name, if it has one.
+    pub name: Option<String>,
+}
@@ -3,8 +3,14 @@
 
 extern crate wasm_bindgen;
 use wasm_bindgen::prelude::*;
+use wasm_bindgen_futures::JsFuture;
 
-use crate::{Metadata, Tag, VariationalAsset};
+use js_sys::Uint8Array;
+use web_sys::File;
+
+use serde_json::json;
+
+use crate::{Metadata, Tag, TagDisplay, VariationalAsset};
 
 // simplified versions of methods for the benefit only of wasm_bind
 #[wasm_bindgen]
@@ -14,12 +20,64 @@ impl VariationalAsset {
         VariationalAsset::from_slice(glb, tag.as_ref(), None).map_err(JsValue::from)
     }
 
+    /// WASM-friendly version of `from_slice`, reading a browser `File` (or `Blob`) directly, so
+    /// the caller needn't first copy its contents into a `Uint8Array` of their own.
+    pub async fn wasm_from_file(file: File, tag: Option<Tag>) -> Result<VariationalAsset, JsValue> {
+        let buffer = JsFuture::from(file.array_buffer()).await?;
+        let glb = Uint8Array::new(&buffer).to_vec();
+        VariationalAsset::from_slice(&glb, tag.as_ref(), None).map_err(JsValue::from)
+    }
+
     /// WASM-friendly version of `meld``; remaps its errors as `JsValue`.
     pub fn wasm_meld(
         base: &VariationalAsset,
         melded: &VariationalAsset,
     ) -> Result<VariationalAsset, JsValue> {
-        VariationalAsset::meld(base, melded).map_err(JsValue::from)
+        VariationalAsset::meld(base, melded)
+            .map(|(asset, _stats)| asset)
+            .map_err(JsValue::from)
+    }
+
+    /// WASM-friendly version of `estimate_meld_size()`; returns the estimate JSON-encoded.
+    pub fn wasm_estimate_meld_size(
+        base: &VariationalAsset,
+        melded: &VariationalAsset,
+    ) -> Result<String, JsValue> {
+        let estimate = VariationalAsset::estimate_meld_size(base, melded).map_err(JsValue::from)?;
+        Ok(json!(estimate).to_string())
+    }
+
+    /// WASM-friendly version of `append()`; remaps its errors as `JsValue`.
+    pub fn wasm_append(&self, new_source: &VariationalAsset) -> Result<VariationalAsset, JsValue> {
+        self.append(new_source).map_err(JsValue::from)
+    }
+
+    /// WASM-friendly version of `assign_variant()`; remaps its errors as `JsValue`.
+    pub fn wasm_assign_variant(
+        &self,
+        tag: Tag,
+        mesh_name: &str,
+        material_name: &str,
+    ) -> Result<VariationalAsset, JsValue> {
+        self.assign_variant(&tag, mesh_name, material_name)
+            .map_err(JsValue::from)
+    }
+
+    /// WASM-friendly version of `with_tag_display()`; takes the display JSON-encoded.
+    pub fn wasm_with_tag_display(
+        &self,
+        tag: Tag,
+        display: &str,
+    ) -> Result<VariationalAsset, JsValue> {
+        let display: TagDisplay = serde_json::from_str(display)
+            .map_err(|e| JsValue::from(format!("Bad tag display JSON: {}", e)))?;
+        self.with_tag_display(&tag, display).map_err(JsValue::from)
+    }
+
+    /// WASM-friendly version of `variant_table()`; returns it JSON-encoded.
+    pub fn wasm_variant_table(&self) -> Result<String, JsValue> {
+        let table = self.variant_table().map_err(JsValue::from)?;
+        Ok(json!(table).to_string())
     }
 
     /// WASM-friendly version of `glb()`; returns an ownable `Vec<u8>` instead of a `&[u8]` slice.
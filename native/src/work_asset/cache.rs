@@ -0,0 +1,151 @@
+// Copyright (c) Facebook, Inc. and its affiliates. All Rights Reserved
+//
+
+//! An on-disk cache of the expensive-to-compute parts of a `WorkAsset`.
+//!
+//! Pipelines frequently run the same source exports through the tool over and over. Each time,
+//! we'd hash every image, expand every material and fingerprint every mesh primitive. With a
+//! `MeldCache`, those results are persisted in a directory, keyed by a content hash of the
+//! source file, and loaded instead of recomputed on the next run.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use serde_derive::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+use sha1::Sha1;
+
+use crate::work_asset::uri::FileStamp;
+use crate::{Fingerprint, MeldKey, MeldOptions, Result};
+
+// bump this whenever the format of meld keys or fingerprints changes
+const CACHE_FORMAT_VERSION: u32 = 6;
+
+// distinguishes the temporary files of concurrent stores within this process
+static TEMP_FILE_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+/// A directory of cached meld keys and fingerprints, keyed by source file content hash.
+///
+/// A cache may be shared by any number of threads or processes at once: entries are written to
+/// temporary files and moved into place, so that a reader only ever sees a complete entry.
+#[derive(Clone, Debug)]
+pub struct MeldCache {
+    dir: PathBuf,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct CacheEntry {
+    version: u32,
+    pub(crate) image_keys: Vec<MeldKey>,
+    pub(crate) material_keys: Vec<MeldKey>,
+    pub(crate) mesh_keys: Vec<MeldKey>,
+    pub(crate) sampler_keys: Vec<MeldKey>,
+    pub(crate) texture_keys: Vec<MeldKey>,
+    pub(crate) mesh_primitive_fingerprints: Vec<Vec<Fingerprint>>,
+}
+
+impl MeldCache {
+    /// Opens (and if necessary creates) a cache in the given directory.
+    pub fn new(dir: &Path) -> Result<MeldCache> {
+        fs::create_dir_all(dir).map_err(|e| {
+            format!(
+                "Couldn't create cache directory {}: {}",
+                dir.display(),
+                e.to_string()
+            )
+        })?;
+        Ok(MeldCache {
+            dir: dir.to_owned(),
+        })
+    }
+
+    /// Computes the cache key of an asset from the bytes of its source file, and the stamps of
+    /// the external files its URIs name; those are hashed by path, size & modification time
+    /// only, as hashing their contents would cost about as much as keying them.
+    ///
+    /// Of the options, only those the keys are computed with are part of the cache key, each
+    /// written out explicitly; see `MeldOptions::keying_options()`.
+    pub(crate) fn content_key(
+        source: &[u8],
+        file_stamps: &[FileStamp],
+        options: &MeldOptions,
+    ) -> Result<String> {
+        let options: Map<String, Value> = options
+            .keying_options()
+            .into_iter()
+            .map(|(name, value)| (name.to_owned(), value))
+            .collect();
+        let files: Vec<Value> = file_stamps
+            .iter()
+            .map(|stamp| json!([stamp.path.to_string_lossy(), stamp.len, stamp.modified]))
+            .collect();
+        let json = serde_json::to_string(&json!({ "options": options, "files": files }))
+            .map_err(|e| format!("JSON serialisation error: {}", e))?;
+        let mut hasher = Sha1::new();
+        hasher.update(json.as_bytes());
+        hasher.update(source);
+        Ok(hasher.digest().to_string())
+    }
+
+    /// Retrieves a cache entry; anything unreadable or outdated is treated as a cache miss.
+    pub(crate) fn load(&self, content_key: &str) -> Option<CacheEntry> {
+        let bytes = fs::read(self.entry_path(content_key)).ok()?;
+        let entry: CacheEntry = serde_json::from_slice(&bytes).ok()?;
+        if entry.version == CACHE_FORMAT_VERSION {
+            Some(entry)
+        } else {
+            None
+        }
+    }
+
+    /// Writes a cache entry, replacing any previous entry for the same content.
+    pub(crate) fn store(&self, content_key: &str, entry: &CacheEntry) -> Result<()> {
+        let path = self.entry_path(content_key);
+        let json =
+            serde_json::to_vec(entry).map_err(|e| format!("JSON serialisation error: {}", e))?;
+        let temp_path = self.dir.join(format!(
+            "{}.{}-{}.tmp",
+            content_key,
+            std::process::id(),
+            TEMP_FILE_COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+        let write_error = |path: &Path, e: std::io::Error| {
+            format!(
+                "Couldn't write cache file {}: {}",
+                path.display(),
+                e.to_string()
+            )
+        };
+        fs::write(&temp_path, json).map_err(|e| write_error(&temp_path, e))?;
+        fs::rename(&temp_path, &path).map_err(|e| {
+            let _ = fs::remove_file(&temp_path);
+            write_error(&path, e)
+        })
+    }
+
+    fn entry_path(&self, content_key: &str) -> PathBuf {
+        self.dir.join(format!("{}.json", content_key))
+    }
+}
+
+impl CacheEntry {
+    pub(crate) fn new(
+        image_keys: Vec<MeldKey>,
+        material_keys: Vec<MeldKey>,
+        mesh_keys: Vec<MeldKey>,
+        sampler_keys: Vec<MeldKey>,
+        texture_keys: Vec<MeldKey>,
+        mesh_primitive_fingerprints: Vec<Vec<Fingerprint>>,
+    ) -> CacheEntry {
+        CacheEntry {
+            version: CACHE_FORMAT_VERSION,
+            image_keys,
+            material_keys,
+            mesh_keys,
+            sampler_keys,
+            texture_keys,
+            mesh_primitive_fingerprints,
+        }
+    }
+}
@@ -0,0 +1,113 @@
+// Copyright (c) Facebook, Inc. and its affiliates. All Rights Reserved
+//
+
+//! Code to put an asset into a canonical form, for comparing assets put together differently.
+
+use gltf::json::Index;
+
+use crate::gltfext::{compact_blob, reorder_blob};
+use crate::{MeldKey, Result, WorkAsset};
+
+use super::split::texture_indices;
+use super::{has_opaque_index_references, has_opaque_view_references};
+
+impl WorkAsset {
+    /// Puts this asset into a canonical form, in which assets with the same meshes, materials and
+    /// variants come out the same, however they were put together; e.g. by melding the same
+    /// sources in different orders.
+    ///
+    /// Materials, textures, images & samplers that no variant uses are dropped, and the rest are
+    /// sorted by meld key. The blob is then rebuilt with the buffer views in the order they're
+    /// first referenced. Variants, extension declarations and extra GLB chunks are sorted too.
+    /// Meshes, accessors, nodes and the like are left in place, since melding never reorders
+    /// them, and so is the `asset` property. The objects of an asset that uses extensions which
+    /// might refer to them in ways we can't see are neither dropped nor reordered.
+    pub fn canonicalize(&mut self) -> Result<()> {
+        self.parse.extensions_used.sort_unstable();
+        self.parse.extensions_used.dedup();
+        self.parse.extensions_required.sort_unstable();
+        self.parse.extensions_required.dedup();
+        self.extra_chunks.sort_unstable();
+        self.variant_order = self.get_tags_in_use()?;
+        self.variant_order.sort_unstable();
+
+        if has_opaque_index_references(&self.parse) || has_opaque_view_references(&self.parse) {
+            let keep_unreferenced = has_opaque_view_references(&self.parse);
+            self.blob = compact_blob(&mut self.parse, &self.blob, keep_unreferenced)?;
+            return Ok(());
+        }
+
+        // materials are used by primitives, either directly or through their variant mappings
+        let mut used = vec![false; self.parse.materials.len()];
+        for (m_ix, mesh) in self.parse.meshes.iter().enumerate() {
+            for (p_ix, primitive) in mesh.primitives.iter().enumerate() {
+                if let Some(ix) = primitive.material {
+                    used[ix.value()] = true;
+                }
+                for key in self.mesh_primitive_variants[m_ix][p_ix].values() {
+                    if let Some(ix) = self.material_ix(key) {
+                        used[ix] = true;
+                    }
+                }
+            }
+        }
+        let new_ix = sort_by_key(&mut self.parse.materials, &mut self.material_keys, &used);
+        for primitive in self.parse.meshes.iter_mut().flat_map(|m| &mut m.primitives) {
+            primitive.material = remap(primitive.material, &new_ix);
+        }
+
+        let mut used = vec![false; self.parse.textures.len()];
+        for ix in self.parse.materials.iter_mut().flat_map(texture_indices) {
+            used[ix.value()] = true;
+        }
+        let new_ix = sort_by_key(&mut self.parse.textures, &mut self.texture_keys, &used);
+        for ix in self.parse.materials.iter_mut().flat_map(texture_indices) {
+            *ix = remap(Some(*ix), &new_ix).unwrap_or(*ix);
+        }
+
+        let mut used = vec![false; self.parse.images.len()];
+        for texture in &self.parse.textures {
+            used[texture.source.value()] = true;
+        }
+        let new_ix = sort_by_key(&mut self.parse.images, &mut self.image_keys, &used);
+        for texture in &mut self.parse.textures {
+            texture.source = remap(Some(texture.source), &new_ix).unwrap_or(texture.source);
+        }
+
+        let mut used = vec![false; self.parse.samplers.len()];
+        for sampler in self.parse.textures.iter().filter_map(|t| t.sampler) {
+            used[sampler.value()] = true;
+        }
+        let new_ix = sort_by_key(&mut self.parse.samplers, &mut self.sampler_keys, &used);
+        for texture in &mut self.parse.textures {
+            texture.sampler = remap(texture.sampler, &new_ix);
+        }
+
+        self.blob = reorder_blob(&mut self.parse, &self.blob)?;
+        Ok(())
+    }
+}
+
+// keep only the objects marked as used, sorted by key, and return the new index of each old one
+fn sort_by_key<T: Clone>(
+    objects: &mut Vec<T>,
+    keys: &mut Vec<MeldKey>,
+    used: &[bool],
+) -> Vec<Option<usize>> {
+    let mut order: Vec<usize> = (0..objects.len()).filter(|&ix| used[ix]).collect();
+    order.sort_by(|&a, &b| keys[a].cmp(&keys[b]));
+
+    let mut new_ix = vec![None; objects.len()];
+    for (new, &old) in order.iter().enumerate() {
+        new_ix[old] = Some(new);
+    }
+    *objects = order.iter().map(|&ix| objects[ix].clone()).collect();
+    *keys = order.iter().map(|&ix| keys[ix].clone()).collect();
+    new_ix
+}
+
+// the new index of a reference to an object that's been moved by sort_by_key()
+fn remap<T>(ix: Option<Index<T>>, new_ix: &[Option<usize>]) -> Option<Index<T>> {
+    ix.and_then(|ix| new_ix[ix.value()])
+        .map(|ix| Index::new(ix as u32))
+}
@@ -0,0 +1,158 @@
+// Copyright (c) Facebook, Inc. and its affiliates. All Rights Reserved
+//
+
+//! Cartesian meld of assets that vary along independent axes.
+//!
+//! Given e.g. one variational asset whose tags vary only the body colour, and another whose tags
+//! vary only the trim finish, we produce an asset that offers every combination of the two, with
+//! no need for anyone to export a source file per combination.
+
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+
+use crate::axes::{self, AxisValues};
+use crate::{MeldKey, Result, Tag, WorkAsset};
+
+impl WorkAsset {
+    /// Melds assets that each vary along a single, different axis into one asset that offers
+    /// every combination of their values.
+    ///
+    /// Each asset's tags, its default tag included, must be structured tags along one and the
+    /// same axis, e.g. `color:red` and `color:blue`; see the `axes` module. Otherwise the assets
+    /// must be meldable as usual. For a combination such as `color:red,finish:matte`, each
+    /// primitive then selects the material of whichever axis it varies along; a primitive that
+    /// varies along more than one axis is an error, since there'd be no telling which should win.
+    ///
+    /// The default tag of the result is the combination of the assets' default tags.
+    pub fn meld_axes(assets: &[&WorkAsset]) -> Result<WorkAsset> {
+        let mut axis_values: BTreeMap<String, BTreeSet<String>> = BTreeMap::new();
+        let mut default_values = AxisValues::new();
+        for (ix, asset) in assets.iter().enumerate() {
+            let (axis, values, default_value) = asset
+                .single_axis()
+                .map_err(|e| format!("Asset {}: {}", ix, e))?;
+            if axis_values.insert(axis.clone(), values).is_some() {
+                return Err(format!("Asset {} varies along axis {} too.", ix, axis));
+            }
+            default_values.insert(axis, default_value);
+        }
+
+        let (first, rest) = assets
+            .split_first()
+            .ok_or_else(|| String::from("No assets to meld."))?;
+        let mut result = (*first).clone();
+        for asset in rest {
+            let (melded, _stats) = WorkAsset::meld(&result, asset)?;
+            result = melded;
+        }
+
+        // every combination of axis values, in the form of structured tags
+        let mut combinations = vec![AxisValues::new()];
+        for (axis, values) in &axis_values {
+            combinations = combinations
+                .iter()
+                .flat_map(|combination| {
+                    values.iter().map(move |value| {
+                        let mut combination = combination.clone();
+                        combination.insert(axis.to_owned(), value.to_owned());
+                        combination
+                    })
+                })
+                .collect();
+        }
+
+        // work out every primitive's new mapping before touching any of them
+        let table = result.variant_table()?;
+        let mut mappings = vec![];
+        for (m_ix, primitives) in table.iter().enumerate() {
+            for (p_ix, entries) in primitives.iter().enumerate() {
+                let default_key = entries
+                    .get(&result.default_tag)
+                    .map(|material| result.material_keys[material.index].to_owned());
+                // as at runtime, a tag without a mapping selects the primitive's own material
+                let material_key = |values: &AxisValues| -> Option<MeldKey> {
+                    entries
+                        .get(&axes::axis_tag(values))
+                        .map(|material| result.material_keys[material.index].to_owned())
+                        .or_else(|| default_key.clone())
+                };
+                let single_value = |axis: &String, value: &String| {
+                    let mut values = AxisValues::new();
+                    values.insert(axis.to_owned(), value.to_owned());
+                    values
+                };
+
+                let varying_axes: Vec<&String> = axis_values
+                    .iter()
+                    .filter(|&(axis, values)| {
+                        let keys: HashSet<Option<MeldKey>> = values
+                            .iter()
+                            .map(|value| material_key(&single_value(axis, value)))
+                            .collect();
+                        keys.len() > 1
+                    })
+                    .map(|(axis, _)| axis)
+                    .collect();
+                if varying_axes.len() > 1 {
+                    return Err(format!(
+                        "Mesh {} primitive {} varies along both {} and {}.",
+                        m_ix, p_ix, varying_axes[0], varying_axes[1]
+                    ));
+                }
+
+                let mut mapping = vec![];
+                for combination in &combinations {
+                    let key = match varying_axes.first() {
+                        Some(&axis) => material_key(&single_value(axis, &combination[axis])),
+                        None => default_key.clone(),
+                    };
+                    if let Some(key) = key {
+                        mapping.push((axes::axis_tag(combination), key));
+                    }
+                }
+                mappings.push((m_ix, p_ix, mapping));
+            }
+        }
+
+        result.default_tag = axes::axis_tag(&default_values);
+        for (m_ix, p_ix, mapping) in mappings {
+            result.mesh_primitive_variants[m_ix][p_ix].clear();
+            for (tag, key) in mapping {
+                result.set_variant(m_ix, p_ix, &tag, &key)?;
+            }
+        }
+        Ok(result)
+    }
+
+    // the axis this asset's tags vary along, all the values they take, and the default one
+    fn single_axis(&self) -> Result<(String, BTreeSet<String>, String)> {
+        let mut tags: Vec<Tag> = vec![self.default_tag.clone()];
+        tags.extend(self.get_tags_in_use()?);
+
+        let mut axis = None;
+        let mut values = BTreeSet::new();
+        let mut default_value = None;
+        for tag in &tags {
+            let tag_values = axes::parse_axis_tag(tag)?.unwrap_or_default();
+            if tag_values.len() != 1 {
+                return Err(format!("Tag {:?} isn't of a single axis.", tag));
+            }
+            let (tag_axis, value) = tag_values.into_iter().next().unwrap();
+            if let Some(axis) = &axis {
+                if *axis != tag_axis {
+                    return Err(format!(
+                        "Tags vary along both axis {} and axis {}.",
+                        axis, tag_axis
+                    ));
+                }
+            }
+            axis = Some(tag_axis);
+            // the default tag comes first
+            default_value.get_or_insert_with(|| value.clone());
+            values.insert(value);
+        }
+        match (axis, default_value) {
+            (Some(axis), Some(default_value)) => Ok((axis, values, default_value)),
+            _ => Err(String::from("Asset has no tags.")),
+        }
+    }
+}
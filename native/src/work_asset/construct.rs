@@ -3,31 +3,107 @@
 
 //! Code to parse & index a glTF asset into `WorkAsset` format.
 
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fs;
-use std::path::{Path, PathBuf};
+use std::path::Path;
 
-use spectral::prelude::*;
+use gltf::json::validation::Validate;
+use gltf::json::{image::MimeType, mesh::Primitive, Index, Mesh, Root};
+use gltf::{Document, Gltf};
 
-use gltf::json::{image::MimeType, mesh::Primitive, Mesh, Root};
-use gltf::Gltf;
+use serde_derive::Deserialize;
+use serde_json::{Map, Value};
 
 use crate::extension;
-use crate::gltfext::{add_buffer_view_from_slice, set_root_buffer};
-use crate::meld_keys::{build_fingerprint, HasKeyForVariants};
-use crate::{Fingerprint, MeldKey, Result, Tag, WorkAsset};
+use crate::glb::GlbChunk;
+use crate::gltfext::{
+    add_buffer_view_from_slice, compact_blob, dedupe_buffer_views, set_root_buffer,
+};
+use crate::meld_keys::{
+    build_fingerprint, build_quantized_fingerprint, sampler_key, HasKeyForVariants,
+};
+use crate::work_asset::cache::{CacheEntry, MeldCache};
+use crate::work_asset::intern::StringTable;
+use crate::work_asset::node_scope::scope_meshes_to_nodes;
+use crate::work_asset::timings::{PhaseTimer, PhaseTimings};
+use crate::work_asset::transforms::{bake_node_transforms, mesh_world_transforms, IDENTITY};
+use crate::work_asset::uri::UriResolver;
+use crate::work_asset::{describe_object, has_opaque_view_references};
+use crate::{
+    Fingerprint, FingerprintAlgorithm, InputLimits, MeldKey, MeldOptions, Metadata,
+    NodeTransformPolicy, Result, Tag, TagPayload, WorkAsset,
+};
+
+// the top-level glTF properties a lenient parse still parses; see `MeldOptions::lenient`
+const PARSED_PROPERTIES: &[&str] = &[
+    "accessors",
+    "asset",
+    "bufferViews",
+    "buffers",
+    "extensions",
+    "extensionsRequired",
+    "extensionsUsed",
+    "extras",
+    "images",
+    "materials",
+    "meshes",
+    "samplers",
+    "textures",
+];
 
 impl WorkAsset {
     /// Constructs a `WorkAsset` from a file `Path` using `::from_slice`.
     pub fn from_file(file: &Path, default_tag: Option<&Tag>) -> Result<WorkAsset> {
+        Self::from_file_with_cache(file, default_tag, None)
+    }
+
+    /// Constructs a `WorkAsset` from a file `Path`, optionally consulting a `MeldCache`.
+    ///
+    /// If the cache holds an entry for this exact asset content, its meld keys and fingerprints
+    /// are used instead of being recomputed. Otherwise they're computed, and stored in the cache.
+    pub fn from_file_with_cache(
+        file: &Path,
+        default_tag: Option<&Tag>,
+        cache: Option<&MeldCache>,
+    ) -> Result<WorkAsset> {
+        Self::from_file_with_options(file, default_tag, cache, &MeldOptions::default())
+    }
+
+    /// Constructs a `WorkAsset` from a file `Path`, keying its objects with the given options,
+    /// and optionally consulting a `MeldCache`; see `from_file_with_cache()` for details.
+    pub fn from_file_with_options(
+        file: &Path,
+        default_tag: Option<&Tag>,
+        cache: Option<&MeldCache>,
+        options: &MeldOptions,
+    ) -> Result<WorkAsset> {
         let slice = fs::read(file).map_err(|e| {
             format!(
                 "Couldn't read asset file {}: {}",
-                file.to_str().unwrap(),
+                file.display(),
                 e.to_string()
             )
         })?;
-        Self::from_slice(&slice, default_tag, file.parent())
+        // errors are prefixed with the file, so that it's clear which of several is to blame
+        let in_file = |e| format!("Can't load asset file {}: {}", file.display(), e);
+        Self::check_version(&slice).map_err(in_file)?;
+        let timer = PhaseTimer::start();
+        let (parse, blob, passthrough) = Self::parse_slice(&slice, options).map_err(in_file)?;
+        let extra_chunks = Self::read_extra_chunks(&slice, options).map_err(in_file)?;
+        let mut asset = Self::build(
+            parse,
+            blob,
+            default_tag,
+            file.parent(),
+            options,
+            false,
+            cache.map(|cache| (cache, slice.as_slice())),
+        )
+        .map_err(in_file)?;
+        asset.passthrough = passthrough;
+        asset.extra_chunks = extra_chunks;
+        asset.timings.construct = timer.elapsed();
+        Ok(asset)
     }
 
     /// Constructs a `WorkAsset` from a glTF byte slice, which can be text (JSON) or binary (GLB).
@@ -42,25 +118,264 @@ impl WorkAsset {
         default_tag: Option<&Tag>,
         file_base: Option<&Path>,
     ) -> Result<WorkAsset> {
-        let result = Gltf::from_slice(gltf).or_else(|e| {
-            Err(format!(
-                "Parse error in VariationalAsset glTF: {}",
-                e.to_string()
-            ))
-        })?;
+        Self::from_slice_with_options(gltf, default_tag, file_base, &MeldOptions::default())
+    }
 
-        // break the `Gltf` object into a `Root` and a byte blob here
-        let parse = result.document.into_json();
-        let blob = if let Some(blob) = result.blob {
-            assert_that!(parse.buffers.len()).is_equal_to(1);
-            assert_that!(parse.buffers[0].byte_length as usize)
-                .is_less_than_or_equal_to(blob.len());
-            blob
+    /// Constructs a `WorkAsset` from a glTF byte slice, keying its objects with the given options.
+    ///
+    /// See `from_slice()` for details.
+    pub fn from_slice_with_options(
+        gltf: &[u8],
+        default_tag: Option<&Tag>,
+        file_base: Option<&Path>,
+        options: &MeldOptions,
+    ) -> Result<WorkAsset> {
+        Self::check_version(gltf)?;
+        let timer = PhaseTimer::start();
+        let (parse, blob, passthrough) = Self::parse_slice(gltf, options)?;
+        let extra_chunks = Self::read_extra_chunks(gltf, options)?;
+        let mut asset = Self::build(parse, blob, default_tag, file_base, options, false, None)?;
+        asset.passthrough = passthrough;
+        asset.extra_chunks = extra_chunks;
+        asset.timings.construct = timer.elapsed();
+        Ok(asset)
+    }
+
+    /// Constructs a `WorkAsset` from the GLB of a previously exported `VariationalAsset`.
+    ///
+    /// Such an asset was already validated on its way in, so we skip the uniqueness checks, and
+    /// only compute fingerprints for meshes with more than one primitive: a mesh with a single
+    /// primitive needs no disambiguation during a meld. For large variational assets that are
+    /// repeatedly appended to, this avoids the bulk of the construction cost.
+    pub fn from_exported_slice(
+        glb: &[u8],
+        default_tag: &Tag,
+        options: &MeldOptions,
+    ) -> Result<WorkAsset> {
+        let timer = PhaseTimer::start();
+        let (parse, blob, passthrough) = Self::parse_slice(glb, options)?;
+        let extra_chunks = Self::read_extra_chunks(glb, options)?;
+        let mut asset = Self::build(parse, blob, Some(default_tag), None, options, true, None)?;
+        asset.passthrough = passthrough;
+        asset.extra_chunks = extra_chunks;
+        asset.timings.construct = timer.elapsed();
+        Ok(asset)
+    }
+
+    /// Constructs a `WorkAsset` from a glTF that's already been parsed by the `gltf` crate.
+    ///
+    /// Any buffers and images with URIs are resolved relative to `file_base`.
+    pub fn from_gltf(
+        gltf: Gltf,
+        default_tag: Option<&Tag>,
+        file_base: Option<&Path>,
+        options: &MeldOptions,
+    ) -> Result<WorkAsset> {
+        let (parse, blob) = Self::split_gltf(gltf)?;
+        Self::from_root(parse, blob, default_tag, file_base, options)
+    }
+
+    // fail early and clearly on glTF 1.0 & anything else we can't read, rather than let it run
+    // into some inscrutable parse error; JSON that doesn't parse at all is left to the parser
+    fn check_version(gltf: &[u8]) -> Result<()> {
+        #[derive(Deserialize)]
+        struct VersionProbe {
+            asset: Option<AssetVersion>,
+        }
+        #[derive(Deserialize)]
+        struct AssetVersion {
+            version: Option<Value>,
+            #[serde(rename = "minVersion")]
+            min_version: Option<Value>,
+        }
+        let json = match GlbChunk::from_bytes(gltf)? {
+            Some((GlbChunk::JSON(json), _)) => json,
+            _ => gltf,
+        };
+        let asset = match serde_json::from_slice::<VersionProbe>(json) {
+            Ok(VersionProbe { asset: Some(asset) }) => asset,
+            _ => return Ok(()),
+        };
+
+        // versions are "major.minor"; a reader may load any minor version of its major version
+        let major_minor = |version: &Value| -> Option<(u32, u32)> {
+            let mut split = version.as_str()?.splitn(2, '.');
+            Some((split.next()?.parse().ok()?, split.next()?.parse().ok()?))
+        };
+        let show = |version: &Value| {
+            version
+                .as_str()
+                .map_or_else(|| version.to_string(), str::to_owned)
+        };
+        if let Some(version) = &asset.version {
+            if major_minor(version).map_or(true, |(major, _)| major != 2) {
+                return Err(format!(
+                    "Unsupported glTF version {}; only glTF 2.0 is supported.",
+                    show(version)
+                ));
+            }
+        }
+        if let Some(min_version) = &asset.min_version {
+            if major_minor(min_version).map_or(true, |version| version > (2, 0)) {
+                return Err(format!(
+                    "Unsupported glTF version; asset requires glTF {} or later.",
+                    show(min_version)
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    // fail early and clearly on extensions we can't honor, rather than mangle what they describe
+    fn check_required_extensions(parse: &Root) -> Result<()> {
+        let unsupported = extension::unsupported_required(parse);
+        if unsupported.is_empty() {
+            return Ok(());
+        }
+        Err(format!(
+            "Asset requires unsupported extensions: {}. Allow unsupported required extensions \
+             to pass their data through untouched.",
+            unsupported.join(", ")
+        ))
+    }
+
+    // parse a glTF byte slice into a `Root`, a byte blob, and any raw JSON left unparsed
+    fn parse_slice(
+        gltf: &[u8],
+        options: &MeldOptions,
+    ) -> Result<(Root, Vec<u8>, Map<String, Value>)> {
+        if options.lenient {
+            Self::check_lenient_options(options)?;
+            return Self::parse_slice_leniently(gltf, &options.input_limits);
+        }
+        let (json, blob) = Self::split_slice(gltf, &options.input_limits)?;
+        let parse: Root = serde_json::from_slice(json)
+            .map_err(|e| format!("Parse error in VariationalAsset glTF: {}", e.to_string()))?;
+        let parse = Document::from_json(parse)
+            .map_err(|e| format!("Parse error in VariationalAsset glTF: {}", e.to_string()))?
+            .into_json();
+        Self::check_blob(&parse, &blob)?;
+        Ok((parse, blob, Map::new()))
+    }
+
+    // a lenient parse leaves nodes & scenes as raw JSON, so refuse options that work on them,
+    // rather than quietly do nothing
+    fn check_lenient_options(options: &MeldOptions) -> Result<()> {
+        let node_option = if options.node_scoped_meshes {
+            Some("node-scoped meshes")
+        } else if options.node_transforms != NodeTransformPolicy::Ignore {
+            Some("node transforms")
+        } else if options.variant_exporters.needs_nodes() {
+            Some("a variant exporter that works on nodes")
         } else {
-            vec![]
+            None
         };
+        match node_option {
+            Some(option) => Err(format!(
+                "Can't use {} with a lenient parse, which leaves nodes unparsed.",
+                option
+            )),
+            None => Ok(()),
+        }
+    }
 
-        Self::new(parse, blob, default_tag, file_base)
+    // parse only those top-level glTF properties we work on, and return the rest as raw JSON
+    fn parse_slice_leniently(
+        gltf: &[u8],
+        limits: &InputLimits,
+    ) -> Result<(Root, Vec<u8>, Map<String, Value>)> {
+        let (json, blob) = Self::split_slice(gltf, limits)?;
+        let mut json: Map<String, Value> = serde_json::from_slice(json)
+            .map_err(|e| format!("Parse error in VariationalAsset glTF: {}", e.to_string()))?;
+
+        let unparsed: Vec<String> = json
+            .keys()
+            .filter(|key| !PARSED_PROPERTIES.contains(&key.as_str()))
+            .cloned()
+            .collect();
+        let mut passthrough = Map::new();
+        for key in unparsed {
+            if let Some(value) = json.remove(&key) {
+                passthrough.insert(key, value);
+            }
+        }
+
+        let parse: Root = serde_json::from_value(Value::Object(json))
+            .map_err(|e| format!("Parse error in VariationalAsset glTF: {}", e.to_string()))?;
+        Self::check_references(&parse)?;
+        Ok((parse, blob, passthrough))
+    }
+
+    // everything downstream indexes freely by the references between glTF objects, so refuse
+    // any that don't resolve; `Document::from_json()` does this for the non-lenient path
+    fn check_references(parse: &Root) -> Result<()> {
+        let mut errors = vec![];
+        parse.validate(parse, gltf::json::Path::new, &mut |path, error| {
+            errors.push(format!("{}: {}", path(), error))
+        });
+        if errors.is_empty() {
+            return Ok(());
+        }
+        Err(format!("Invalid glTF: {}", errors.join("; ")))
+    }
+
+    // split a glTF byte slice into its JSON and its blob; we read GLB chunks ourselves so that
+    // the BIN chunk is copied exactly once, straight into the blob we then own; any size limits
+    // are checked before that copy, and before the JSON is parsed
+    fn split_slice<'a>(gltf: &'a [u8], limits: &InputLimits) -> Result<(&'a [u8], Vec<u8>)> {
+        let (json, bin) = match GlbChunk::from_bytes(gltf)? {
+            Some((GlbChunk::JSON(json), Some(GlbChunk::BIN(bin)))) => (json, bin),
+            Some((GlbChunk::JSON(json), _)) => (json, &[][..]),
+            _ => (gltf, &[][..]),
+        };
+        limits.check_sizes(json.len(), bin.len())?;
+        Ok((json, bin.to_vec()))
+    }
+
+    // copy out any GLB chunks beyond JSON & BIN, unless we're asked to drop them
+    fn read_extra_chunks(gltf: &[u8], options: &MeldOptions) -> Result<Vec<(u32, Vec<u8>)>> {
+        if options.drop_extra_chunks {
+            return Ok(vec![]);
+        }
+        let extras = match GlbChunk::from_bytes_with_extras(gltf)? {
+            Some((_, _, extras)) => extras,
+            None => vec![],
+        };
+        Ok(extras
+            .into_iter()
+            .filter_map(|chunk| match chunk {
+                GlbChunk::Other(magic, bytes) => Some((magic, bytes.to_vec())),
+                _ => None,
+            })
+            .collect())
+    }
+
+    // break the `Gltf` object into a `Root` and a byte blob
+    fn split_gltf(result: Gltf) -> Result<(Root, Vec<u8>)> {
+        let parse = result.document.into_json();
+        let blob = result.blob.unwrap_or_default();
+        Self::check_blob(&parse, &blob)?;
+        Ok((parse, blob))
+    }
+
+    // a GLB blob must back the one & only buffer
+    fn check_blob(parse: &Root, blob: &[u8]) -> Result<()> {
+        if !blob.is_empty() {
+            if parse.buffers.len() != 1 {
+                return Err(format!(
+                    "A GLB with a binary chunk must have exactly one buffer, not {}.",
+                    parse.buffers.len()
+                ));
+            }
+            if parse.buffers[0].byte_length as usize > blob.len() {
+                return Err(format!(
+                    "Buffer claims {} bytes, but the binary chunk holds only {}.",
+                    parse.buffers[0].byte_length,
+                    blob.len()
+                ));
+            }
+        }
+        Ok(())
     }
 
     /// Constructs a `WorkAsset` given a JSON `Root`, a byte blob, default tag & file base.
@@ -70,10 +385,10 @@ impl WorkAsset {
     /// the JSON. After this step, the asset is entirely self-contained, and the `file_base`
     /// argument is no longer used.
     ///
-    /// Next, we validate/retrieve any default tag embedded using the `KHR_materials_variants`
-    /// extension. This tag must match the `default_tag` provided as argument, if any, and if none
-    /// was provided it must exist in the asset. This ensure `WorkAsset.default_tag` always exists
-    /// and makes sense.
+    /// Next, any variant data in the form of the earlier `FB_material_variants` draft extension is
+    /// converted to `KHR_materials_variants` form. Such legacy assets embed a default tag, which
+    /// must match the `default_tag` provided as argument, if any. Failing both, the default tag
+    /// is simply "default". This ensures `WorkAsset.default_tag` always exists and makes sense.
     ///
     /// Then, we construct `MeldKey` strings for every glTF object we track – `Image`, `Sampler`,
     /// `Texture`, `Material` and `Mesh`. Please consult the `::meld_keys` module for details on
@@ -91,21 +406,100 @@ impl WorkAsset {
     /// parsed and converted to a Tag->MeldKey mapping, filling in `mesh_primitive_variants` and
     /// completing the `WorkAsset` construction.
     pub fn new(
+        parse: Root,
+        blob: Vec<u8>,
+        default_tag: Option<&Tag>,
+        file_base: Option<&Path>,
+    ) -> Result<WorkAsset> {
+        Self::from_root(parse, blob, default_tag, file_base, &MeldOptions::default())
+    }
+
+    /// Constructs a `WorkAsset` from an already parsed, or programmatically generated, glTF.
+    ///
+    /// The `blob` holds the contents of the GLB-style buffer, i.e. the one buffer without a URI,
+    /// if any; it may be empty. Buffers and images with URIs are resolved relative to `file_base`.
+    /// The asset then goes through exactly the same transformation & validation as it would had
+    /// it been loaded with `from_slice_with_options()`; see `new()` for details.
+    pub fn from_root(
+        root: Root,
+        blob: Vec<u8>,
+        default_tag: Option<&Tag>,
+        file_base: Option<&Path>,
+        options: &MeldOptions,
+    ) -> Result<WorkAsset> {
+        Self::check_references(&root)?;
+        Self::build(root, blob, default_tag, file_base, options, false, None)
+    }
+
+    // the cache, if any, comes with the bytes of the source file it keys the asset by
+    fn build(
         mut parse: Root,
         mut blob: Vec<u8>,
         default_tag: Option<&Tag>,
         file_base: Option<&Path>,
+        options: &MeldOptions,
+        trusted: bool,
+        cache: Option<(&MeldCache, &[u8])>,
     ) -> Result<WorkAsset> {
-        Self::transform_parse(&mut parse, &mut blob, file_base)?;
+        let mut timings = PhaseTimings::default();
+        let construct_timer = PhaseTimer::start();
+
+        // external buffers & images count towards the blob limit as they're about to be read
+        let limits = &options.input_limits;
+        limits.check_counts(&parse)?;
+        if !options.allow_unsupported_required {
+            Self::check_required_extensions(&parse)?;
+        }
+        let timer = PhaseTimer::start();
+        let mut uris = UriResolver::new(file_base, options);
+        let payloads = Self::transform_parse(&mut parse, &mut blob, &mut uris)?;
+        if options.node_scoped_meshes {
+            scope_meshes_to_nodes(&mut parse)?;
+        }
+        if options.node_transforms == NodeTransformPolicy::Bake {
+            bake_node_transforms(&mut parse, &mut blob)?;
+        }
+        timings.transform = timer.elapsed();
+        limits.check_sizes(0, blob.len())?;
+
+        // bring any asset from the days of the `FB_material_variants` draft up to date
+        let legacy_tag = extension::upgrade_legacy(&mut parse)?;
+        // and take out any variants in conventions of its own, before its objects are keyed
+        let imported = options.variant_importers.import(&mut parse)?;
 
         let default = Tag::from("default");
-        let tag = default_tag.unwrap_or(&default);
+        let tag = match (default_tag, &legacy_tag) {
+            (Some(tag), Some(legacy_tag)) if tag != legacy_tag => {
+                return Err(format!(
+                    "Default tag {} doesn't match the legacy asset's default tag {}.",
+                    tag, legacy_tag
+                ));
+            }
+            (Some(tag), _) => tag,
+            (None, Some(legacy_tag)) => legacy_tag,
+            (None, None) => &default,
+        };
+        let tag = options.tag_policy.apply(tag)?;
+
+        let cache_key = match cache {
+            Some((_, source)) => Some(MeldCache::content_key(source, uris.file_stamps(), options)?),
+            None => None,
+        };
 
         let mut asset = WorkAsset {
             parse,
             blob,
-            default_tag: tag.to_owned(),
+            default_tag: tag,
+            options: options.clone(),
+            extension_revision: None,
+            passthrough: Map::new(),
+            extra_chunks: vec![],
+            load_warnings: uris.into_warnings(),
             mesh_primitive_variants: vec![],
+            strings: StringTable::default(),
+            variant_order: vec![],
+            tag_displays: HashMap::new(),
+            tag_payloads: HashMap::new(),
 
             image_keys: vec![],
             material_keys: vec![],
@@ -114,27 +508,117 @@ impl WorkAsset {
             texture_keys: vec![],
 
             mesh_primitive_fingerprints: vec![],
+
+            timings: PhaseTimings::default(),
         };
 
-        // there is a strict dependency order here which must be observed
-        asset.image_keys = asset.build_meld_keys(&asset.parse.images)?;
-        asset.sampler_keys = asset.build_meld_keys(&asset.parse.samplers)?;
-        asset.texture_keys = asset.build_meld_keys(&asset.parse.textures)?;
-        asset.material_keys = asset.build_meld_keys(&asset.parse.materials)?;
-        asset.mesh_keys = asset.build_meld_keys(&asset.parse.meshes)?;
-        asset.mesh_primitive_fingerprints = asset.build_fingerprints()?;
+        let cached = match (cache, &cache_key) {
+            (Some((cache, _)), Some(key)) => cache.load(key),
+            _ => None,
+        };
+
+        if let Some(entry) = cached.filter(|entry| asset.matches_cache_entry(entry)) {
+            asset.image_keys = entry.image_keys;
+            asset.sampler_keys = entry.sampler_keys;
+            asset.texture_keys = entry.texture_keys;
+            asset.material_keys = entry.material_keys;
+            asset.mesh_keys = entry.mesh_keys;
+            asset.mesh_primitive_fingerprints = entry.mesh_primitive_fingerprints;
+        } else {
+            // there is a strict dependency order here which must be observed
+            let timer = PhaseTimer::start();
+            asset.image_keys = asset.build_meld_keys(&asset.parse.images)?;
+            asset.sampler_keys = asset.build_meld_keys(&asset.parse.samplers)?;
+            asset.texture_keys = asset.build_meld_keys(&asset.parse.textures)?;
+            asset.material_keys = asset.build_meld_keys(&asset.parse.materials)?;
+            asset.mesh_keys = asset.build_meld_keys(&asset.parse.meshes)?;
+            timings.keys = timer.elapsed();
+
+            let timer = PhaseTimer::start();
+            asset.mesh_primitive_fingerprints = asset.build_fingerprints(trusted)?;
+            timings.fingerprints = timer.elapsed();
+
+            if let (Some((cache, _)), Some(key)) = (cache, &cache_key) {
+                cache.store(key, &asset.to_cache_entry())?;
+            }
+        }
 
-        asset.ensure_unique_mesh_keys()?;
-        asset.ensure_uniqueish_fingerprints()?;
+        if !trusted {
+            asset.ensure_unique_mesh_keys()?;
+            asset.ensure_uniqueish_fingerprints()?;
+        }
 
+        asset.extension_revision = extension::detect_revision(&asset.parse)?;
         let variant_lookup = extension::get_variant_lookup(&asset.parse)?;
-        let mesh_primitive_variants = asset.map_variants(variant_lookup)?;
-        asset.mesh_primitive_variants = mesh_primitive_variants;
+        let variant_lookup = Self::normalize_variant_lookup(variant_lookup, options)?;
+        let mut variant_ixs: Vec<usize> = variant_lookup.keys().cloned().collect();
+        variant_ixs.sort_unstable();
+        asset.variant_order = variant_ixs
+            .iter()
+            .map(|ix| variant_lookup[ix].clone())
+            .collect();
+        for (ix, display) in extension::get_variant_displays(&asset.parse)? {
+            if let Some(tag) = variant_lookup.get(&ix) {
+                asset.tag_displays.insert(tag.to_owned(), display);
+            }
+        }
+        for (ix, payloads) in payloads {
+            if let Some(tag) = variant_lookup.get(&ix) {
+                asset.tag_payloads.insert(tag.to_owned(), payloads);
+            }
+        }
+        let mut mesh_primitive_variants = asset.map_variants(variant_lookup)?;
+        if extension::has_compact_mappings_marker(&asset.parse)? {
+            asset.expand_compact_mappings(&mut mesh_primitive_variants);
+        }
+        let strings = &mut asset.strings;
+        asset.mesh_primitive_variants = mesh_primitive_variants
+            .iter()
+            .map(|mappings| mappings.iter().map(|m| strings.intern_mapping(m)).collect())
+            .collect();
 
+        asset.merge_imported_variants(imported)?;
+
+        if let (Some(separator), false) = (&options.material_tag_separator, trusted) {
+            asset.derive_variants_from_material_names(separator)?;
+        }
+
+        timings.construct = construct_timer.elapsed();
+        asset.timings = timings;
         Ok(asset)
     }
 
-    fn build_meld_keys<T: HasKeyForVariants>(&self, objects: &Vec<T>) -> Result<Vec<MeldKey>> {
+    fn to_cache_entry(&self) -> CacheEntry {
+        CacheEntry::new(
+            self.image_keys.clone(),
+            self.material_keys.clone(),
+            self.mesh_keys.clone(),
+            self.sampler_keys.clone(),
+            self.texture_keys.clone(),
+            self.mesh_primitive_fingerprints.clone(),
+        )
+    }
+
+    // a sanity check that a cache entry at least has the right shape for this asset
+    fn matches_cache_entry(&self, entry: &CacheEntry) -> bool {
+        let parse = &self.parse;
+        entry.image_keys.len() == parse.images.len()
+            && entry.sampler_keys.len() == parse.samplers.len()
+            && entry.texture_keys.len() == parse.textures.len()
+            && entry.material_keys.len() == parse.materials.len()
+            && entry.mesh_keys.len() == parse.meshes.len()
+            && entry.mesh_primitive_fingerprints.len() == parse.meshes.len()
+            && entry
+                .mesh_primitive_fingerprints
+                .iter()
+                .zip(&parse.meshes)
+                .all(|(prints, mesh)| prints.len() == mesh.primitives.len())
+    }
+
+    pub(crate) fn build_meld_keys<T: HasKeyForVariants>(
+        &self,
+        objects: &Vec<T>,
+    ) -> Result<Vec<MeldKey>> {
         let vec_of_results: Vec<Result<MeldKey>> = objects
             .iter()
             .map(|o| o.build_meld_key(self))
@@ -143,14 +627,31 @@ impl WorkAsset {
         vec_of_results.into_iter().collect()
     }
 
-    fn build_fingerprints(&self) -> Result<Vec<Vec<Fingerprint>>> {
-        let gltf = self.to_owned_gltf();
-
+    // if `skip_unambiguous` is set, single-primitive meshes are given no fingerprint at all
+    fn build_fingerprints(&self, skip_unambiguous: bool) -> Result<Vec<Vec<Fingerprint>>> {
+        // baked transforms are already in the vertices, and ignored ones stay out of them
+        let transforms = match self.options.node_transforms {
+            NodeTransformPolicy::Compare => mesh_world_transforms(&self.parse)?,
+            _ => vec![None; self.parse.meshes.len()],
+        };
         let mut result = vec![];
-        for mesh in gltf.meshes() {
+        for (mesh, transform) in self.parse.meshes.iter().zip(transforms) {
             let mut fingerprints = vec![];
-            for primitive in mesh.primitives() {
-                fingerprints.push(build_fingerprint(&primitive, &self.blob)?);
+            if skip_unambiguous && mesh.primitives.len() == 1 {
+                result.push(fingerprints);
+                continue;
+            }
+            let transform = transform.filter(|transform| *transform != IDENTITY);
+            for primitive in &mesh.primitives {
+                let (root, blob, transform) = (&self.parse, &self.blob, transform.as_ref());
+                fingerprints.push(match self.options.fingerprint_algorithm {
+                    FingerprintAlgorithm::Additive => {
+                        build_fingerprint(root, primitive, blob, transform)?
+                    }
+                    FingerprintAlgorithm::Quantized(grid) => {
+                        build_quantized_fingerprint(root, primitive, blob, transform, grid)?
+                    }
+                });
             }
             result.push(fingerprints);
         }
@@ -167,7 +668,12 @@ impl WorkAsset {
             seen.insert(mesh_key);
         }
         if !dups.is_empty() {
-            Err(format!("Aii, non-unique meld keys: {:#?}", dups))
+            let mut dups: Vec<&str> = dups.iter().map(|key| key.as_str()).collect();
+            dups.sort_unstable();
+            Err(format!(
+                "Meshes must have unique names or mesh IDs, but these are repeated: {}",
+                dups.join(", ")
+            ))
         } else {
             Ok(())
         }
@@ -179,9 +685,15 @@ impl WorkAsset {
                 if let Some(other_print) =
                     self.find_almost_equal_fingerprint(mesh_ix, fingerprint, Some(primitive_ix))
                 {
+                    let gap = (fingerprint - fingerprints[other_print]).abs();
+                    let suggestion = if gap > 0.0 {
+                        format!("an epsilon of {:e} would tell them apart", gap / 2.0)
+                    } else {
+                        String::from("no epsilon can tell them apart")
+                    };
                     return Err(format!(
-                        "Can't cope with primitives {} and {} of mesh {} being identical.",
-                        primitive_ix, other_print, mesh_ix
+                        "Can't cope with primitives {} and {} of {} being identical: their fingerprints are {:e} apart, within the epsilon of {:e}; {}.",
+                        primitive_ix, other_print, self.describe_mesh(mesh_ix), gap, self.options.fingerprint_epsilon, suggestion
                     ));
                 }
             }
@@ -190,30 +702,173 @@ impl WorkAsset {
     }
 
     fn map_variants(&self, variant_ix_lookup: HashMap<usize, Tag>) -> Result<Vec<Vec<HashMap<Tag, MeldKey>>>> {
-        let map_material = |(tag, ix): (&MeldKey, &usize)| -> Result<(Tag, MeldKey)> {
-            Ok((tag.to_string(), self.material_keys[*ix].to_owned()))
-        };
-        let map_primitive = |p: &Primitive| -> Result<HashMap<Tag, MeldKey>> {
-            let variant_map = extension::extract_variant_map(p, &variant_ix_lookup)?;
-            variant_map.iter().map(map_material).collect()
-        };
-        let map_mesh = |m: &Mesh| -> Result<Vec<HashMap<Tag, MeldKey>>> {
-            m.primitives.iter().map(map_primitive).collect()
-        };
-        self.parse.meshes.iter().map(map_mesh).collect()
+        let mut result = vec![];
+        for (m_ix, mesh) in self.parse.meshes.iter().enumerate() {
+            let mut mesh_result = vec![];
+            for (p_ix, primitive) in mesh.primitives.iter().enumerate() {
+                let in_primitive = |e| format!("In {}: {}", self.describe_primitive(m_ix, p_ix), e);
+                let variant_map = extension::extract_variant_map(primitive, &variant_ix_lookup)
+                    .map_err(in_primitive)?;
+                let mut primitive_result = HashMap::new();
+                for (tag, ix) in variant_map {
+                    let material_key = self.material_keys.get(ix).ok_or_else(|| {
+                        in_primitive(format!(
+                            "Variant {} references material {}, but there are only {}.",
+                            tag,
+                            ix,
+                            self.material_keys.len()
+                        ))
+                    })?;
+                    primitive_result.insert(tag, material_key.to_owned());
+                }
+                mesh_result.push(primitive_result);
+            }
+            result.push(mesh_result);
+        }
+        Ok(result)
     }
 
-    // ensure the glTF is in the state that WorkAsset expects
+    // apply `MeldOptions::tag_policy` to the tags of the root `KHR_materials_variants` extension
+    fn normalize_variant_lookup(
+        variant_ix_lookup: HashMap<usize, Tag>,
+        options: &MeldOptions,
+    ) -> Result<HashMap<usize, Tag>> {
+        let mut variant_ixs: Vec<usize> = variant_ix_lookup.keys().cloned().collect();
+        variant_ixs.sort_unstable();
+
+        let mut result = HashMap::new();
+        let mut seen = HashMap::new();
+        for ix in variant_ixs {
+            let tag = options
+                .tag_policy
+                .apply(&variant_ix_lookup[&ix])
+                .map_err(|e| format!("Variant {}: {}", ix, e))?;
+            if let Some(previous_ix) = seen.insert(tag.clone(), ix) {
+                return Err(format!(
+                    "Variant {} normalizes to the same tag ({}) as variant {}.",
+                    ix, tag, previous_ix,
+                ));
+            }
+            result.insert(ix, tag);
+        }
+        Ok(result)
+    }
+
+    // see `MeldOptions::material_tag_separator`
+    fn derive_variants_from_material_names(&mut self, separator: &str) -> Result<()> {
+        // group material keys by name stem, then by the tag suffix of their name
+        let mut stem_variants: HashMap<String, HashMap<Tag, MeldKey>> = HashMap::new();
+        let mut material_stems = vec![];
+        for (ix, material) in self.parse.materials.iter().enumerate() {
+            let split = material
+                .name
+                .as_ref()
+                .and_then(|name| name.rfind(separator).map(|pos| name.split_at(pos)))
+                .map(|(stem, suffix)| (stem, &suffix[separator.len()..]))
+                .filter(|(stem, tag)| !stem.is_empty() && !tag.is_empty());
+            if let Some((stem, tag)) = split {
+                let tag = self
+                    .options
+                    .tag_policy
+                    .apply(tag)
+                    .map_err(|e| format!("In {}: {}", self.describe_material(ix), e))?;
+                let key = &self.material_keys[ix];
+                let variants = stem_variants.entry(stem.to_owned()).or_default();
+                if let Some(previous) = variants.insert(tag.clone(), key.to_owned()) {
+                    if previous != *key {
+                        return Err(format!(
+                            "Material {} is named {}{}{} like another, different material.",
+                            ix, stem, separator, tag
+                        ));
+                    }
+                }
+            }
+            material_stems.push(split.map(|(stem, _)| stem.to_owned()));
+        }
+
+        for (m_ix, mesh) in self.parse.meshes.iter().enumerate() {
+            for (p_ix, primitive) in mesh.primitives.iter().enumerate() {
+                let stem = primitive
+                    .material
+                    .and_then(|ix| material_stems[ix.value()].as_ref());
+                if let Some(variants) = stem.and_then(|stem| stem_variants.get(stem)) {
+                    let mapping = &mut self.mesh_primitive_variants[m_ix][p_ix];
+                    for (tag, key) in variants {
+                        if *tag != self.default_tag && !mapping.contains_key(tag.as_str()) {
+                            mapping.insert(self.strings.intern(tag), self.strings.intern(key));
+                        }
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // fold the mappings of any `VariantImporter` into those of the extension, which win out
+    fn merge_imported_variants(&mut self, imported: Vec<Vec<HashMap<Tag, usize>>>) -> Result<()> {
+        for (m_ix, mesh_mappings) in imported.into_iter().enumerate() {
+            for (p_ix, mapping) in mesh_mappings.into_iter().enumerate() {
+                for (tag, material_ix) in mapping {
+                    let tag = self.options.tag_policy.apply(&tag).map_err(|e| {
+                        format!("In {}: {}", self.describe_primitive(m_ix, p_ix), e)
+                    })?;
+                    let mapping = &mut self.mesh_primitive_variants[m_ix][p_ix];
+                    if tag != self.default_tag && !mapping.contains_key(tag.as_str()) {
+                        let key = &self.material_keys[material_ix];
+                        mapping.insert(self.strings.intern(&tag), self.strings.intern(key));
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // put back the mappings that `MeldOptions::compact_variant_mappings` left out on export: on
+    // a compacted primitive with a material, any variant its mapping doesn't list selects that
+    // material; compaction keeps the default tag's entry, so a primitive with no mapping at all
+    // never varied, and is left alone
+    fn expand_compact_mappings(&self, mappings: &mut Vec<Vec<HashMap<Tag, MeldKey>>>) {
+        for (m_ix, mesh) in self.parse.meshes.iter().enumerate() {
+            for (p_ix, primitive) in mesh.primitives.iter().enumerate() {
+                if mappings[m_ix][p_ix].is_empty() {
+                    continue;
+                }
+                if let Some(material_ix) = primitive.material {
+                    let key = &self.material_keys[material_ix.value()];
+                    for tag in &self.variant_order {
+                        mappings[m_ix][p_ix]
+                            .entry(tag.to_owned())
+                            .or_insert_with(|| key.to_owned());
+                    }
+                }
+            }
+        }
+    }
+
+    // ensure the glTF is in the state that WorkAsset expects, returning the variant payloads
+    // by variant index; they're read before buffer views are collapsed, as only the root
+    // extension refers to their views, and collapsing doesn't look there
     fn transform_parse(
         root: &mut Root,
         blob: &mut Vec<u8>,
-        file_base: Option<&Path>,
-    ) -> Result<()> {
+        uris: &mut UriResolver,
+    ) -> Result<HashMap<usize, BTreeMap<String, TagPayload>>> {
         // load from URI any non-GLB buffers
-        Self::transform_buffers(root, blob, file_base)?;
+        Self::transform_buffers(root, blob, uris)?;
         // load from URI any images not already embedded
-        Self::transform_images(root, blob, file_base)?;
-        Ok(())
+        Self::transform_images(root, blob, uris)?;
+        // the payload views get rewritten on export, so they're free to go stale after this
+        let payloads = extension::get_variant_payloads(root, blob)?;
+        // collapse logically identical samplers
+        Self::transform_samplers(root);
+        // collapse identical buffer views, and drop the bytes of the redundant ones
+        if dedupe_buffer_views(root, blob)? > 0 {
+            let keep_unreferenced = has_opaque_view_references(root);
+            *blob = compact_blob(root, blob, keep_unreferenced)?;
+        }
+        // forget any metadata from a previous export; we'll compute our own
+        Metadata::strip_embedded(root)?;
+        Ok(payloads)
     }
 
     // resolve any buffers in the asset that reference URIs, read those files
@@ -222,41 +877,51 @@ impl WorkAsset {
     fn transform_buffers(
         root: &mut Root,
         blob: &mut Vec<u8>,
-        file_base: Option<&Path>,
+        uris: &mut UriResolver,
     ) -> Result<()> {
-        assert_that!(blob.len() % 4).is_equal_to(0);
+        if blob.len() % 4 != 0 {
+            return Err(format!(
+                "Binary chunk length {} is not a multiple of 4.",
+                blob.len()
+            ));
+        }
 
         for buffer in &mut root.buffers {
             if let Some(uri) = &buffer.uri {
-                let mut buffer_bytes = Self::read_from_uri(uri, file_base)?;
-                blob.append(&mut buffer_bytes);
+                let mut buffer_bytes = uris.read(uri, blob.len())?;
+                if blob.is_empty() {
+                    // the common case of a single external buffer needs no copying at all
+                    *blob = buffer_bytes;
+                } else {
+                    blob.append(&mut buffer_bytes);
+                }
                 while (blob.len() % 4) != 0 {
                     blob.push(0x00);
                 }
             }
         }
 
-        set_root_buffer(blob, &mut root.buffers);
+        set_root_buffer(blob, &mut root.buffers)?;
 
         Ok(())
     }
 
     // resolve any images in the asset that reference URIs, read those files and create
     // buffer_views for them and add them + buffer views to the asset.
-    fn transform_images(
-        root: &mut Root,
-        blob: &mut Vec<u8>,
-        file_base: Option<&Path>,
-    ) -> Result<()> {
+    fn transform_images(root: &mut Root, blob: &mut Vec<u8>, uris: &mut UriResolver) -> Result<()> {
         let images = &mut root.images;
         let buffer_views = &mut root.buffer_views;
 
-        for img in images {
+        for (ix, img) in images.iter_mut().enumerate() {
             if img.buffer_view.is_none() {
                 if let Some(uri) = &img.uri {
-                    let image_bytes = Self::read_from_uri(uri, file_base)?;
+                    let image_bytes = uris.read(uri, blob.len())?;
                     let view_ix =
-                        add_buffer_view_from_slice(image_bytes.as_slice(), buffer_views, blob);
+                        add_buffer_view_from_slice(image_bytes.as_slice(), buffer_views, blob)
+                            .map_err(|e| {
+                                let name = img.name.as_ref().or(Some(uri));
+                                format!("{}: {}", describe_object("Image", ix, name), e)
+                            })?;
 
                     img.buffer_view = Some(view_ix);
                     img.mime_type = Some(Self::guess_mime_type(uri)?);
@@ -267,9 +932,38 @@ impl WorkAsset {
         Ok(())
     }
 
+    // samplers that differ only in e.g. name are the same sampler as far as melding goes; keep
+    // only the first of each such group, and point all textures at it
+    fn transform_samplers(root: &mut Root) {
+        let mut kept_keys: Vec<MeldKey> = vec![];
+        let mut kept_samplers = vec![];
+        let mut new_ix = vec![];
+        for sampler in root.samplers.drain(..) {
+            let key = sampler_key(&sampler);
+            match kept_keys.iter().position(|k| *k == key) {
+                Some(ix) => new_ix.push(ix),
+                None => {
+                    new_ix.push(kept_samplers.len());
+                    kept_samplers.push(sampler);
+                    kept_keys.push(key);
+                }
+            }
+        }
+        root.samplers = kept_samplers;
+        for texture in &mut root.textures {
+            texture.sampler = texture
+                .sampler
+                .map(|ix| Index::new(new_ix[ix.value()] as u32));
+        }
+    }
+
     fn guess_mime_type(uri: &String) -> Result<MimeType> {
-        if let Some(extension) = Path::new(uri).extension() {
-            match &extension.to_str().unwrap().to_ascii_lowercase()[..] {
+        let extension = Path::new(uri)
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .map(str::to_ascii_lowercase);
+        if let Some(extension) = extension {
+            match &extension[..] {
                 "jpg" | "jpeg" => {
                     return Ok(MimeType("image/jpeg".to_string()));
                 }
@@ -281,27 +975,4 @@ impl WorkAsset {
         };
         Err(format!("Can't guess mime type of URI: {}", uri))
     }
-
-    fn read_from_uri(uri: &str, file_base: Option<&Path>) -> Result<Vec<u8>> {
-        // this is very temporary, lifted lifted from gltf::import.rs
-        let path = if uri.contains(":") {
-            if uri.starts_with("file://") {
-                &uri["file://".len()..]
-            } else if uri.starts_with("file:") {
-                &uri["file:".len()..]
-            } else {
-                panic!("Can only handle file:// URIs yet.");
-            }
-        } else {
-            &uri[..]
-        };
-        let mut path = PathBuf::from(path);
-        if path.is_relative() {
-            if let Some(file_base) = file_base {
-                path = file_base.join(path);
-            }
-        }
-        Ok(fs::read(path.as_path())
-            .map_err(|e| format!("Error reading file {}: {}", path.display(), e.to_string()))?)
-    }
 }
@@ -0,0 +1,166 @@
+// Copyright (c) Facebook, Inc. and its affiliates. All Rights Reserved
+//
+
+//! Code to match up mesh primitives explicitly, for assets that fingerprints can't cope with.
+
+use serde_derive::{Deserialize, Serialize};
+
+use crate::{Result, WorkAsset};
+
+/// One primitive of one mesh, on one side of a `PrimitiveCorrespondence`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PrimitiveRef {
+    /// The index of the mesh.
+    pub mesh: usize,
+    /// The index of the primitive within the mesh.
+    pub primitive: usize,
+    /// The name of the mesh, for the benefit of whoever edits the file; it's never consulted.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mesh_name: Option<String>,
+}
+
+/// A primitive of the base asset, and the primitive of the melded-in asset it's to match.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct PrimitivePair {
+    /// The primitive of the base asset.
+    pub base: PrimitiveRef,
+    /// The primitive of the melded-in asset.
+    pub other: PrimitiveRef,
+}
+
+/// Explicit matches between the mesh primitives of the two sides of a meld, which take
+/// precedence over matching meshes by name & primitives by fingerprint; see
+/// `WorkAsset::meld_with_correspondence()`.
+///
+/// This is for pathological assets, e.g. with primitives too alike for any fingerprint to tell
+/// apart. As JSON, it looks like `{"primitives": [{"base": {"mesh": 0, "primitive": 1},
+/// "other": {"mesh": 2, "primitive": 0}}]}`. `WorkAsset::correspondence_template()` writes one
+/// out to start from.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct PrimitiveCorrespondence {
+    /// The matched primitives, in no particular order.
+    #[serde(default)]
+    pub primitives: Vec<PrimitivePair>,
+}
+
+impl PrimitiveCorrespondence {
+    /// Parses a correspondence from JSON.
+    pub fn from_json(json: &str) -> Result<PrimitiveCorrespondence> {
+        serde_json::from_str(json).map_err(|e| format!("Bad primitive correspondence: {}", e))
+    }
+
+    /// Serialises this correspondence as pretty-printed JSON.
+    pub fn to_json(&self) -> Result<String> {
+        serde_json::to_string_pretty(self)
+            .map_err(|e| format!("Couldn't serialize primitive correspondence: {}", e))
+    }
+
+    /// Whether this correspondence matches no primitives at all.
+    pub fn is_empty(&self) -> bool {
+        self.primitives.is_empty()
+    }
+
+    // the primitive of the melded-in asset matched to the given primitive of the base, if any
+    pub(crate) fn other_for(
+        &self,
+        base_mesh: usize,
+        base_primitive: usize,
+    ) -> Option<&PrimitiveRef> {
+        self.primitives
+            .iter()
+            .find(|pair| pair.base.mesh == base_mesh && pair.base.primitive == base_primitive)
+            .map(|pair| &pair.other)
+    }
+
+    // check that every primitive exists, and none is matched twice
+    pub(crate) fn check(&self, base: &WorkAsset, other: &WorkAsset) -> Result<()> {
+        let exists = |asset: &WorkAsset, primitive: &PrimitiveRef| {
+            asset
+                .meshes()
+                .get(primitive.mesh)
+                .map_or(false, |mesh| primitive.primitive < mesh.primitives.len())
+        };
+        let same =
+            |a: &PrimitiveRef, b: &PrimitiveRef| (a.mesh, a.primitive) == (b.mesh, b.primitive);
+        for (ix, pair) in self.primitives.iter().enumerate() {
+            if !exists(base, &pair.base) {
+                return Err(format!(
+                    "Correspondence entry {}: the base has no mesh {}, primitive {}.",
+                    ix, pair.base.mesh, pair.base.primitive
+                ));
+            }
+            if !exists(other, &pair.other) {
+                return Err(format!(
+                    "Correspondence entry {}: the melded asset has no mesh {}, primitive {}.",
+                    ix, pair.other.mesh, pair.other.primitive
+                ));
+            }
+            let twice = self.primitives[..ix].iter().any(|earlier| {
+                same(&earlier.base, &pair.base) || same(&earlier.other, &pair.other)
+            });
+            if twice {
+                return Err(format!(
+                    "Correspondence entry {}: {} or {} is matched twice.",
+                    ix,
+                    base.describe_primitive(pair.base.mesh, pair.base.primitive),
+                    other.describe_primitive(pair.other.mesh, pair.other.primitive)
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl WorkAsset {
+    /// A `PrimitiveCorrespondence` to start from, for melding *other* into *base*.
+    ///
+    /// Every primitive of every mesh that `meld()` would match up is listed, with its closest
+    /// counterpart by fingerprint, ambiguous or not; and so is every primitive of a mesh that has
+    /// no namesake in *base*, but a likely candidate, as suggested by `diagnose_meld()`. Whoever
+    /// edits the result only needs to fix the entries that are wrong.
+    pub fn correspondence_template(
+        base: &WorkAsset,
+        other: &WorkAsset,
+    ) -> Result<PrimitiveCorrespondence> {
+        let mut mesh_matches = Self::mesh_correspondence(base, other);
+        for problem in Self::diagnose_meld(base, other)?.problems {
+            let likeliest = problem
+                .candidates
+                .iter()
+                .find(|candidate| candidate.fingerprint_distance.is_some());
+            if let Some(candidate) = likeliest {
+                mesh_matches[problem.other_mesh] = Some(candidate.base_mesh);
+            }
+        }
+
+        let primitive_ref = |asset: &WorkAsset, mesh: usize, primitive: usize| PrimitiveRef {
+            mesh,
+            primitive,
+            mesh_name: asset.meshes()[mesh].name.clone(),
+        };
+        let mut correspondence = PrimitiveCorrespondence::default();
+        for (other_mesh_ix, base_mesh_ix) in mesh_matches.into_iter().enumerate() {
+            let base_mesh_ix = match base_mesh_ix {
+                Some(base_mesh_ix) => base_mesh_ix,
+                None => continue,
+            };
+            let primitive_count = base.meshes()[base_mesh_ix].primitives.len();
+            if primitive_count != other.meshes()[other_mesh_ix].primitives.len() {
+                continue;
+            }
+            let base_prints = &base.mesh_primitive_fingerprints[base_mesh_ix];
+            for primitive_ix in 0..primitive_count {
+                let closest = base_prints.get(primitive_ix).and_then(|print| {
+                    let ranked = other.rank_fingerprints(other_mesh_ix, print, None);
+                    ranked.first().map(|&(closest_ix, _)| closest_ix)
+                });
+                correspondence.primitives.push(PrimitivePair {
+                    base: primitive_ref(base, base_mesh_ix, primitive_ix),
+                    other: primitive_ref(other, other_mesh_ix, closest.unwrap_or(primitive_ix)),
+                });
+            }
+        }
+        Ok(correspondence)
+    }
+}
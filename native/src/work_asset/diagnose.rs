@@ -0,0 +1,229 @@
+// Copyright (c) Facebook, Inc. and its affiliates. All Rights Reserved
+//
+
+//! Code to explain why two assets don't meld, and what might make them.
+
+use std::collections::HashSet;
+
+use serde_derive::{Deserialize, Serialize};
+
+use crate::{Result, WorkAsset};
+
+/// Why a mesh of the melded-in asset can't be melded into the base; see `MeshProblem`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum MeshMismatch {
+    /// The base has no mesh of the same name, nor of the same ID; see `MeldOptions::mesh_id_key`.
+    NoSuchName,
+    /// The corresponding mesh of the base has a different number of primitives.
+    PrimitiveCount {
+        /// The number of primitives of the base's mesh.
+        base: usize,
+        /// The number of primitives of the melded-in mesh.
+        other: usize,
+    },
+    /// A primitive of the corresponding mesh of the base matches no primitive of the melded-in
+    /// mesh, or several, by fingerprint.
+    Fingerprint {
+        /// The primitive of the base's mesh.
+        primitive: usize,
+        /// How many primitives of the melded-in mesh are within epsilon of it.
+        matches: usize,
+        /// The distance to the closest primitive of the melded-in mesh, if any.
+        closest_distance: Option<f64>,
+    },
+}
+
+/// A mesh of the base that the melded-in mesh might have been meant to match.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MeshCandidate {
+    /// The index of the base's mesh.
+    pub base_mesh: usize,
+    /// The name of the base's mesh, if it has one.
+    pub name: Option<String>,
+    /// If the two meshes have the same number of primitives, the sum of the distances from each
+    /// of the base's primitives to the closest of the melded-in mesh's; single primitives match
+    /// at a distance of zero, as they do when melding.
+    pub fingerprint_distance: Option<f64>,
+}
+
+/// A mesh of the melded-in asset that can't be melded into the base, and why.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MeshProblem {
+    /// The index of the melded-in mesh.
+    pub other_mesh: usize,
+    /// The name of the melded-in mesh, if it has one.
+    pub name: Option<String>,
+    /// What went wrong.
+    pub mismatch: MeshMismatch,
+    /// For a mesh with no namesake, the base's meshes that nothing else matches, most likely
+    /// first: those with the same number of primitives, by fingerprint distance, then the rest.
+    pub candidates: Vec<MeshCandidate>,
+}
+
+/// Why two assets don't meld, and what might make them; see `WorkAsset::diagnose_meld()`.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct MeldDiagnosis {
+    /// Every mesh of the melded-in asset that can't be melded, in order.
+    pub problems: Vec<MeshProblem>,
+    /// A fingerprint epsilon with which every primitive of the meshes matched up by name would
+    /// match exactly one other, if the current one doesn't do that and there is such a value.
+    pub suggested_epsilon: Option<f64>,
+    /// Whether some primitives can't be told apart by any epsilon, e.g. mirrored copies of the
+    /// same geometry, which quantized fingerprints may be able to distinguish.
+    pub ambiguous_fingerprints: bool,
+}
+
+impl MeldDiagnosis {
+    /// Whether no problems were found, i.e. the two assets should meld as they are.
+    pub fn is_empty(&self) -> bool {
+        self.problems.is_empty()
+    }
+}
+
+impl WorkAsset {
+    /// Explains why melding *other* into *base* fails, or would fail.
+    ///
+    /// This goes through the same matching of meshes and primitives as `meld()`, but rather than
+    /// stop at the first problem, it collects them all, and along with each mesh that has no
+    /// namesake in *base*, lists the meshes that might be meant instead. Where a different
+    /// `MeldOptions::fingerprint_epsilon` would have matched every primitive, that's suggested.
+    pub fn diagnose_meld(base: &WorkAsset, other: &WorkAsset) -> Result<MeldDiagnosis> {
+        let mut diagnosis = MeldDiagnosis::default();
+        let epsilon = other.options.fingerprint_epsilon;
+
+        // the bounds any working epsilon must lie between
+        let (mut lower, mut upper) = (0.0f64, std::f64::INFINITY);
+        let mut fingerprint_problems = false;
+        let mut claimed = HashSet::new();
+
+        let correspondence = WorkAsset::mesh_correspondence(base, other);
+        for (other_mesh_ix, other_mesh) in other.meshes().iter().enumerate() {
+            let name = other_mesh.name.clone();
+            let base_mesh_ix = match correspondence[other_mesh_ix] {
+                Some(base_mesh_ix) => base_mesh_ix,
+                None => {
+                    diagnosis.problems.push(MeshProblem {
+                        other_mesh: other_mesh_ix,
+                        name,
+                        mismatch: MeshMismatch::NoSuchName,
+                        candidates: vec![],
+                    });
+                    continue;
+                }
+            };
+            claimed.insert(base_mesh_ix);
+
+            let base_count = base.meshes()[base_mesh_ix].primitives.len();
+            let other_count = other_mesh.primitives.len();
+            if base_count != other_count {
+                diagnosis.problems.push(MeshProblem {
+                    other_mesh: other_mesh_ix,
+                    name,
+                    mismatch: MeshMismatch::PrimitiveCount {
+                        base: base_count,
+                        other: other_count,
+                    },
+                    candidates: vec![],
+                });
+                continue;
+            }
+
+            let base_prints = &base.mesh_primitive_fingerprints[base_mesh_ix];
+            for (primitive_ix, base_print) in base_prints.iter().enumerate() {
+                let ranked = other.rank_fingerprints(other_mesh_ix, base_print, None);
+                let gap = ranked.first().map(|&(_, gap)| gap);
+                let next_gap = ranked.get(1).map_or(std::f64::INFINITY, |&(_, gap)| gap);
+                if let Some(gap) = gap {
+                    lower = lower.max(gap);
+                    upper = upper.min(next_gap);
+                    if gap == next_gap {
+                        diagnosis.ambiguous_fingerprints = true;
+                    }
+                }
+                let matches = ranked.iter().filter(|(_, gap)| *gap < epsilon).count();
+                if matches != 1 {
+                    fingerprint_problems = true;
+                    diagnosis.problems.push(MeshProblem {
+                        other_mesh: other_mesh_ix,
+                        name: name.clone(),
+                        mismatch: MeshMismatch::Fingerprint {
+                            primitive: primitive_ix,
+                            matches,
+                            closest_distance: gap,
+                        },
+                        candidates: vec![],
+                    });
+                }
+            }
+        }
+
+        if fingerprint_problems && lower < upper {
+            diagnosis.suggested_epsilon = Some(if lower > 0.0 {
+                (2.0 * lower).min((lower + upper) / 2.0)
+            } else {
+                upper / 2.0
+            })
+            .filter(|suggestion| suggestion.is_finite() && *suggestion > 0.0);
+        }
+
+        // offer the base's unclaimed meshes to those that have no namesake
+        for problem in &mut diagnosis.problems {
+            if problem.mismatch == MeshMismatch::NoSuchName {
+                problem.candidates = base
+                    .meshes()
+                    .iter()
+                    .enumerate()
+                    .filter(|(base_mesh_ix, _)| !claimed.contains(base_mesh_ix))
+                    .map(|(base_mesh_ix, base_mesh)| MeshCandidate {
+                        base_mesh: base_mesh_ix,
+                        name: base_mesh.name.clone(),
+                        fingerprint_distance: fingerprint_distance(
+                            base,
+                            base_mesh_ix,
+                            other,
+                            problem.other_mesh,
+                        ),
+                    })
+                    .collect();
+                problem.candidates.sort_by(|a, b| {
+                    match (a.fingerprint_distance, b.fingerprint_distance) {
+                        (Some(a), Some(b)) => {
+                            a.partial_cmp(&b).unwrap_or(std::cmp::Ordering::Equal)
+                        }
+                        (Some(_), None) => std::cmp::Ordering::Less,
+                        (None, Some(_)) => std::cmp::Ordering::Greater,
+                        (None, None) => std::cmp::Ordering::Equal,
+                    }
+                });
+            }
+        }
+        Ok(diagnosis)
+    }
+}
+
+// how far apart the primitives of two meshes are, if they have as many primitives; like meld(),
+// this takes single-primitive meshes, which go without fingerprints, to match trivially
+fn fingerprint_distance(
+    base: &WorkAsset,
+    base_mesh_ix: usize,
+    other: &WorkAsset,
+    other_mesh_ix: usize,
+) -> Option<f64> {
+    let base_prints = &base.mesh_primitive_fingerprints[base_mesh_ix];
+    let primitive_count = base.meshes()[base_mesh_ix].primitives.len();
+    if primitive_count != other.meshes()[other_mesh_ix].primitives.len() {
+        return None;
+    }
+    if base_prints.is_empty() && primitive_count == 1 {
+        return Some(0.0);
+    }
+    base_prints
+        .iter()
+        .map(|print| {
+            other
+                .rank_fingerprints(other_mesh_ix, print, None)
+                .first()
+                .map(|&(_, gap)| gap)
+        })
+        .sum()
+}
@@ -0,0 +1,447 @@
+// Copyright (c) Facebook, Inc. and its affiliates. All Rights Reserved
+//
+
+//! Code to compress the geometry of an exported asset with Draco, using the
+//! `KHR_draco_mesh_compression` extension.
+//!
+//! We write Draco's sequential mesh encoding, with the triangle indices stored as they are, and
+//! floating-point attributes quantized but neither predicted nor entropy coded. That's not as
+//! compact as the Edgebreaker encoding of Draco's own encoder, but every Draco decoder reads it,
+//! quantization does most of the work, and the results are usually gzipped on the wire besides.
+
+use std::collections::HashSet;
+
+use gltf::json::accessor::{Accessor, ComponentType, GenericComponentType, Type};
+use gltf::json::mesh::{Mode, Primitive, Semantic};
+use gltf::json::validation::Checked;
+use gltf::json::{Index, Root};
+
+use serde_json::{json, Map, Value};
+
+use crate::gltfext::{add_buffer_view_from_slice, compact_blob, set_root_buffer};
+use crate::{DracoSettings, Result};
+
+use super::has_opaque_view_references;
+
+const KHR_DRACO_MESH_COMPRESSION: &str = "KHR_draco_mesh_compression";
+
+// the version of the Draco bitstream we write, and the constants of it we use
+const DRACO_VERSION: [u8; 2] = [2, 2];
+const TRIANGULAR_MESH: u8 = 1;
+const SEQUENTIAL_ENCODING: u8 = 0;
+const UNCOMPRESSED_INDICES: u8 = 1;
+const GENERIC_ATTRIBUTE_DECODER: u8 = 0;
+const QUANTIZATION_ATTRIBUTE_DECODER: u8 = 2;
+const PREDICTION_NONE: i8 = -2;
+const UNCOMPRESSED_VALUES: u8 = 0;
+const MAX_QUANTIZATION_BITS: u8 = 30;
+
+// the elements of an accessor, tightly packed
+struct PackedAccessor {
+    component_type: ComponentType,
+    components: usize,
+    normalized: bool,
+    count: usize,
+    bytes: Vec<u8>,
+}
+
+// a vertex attribute of a primitive, ready to be encoded
+struct DracoAttribute {
+    semantic: String,
+    attribute_type: u8,
+    data_type: u8,
+    quantization_bits: Option<u8>,
+    data: PackedAccessor,
+}
+
+/// Compresses the geometry of each primitive that Draco can represent, and returns the new blob.
+///
+/// Accessors that only compressed primitives use lose their buffer views, whose bytes are then
+/// compacted away. Primitives that aren't indexed triangles, or that have morph targets or
+/// sparse accessors, are left as they are.
+pub fn compress_geometry(
+    root: &mut Root,
+    blob: &[u8],
+    settings: &DracoSettings,
+) -> Result<Vec<u8>> {
+    let all_bits = [
+        settings.position_bits,
+        settings.normal_bits,
+        settings.texcoord_bits,
+        settings.generic_bits,
+    ];
+    if all_bits
+        .iter()
+        .any(|&bits| bits < 1 || bits > MAX_QUANTIZATION_BITS)
+    {
+        return Err(format!(
+            "Draco quantization bits must lie between 1 and {}, not {:?}.",
+            MAX_QUANTIZATION_BITS, settings
+        ));
+    }
+    if has_opaque_view_references(root) {
+        return Err(format!(
+            "Can't compress the geometry of an asset whose extensions may reference it unseen."
+        ));
+    }
+
+    let mut encoded = vec![];
+    for (m_ix, mesh) in root.meshes.iter().enumerate() {
+        for (p_ix, primitive) in mesh.primitives.iter().enumerate() {
+            if let Some(draco) = encode_primitive(root, blob, primitive, settings)? {
+                encoded.push((m_ix, p_ix, draco));
+            }
+        }
+    }
+    if encoded.is_empty() {
+        return Ok(blob.to_vec());
+    }
+
+    // accessors still needed in their uncompressed form keep their buffer views
+    let compressed: HashSet<(usize, usize)> = encoded.iter().map(|(m, p, _)| (*m, *p)).collect();
+    let mut kept = HashSet::new();
+    let mut stripped = HashSet::new();
+    for (m_ix, mesh) in root.meshes.iter().enumerate() {
+        for (p_ix, primitive) in mesh.primitives.iter().enumerate() {
+            if compressed.contains(&(m_ix, p_ix)) {
+                stripped.extend(primitive_accessors(primitive));
+            } else {
+                kept.extend(primitive_accessors(primitive));
+            }
+        }
+    }
+    kept.extend(
+        root.skins
+            .iter()
+            .filter_map(|skin| skin.inverse_bind_matrices)
+            .map(|ix| ix.value()),
+    );
+    for sampler in root.animations.iter().flat_map(|a| &a.samplers) {
+        kept.insert(sampler.input.value());
+        kept.insert(sampler.output.value());
+    }
+    for accessor_ix in stripped.difference(&kept) {
+        let accessor = &mut root.accessors[*accessor_ix];
+        accessor.buffer_view = None;
+        accessor.byte_offset = 0;
+    }
+    let mut blob = compact_blob(root, blob, false)?;
+
+    for (m_ix, p_ix, (bytes, attributes)) in encoded {
+        let view_ix = add_buffer_view_from_slice(&bytes, &mut root.buffer_views, &mut blob)?;
+        let extension = json!({
+            "bufferView": view_ix.value(),
+            "attributes": attributes,
+        });
+        let value = serde_json::to_string(&extension)
+            .and_then(|s| serde_json::from_str(&s))
+            .map_err(|e| format!("Failed to write Draco primitive extension: {}", e))?;
+        root.meshes[m_ix].primitives[p_ix]
+            .extensions
+            .get_or_insert(Default::default())
+            .others
+            .insert(KHR_DRACO_MESH_COMPRESSION.to_owned(), value);
+    }
+    set_root_buffer(&blob, &mut root.buffers)?;
+
+    let name = KHR_DRACO_MESH_COMPRESSION.to_owned();
+    if !root.extensions_used.contains(&name) {
+        root.extensions_used.push(name.clone());
+    }
+    if !root.extensions_required.contains(&name) {
+        root.extensions_required.push(name);
+    }
+    Ok(blob)
+}
+
+// the indices of all the accessors a primitive references
+fn primitive_accessors(primitive: &Primitive) -> Vec<usize> {
+    let target_accessors = primitive
+        .targets
+        .iter()
+        .flatten()
+        .flat_map(|target| vec![target.positions, target.normals, target.tangents])
+        .flatten();
+    primitive
+        .attributes
+        .values()
+        .cloned()
+        .chain(primitive.indices)
+        .chain(target_accessors)
+        .map(|ix| ix.value())
+        .collect()
+}
+
+// the Draco encoding of the primitive, and the mapping of its attribute semantics to Draco's
+// attribute ids, or `None` if it isn't something we can encode
+fn encode_primitive(
+    root: &Root,
+    blob: &[u8],
+    primitive: &Primitive,
+    settings: &DracoSettings,
+) -> Result<Option<(Vec<u8>, Map<String, Value>)>> {
+    match primitive.mode {
+        Checked::Valid(Mode::Triangles) if primitive.targets.is_none() => {}
+        _ => return Ok(None),
+    }
+    let indices = match primitive.indices {
+        Some(ix) => match read_packed(root, blob, ix)? {
+            Some(indices) => indices,
+            None => return Ok(None),
+        },
+        None => return Ok(None),
+    };
+    let indices = match read_indices(&indices) {
+        Some(indices) if !indices.is_empty() && indices.len() % 3 == 0 => indices,
+        _ => return Ok(None),
+    };
+
+    let mut attributes = vec![];
+    for (semantic, accessor_ix) in &primitive.attributes {
+        let semantic = match semantic {
+            Checked::Valid(semantic) => semantic,
+            Checked::Invalid => return Ok(None),
+        };
+        let data = match read_packed(root, blob, *accessor_ix)? {
+            Some(data) => data,
+            None => return Ok(None),
+        };
+        let (attribute_type, bits) = match semantic {
+            Semantic::Positions => (0, settings.position_bits),
+            Semantic::Normals => (1, settings.normal_bits),
+            Semantic::Colors(_) => (2, settings.generic_bits),
+            Semantic::TexCoords(_) => (3, settings.texcoord_bits),
+            _ => (4, settings.generic_bits),
+        };
+        let data_type = match data.component_type {
+            ComponentType::I8 => 1,
+            ComponentType::U8 => 2,
+            ComponentType::I16 => 3,
+            ComponentType::U16 => 4,
+            ComponentType::U32 => 6,
+            ComponentType::F32 => 9,
+        };
+        let quantization_bits = match data.component_type {
+            ComponentType::F32 => Some(bits),
+            _ => None,
+        };
+        attributes.push(DracoAttribute {
+            semantic: semantic.to_string(),
+            attribute_type,
+            data_type,
+            quantization_bits,
+            data,
+        });
+    }
+    attributes.sort_by(|a, b| a.semantic.cmp(&b.semantic));
+
+    // every vertex must be referenced, as Draco can't have more points than face corners
+    let point_count = match attributes.first() {
+        Some(attribute) => attribute.data.count,
+        None => return Ok(None),
+    };
+    if attributes.iter().any(|a| a.data.count != point_count) || point_count > indices.len() {
+        return Ok(None);
+    }
+    if let Some(index) = indices.iter().find(|&&index| index as usize >= point_count) {
+        return Err(format!(
+            "Primitive references vertex {}, but has only {}.",
+            index, point_count
+        ));
+    }
+
+    let bytes = encode_mesh(&indices, point_count, &attributes)?;
+    let ids = attributes
+        .iter()
+        .enumerate()
+        .map(|(id, attribute)| (attribute.semantic.clone(), Value::from(id)))
+        .collect();
+    Ok(Some((bytes, ids)))
+}
+
+// the elements of an accessor, tightly packed, or `None` if they don't live in a buffer view of
+// their own, or aren't vectors
+fn read_packed(root: &Root, blob: &[u8], ix: Index<Accessor>) -> Result<Option<PackedAccessor>> {
+    let accessor = root
+        .accessors
+        .get(ix.value())
+        .ok_or_else(|| format!("Primitive references non-existent accessor {}.", ix))?;
+    let (component_type, components) = match (&accessor.component_type, &accessor.type_) {
+        (Checked::Valid(GenericComponentType(component_type)), Checked::Valid(type_)) => {
+            match type_ {
+                Type::Scalar | Type::Vec2 | Type::Vec3 | Type::Vec4 => {
+                    (*component_type, type_.multiplicity())
+                }
+                _ => return Ok(None),
+            }
+        }
+        _ => return Ok(None),
+    };
+    let view = match accessor.buffer_view {
+        Some(view_ix) if accessor.sparse.is_none() => root
+            .buffer_views
+            .get(view_ix.value())
+            .ok_or_else(|| format!("Accessor {} has non-existent buffer view.", ix))?,
+        _ => return Ok(None),
+    };
+
+    let element_size = components * component_type.size();
+    let stride = view
+        .byte_stride
+        .map_or(element_size, |stride| stride as usize);
+    let view_start = view.byte_offset.unwrap_or(0) as usize;
+    let view_bytes = blob
+        .get(view_start..view_start + view.byte_length as usize)
+        .ok_or_else(|| format!("Accessor {} has a buffer view beyond the blob.", ix))?;
+
+    let count = accessor.count as usize;
+    let mut bytes = Vec::with_capacity(count * element_size);
+    for element in 0..count {
+        let start = accessor.byte_offset as usize + element * stride;
+        let element = view_bytes
+            .get(start..start + element_size)
+            .ok_or_else(|| format!("Accessor {} reaches beyond its buffer view.", ix))?;
+        bytes.extend_from_slice(element);
+    }
+    Ok(Some(PackedAccessor {
+        component_type,
+        components,
+        normalized: accessor.normalized,
+        count,
+        bytes,
+    }))
+}
+
+// the vertex indices of a scalar index accessor, or `None` if it isn't one
+fn read_indices(indices: &PackedAccessor) -> Option<Vec<u32>> {
+    if indices.components != 1 {
+        return None;
+    }
+    let size = indices.component_type.size();
+    let read = |b: &[u8]| match indices.component_type {
+        ComponentType::U8 => Some(b[0] as u32),
+        ComponentType::U16 => Some(u16::from_le_bytes([b[0], b[1]]) as u32),
+        ComponentType::U32 => Some(u32::from_le_bytes([b[0], b[1], b[2], b[3]])),
+        _ => None,
+    };
+    indices.bytes.chunks(size).map(read).collect()
+}
+
+// the Draco bitstream of a triangle mesh with the given indices & attributes
+fn encode_mesh(
+    indices: &[u32],
+    point_count: usize,
+    attributes: &[DracoAttribute],
+) -> Result<Vec<u8>> {
+    let mut out = b"DRACO".to_vec();
+    out.extend_from_slice(&DRACO_VERSION);
+    out.push(TRIANGULAR_MESH);
+    out.push(SEQUENTIAL_ENCODING);
+    out.extend_from_slice(&0u16.to_le_bytes());
+
+    // the connectivity: face & point counts, then the indices in as few bytes as will do
+    write_varint(&mut out, (indices.len() / 3) as u64);
+    write_varint(&mut out, point_count as u64);
+    out.push(UNCOMPRESSED_INDICES);
+    for &index in indices {
+        if point_count < 1 << 8 {
+            out.push(index as u8);
+        } else if point_count < 1 << 16 {
+            out.extend_from_slice(&(index as u16).to_le_bytes());
+        } else if point_count < 1 << 21 {
+            write_varint(&mut out, index as u64);
+        } else {
+            out.extend_from_slice(&index.to_le_bytes());
+        }
+    }
+
+    // a single attributes decoder, which describes every attribute, and how it's encoded
+    out.push(1);
+    write_varint(&mut out, attributes.len() as u64);
+    for (id, attribute) in attributes.iter().enumerate() {
+        out.push(attribute.attribute_type);
+        out.push(attribute.data_type);
+        out.push(attribute.data.components as u8);
+        out.push(attribute.data.normalized as u8);
+        write_varint(&mut out, id as u64);
+    }
+    for attribute in attributes {
+        out.push(match attribute.quantization_bits {
+            Some(_) => QUANTIZATION_ATTRIBUTE_DECODER,
+            None => GENERIC_ATTRIBUTE_DECODER,
+        });
+    }
+
+    // then the values of every attribute, followed by the parameters of the quantized ones
+    let mut parameters = vec![];
+    for attribute in attributes {
+        match attribute.quantization_bits {
+            Some(bits) => {
+                let (symbols, params) = quantize(attribute, bits)?;
+                let max_symbol = symbols.iter().cloned().max().unwrap_or(0);
+                let num_bytes = ((32 - max_symbol.leading_zeros() as usize + 7) / 8).max(1);
+                out.push(PREDICTION_NONE as u8);
+                out.push(UNCOMPRESSED_VALUES);
+                out.push(num_bytes as u8);
+                for symbol in symbols {
+                    out.extend_from_slice(&symbol.to_le_bytes()[..num_bytes]);
+                }
+                parameters.extend(params);
+            }
+            None => out.extend_from_slice(&attribute.data.bytes),
+        }
+    }
+    out.extend(parameters);
+    Ok(out)
+}
+
+// the quantized values of a floating-point attribute, as the zigzag-coded symbols Draco reads,
+// and the parameters its decoder needs to restore them: minima, range & bit count
+fn quantize(attribute: &DracoAttribute, bits: u8) -> Result<(Vec<u32>, Vec<u8>)> {
+    let values: Vec<f32> = attribute
+        .data
+        .bytes
+        .chunks(4)
+        .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+        .collect();
+    if values.iter().any(|value| !value.is_finite()) {
+        return Err(format!(
+            "Can't compress {} attribute with values that aren't finite.",
+            attribute.semantic
+        ));
+    }
+
+    let components = attribute.data.components;
+    let mut min = vec![f32::INFINITY; components];
+    let mut max = vec![f32::NEG_INFINITY; components];
+    for (ix, value) in values.iter().enumerate() {
+        min[ix % components] = min[ix % components].min(*value);
+        max[ix % components] = max[ix % components].max(*value);
+    }
+    let range = (0..components).map(|c| max[c] - min[c]).fold(0.0, f32::max);
+    let range = if range > 0.0 { range } else { 1.0 };
+
+    let scale = ((1u32 << bits) - 1) as f32 / range;
+    let symbols = values
+        .iter()
+        .enumerate()
+        .map(|(ix, value)| (((value - min[ix % components]) * scale + 0.5).floor() as u32) << 1)
+        .collect();
+
+    let mut parameters = vec![];
+    for min in min {
+        parameters.extend_from_slice(&min.to_le_bytes());
+    }
+    parameters.extend_from_slice(&range.to_le_bytes());
+    parameters.push(bits);
+    Ok((symbols, parameters))
+}
+
+// an unsigned LEB128 varint, as Draco writes them
+fn write_varint(out: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        out.push((value as u8 & 0x7f) | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
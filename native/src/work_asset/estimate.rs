@@ -0,0 +1,242 @@
+// Copyright (c) Facebook, Inc. and its affiliates. All Rights Reserved
+//
+
+//! Code to predict the outcome of a meld from meld keys alone, without performing it.
+
+use std::collections::HashSet;
+
+use serde_derive::{Deserialize, Serialize};
+
+use gltf::json::{Index, Material, Texture};
+
+use crate::{MeldCounts, MeldKey, Result, Tag, WorkAsset};
+
+// the approximate JSON growth for each new tag->material mapping on a primitive
+const MAPPING_JSON_BYTES: usize = 32;
+// the approximate JSON growth for each new tag, beyond the tag itself
+const TAG_JSON_BYTES: usize = 16;
+// the GLB header, and the headers of its two chunks
+const GLB_HEADER_BYTES: usize = 12 + 8 + 8;
+
+/// A prediction of what a meld would do; see `WorkAsset::estimate_meld()`.
+///
+/// Object counts are of distinct objects, where `MeldStats` counts every reference to them.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MeldEstimate {
+    /// Images the base already has vs. those that would be copied over.
+    pub images: MeldCounts,
+    /// Samplers the base already has vs. those that would be copied over.
+    pub samplers: MeldCounts,
+    /// Textures the base already has vs. those that would be copied over.
+    pub textures: MeldCounts,
+    /// Materials the base already has vs. those that would be copied over.
+    pub materials: MeldCounts,
+    /// The byte size of the other asset's texture images that the base already has.
+    pub shared_texture_bytes: usize,
+    /// The byte size of the texture images that would be copied over.
+    pub new_texture_bytes: usize,
+    /// Roughly how much the glTF JSON would grow.
+    pub json_bytes_added: usize,
+    /// Tags that would be new to the base, in the order they're first encountered.
+    pub tags_added: Vec<Tag>,
+    /// Roughly the byte size of the melded asset's GLB.
+    pub output_bytes: usize,
+}
+
+// the keys of the objects already accounted for
+#[derive(Default)]
+struct Seen {
+    images: HashSet<MeldKey>,
+    samplers: HashSet<MeldKey>,
+    textures: HashSet<MeldKey>,
+    materials: HashSet<MeldKey>,
+}
+
+impl WorkAsset {
+    /// Predicts what melding *other* into *base* would do, without doing it.
+    ///
+    /// This walks the same tag mappings as `meld()`, but only compares meld keys, and never
+    /// copies anything; it's cheap enough to run interactively, e.g. before a user commits to a
+    /// meld. Primitives are assumed to correspond in order, rather than being matched up by
+    /// fingerprint, and sizes are approximate: the base's unreferenced bytes, which an export
+    /// would compact away, are included, and JSON growth is only roughly estimated.
+    ///
+    /// Meshes without a counterpart in *base*, or with a different number of primitives than
+    /// their counterpart, are an error, just as they are for `meld()`.
+    pub fn estimate_meld(base: &WorkAsset, other: &WorkAsset) -> Result<MeldEstimate> {
+        if let Some(difference) = base.options.meld_incompatibility(&other.options) {
+            return Err(format!(
+                "Can't meld assets loaded with different options: {}.",
+                difference
+            ));
+        }
+        let mut estimate = MeldEstimate::default();
+        let mut seen = Seen::default();
+
+        let mut base_tags: HashSet<Tag> = base.get_tags_in_use()?.into_iter().collect();
+        base_tags.insert(base.default_tag.clone());
+
+        let correspondence = WorkAsset::mesh_correspondence(base, other);
+        for (other_mesh_ix, base_mesh_ix) in correspondence.into_iter().enumerate() {
+            let base_mesh_ix = base_mesh_ix.ok_or_else(|| {
+                format!(
+                    "Melded {} has no mesh of the same name or ID in base.",
+                    other.describe_mesh(other_mesh_ix)
+                )
+            })?;
+            let primitive_count = other.meshes()[other_mesh_ix].primitives.len();
+            let base_primitive_count = base.meshes()[base_mesh_ix].primitives.len();
+            if base_primitive_count != primitive_count {
+                return Err(format!(
+                    "Base {} has {} primitives, but its melded counterpart has {}.",
+                    base.describe_mesh(base_mesh_ix),
+                    base_primitive_count,
+                    primitive_count
+                ));
+            }
+            for p_ix in 0..primitive_count {
+                if base.is_fixed_primitive(base_mesh_ix, p_ix) {
+                    continue;
+                }
+                let base_tags_here = base.primitive_tags(base_mesh_ix, p_ix);
+                for (tag, material_key) in other.primitive_materials(other_mesh_ix, p_ix) {
+                    if base_tags_here.contains(&tag) {
+                        continue;
+                    }
+                    estimate.json_bytes_added += MAPPING_JSON_BYTES;
+                    if !base_tags.contains(&tag) && !estimate.tags_added.contains(&tag) {
+                        estimate.json_bytes_added += TAG_JSON_BYTES + tag.len();
+                        estimate.tags_added.push(tag);
+                    }
+                    estimate_material(base, other, &material_key, &mut seen, &mut estimate)?;
+                }
+            }
+        }
+
+        estimate.output_bytes = json_size(&base.parse)?
+            + estimate.json_bytes_added
+            + base.blob.len()
+            + estimate.new_texture_bytes
+            + GLB_HEADER_BYTES;
+        Ok(estimate)
+    }
+
+    // the tags mapped on the given primitive, including the default tag if it has a material
+    fn primitive_tags(&self, m_ix: usize, p_ix: usize) -> HashSet<Tag> {
+        self.primitive_materials(m_ix, p_ix)
+            .into_iter()
+            .map(|(tag, _)| tag)
+            .collect()
+    }
+
+    // the tag->material key mapping of the given primitive, including its default material
+    fn primitive_materials(&self, m_ix: usize, p_ix: usize) -> Vec<(Tag, MeldKey)> {
+        let mut result: Vec<(Tag, MeldKey)> = self
+            .interned_mapping(m_ix, p_ix)
+            .iter()
+            .filter(|(tag, _)| ***tag != *self.default_tag)
+            .map(|(tag, key)| (tag.to_string(), key.to_string()))
+            .collect();
+        if let Some(material_ix) = self.meshes()[m_ix].primitives[p_ix].material {
+            let key = self.material_keys[material_ix.value()].to_owned();
+            result.push((self.default_tag.clone(), key));
+        }
+        // order deterministically, so that `tags_added` is too
+        result.sort_unstable();
+        result
+    }
+}
+
+fn estimate_material(
+    base: &WorkAsset,
+    other: &WorkAsset,
+    key: &MeldKey,
+    seen: &mut Seen,
+    estimate: &mut MeldEstimate,
+) -> Result<()> {
+    if !seen.materials.insert(key.to_owned()) {
+        return Ok(());
+    }
+    let material_ix = other
+        .material_ix(key)
+        .ok_or_else(|| format!("Huh? No {} to estimate.", other.describe_material_key(key)))?;
+    let material = &other.materials()[material_ix];
+    let reused = base.material_ix(key).is_some();
+    estimate.materials.count(reused);
+    if !reused {
+        estimate.json_bytes_added += json_size(material)?;
+    }
+    // even a reused material's textures are walked, so that shared image bytes are counted
+    for texture_ix in material_textures(material) {
+        estimate_texture(base, other, texture_ix.value(), seen, estimate)?;
+    }
+    Ok(())
+}
+
+fn estimate_texture(
+    base: &WorkAsset,
+    other: &WorkAsset,
+    texture_ix: usize,
+    seen: &mut Seen,
+    estimate: &mut MeldEstimate,
+) -> Result<()> {
+    let key = &other.texture_keys()[texture_ix];
+    if !seen.textures.insert(key.to_owned()) {
+        return Ok(());
+    }
+    let texture = &other.textures()[texture_ix];
+    let reused = base.texture_ix(key).is_some();
+    estimate.textures.count(reused);
+    if !reused {
+        estimate.json_bytes_added += json_size(texture)?;
+    }
+
+    let image_ix = texture.source.value();
+    let key = &other.image_keys()[image_ix];
+    if seen.images.insert(key.to_owned()) {
+        let image = &other.images()[image_ix];
+        let size = other.read_image_bytes(image)?.len();
+        let reused = base.image_ix(key).is_some();
+        estimate.images.count(reused);
+        if reused {
+            estimate.shared_texture_bytes += size;
+        } else {
+            // copied images are appended at 4-byte alignment, each with a new buffer view
+            estimate.new_texture_bytes += (size + 3) & !3;
+            estimate.json_bytes_added += json_size(image)? + MAPPING_JSON_BYTES;
+        }
+    }
+
+    if let Some(sampler_ix) = texture.sampler {
+        let key = &other.sampler_keys()[sampler_ix.value()];
+        if seen.samplers.insert(key.to_owned()) {
+            let reused = base.sampler_ix(key).is_some();
+            estimate.samplers.count(reused);
+            if !reused {
+                estimate.json_bytes_added += json_size(&other.samplers()[sampler_ix.value()])?;
+            }
+        }
+    }
+    Ok(())
+}
+
+// the indices of all the textures used by the given material
+fn material_textures(material: &Material) -> Vec<Index<Texture>> {
+    let pbr = &material.pbr_metallic_roughness;
+    vec![
+        pbr.base_color_texture.as_ref().map(|t| t.index),
+        pbr.metallic_roughness_texture.as_ref().map(|t| t.index),
+        material.normal_texture.as_ref().map(|t| t.index),
+        material.occlusion_texture.as_ref().map(|t| t.index),
+        material.emissive_texture.as_ref().map(|t| t.index),
+    ]
+    .into_iter()
+    .filter_map(|ix| ix)
+    .collect()
+}
+
+fn json_size<T: serde::Serialize>(object: &T) -> Result<usize> {
+    serde_json::to_vec(object)
+        .map(|json| json.len())
+        .map_err(|e| format!("JSON serialisation error: {}", e))
+}
@@ -3,15 +3,31 @@
 
 //! Code to generate a glTF asset from a `WorkAsset` instance.
 
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::sync::Arc;
 
-use gltf::json::{Material, Root};
+use gltf::json::{Index, Material, Root, Texture};
 
+use serde_json::{value::RawValue, Map, Value};
+use sha1::Sha1;
+
+use crate::axes;
 use crate::extension;
-use crate::{AssetSizes, Metadata, Result, Tag, VariationalAsset};
+use crate::meld_keys::MESH_ID_EXTRAS_KEY;
+use crate::{
+    AssetSizes, DedupeSummary, DracoSettings, ImageInfo, Metadata, ObjectCounts, Result, Tag,
+    VariationalAsset,
+};
 
 use crate::glb::GlbChunk;
-
+use crate::gltfext::{
+    check_buffer_view_bounds, check_position_bounds, compact_blob, get_slice_from_buffer_view,
+};
+
+#[cfg(feature = "draco-compression")]
+use super::draco::compress_geometry;
+#[cfg(feature = "meshopt-compression")]
+use super::meshopt::compress_vertex_views;
 use super::*;
 
 impl<'a> WorkAsset {
@@ -25,12 +41,15 @@ impl<'a> WorkAsset {
     ///   and wherever there is a non-empty variational Tag->Material mapping, convert that to
     ///   `KHR_materials_variants` form and write it into the mesh primitive's JSON.
     ///
+    /// Along the way, the binary blob is compacted: any bytes not covered by a buffer view are
+    /// dropped, as are buffer views nothing refers to. The savings are noted in the metadata.
+    ///
     /// Next, we count up all the metadata.
     ///
     /// Finally, the binary glTF (GLB) blob is generated, by serialising the glTF JSON into
     /// text form, and merging it with the binary blob (see `::crate::glb` for details.)
     pub fn export(&self) -> Result<VariationalAsset> {
-        let (parse, blob, metadata) = self.prepare_for_export()?;
+        let (parse, blob, metadata, sidecars) = self.prepare_for_export()?;
         let default_tag = self.default_tag.clone();
         let glb = self.build_glb_for_export(parse, blob.as_slice())?;
 
@@ -38,45 +57,249 @@ impl<'a> WorkAsset {
             glb,
             default_tag,
             metadata,
+            sidecars,
+            load_warnings: self.load_warnings.clone(),
+            work_asset: None,
         })
     }
 
-    fn prepare_for_export(&self) -> Result<(Root, Vec<u8>, Metadata)> {
-        // clone our Root & blob for new export
+    /// Exports this `WorkAsset` as `export()` does, and hangs on to it in the result if
+    /// `MeldOptions::keep_work_assets` says so.
+    ///
+    /// A later meld of the result with the same `MeldOptions` then uses this `WorkAsset` as-is,
+    /// rather than parsing, keying and fingerprinting the exported GLB all over again.
+    pub fn into_exported(self) -> Result<VariationalAsset> {
+        let mut exported = self.export()?;
+        if self.options.keep_work_assets && self.options.texture_encodings.is_empty() {
+            exported.work_asset = Some(Arc::new(self));
+        }
+        Ok(exported)
+    }
+
+    /// Exports this `WorkAsset` to GLB as `export()` does, but with the geometry of its mesh
+    /// primitives compressed with Draco, per `KHR_draco_mesh_compression`, which viewers must
+    /// then support.
+    ///
+    /// Floating-point vertex attributes are quantized as the settings say; other attributes, and
+    /// the triangle indices, are kept exactly. Primitives Draco can't represent, e.g. because
+    /// they have morph targets, are left as they are.
+    ///
+    /// This is for delivering finished assets: we can't decode Draco, so the result can't be
+    /// melded further. Compression requires building with the draco-compression feature.
+    pub fn export_draco(&self, settings: &DracoSettings) -> Result<Vec<u8>> {
+        let (mut root, blob, _, _) = self.prepare_for_export()?;
+        let blob = compress_geometry(&mut root, &blob, settings)?;
+        self.build_glb_for_export(root, &blob)
+    }
+
+    /// Exports this `WorkAsset` to GLB as `export()` does, but with its vertex data compressed
+    /// with the meshoptimizer codec, per `EXT_meshopt_compression`, which viewers must then
+    /// support.
+    ///
+    /// Compression is lossless, and applies to the buffer views that hold only vertex attributes
+    /// in a layout the codec takes; index buffers & images are left as they are.
+    ///
+    /// Like `export_draco()`, this is for delivering finished assets: the result can't be melded
+    /// further. Compression requires building with the meshopt-compression feature.
+    pub fn export_meshopt(&self) -> Result<Vec<u8>> {
+        let (mut root, blob, _, _) = self.prepare_for_export()?;
+        let (blob, extensions) = compress_vertex_views(&mut root, &blob)?;
+
+        // gltf-json has no room for these extensions, so we write them into the JSON directly
+        let mut json = serde_json::to_value(&root)
+            .map_err(|e| format!("JSON deserialisation error: {}", e))?;
+        if let Value::Object(ref mut map) = json {
+            map.extend(self.passthrough.clone());
+        }
+        // alongside whatever extensions the object already has
+        for (property, ix, extension) in extensions {
+            let existing = &mut json[property][ix]["extensions"];
+            if !existing.is_object() {
+                *existing = Value::Object(Map::new());
+            }
+            if let (Value::Object(existing), Value::Object(extension)) = (existing, extension) {
+                existing.extend(extension);
+            }
+        }
+        let json = serde_json::to_vec_pretty(&json)
+            .map_err(|e| format!("JSON deserialisation error: {}", e))?;
+        self.build_glb_from_json(&json, &blob)
+    }
+
+    /// Exports this `WorkAsset` as `export()` does, then checks the result before returning it.
+    ///
+    /// The produced GLB is parsed anew, its buffer views are checked to lie within its blob, and
+    /// its variant mappings are extracted and compared with our own `mesh_primitive_variants`.
+    /// Any discrepancy is returned as an error, rather than as a subtly broken asset.
+    pub fn export_verified(&self) -> Result<VariationalAsset> {
+        let exported = self.export()?;
+        let reparsed =
+            WorkAsset::from_exported_slice(exported.glb(), &self.default_tag, &self.options)
+                .map_err(|e| format!("Verification failed; exported asset doesn't parse: {}", e))?;
+
+        check_buffer_view_bounds(&reparsed.parse, &reparsed.blob)
+            .map_err(|e| format!("Verification failed: {}", e))?;
+
+        if reparsed.parse.meshes.len() != self.parse.meshes.len() {
+            return Err(format!(
+                "Verification failed: {} meshes were exported as {}.",
+                self.parse.meshes.len(),
+                reparsed.parse.meshes.len()
+            ));
+        }
+        for (m_ix, mesh) in self.parse.meshes.iter().enumerate() {
+            for p_ix in 0..mesh.primitives.len() {
+                let ours = self.effective_variant_mapping(m_ix, p_ix);
+                let theirs = reparsed.effective_variant_mapping(m_ix, p_ix);
+                if ours != theirs {
+                    return Err(format!(
+                        "Verification failed: {} was exported with variant mapping {:?} rather than {:?}.",
+                        self.describe_primitive(m_ix, p_ix), theirs, ours
+                    ));
+                }
+            }
+        }
+        Ok(exported)
+    }
+
+    // the primitive's variant mapping resolved to material indices, with the default tag always
+    // mapped to its material, if any; this is invariant under export, which only writes out the
+    // default tag when it must, and may re-encode images and thus change material keys
+    fn effective_variant_mapping(&self, m_ix: usize, p_ix: usize) -> Option<HashMap<Tag, usize>> {
+        let primitive = self.parse.meshes[m_ix].primitives.get(p_ix)?;
+        let mut mapping = HashMap::new();
+        for (tag, material_key) in self.interned_mapping(m_ix, p_ix) {
+            if **tag != *self.default_tag {
+                mapping.insert(tag.to_string(), self.material_ix(material_key)?);
+            }
+        }
+        if let Some(material_ix) = primitive.material {
+            mapping.insert(self.default_tag.clone(), material_ix.value());
+        }
+        Some(mapping)
+    }
+
+    fn prepare_for_export(&self) -> Result<(Root, Vec<u8>, Metadata, BTreeMap<String, Vec<u8>>)> {
+        // clone our Root, re-encode any textures as configured, and compact the blob
         let mut root = self.parse.clone();
-        let blob = self.blob.clone();
+        let source_blob = self.reencode_textures(&mut root)?;
+        let keep_unreferenced = has_opaque_view_references(&root);
+        let mut blob = compact_blob(&mut root, &source_blob, keep_unreferenced)?;
+        let compacted_len = blob.len();
+
+        // check or fix the bounds of vertex positions, as configured
+        self.export_position_bounds(&mut root, &blob)?;
 
         // make note of the use of our glTF extension
-        extension::install(&mut root);
+        extension::install(&mut root, self.options.require_variants_extension);
+
+        // let meshes keep their identity across renames, for later melds
+        self.export_mesh_ids(&mut root)?;
 
         // then mutate the clone with our variational state
-        self.export_variant_root_lookup(&mut root)?;
+        self.export_variant_root_lookup(&mut root, &mut blob)?;
 
         let variant_ix_lookup = extension::get_variant_lookup(&root)?;
 
         // finally write out the tag->material_ix mapping to glTF JSON
-        let metadata = self.export_variant_mapping(&mut root, &variant_ix_lookup)?;
+        let mut metadata = self.export_variant_mapping(&mut root, &blob, &variant_ix_lookup)?;
+
+        // optionally duplicate it all for viewers that only know the older draft extension
+        if self.options.write_legacy_extension {
+            extension::write_legacy(&mut root, &self.default_tag)?;
+        }
+
+        // and in any other conventions asked for, which may come with sidecar files
+        let exporters = &self.options.variant_exporters;
+        let mut sidecars = BTreeMap::new();
+        if !exporters.is_empty() {
+            let document = extension::read(&root)?;
+            sidecars = exporters.export(&mut root, &document)?;
+        }
 
-        Ok((root, blob, metadata))
+        metadata.blob_bytes_saved = source_blob.len().saturating_sub(compacted_len);
+
+        // optionally make the asset self-describing
+        if self.options.embed_metadata {
+            metadata.embed(&mut root, &self.default_tag)?;
+        }
+
+        Ok((root, blob, metadata, sidecars))
+    }
+
+    fn export_position_bounds(&self, root: &mut Root, blob: &Vec<u8>) -> Result<()> {
+        match self.options.position_bounds {
+            BoundsPolicy::Keep => {}
+            BoundsPolicy::Verify => {
+                let wrong = check_position_bounds(root, blob, false)?;
+                if !wrong.is_empty() {
+                    return Err(format!(
+                        "Position accessors {:?} have min/max bounds that don't match their data.",
+                        wrong
+                    ));
+                }
+            }
+            BoundsPolicy::Recompute => {
+                check_position_bounds(root, blob, true)?;
+            }
+        }
+        Ok(())
+    }
+
+    // stamp each mesh's meld key into its extras, where keying looks first; other extras are
+    // kept, but a mesh whose extras aren't a JSON object is left alone, and keyed by name
+    fn export_mesh_ids(&self, root: &mut Root) -> Result<()> {
+        for (mesh, key) in root.meshes.iter_mut().zip(&self.mesh_keys) {
+            let mut extras = match &mesh.extras {
+                Some(raw) => match serde_json::from_str(raw.get()) {
+                    Ok(Value::Object(map)) => map,
+                    _ => continue,
+                },
+                None => Map::new(),
+            };
+            extras.insert(MESH_ID_EXTRAS_KEY.to_owned(), Value::from(key.as_str()));
+            let json = Value::Object(extras).to_string();
+            mesh.extras =
+                Some(RawValue::from_string(json).map_err(|e| format!("Bad mesh extras: {}", e))?);
+        }
+        Ok(())
     }
 
-    fn export_variant_root_lookup(&self, root: &mut Root) -> Result<()> {
+    fn export_variant_root_lookup(&self, root: &mut Root, blob: &mut Vec<u8>) -> Result<()> {
         let tags_in_use = self.get_tags_in_use()?;
-        extension::write_root_variant_lookup_map(root, &tags_in_use)
+        extension::write_root_variant_lookup_map(root, &tags_in_use)?;
+        extension::write_variant_displays(root, &self.tag_displays)?;
+        extension::write_variant_payloads(root, blob, &self.tag_payloads)?;
+        // so that a reload knows to put back what compaction leaves out
+        if self.options.compact_variant_mappings {
+            extension::write_compact_mappings_marker(root)?;
+        }
+        Ok(())
     }
 
     // export our `mesh_primitive_variants` member into glTF form, by transforming the
     // tag->material_key mapping of each mesh/primitive to a tag->material_ix one, then
     // finally calling the glTF extension code to actually convert it to JSON.
-    fn export_variant_mapping(&self, root: &mut Root, variant_ix_lookup: &HashMap<usize, Tag>) -> Result<Metadata> {
+    fn export_variant_mapping(
+        &self,
+        root: &mut Root,
+        blob: &Vec<u8>,
+        variant_ix_lookup: &HashMap<usize, Tag>,
+    ) -> Result<Metadata> {
         let mut image_sizer = ImageSizes::new(&self);
+        let revision = self
+            .options
+            .extension_revision
+            .or(self.extension_revision)
+            .unwrap_or_default();
+        let mut variational_materials = HashSet::new();
 
         // for each mesh...
         for (m_ix, mesh) in root.meshes.iter_mut().enumerate() {
             // and for each of that mesh's primitives...
             for (p_ix, primitive) in mesh.primitives.iter_mut().enumerate() {
                 // retrieve the mapping of tag->material_key
-                let variant_mapping = self.variant_mapping(m_ix, p_ix);
+                let variant_mapping = self.interned_mapping(m_ix, p_ix);
 
                 // prepare to build the mapping of tag->material_ix
                 let mut tag_to_ix = HashMap::new();
@@ -85,7 +308,7 @@ impl<'a> WorkAsset {
                 for (tag, material_key) in variant_mapping.iter() {
                     // map the material key to a glTF material index...
                     if let Some(material_ix) = self.material_ix(material_key) {
-                        if *tag == self.default_tag {
+                        if **tag == *self.default_tag {
                             // there may be a mapping for the default tag, but if so the primitive
                             // must have a default material too, and they must match, and we do
                             // not keep or count it – it's treated elsewhere further down
@@ -94,25 +317,35 @@ impl<'a> WorkAsset {
                                     continue;
                                 }
                                 return Err(format!(
-                                    "Huh? Default material {} != variant map entry {} of default tag {}.",
-                                    default_material_ix,
-                                    material_ix, self.default_tag
-                            ));
+                                    "Huh? {} has default {}, but default tag {} maps to {}.",
+                                    self.describe_primitive(m_ix, p_ix),
+                                    self.describe_material(default_material_ix.value()),
+                                    self.default_tag,
+                                    self.describe_material(material_ix)
+                                ));
                             }
                             return Err(format!(
-                                "Huh? No default material, but variant map entry {} of default tag {} ",
-                                material_ix, self.default_tag
+                                "Huh? {} has no default material, but default tag {} maps to {}.",
+                                self.describe_primitive(m_ix, p_ix),
+                                self.default_tag,
+                                self.describe_material(material_ix)
                             ));
                         }
 
                         // place it into the tag->material_ix mapping
-                        tag_to_ix.insert(tag.to_owned(), material_ix);
+                        tag_to_ix.insert(tag.to_string(), material_ix);
+                        variational_materials.insert(material_ix);
 
                         // and update metadata
                         image_sizer.accumulate_material(material_ix, true);
                         image_sizer.accumulate_tagged_material(material_ix, tag);
                     } else {
-                        return Err(format!("Huh? Non-existent meld key: {}", material_key));
+                        return Err(format!(
+                            "Huh? {} maps tag {} to {}.",
+                            self.describe_primitive(m_ix, p_ix),
+                            tag,
+                            self.describe_material_key(material_key)
+                        ));
                     }
                 }
 
@@ -127,27 +360,58 @@ impl<'a> WorkAsset {
                     if is_variational {
                         // only map the default tag if there's other tags already in the mapping
                         tag_to_ix.insert(self.default_tag.clone(), default_material_ix);
+                        variational_materials.insert(default_material_ix);
+                    }
+
+                    // variants the extension doesn't list select the primitive's material anyway;
+                    // the default tag stays, so a reload can tell which primitives were compacted
+                    if self.options.compact_variant_mappings {
+                        let default_tag = &self.default_tag;
+                        tag_to_ix
+                            .retain(|tag, &mut ix| ix != default_material_ix || tag == default_tag);
                     }
                 };
 
-                extension::write_variant_map(primitive, &tag_to_ix, &variant_ix_lookup)?;
+                extension::write_variant_map(primitive, &tag_to_ix, &variant_ix_lookup, revision)?;
             }
         }
 
         // ask metadata sizer to count up all the totals
-        let (total_image_size, variational_image_size, per_tag_image_size) = image_sizer.count()?;
+        let (total_image_size, variational_image_size, per_tag_image_size, exclusive_image_size) =
+            image_sizer.count(root, blob)?;
         // use it to create an authoritative set of all variational tags
-        let tags: HashSet<Tag> = per_tag_image_size.keys().cloned().collect();
+        let tags: BTreeSet<Tag> = per_tag_image_size.keys().cloned().collect();
+
+        let dedupe = image_sizer.dedupe(root, blob)?;
+        let images = image_sizer.image_infos(root, blob)?;
+        let per_tag_exclusive_images = image_sizer.exclusive_images();
+        let per_tag_exclusive_materials = image_sizer.exclusive_materials();
 
         // use it also to create the Tag->AssetSize mapping
-        let per_tag_sizes: HashMap<Tag, AssetSizes> = tags
+        let per_tag_sizes: BTreeMap<Tag, AssetSizes> = tags
             .iter()
             .map(|tag| (tag.to_owned(), AssetSizes::new(per_tag_image_size[tag])))
             .collect();
+        let per_tag_exclusive_sizes: BTreeMap<Tag, AssetSizes> = tags
+            .iter()
+            .map(|tag| (tag.to_owned(), AssetSizes::new(exclusive_image_size[tag])))
+            .collect();
+
+        // structured tags also contribute to a per-axis view of the variants
+        let axes = axes::collect_axes(&tags)?;
+
+        let tag_displays = self
+            .tag_displays
+            .iter()
+            .filter(|(tag, _)| tags.contains(*tag))
+            .map(|(tag, display)| (tag.to_owned(), display.clone()))
+            .collect();
 
         // finally construct & return the Metadata structure
         Ok(Metadata {
             tags,
+            axes,
+            tag_displays,
             total_sizes: AssetSizes {
                 texture_bytes: total_image_size,
             },
@@ -155,32 +419,83 @@ impl<'a> WorkAsset {
                 texture_bytes: variational_image_size,
             },
             per_tag_sizes,
+            per_tag_exclusive_sizes,
+            per_tag_exclusive_images,
+            per_tag_exclusive_materials,
+            blob_bytes_saved: 0,
+            counts: ObjectCounts {
+                materials: root.materials.len(),
+                variational_materials: variational_materials.len(),
+                textures: root.textures.len(),
+                images: root.images.len(),
+                samplers: root.samplers.len(),
+            },
+            dedupe,
+            images,
         })
     }
 
     // given a `Root` and a binary blob, create an actual GLB file
-    fn build_glb_for_export(&self, export_parse: Root, export_blob: &[u8]) -> Result<Vec<u8>> {
-        let json = export_parse.to_string_pretty();
-        let json = json
-            .map(|s| s.into_bytes())
-            .map_err(|e| format!("JSON deserialisation error: {}", e))?;
+    pub(crate) fn build_glb_for_export(
+        &self,
+        export_parse: Root,
+        export_blob: &[u8],
+    ) -> Result<Vec<u8>> {
+        let json = self.build_json_for_export(export_parse)?;
+        self.build_glb_from_json(&json, export_blob)
+    }
 
-        let json_chunk = GlbChunk::JSON(&json);
+    // the given export root as JSON, with any passthrough properties spliced back in
+    pub(crate) fn build_json_for_export(&self, export_parse: Root) -> Result<Vec<u8>> {
+        let json = if self.passthrough.is_empty() {
+            export_parse.to_string_pretty()
+        } else {
+            // splice back in whatever top-level JSON we were asked to carry through untouched
+            serde_json::to_value(&export_parse).and_then(|mut value| {
+                if let Value::Object(ref mut map) = value {
+                    map.extend(self.passthrough.clone());
+                }
+                serde_json::to_string_pretty(&value)
+            })
+        };
+        json.map(|s| s.into_bytes())
+            .map_err(|e| format!("JSON deserialisation error: {}", e))
+    }
+
+    // put the given glTF JSON & blob together with our extra chunks, into GLB
+    fn build_glb_from_json(&self, json: &[u8], export_blob: &[u8]) -> Result<Vec<u8>> {
+        let json_chunk = GlbChunk::JSON(json);
         let bin_chunk = if !export_blob.is_empty() {
             Some(GlbChunk::BIN(export_blob))
         } else {
             None
         };
 
-        Ok(GlbChunk::to_bytes(json_chunk, bin_chunk)?)
+        Ok(GlbChunk::to_bytes_with_extras(
+            json_chunk,
+            bin_chunk,
+            self.extra_glb_chunks(),
+        )?)
+    }
+
+    // our extra chunks, to be written back out verbatim
+    pub(crate) fn extra_glb_chunks(&self) -> Vec<GlbChunk> {
+        self.extra_chunks
+            .iter()
+            .map(|(magic, bytes)| GlbChunk::Other(*magic, bytes))
+            .collect()
     }
 }
 
+type TagSizes = HashMap<Tag, usize>;
+
 struct ImageSizes<'a> {
     asset: &'a WorkAsset,
     all_images: HashSet<usize>,
     variational_images: HashSet<usize>,
     per_tag_images: HashMap<Tag, HashSet<usize>>,
+    variational_materials: HashSet<usize>,
+    per_tag_materials: HashMap<Tag, HashSet<usize>>,
 }
 
 impl<'a> ImageSizes<'a> {
@@ -190,32 +505,121 @@ impl<'a> ImageSizes<'a> {
             all_images: HashSet::new(),
             variational_images: HashSet::new(),
             per_tag_images: HashMap::new(),
+            variational_materials: HashSet::new(),
+            per_tag_materials: HashMap::new(),
         }
     }
     fn accumulate_material(&mut self, ix: usize, is_variational: bool) {
-        let materials = self.asset.materials();
-        accumulate_material_into_set(&materials[ix], &mut self.all_images);
+        let asset = self.asset;
+        accumulate_material_into_set(asset, &asset.materials()[ix], &mut self.all_images);
         if is_variational {
-            accumulate_material_into_set(&materials[ix], &mut self.variational_images);
+            let set = &mut self.variational_images;
+            accumulate_material_into_set(asset, &asset.materials()[ix], set);
+            self.variational_materials.insert(ix);
         }
     }
 
-    fn accumulate_tagged_material(&mut self, ix: usize, tag: &Tag) {
-        let materials = self.asset.materials();
+    fn accumulate_tagged_material(&mut self, ix: usize, tag: &str) {
+        let asset = self.asset;
         let image_set = self
             .per_tag_images
             .entry(tag.to_owned())
             .or_insert(HashSet::new());
-        accumulate_material_into_set(&materials[ix], image_set);
+        accumulate_material_into_set(asset, &asset.materials()[ix], image_set);
+        self.per_tag_materials
+            .entry(tag.to_owned())
+            .or_insert(HashSet::new())
+            .insert(ix);
+    }
+
+    // for each tag, the images that only its variational materials use, in order of index
+    fn exclusive_images(&self) -> BTreeMap<Tag, Vec<usize>> {
+        exclusive_to_tags(&self.per_tag_images, &self.variational_images)
     }
 
-    fn count(&self) -> Result<(usize, usize, HashMap<Tag, usize>)> {
+    // for each tag, the variational materials that only it uses, in order of index
+    fn exclusive_materials(&self) -> BTreeMap<Tag, Vec<usize>> {
+        exclusive_to_tags(&self.per_tag_materials, &self.variational_materials)
+    }
+
+    // a summary of how images are shared between tags, and of images stored more than once
+    fn dedupe(&self, root: &Root, blob: &Vec<u8>) -> Result<DedupeSummary> {
+        let mut summary = DedupeSummary::default();
+        let mut stored: HashMap<MeldKey, HashSet<(Option<u32>, u32)>> = HashMap::new();
+
+        let mut image_ixs: Vec<&usize> = self.all_images.iter().collect();
+        image_ixs.sort_unstable();
+        for &image_ix in image_ixs {
+            let size = image_size(root, blob, image_ix)?;
+            let tag_count = self
+                .per_tag_images
+                .values()
+                .filter(|image_set| image_set.contains(&image_ix))
+                .count();
+            match tag_count {
+                0 => continue,
+                1 => summary.exclusive_bytes += size,
+                _ => summary.shared_bytes += size,
+            }
+            *summary.bytes_by_tag_count.entry(tag_count).or_insert(0) += size;
+
+            // identical bytes at a different place in the blob are an extra copy
+            if let Some(view_ix) = root.images[image_ix].buffer_view {
+                let view = &root.buffer_views[view_ix.value()];
+                let bytes = get_slice_from_buffer_view(view, blob)?;
+                let key = Sha1::from(bytes).digest().to_string();
+                let locations = stored.entry(key).or_insert_with(HashSet::new);
+                let location = (view.byte_offset, view.byte_length);
+                if locations.insert(location) && locations.len() > 1 {
+                    summary.duplicated_bytes += size;
+                }
+            }
+        }
+        Ok(summary)
+    }
+
+    // the dimensions & format of each image we can read the header of, in order of index
+    fn image_infos(&self, root: &Root, blob: &Vec<u8>) -> Result<Vec<ImageInfo>> {
+        let mut image_ixs: Vec<&usize> = self.all_images.iter().collect();
+        image_ixs.sort_unstable();
+        let mut infos = vec![];
+        for &image_ix in image_ixs {
+            let bytes = match root.images[image_ix].buffer_view {
+                Some(view_ix) => {
+                    get_slice_from_buffer_view(&root.buffer_views[view_ix.value()], blob)?
+                }
+                None => continue,
+            };
+            if let Some((width, height, channels, format)) = textures::read_image_header(bytes)? {
+                let mut tags: Vec<Tag> = self
+                    .per_tag_images
+                    .iter()
+                    .filter(|(_, image_set)| image_set.contains(&image_ix))
+                    .map(|(tag, _)| tag.to_owned())
+                    .collect();
+                tags.sort_unstable();
+                infos.push(ImageInfo {
+                    image: image_ix,
+                    width,
+                    height,
+                    channels,
+                    format: format.to_owned(),
+                    bytes: bytes.len(),
+                    tags,
+                });
+            }
+        }
+        Ok(infos)
+    }
+
+    // the total, variational, per-tag and per-tag exclusive image sizes
+    fn count(&self, root: &Root, blob: &Vec<u8>) -> Result<(usize, usize, TagSizes, TagSizes)> {
         let mut all = 0;
         let mut variational = 0;
         let mut size_map = HashMap::new();
 
         for image_ix in &self.all_images {
-            let size = image_size(&self.asset, *image_ix)?;
+            let size = image_size(root, blob, *image_ix)?;
             size_map.insert(image_ix, size);
 
             all += size;
@@ -240,29 +644,98 @@ impl<'a> ImageSizes<'a> {
             result
         };
 
-        Ok((all, variational, tagged))
+        // images that only a single tag's variational materials use
+        let exclusive = self
+            .exclusive_images()
+            .into_iter()
+            .map(|(tag, image_ixs)| {
+                let sum = image_ixs
+                    .iter()
+                    .filter_map(|image_ix| size_map.get(image_ix))
+                    .sum();
+                (tag, sum)
+            })
+            .collect();
+
+        Ok((all, variational, tagged, exclusive))
     }
 }
 
-fn image_size(asset: &WorkAsset, image_ix: usize) -> Result<usize> {
-    Ok(asset.read_image_bytes(&asset.images()[image_ix])?.len())
+// for each tag, those of its objects that are variational, and that no other tag uses
+fn exclusive_to_tags(
+    per_tag: &HashMap<Tag, HashSet<usize>>,
+    variational: &HashSet<usize>,
+) -> BTreeMap<Tag, Vec<usize>> {
+    per_tag
+        .iter()
+        .map(|(tag, ix_set)| {
+            let mut exclusive: Vec<usize> = ix_set
+                .iter()
+                .filter(|&ix| variational.contains(ix))
+                .filter(|&ix| {
+                    per_tag
+                        .iter()
+                        .all(|(other, other_set)| other == tag || !other_set.contains(ix))
+                })
+                .cloned()
+                .collect();
+            exclusive.sort_unstable();
+            (tag.clone(), exclusive)
+        })
+        .collect()
+}
+
+// the size of an image as exported, which may differ from ours if it was re-encoded
+fn image_size(root: &Root, blob: &Vec<u8>, image_ix: usize) -> Result<usize> {
+    match root.images[image_ix].buffer_view {
+        Some(view_ix) => {
+            let view = &root.buffer_views[view_ix.value()];
+            Ok(get_slice_from_buffer_view(view, blob)?.len())
+        }
+        None => Err(format!("Internal error: Image with a URI field?!")),
+    }
 }
 
-fn accumulate_material_into_set(material: &Material, image_set: &mut HashSet<usize>) {
+// add the indices of the images used by the textures of the given material to the set
+fn accumulate_material_into_set(
+    asset: &WorkAsset,
+    material: &Material,
+    image_set: &mut HashSet<usize>,
+) {
+    let mut insert = |texture_ix: Index<Texture>| {
+        image_set.insert(asset.textures()[texture_ix.value()].source.value());
+    };
     let pbr = &material.pbr_metallic_roughness;
     if let Some(ref tex_info) = pbr.base_color_texture {
-        image_set.insert(tex_info.index.value());
+        insert(tex_info.index);
     }
     if let Some(ref tex_info) = pbr.metallic_roughness_texture {
-        image_set.insert(tex_info.index.value());
+        insert(tex_info.index);
     }
     if let Some(ref tex_info) = material.normal_texture {
-        image_set.insert(tex_info.index.value());
+        insert(tex_info.index);
     }
     if let Some(ref tex_info) = material.occlusion_texture {
-        image_set.insert(tex_info.index.value());
+        insert(tex_info.index);
     }
     if let Some(ref tex_info) = material.emissive_texture {
-        image_set.insert(tex_info.index.value());
+        insert(tex_info.index);
     }
 }
+
+#[cfg(not(feature = "draco-compression"))]
+fn compress_geometry(_root: &mut Root, _blob: &[u8], _settings: &DracoSettings) -> Result<Vec<u8>> {
+    Err(format!(
+        "Draco compression requires building with the draco-compression feature."
+    ))
+}
+
+#[cfg(not(feature = "meshopt-compression"))]
+fn compress_vertex_views(
+    _root: &mut Root,
+    _blob: &[u8],
+) -> Result<(Vec<u8>, Vec<(&'static str, usize, Value)>)> {
+    Err(format!(
+        "Meshopt compression requires building with the meshopt-compression feature."
+    ))
+}
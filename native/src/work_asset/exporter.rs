@@ -0,0 +1,258 @@
+// Copyright (c) Facebook, Inc. and its affiliates. All Rights Reserved
+//
+
+//! Code to write variants in conventions other than `KHR_materials_variants`, alongside it; see
+//! `MeldOptions::variant_exporters`.
+
+use std::collections::BTreeMap;
+use std::fmt::Debug;
+use std::sync::Arc;
+
+use gltf::json::Root;
+
+use serde_json::value::RawValue;
+use serde_json::{json, Map, Value};
+
+use crate::{Result, VariantDocument};
+
+/// A convention in which to write an asset's variants, in addition to `KHR_materials_variants`,
+/// for tools & engines that don't read the extension.
+///
+/// As an asset is exported, each exporter is handed the glTF about to be written, along with
+/// the variants of its extension, which it may write into the glTF, or into files of its own
+/// that are returned by name, or both. See `VariationalAsset::sidecars()`.
+///
+/// The `Debug` form of an exporter should tell apart exporters that export differently.
+pub trait VariantExporter: Debug + Send + Sync {
+    /// Writes the variants of the document in this convention, returning the contents of any
+    /// sidecar files by file name.
+    fn export(
+        &self,
+        root: &mut Root,
+        document: &VariantDocument,
+    ) -> Result<BTreeMap<String, Vec<u8>>>;
+
+    /// Whether this exporter works on the asset's nodes, which a `lenient` parse leaves as raw
+    /// JSON, out of its reach; such an exporter is refused for a lenient parse.
+    fn needs_nodes(&self) -> bool {
+        false
+    }
+}
+
+/// The exporters of `MeldOptions::variant_exporters`, applied in order.
+///
+/// Two lists are equal when their exporters have the same `Debug` form, one by one.
+#[derive(Clone, Debug, Default)]
+pub struct VariantExporters(pub Vec<Arc<dyn VariantExporter>>);
+
+impl PartialEq for VariantExporters {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.len() == other.0.len()
+            && self
+                .0
+                .iter()
+                .zip(&other.0)
+                .all(|(a, b)| format!("{:?}", a) == format!("{:?}", b))
+    }
+}
+
+impl VariantExporters {
+    /// Runs each exporter in turn, collecting their sidecar files; no two exporters may write
+    /// a sidecar of the same name.
+    pub fn export(
+        &self,
+        root: &mut Root,
+        document: &VariantDocument,
+    ) -> Result<BTreeMap<String, Vec<u8>>> {
+        let mut sidecars = BTreeMap::new();
+        for exporter in &self.0 {
+            for (name, bytes) in exporter.export(root, document)? {
+                if sidecars.insert(name.clone(), bytes).is_some() {
+                    return Err(format!(
+                        "Variant exporter {:?} writes sidecar {}, as an earlier one did.",
+                        exporter, name
+                    ));
+                }
+            }
+        }
+        Ok(sidecars)
+    }
+
+    /// Whether any of the exporters works on the asset's nodes; see `VariantExporter::needs_nodes`.
+    pub fn needs_nodes(&self) -> bool {
+        self.0.iter().any(|exporter| exporter.needs_nodes())
+    }
+
+    /// Whether there are no exporters to run.
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+/// Exports variants into primitive `extras` properties that name a tag after a common prefix,
+/// and hold the index of the material the tag selects, e.g. `"variant_red": 3`, the way some
+/// engine importers like them; `ExtrasVariantImporter` reads them back.
+///
+/// Any properties of the prefix a primitive already has are replaced; other `extras` are kept,
+/// but a primitive whose `extras` aren't a JSON object is left alone.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ExtrasVariantExporter {
+    /// The prefix of the `extras` properties to write, e.g. `variant_`.
+    pub prefix: String,
+}
+
+impl Default for ExtrasVariantExporter {
+    fn default() -> Self {
+        ExtrasVariantExporter {
+            prefix: String::from("variant_"),
+        }
+    }
+}
+
+impl VariantExporter for ExtrasVariantExporter {
+    fn export(
+        &self,
+        root: &mut Root,
+        document: &VariantDocument,
+    ) -> Result<BTreeMap<String, Vec<u8>>> {
+        for (mesh, mesh_mappings) in root.meshes.iter_mut().zip(&document.mappings) {
+            for (primitive, mapping) in mesh.primitives.iter_mut().zip(mesh_mappings) {
+                let mut extras = match &primitive.extras {
+                    Some(raw) => match serde_json::from_str(raw.get()) {
+                        Ok(Value::Object(map)) => map,
+                        _ => continue,
+                    },
+                    None => Map::new(),
+                };
+                extras.retain(|key, _| !key.starts_with(&self.prefix));
+                for (tag, material_ix) in mapping {
+                    let key = format!("{}{}", self.prefix, tag);
+                    extras.insert(key, Value::from(*material_ix));
+                }
+                primitive.extras = if extras.is_empty() {
+                    None
+                } else {
+                    let json = Value::Object(extras).to_string();
+                    Some(
+                        RawValue::from_string(json)
+                            .map_err(|e| format!("Bad primitive extras: {}", e))?,
+                    )
+                };
+            }
+        }
+        Ok(BTreeMap::new())
+    }
+}
+
+/// Exports variants as a JSON switch table in a sidecar file, for runtimes that would rather
+/// not dig through glTF: the variant tags, the material names, and for each primitive of each
+/// mesh, the index of the material each tag selects, e.g.
+///
+/// ```json
+/// {
+///   "variants": ["matte", "shiny"],
+///   "materials": ["lambert1", "lambert1_shiny"],
+///   "meshes": [{ "name": "Pinecone", "primitives": [{ "matte": 0, "shiny": 1 }] }]
+/// }
+/// ```
+///
+/// The glTF itself is left alone.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SidecarVariantExporter {
+    /// The file name to write the table under, e.g. `variants.json`.
+    pub file_name: String,
+}
+
+impl VariantExporter for SidecarVariantExporter {
+    fn export(
+        &self,
+        root: &mut Root,
+        document: &VariantDocument,
+    ) -> Result<BTreeMap<String, Vec<u8>>> {
+        let materials: Vec<Option<&String>> =
+            root.materials.iter().map(|m| m.name.as_ref()).collect();
+        let meshes: Vec<Value> = root
+            .meshes
+            .iter()
+            .zip(&document.mappings)
+            .map(|(mesh, mesh_mappings)| {
+                let primitives: Vec<BTreeMap<&String, usize>> = mesh_mappings
+                    .iter()
+                    .map(|mapping| mapping.iter().map(|(tag, ix)| (tag, *ix)).collect())
+                    .collect();
+                json!({ "name": mesh.name, "primitives": primitives })
+            })
+            .collect();
+        let table = json!({
+            "variants": document.variants,
+            "materials": materials,
+            "meshes": meshes,
+        });
+        let bytes = serde_json::to_vec_pretty(&table)
+            .map_err(|e| format!("Couldn't serialize variant table: {}", e))?;
+
+        let mut sidecars = BTreeMap::new();
+        sidecars.insert(self.file_name.clone(), bytes);
+        Ok(sidecars)
+    }
+}
+
+/// Exports variants as a compact runtime switch table in a sidecar file, for engines without
+/// `KHR_materials_variants` support: for each tag, a list with an entry per node, in glTF node
+/// order, of the material index of each primitive of the node's mesh under that tag, or `null`
+/// for nodes without a mesh, e.g.
+///
+/// ```json
+/// {
+///   "nodes": ["RootNode", "Pinecone"],
+///   "variants": { "matte": [null, [0]], "shiny": [null, [1]] }
+/// }
+/// ```
+///
+/// Every entry is resolved, with primitives that a tag doesn't vary listing their own material,
+/// so that a loader need only assign the materials of the chosen tag, node by node. Node names
+/// are listed for engines that don't keep glTF node order. As it works on nodes, it can't be
+/// used with the `lenient` option, which keeps them out of our reach.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SwitchTableVariantExporter {
+    /// The file name to write the table under, e.g. `switches.json`.
+    pub file_name: String,
+}
+
+impl VariantExporter for SwitchTableVariantExporter {
+    fn export(
+        &self,
+        root: &mut Root,
+        document: &VariantDocument,
+    ) -> Result<BTreeMap<String, Vec<u8>>> {
+        let nodes: Vec<Option<&String>> = root.nodes.iter().map(|n| n.name.as_ref()).collect();
+        let mut variants = Map::new();
+        for tag in &document.variants {
+            let node_materials: Vec<Option<Vec<Option<usize>>>> = root
+                .nodes
+                .iter()
+                .map(|node| {
+                    let m_ix = node.mesh?.value();
+                    let primitives = &root.meshes[m_ix].primitives;
+                    let materials = primitives.iter().enumerate().map(|(p_ix, primitive)| {
+                        let mapped = document.mappings[m_ix][p_ix].get(tag).cloned();
+                        mapped.or_else(|| primitive.material.map(|ix| ix.value()))
+                    });
+                    Some(materials.collect())
+                })
+                .collect();
+            variants.insert(tag.to_owned(), json!(node_materials));
+        }
+        let table = json!({ "nodes": nodes, "variants": variants });
+        let bytes = serde_json::to_vec(&table)
+            .map_err(|e| format!("Couldn't serialize switch table: {}", e))?;
+
+        let mut sidecars = BTreeMap::new();
+        sidecars.insert(self.file_name.clone(), bytes);
+        Ok(sidecars)
+    }
+
+    fn needs_nodes(&self) -> bool {
+        true
+    }
+}
@@ -0,0 +1,173 @@
+// Copyright (c) Facebook, Inc. and its affiliates. All Rights Reserved
+//
+
+//! Code to read variants that assets encode in conventions of their own, rather than through
+//! `KHR_materials_variants`; see `MeldOptions::variant_importers`.
+
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::sync::Arc;
+
+use gltf::json::Root;
+
+use serde_json::value::RawValue;
+use serde_json::{Map, Value};
+
+use crate::{Result, Tag};
+
+/// A convention by which an asset encodes its variants other than in `KHR_materials_variants`.
+///
+/// As an asset is loaded, each importer takes the data of its convention out of the asset, and
+/// returns it as a mapping from tag to material index, for each primitive of each mesh. These
+/// mappings are then folded into those of the extension, if any, which take precedence.
+///
+/// The `Debug` form of an importer should tell apart importers that import differently, as it
+/// goes into the `MeldCache` key of the options.
+pub trait VariantImporter: Debug + Send + Sync {
+    /// Removes the variant data of this convention from the asset, returning for each primitive
+    /// of each mesh the index of the material each tag selects; a primitive without any variant
+    /// data has an empty mapping, and an asset without any may return no mappings at all.
+    fn import(&self, root: &mut Root) -> Result<Vec<Vec<HashMap<Tag, usize>>>>;
+}
+
+/// The importers of `MeldOptions::variant_importers`, applied in order.
+///
+/// Two lists are equal when their importers have the same `Debug` form, one by one.
+#[derive(Clone, Debug, Default)]
+pub struct VariantImporters(pub Vec<Arc<dyn VariantImporter>>);
+
+impl PartialEq for VariantImporters {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.len() == other.0.len()
+            && self
+                .0
+                .iter()
+                .zip(&other.0)
+                .all(|(a, b)| format!("{:?}", a) == format!("{:?}", b))
+    }
+}
+
+impl VariantImporters {
+    /// Runs each importer in turn, merging their mappings; an earlier importer's mapping of a
+    /// tag takes precedence over a later one's.
+    pub fn import(&self, root: &mut Root) -> Result<Vec<Vec<HashMap<Tag, usize>>>> {
+        let mut result: Vec<Vec<HashMap<Tag, usize>>> = root
+            .meshes
+            .iter()
+            .map(|mesh| vec![HashMap::new(); mesh.primitives.len()])
+            .collect();
+        for importer in &self.0 {
+            let mappings = importer.import(root)?;
+            if mappings.len() > result.len() {
+                return Err(format!(
+                    "Variant importer {:?} maps {} meshes, but the asset has {}.",
+                    importer,
+                    mappings.len(),
+                    result.len()
+                ));
+            }
+            for (m_ix, mesh_mappings) in mappings.into_iter().enumerate() {
+                if mesh_mappings.len() > result[m_ix].len() {
+                    return Err(format!(
+                        "Variant importer {:?} maps {} primitives of mesh {}, but it has {}.",
+                        importer,
+                        mesh_mappings.len(),
+                        m_ix,
+                        result[m_ix].len()
+                    ));
+                }
+                for (p_ix, mapping) in mesh_mappings.into_iter().enumerate() {
+                    for (tag, material_ix) in mapping {
+                        if material_ix >= root.materials.len() {
+                            return Err(format!(
+                                "Variant importer {:?} maps tag {} of mesh {} primitive {} to \
+                                 material {}, but there are only {}.",
+                                importer,
+                                tag,
+                                m_ix,
+                                p_ix,
+                                material_ix,
+                                root.materials.len()
+                            ));
+                        }
+                        result[m_ix][p_ix].entry(tag).or_insert(material_ix);
+                    }
+                }
+            }
+        }
+        Ok(result)
+    }
+
+    /// Whether there are no importers to run.
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+/// Imports variants from primitive `extras` properties that name a tag after a common prefix,
+/// and hold the index of the material the tag selects, e.g. `"variant_red": 3`.
+///
+/// Such properties are removed as they're imported, as the material indices they hold won't
+/// survive a meld; any other `extras` are left alone.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ExtrasVariantImporter {
+    /// The prefix of the `extras` properties to import, e.g. `variant_`.
+    pub prefix: String,
+}
+
+impl Default for ExtrasVariantImporter {
+    fn default() -> Self {
+        ExtrasVariantImporter {
+            prefix: String::from("variant_"),
+        }
+    }
+}
+
+impl VariantImporter for ExtrasVariantImporter {
+    fn import(&self, root: &mut Root) -> Result<Vec<Vec<HashMap<Tag, usize>>>> {
+        let mut result = vec![];
+        for (m_ix, mesh) in root.meshes.iter_mut().enumerate() {
+            let mut mesh_mappings = vec![];
+            for (p_ix, primitive) in mesh.primitives.iter_mut().enumerate() {
+                let mut extras: Map<String, Value> = match &primitive.extras {
+                    Some(raw) => match serde_json::from_str(raw.get()) {
+                        Ok(Value::Object(map)) => map,
+                        _ => Map::new(),
+                    },
+                    None => Map::new(),
+                };
+                let keys: Vec<String> = extras
+                    .keys()
+                    .filter(|key| key.len() > self.prefix.len() && key.starts_with(&self.prefix))
+                    .cloned()
+                    .collect();
+
+                let mut mapping = HashMap::new();
+                for key in &keys {
+                    let material_ix = extras[key].as_u64().ok_or_else(|| {
+                        format!(
+                            "Mesh {} primitive {}: extras property {} is not a material index.",
+                            m_ix, p_ix, key
+                        )
+                    })?;
+                    mapping.insert(Tag::from(&key[self.prefix.len()..]), material_ix as usize);
+                    extras.remove(key);
+                }
+                if !keys.is_empty() {
+                    primitive.extras = if extras.is_empty() {
+                        None
+                    } else {
+                        let json = Value::Object(extras).to_string();
+                        Some(
+                            RawValue::from_string(json)
+                                .map_err(|e| format!("Bad primitive extras: {}", e))?,
+                        )
+                    };
+                }
+                mesh_mappings.push(mapping);
+            }
+            result.push(mesh_mappings);
+        }
+        Ok(result)
+    }
+}
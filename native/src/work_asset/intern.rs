@@ -0,0 +1,53 @@
+// Copyright (c) Facebook, Inc. and its affiliates. All Rights Reserved
+//
+
+//! Code to share the strings of tags & material keys among an asset's many variant mappings.
+//!
+//! An asset with hundreds of primitives and a dozen tags would otherwise hold thousands of
+//! copies of the same few strings, each its own allocation, and each cloned again whenever a
+//! mapping is copied during a meld. Interned, a copy is just a reference count.
+
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+
+use crate::{MeldKey, Tag};
+
+/// A tag or key as held in variant mappings; clones share the one allocation.
+pub(crate) type Interned = Arc<str>;
+
+/// The variant mapping of one primitive, from tag to material key, as held by a `WorkAsset`.
+pub(crate) type InternedMapping = HashMap<Interned, Interned>;
+
+/// The strings an asset's variant mappings share, so that each is allocated but once.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct StringTable {
+    strings: HashSet<Interned>,
+}
+
+impl StringTable {
+    /// The shared copy of the given string, added to the table if need be.
+    pub fn intern(&mut self, string: &str) -> Interned {
+        if let Some(interned) = self.strings.get(string) {
+            return interned.clone();
+        }
+        let interned: Interned = Arc::from(string);
+        self.strings.insert(interned.clone());
+        interned
+    }
+
+    /// The given mapping of plain strings, interned.
+    pub fn intern_mapping(&mut self, mapping: &HashMap<Tag, MeldKey>) -> InternedMapping {
+        mapping
+            .iter()
+            .map(|(tag, key)| (self.intern(tag), self.intern(key)))
+            .collect()
+    }
+}
+
+/// The given interned mapping, as plain strings for the public API.
+pub(crate) fn plain_mapping(mapping: &InternedMapping) -> HashMap<Tag, MeldKey> {
+    mapping
+        .iter()
+        .map(|(tag, key)| (tag.to_string(), key.to_string()))
+        .collect()
+}
@@ -7,11 +7,93 @@
 //! one asset, locating its equivalent in another asset, and melding together the tagged material
 //! uses of the two.
 
-use spectral::prelude::*;
+use std::collections::HashSet;
 
-use gltf::json::{buffer::View, texture::Sampler, Image, Index, Material, Texture};
+use serde_derive::{Deserialize, Serialize};
+use serde_json::value::RawValue;
+use serde_json::{json, Map, Value};
 
-use crate::{Result, WorkAsset};
+use gltf::json::{
+    buffer::View, mesh::Primitive, texture::Sampler, Asset, Image, Index, Material, Texture,
+};
+
+use crate::extension;
+use crate::meld_keys::HasKeyForVariants;
+use crate::work_asset::describe_object;
+use crate::work_asset::memory::MemoryBudget;
+use crate::work_asset::timings::PhaseTimer;
+use crate::{
+    AssetInfoPolicy, ColorPolicy, MaterialFilterPolicy, MeldKey, NameMergePolicy, PhaseTimings,
+    PrimitiveCorrespondence, Result, Tag, WorkAsset,
+};
+
+// separates the copyrights of melded assets
+const COPYRIGHT_SEPARATOR: &str = "; ";
+
+// the `asset.extras` property that lists the generators of every asset in a meld
+const CONTRIBUTING_GENERATORS_KEY: &str = "contributingGenerators";
+
+// the `extras` property that lists the other names an image or texture went by in its sources
+const NAME_ALIASES_KEY: &str = "nameAliases";
+
+/// How many references to a category of glTF object a meld resolved, and how.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MeldCounts {
+    /// References that were satisfied by an equivalent object already in the base.
+    pub reused: usize,
+    /// References that required copying a new object over from the other asset.
+    pub copied: usize,
+}
+
+/// Statistics describing what a single meld operation actually did.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MeldStats {
+    /// Reused vs. copied `Image` objects.
+    pub images: MeldCounts,
+    /// Reused vs. copied `Sampler` objects.
+    pub samplers: MeldCounts,
+    /// Reused vs. copied `Texture` objects.
+    pub textures: MeldCounts,
+    /// Reused vs. copied `Material` objects.
+    pub materials: MeldCounts,
+    /// The number of bytes (including alignment padding) appended to the base's binary blob.
+    pub blob_bytes_appended: usize,
+    /// Tags that did not exist anywhere in the base, in the order they were first encountered.
+    pub tags_added: Vec<Tag>,
+    /// The number of mesh primitives that gained at least one new tag mapping.
+    pub primitives_touched: usize,
+    /// The bytes of JSON the meld added to the root `KHR_materials_variants` extension, which
+    /// lists the variants. This is only known once the result is exported, and so is counted
+    /// by `VariationalAsset::meld()`, but not `WorkAsset::meld()`.
+    #[serde(default)]
+    pub variants_root_json_added: usize,
+    /// The bytes of JSON the meld added to the `KHR_materials_variants` mappings of the mesh
+    /// primitives, all told; like `variants_root_json_added`, only once the result is exported.
+    #[serde(default)]
+    pub variants_mapping_json_added: usize,
+    /// How long each phase took, if the crate was built with the `profiling` feature. This
+    /// includes constructing the two source assets, and any export of the result.
+    #[serde(default)]
+    pub timings: PhaseTimings,
+    /// The approximate peak number of bytes of asset data held during the meld, including any
+    /// export of the result; see `MeldOptions::memory_budget`.
+    #[serde(default)]
+    pub peak_memory: usize,
+    /// Whatever about the meld may not be what was intended, e.g. that the melded-in asset gives
+    /// a primitive that's fixed by `MeldOptions::fixed_primitives` a different material.
+    #[serde(default)]
+    pub warnings: Vec<String>,
+}
+
+impl MeldCounts {
+    pub(crate) fn count(&mut self, reused: bool) {
+        if reused {
+            self.reused += 1;
+        } else {
+            self.copied += 1;
+        }
+    }
+}
 
 impl<'a> WorkAsset {
     /// Meld `WorkAsset` *other* into `WorkAsset` *base*, returning the result.
@@ -21,86 +103,529 @@ impl<'a> WorkAsset {
     /// melding textures, which requires melding images sources, and so on. For each such meld, the
     /// object may already exist in *base*, in which case we return its existing index reference, or
     /// it may be new, in which case we copy it over and return the newly created index.
-    pub fn meld(base: &'a WorkAsset, other: &'a WorkAsset) -> Result<WorkAsset> {
+    ///
+    /// Along with the result, we return a `MeldStats` summary of what was reused and copied, and
+    /// of how long constructing the two assets and melding them took.
+    ///
+    /// Either asset may itself be variational, and the result has the union of their tags. Each
+    /// side's default tag is just another tag to the other side; so a tag mapped by both must
+    /// select the same material on every primitive they share, whether it's either side's default
+    /// or not. The base's tags keep their order in the result, and the other side's new tags
+    /// follow in theirs. Should both assets specify how to present the same tag, or attach the
+    /// same payload to it, the base's wins out.
+    ///
+    /// The primitives of `MeldOptions::fixed_primitives` are an exception: they keep the base's
+    /// default material, and lose any variant mappings they had. So are primitives whose default
+    /// material `MeldOptions::material_filter` doesn't let vary: a new tag that selects another
+    /// material for one is an error, or is left out of its mapping, as per the filter's policy.
+    ///
+    /// Melding in an asset whose variants *base* already has is a no-op: in particular, melding
+    /// an asset with itself copies nothing, appends nothing to the blob, and exports to the very
+    /// same bytes; so scripts that meld the same source twice do no harm.
+    pub fn meld(base: &'a WorkAsset, other: &'a WorkAsset) -> Result<(WorkAsset, MeldStats)> {
+        Self::meld_with_correspondence(base, other, &PrimitiveCorrespondence::default())
+    }
+
+    /// Meld `WorkAsset` *other* into `WorkAsset` *base*, as `meld()`, except that the primitives
+    /// *correspondence* matches up are melded together regardless of names & fingerprints.
+    ///
+    /// A mesh of *other* with any primitive in *correspondence* melds into the mesh of *base*
+    /// that primitive is matched to; its other primitives are matched by fingerprint as usual.
+    pub fn meld_with_correspondence(
+        base: &'a WorkAsset,
+        other: &'a WorkAsset,
+        correspondence: &PrimitiveCorrespondence,
+    ) -> Result<(WorkAsset, MeldStats)> {
+        correspondence.check(base, other)?;
+        if let Some(difference) = base.options.meld_incompatibility(&other.options) {
+            return Err(format!(
+                "Can't meld assets loaded with different options: {}.",
+                difference
+            ));
+        }
+        let timer = PhaseTimer::start();
+        let mut memory = MemoryBudget::new(base.options.memory_budget);
+        memory.hold(base.approximate_size(), "the base asset")?;
+        memory.hold(other.approximate_size(), "the melded-in asset")?;
+        memory.hold(base.approximate_size(), "a copy of the base asset")?;
+
         let mut result = base.clone();
-        for (other_mesh_ix, other_mesh_key) in other.mesh_keys.iter().enumerate() {
-            if let Some(base_mesh_ix) = base.mesh_ix(&other_mesh_key) {
+        let mut stats = MeldStats::default();
+        let mut held_blob_len = result.blob.len();
+
+        // the base's displays & payloads win out, should both assets have one for the same tag
+        for (tag, display) in &other.tag_displays {
+            result
+                .tag_displays
+                .entry(tag.to_owned())
+                .or_insert_with(|| display.clone());
+        }
+
+        for (tag, payloads) in &other.tag_payloads {
+            let result_payloads = result.tag_payloads.entry(tag.to_owned()).or_default();
+            for (key, payload) in payloads {
+                result_payloads
+                    .entry(key.to_owned())
+                    .or_insert_with(|| payload.clone());
+            }
+        }
+
+        if base.options.asset_info_policy == AssetInfoPolicy::Merge {
+            merge_asset_info(&mut result.parse.asset, &other.parse.asset)?;
+        }
+
+        // whatever extensions the other side's objects depend on, the result now does too
+        extension::merge_declarations(&mut result.parse, &other.parse);
+
+        // extra GLB chunks are opaque to us; keep one copy of each
+        for chunk in &other.extra_chunks {
+            if !result.extra_chunks.contains(chunk) {
+                result.extra_chunks.push(chunk.clone());
+            }
+        }
+
+        let mut base_tags: HashSet<Tag> = base.get_tags_in_use()?.into_iter().collect();
+        base_tags.insert(base.default_tag.clone());
+
+        // the base's tags keep their places, and the other side's new tags follow in its order
+        let mut variant_order = base.get_tags_in_use()?;
+        variant_order.push(base.default_tag.clone());
+        variant_order.extend(other.get_tags_in_use()?);
+        variant_order.push(other.default_tag.clone());
+        result.variant_order.clear();
+        for tag in variant_order {
+            if !result.variant_order.contains(&tag) {
+                result.variant_order.push(tag);
+            }
+        }
+        // a plain base takes on the revision of the extension a variational other side has
+        result.extension_revision = base.extension_revision.or(other.extension_revision);
+
+        let mut mesh_matches = Self::mesh_correspondence(base, other);
+        for pair in &correspondence.primitives {
+            mesh_matches[pair.other.mesh] = Some(pair.base.mesh);
+        }
+        for (other_mesh_ix, base_mesh_ix) in mesh_matches.into_iter().enumerate() {
+            if let Some(base_mesh_ix) = base_mesh_ix {
                 let base_primitives = &base.meshes()[base_mesh_ix].primitives;
                 let other_primitives = &other.meshes()[other_mesh_ix].primitives;
-                assert_that!(base_primitives.len()).is_equal_to(other_primitives.len());
+                if base_primitives.len() != other_primitives.len() {
+                    return Err(format!(
+                        "Base {} has {} primitives, but its melded counterpart has {}.",
+                        base.describe_mesh(base_mesh_ix),
+                        base_primitives.len(),
+                        other_primitives.len()
+                    ));
+                }
 
                 for primitive_ix in 0..other_primitives.len() {
-                    let mut base_map = base.variant_mapping(base_mesh_ix, primitive_ix).clone();
+                    let mut base_map = base.interned_mapping(base_mesh_ix, primitive_ix).clone();
                     let base_primitive = &base_primitives[primitive_ix];
                     if let Some(base_material) = base_primitive.material {
-                        if !base_map.contains_key(&base.default_tag) {
+                        if !base_map.contains_key(base.default_tag.as_str()) {
                             base_map.insert(
-                                base.default_tag.clone(),
-                                base.material_keys[base_material.value()].to_owned(),
+                                result.strings.intern(&base.default_tag),
+                                result
+                                    .strings
+                                    .intern(&base.material_keys[base_material.value()]),
                             );
                         }
                     }
 
-                    let mut other_map = other.variant_mapping(other_mesh_ix, primitive_ix).clone();
+                    let other_primitive_ix = match correspondence.other_for(base_mesh_ix, primitive_ix) {
+                        Some(explicit) if explicit.mesh == other_mesh_ix => explicit.primitive,
+                        Some(explicit) => {
+                            return Err(format!(
+                                "The correspondence matches base {} to melded {}, but the rest of its mesh to melded {}.",
+                                base.describe_primitive(base_mesh_ix, primitive_ix),
+                                other.describe_primitive(explicit.mesh, explicit.primitive),
+                                other.describe_mesh(other_mesh_ix)
+                            ))
+                        }
+                        None => match_by_fingerprint(base, base_mesh_ix, primitive_ix, other, other_mesh_ix)?,
+                    };
+                    let mut other_map = other
+                        .interned_mapping(other_mesh_ix, other_primitive_ix)
+                        .clone();
 
-                    let base_print = base.mesh_primitive_fingerprints[base_mesh_ix][primitive_ix];
-                    let other_primitive_ix = other
-                        .find_almost_equal_fingerprint(other_mesh_ix, &base_print, None)
-                        .ok_or(format!(
-                            "Melded asset has no equivalent to base mesh {}, primitive {}.",
-                            base_mesh_ix, primitive_ix
-                        ))?;
                     if let Some(other_material) = other_primitives[other_primitive_ix].material {
-                        if !other_map.contains_key(&other.default_tag) {
+                        if !other_map.contains_key(other.default_tag.as_str()) {
                             other_map.insert(
-                                other.default_tag.clone(),
-                                other.material_keys[other_material.value()].to_owned(),
+                                result.strings.intern(&other.default_tag),
+                                result
+                                    .strings
+                                    .intern(&other.material_keys[other_material.value()]),
                             );
                         }
                     }
 
+                    // a fixed primitive keeps the base's default material, whatever's mapped
+                    if base.is_fixed_primitive(base_mesh_ix, primitive_ix) {
+                        let base_key = base_primitive
+                            .material
+                            .map(|ix| base.material_keys[ix.value()].as_str());
+                        if other_map.values().any(|key| Some(&**key) != base_key) {
+                            stats.warnings.push(format!(
+                                "Base {} is fixed, but melded {} gives it a different material.",
+                                base.describe_primitive(base_mesh_ix, primitive_ix),
+                                other.describe_primitive(other_mesh_ix, other_primitive_ix),
+                            ));
+                        }
+                        result.mesh_primitive_variants[base_mesh_ix][primitive_ix].clear();
+                        continue;
+                    }
+
+                    // a primitive without a material uses the glTF spec's default material; if
+                    // the other side's default tag selects a material, that must be made explicit
+                    let base_implicit = !base_map.contains_key(base.default_tag.as_str());
+                    if base_implicit != !other_map.contains_key(other.default_tag.as_str()) {
+                        let (key, ix) = implicit_default_material(&mut result)?;
+                        let key = result.strings.intern(&key);
+                        if base_implicit {
+                            base_map.insert(result.strings.intern(&base.default_tag), key);
+                            let result_primitive =
+                                &mut result.parse.meshes[base_mesh_ix].primitives[primitive_ix];
+                            result_primitive.material = Some(Index::new(ix as u32));
+                        } else {
+                            other_map.insert(result.strings.intern(&other.default_tag), key);
+                        }
+                    }
+
                     let mut result_map = base_map.clone();
+                    let mut touched = false;
+                    let may_vary = material_may_vary(base, base_primitive);
 
                     for other_tag in other_map.keys() {
                         if base_map.contains_key(other_tag) {
                             if base_map[other_tag] != other_map[other_tag] {
                                 return Err(format!(
-                                    "Base[{}/{}] vs Foreign[{}/{}]: Tag {} material mismatch!",
-                                    base_mesh_ix,
-                                    primitive_ix,
-                                    other_mesh_ix,
-                                    primitive_ix,
+                                    "Tag {} selects different materials on base {} ({}) and melded {} ({}).",
                                     other_tag,
+                                    base.describe_primitive(base_mesh_ix, primitive_ix),
+                                    base.describe_material_key(&base_map[other_tag]),
+                                    other.describe_primitive(other_mesh_ix, other_primitive_ix),
+                                    other.describe_material_key(&other_map[other_tag]),
                                 ));
                             }
                             continue;
                         }
                         let other_material_key = &other_map[other_tag];
+                        if !may_vary
+                            && base_map.get(base.default_tag.as_str()) != Some(other_material_key)
+                        {
+                            match base.options.material_filter.policy {
+                                MaterialFilterPolicy::Error => {
+                                    return Err(format!(
+                                        "Tag {} varies the material of base {} ({}), which may not vary.",
+                                        other_tag,
+                                        base.describe_primitive(base_mesh_ix, primitive_ix),
+                                        base_primitive.material.map_or_else(
+                                            || String::from("the glTF default material"),
+                                            |ix| base.describe_material(ix.value())
+                                        ),
+                                    ));
+                                }
+                                MaterialFilterPolicy::Ignore => continue,
+                            }
+                        }
 
                         if let Some(other_material_ix) = other.material_ix(&other_material_key) {
                             let _new_material_ix = meld_in_material(
                                 &mut result,
                                 other,
                                 Index::new(other_material_ix as u32),
-                            );
-                            result_map.insert(other_tag.clone(), other_material_key.clone());
-                        } else {
+                                &mut stats,
+                            )?;
+                        } else if result.material_ix(&other_material_key).is_none() {
+                            // of the materials the other side maps to, only the implicit default
+                            // may be missing from it, having been added to the result above
                             return Err(format!(
-                                "Other[{}/{}]: Material key {} not found!",
-                                other_mesh_ix, primitive_ix, other_material_key
+                                "Melded {} maps tag {} to {}.",
+                                other.describe_primitive(other_mesh_ix, other_primitive_ix),
+                                other_tag,
+                                other.describe_material_key(other_material_key)
                             ));
                         }
+                        result_map.insert(
+                            result.strings.intern(other_tag),
+                            result.strings.intern(other_material_key),
+                        );
+                        touched = true;
+                        if !base_tags.contains(&**other_tag)
+                            && !stats.tags_added.iter().any(|tag| **tag == **other_tag)
+                        {
+                            stats.tags_added.push(other_tag.to_string());
+                        }
+                    }
+                    if touched {
+                        stats.primitives_touched += 1;
                     }
                     result.mesh_primitive_variants[base_mesh_ix][primitive_ix] = result_map;
                 }
+                memory.hold(result.blob.len() - held_blob_len, "melded-in data")?;
+                held_blob_len = result.blob.len();
             } else {
                 return Err(format!(
-                    "meldd mesh #{} has no corresponding mesh in base!",
-                    other_mesh_ix
+                    "Melded {} has no mesh of the same name or ID in base.",
+                    other.describe_mesh(other_mesh_ix)
                 ));
             }
         }
-        Ok(result)
+        if base.options.name_merge_policy != NameMergePolicy::Ignore {
+            merge_object_names(&mut result, other)?;
+        }
+
+        stats.blob_bytes_appended = result.blob.len() - base.blob.len();
+        stats.timings.add(&base.timings);
+        stats.timings.add(&other.timings);
+        stats.timings.meld = timer.elapsed();
+        stats.peak_memory = memory.peak();
+        result.timings = PhaseTimings::default();
+        Ok((result, stats))
+    }
+
+    /// For each mesh of *other*, the index of the mesh of *base* it melds into, if any.
+    ///
+    /// Meshes are matched up by their meld keys, i.e. by name; except that two meshes that both
+    /// carry an authoring tool's ID, as per `MeldOptions::mesh_id_key`, match if and only if
+    /// their IDs do.
+    pub fn mesh_correspondence(base: &WorkAsset, other: &WorkAsset) -> Vec<Option<usize>> {
+        let base_ids = base.mesh_source_ids();
+        let other_ids = other.mesh_source_ids();
+        other
+            .mesh_keys
+            .iter()
+            .zip(&other_ids)
+            .map(|(other_key, other_id)| match other_id {
+                Some(other_id) => base_ids
+                    .iter()
+                    .position(|base_id| base_id.as_ref() == Some(other_id))
+                    .or_else(|| base.mesh_ix(other_key).filter(|&ix| base_ids[ix].is_none())),
+                None => base.mesh_ix(other_key),
+            })
+            .collect()
+    }
+}
+
+// the primitive of the melded-in mesh whose fingerprint alone is within epsilon of the given
+// primitive of the base mesh
+fn match_by_fingerprint(
+    base: &WorkAsset,
+    base_mesh_ix: usize,
+    primitive_ix: usize,
+    other: &WorkAsset,
+    other_mesh_ix: usize,
+) -> Result<usize> {
+    let base_prints = &base.mesh_primitive_fingerprints[base_mesh_ix];
+    match base_prints.get(primitive_ix) {
+        Some(base_print) => {
+            let ranked = other.rank_fingerprints(other_mesh_ix, base_print, None);
+            let epsilon = other.options.fingerprint_epsilon;
+            match ranked.iter().filter(|(_, gap)| *gap < epsilon).count() {
+                1 => Ok(ranked[0].0),
+                matches => {
+                    let problem = match matches {
+                        0 => "no equivalent",
+                        _ => "several equivalents",
+                    };
+                    Err(format!(
+                        "Melded asset has {} to base {}: {}.",
+                        problem,
+                        base.describe_primitive(base_mesh_ix, primitive_ix),
+                        other.describe_fingerprint_gaps(&ranked)
+                    ))
+                }
+            }
+        }
+        // only single-primitive meshes are ever left without fingerprints
+        None if other.meshes()[other_mesh_ix].primitives.len() == 1 => Ok(0),
+        None => Err(format!(
+            "Melded asset has no equivalent to base {}.",
+            base.describe_primitive(base_mesh_ix, primitive_ix)
+        )),
+    }
+}
+
+// combine the `asset` properties of a meld's two sides; see `AssetInfoPolicy::Merge`
+fn merge_asset_info(asset: &mut Asset, other: &Asset) -> Result<()> {
+    if let Some(other_copyright) = &other.copyright {
+        // either side may itself be a meld, crediting several; credit each of them just once
+        let mut credits: Vec<String> = match &asset.copyright {
+            Some(copyright) => copyright
+                .split(COPYRIGHT_SEPARATOR)
+                .map(str::to_owned)
+                .collect(),
+            None => vec![],
+        };
+        for credit in other_copyright.split(COPYRIGHT_SEPARATOR) {
+            if !credits.iter().any(|c| c == credit) {
+                credits.push(credit.to_owned());
+            }
+        }
+        asset.copyright = Some(credits.join(COPYRIGHT_SEPARATOR));
+    }
+
+    let (extras, other_extras) = (parse_extras(asset)?, parse_extras(other)?);
+    let mut generators = contributing_generators(asset, &extras);
+    for generator in contributing_generators(other, &other_extras) {
+        if !generators.contains(&generator) {
+            generators.push(generator);
+        }
+    }
+    asset.generator = asset.generator.take().or_else(|| other.generator.clone());
+
+    let mut extras = match (extras, other_extras) {
+        (Some(Value::Object(mut map)), Some(Value::Object(other_map))) => {
+            for (key, value) in other_map {
+                map.entry(key).or_insert(value);
+            }
+            Some(Value::Object(map))
+        }
+        (extras, other_extras) => extras.or(other_extras),
+    };
+    if generators.len() > 1 {
+        // extras that aren't an object have no room for the list
+        if let Value::Object(map) = extras.get_or_insert_with(|| json!({})) {
+            map.insert(CONTRIBUTING_GENERATORS_KEY.to_owned(), json!(generators));
+        }
+    }
+    asset.extras = extras
+        .map(|extras| RawValue::from_string(extras.to_string()))
+        .transpose()
+        .map_err(|e| format!("Bad asset extras: {}", e))?;
+    Ok(())
+}
+
+fn parse_extras(asset: &Asset) -> Result<Option<Value>> {
+    match &asset.extras {
+        Some(raw) => serde_json::from_str(raw.get())
+            .map(Some)
+            .map_err(|e| format!("Bad asset extras: {}", e)),
+        None => Ok(None),
+    }
+}
+
+// the generators an asset is made from: those listed by an earlier meld, or else its own
+fn contributing_generators(asset: &Asset, extras: &Option<Value>) -> Vec<String> {
+    let listed = extras
+        .as_ref()
+        .and_then(|extras| extras.get(CONTRIBUTING_GENERATORS_KEY))
+        .and_then(Value::as_array);
+    match listed {
+        Some(listed) => listed
+            .iter()
+            .filter_map(Value::as_str)
+            .map(str::to_owned)
+            .collect(),
+        None => asset.generator.iter().cloned().collect(),
+    }
+}
+
+// reconcile the names of those images & textures of *other* that *result* has an equivalent of,
+// as per `MeldOptions::name_merge_policy`; these are all the ones that don't come out of the
+// meld as copies of their own, however the meld came to reuse them
+fn merge_object_names(result: &mut WorkAsset, other: &WorkAsset) -> Result<()> {
+    let policy = result.options.name_merge_policy;
+    for (other_ix, key) in other.image_keys().iter().enumerate() {
+        if let Some(ix) = result.image_ix(key) {
+            let other_image = &other.images()[other_ix];
+            let image = &mut result.parse.images[ix];
+            merge_names(
+                policy,
+                &mut image.name,
+                &mut image.extras,
+                &other_image.name,
+                &other_image.extras,
+            )
+            .map_err(|e| format!("Melded {}: {}", other.describe_image(other_ix), e))?;
+        }
+    }
+    for (other_ix, key) in other.texture_keys().iter().enumerate() {
+        if let Some(ix) = result.texture_ix(key) {
+            let other_texture = &other.textures()[other_ix];
+            let texture = &mut result.parse.textures[ix];
+            merge_names(
+                policy,
+                &mut texture.name,
+                &mut texture.extras,
+                &other_texture.name,
+                &other_texture.extras,
+            )
+            .map_err(|e| {
+                let texture = describe_object("texture", other_ix, other_texture.name.as_ref());
+                format!("Melded {}: {}", texture, e)
+            })?;
+        }
+    }
+    Ok(())
+}
+
+// pick the surviving name of two equivalent objects, and list every other name either side
+// went by, including any aliases of earlier melds, in the survivor's extras
+fn merge_names(
+    policy: NameMergePolicy,
+    name: &mut Option<String>,
+    extras: &mut Option<Box<RawValue>>,
+    other_name: &Option<String>,
+    other_extras: &Option<Box<RawValue>>,
+) -> Result<()> {
+    let mut map = match parse_object_extras(extras)? {
+        Some(Value::Object(map)) => map,
+        // extras that aren't an object have no room for aliases
+        Some(_) => return Ok(()),
+        None => Map::new(),
+    };
+    let old_aliases = name_aliases(map.get(NAME_ALIASES_KEY));
+    let other_aliases = name_aliases(
+        parse_object_extras(other_extras)?
+            .as_ref()
+            .and_then(|extras| extras.get(NAME_ALIASES_KEY)),
+    );
+
+    let survivor = match policy {
+        NameMergePolicy::KeepOther => other_name.clone().or_else(|| name.clone()),
+        _ => name.clone().or_else(|| other_name.clone()),
+    };
+    let ours = old_aliases.iter().chain(name.iter());
+    let theirs = other_aliases.iter().chain(other_name.iter());
+    let mut aliases: Vec<String> = vec![];
+    for alias in ours.chain(theirs) {
+        if Some(alias) != survivor.as_ref() && !aliases.contains(alias) {
+            aliases.push(alias.to_owned());
+        }
+    }
+    *name = survivor;
+
+    // leave the extras alone unless there's news, so that a meld with itself changes nothing
+    if aliases == old_aliases {
+        return Ok(());
+    }
+    map.insert(NAME_ALIASES_KEY.to_owned(), json!(aliases));
+    *extras = Some(
+        RawValue::from_string(Value::Object(map).to_string())
+            .map_err(|e| format!("Bad extras: {}", e))?,
+    );
+    Ok(())
+}
+
+fn parse_object_extras(extras: &Option<Box<RawValue>>) -> Result<Option<Value>> {
+    match extras {
+        Some(raw) => serde_json::from_str(raw.get())
+            .map(Some)
+            .map_err(|e| format!("Bad extras: {}", e)),
+        None => Ok(None),
+    }
+}
+
+// the names listed in a `nameAliases` property, if it's there
+fn name_aliases(listed: Option<&Value>) -> Vec<String> {
+    match listed.and_then(Value::as_array) {
+        Some(listed) => listed
+            .iter()
+            .filter_map(Value::as_str)
+            .map(str::to_owned)
+            .collect(),
+        None => vec![],
     }
 }
 
@@ -109,20 +634,34 @@ impl<'a> WorkAsset {
 // quite a bit. We'll stick with a bit of copy-and-paste boilerplate for now.
 
 /// Meld a glTF `image` (i.e. texture source) from from *other* into *base*.
-fn meld_in_image(base: &mut WorkAsset, other: &WorkAsset, other_ix: Index<Image>) -> Index<Image> {
+fn meld_in_image(
+    base: &mut WorkAsset,
+    other: &WorkAsset,
+    other_ix: Index<Image>,
+    stats: &mut MeldStats,
+) -> Result<Index<Image>> {
     let other_ix = other_ix.value();
     let key = &other.image_keys[other_ix];
-    if let Some(ix) = base.image_ix(key) {
-        return Index::new(ix as u32);
+    let existing_ix = base.image_ix(key);
+    stats.images.count(existing_ix.is_some());
+    if let Some(ix) = existing_ix {
+        return Ok(Index::new(ix as u32));
     }
     let mut new_object = other.images()[other_ix].clone();
 
     // meld logic
-    assert_that!(new_object.buffer_view).is_some();
-    new_object.buffer_view = Some(copy_byte_view(base, other, new_object.buffer_view.unwrap()));
+    let other_view_ix = new_object.buffer_view.ok_or_else(|| {
+        format!(
+            "Melded {} has no buffer view.",
+            other.describe_image(other_ix)
+        )
+    })?;
+    let view_ix = copy_byte_view(base, other, other_view_ix)
+        .map_err(|e| format!("Melded {}: {}", other.describe_image(other_ix), e))?;
+    new_object.buffer_view = Some(view_ix);
     // end meld logic
 
-    Index::new(base.push_image(new_object, key) as u32)
+    Ok(Index::new(base.push_image(new_object, key) as u32))
 }
 
 /// Meld a glTF `sampler` (texture filter/wrap configuration) from *other* into *base*.
@@ -130,10 +669,13 @@ fn meld_in_sampler(
     base: &mut WorkAsset,
     other: &WorkAsset,
     other_ix: Index<Sampler>,
+    stats: &mut MeldStats,
 ) -> Index<Sampler> {
     let other_ix = other_ix.value();
     let key = &other.sampler_keys()[other_ix];
-    if let Some(ix) = base.sampler_ix(key) {
+    let existing_ix = base.sampler_ix(key);
+    stats.samplers.count(existing_ix.is_some());
+    if let Some(ix) = existing_ix {
         return Index::new(ix as u32);
     }
     let new_object = other.samplers()[other_ix].clone();
@@ -148,20 +690,25 @@ fn meld_in_texture(
     base: &mut WorkAsset,
     other: &WorkAsset,
     other_ix: Index<Texture>,
-) -> Index<Texture> {
+    stats: &mut MeldStats,
+) -> Result<Index<Texture>> {
     let other_ix = other_ix.value();
     let key = &other.texture_keys()[other_ix];
-    if let Some(ix) = base.texture_ix(key) {
-        return Index::new(ix as u32);
+    let existing_ix = base.texture_ix(key);
+    stats.textures.count(existing_ix.is_some());
+    if let Some(ix) = existing_ix {
+        return Ok(Index::new(ix as u32));
     }
     let mut new_object = other.textures()[other_ix].clone();
 
     // meld logic
-    new_object.source = meld_in_image(base, other, new_object.source);
-    new_object.sampler = new_object.sampler.map(|s| meld_in_sampler(base, other, s));
+    new_object.source = meld_in_image(base, other, new_object.source, stats)?;
+    new_object.sampler = new_object
+        .sampler
+        .map(|s| meld_in_sampler(base, other, s, stats));
     // end meld logic
 
-    Index::new(base.push_texture(new_object, key) as u32)
+    Ok(Index::new(base.push_texture(new_object, key) as u32))
 }
 
 /// Meld a glTF `material` from *other* into *base*.
@@ -171,47 +718,83 @@ fn meld_in_material(
     base: &mut WorkAsset,
     other: &WorkAsset,
     other_ix: Index<Material>,
-) -> Index<Material> {
+    stats: &mut MeldStats,
+) -> Result<Index<Material>> {
     let other_ix = other_ix.value();
     let key = &other.material_keys[other_ix];
-    if let Some(ix) = base.material_ix(key) {
-        return Index::new(ix as u32);
+    let existing_ix = base.material_ix(key);
+    stats.materials.count(existing_ix.is_some());
+    if let Some(ix) = existing_ix {
+        if base.options.color_tolerance.is_some()
+            && base.options.color_policy == ColorPolicy::KeepOther
+        {
+            // the materials' colours may be merely tolerably equal; let the other's win
+            let (base_material, other_material) =
+                (&mut base.parse.materials[ix], &other.materials()[other_ix]);
+            base_material.pbr_metallic_roughness.base_color_factor =
+                other_material.pbr_metallic_roughness.base_color_factor;
+            base_material.emissive_factor = other_material.emissive_factor;
+        }
+        return Ok(Index::new(ix as u32));
     }
     let mut new_object = other.materials()[other_ix].clone();
 
     // laboriously hand-meld the five relevant textures
     if let Some(mut info) = new_object.normal_texture {
-        info.index = meld_in_texture(base, other, info.index);
+        info.index = meld_in_texture(base, other, info.index, stats)?;
         new_object.normal_texture = Some(info);
     }
     if let Some(mut info) = new_object.occlusion_texture {
-        info.index = meld_in_texture(base, other, info.index);
+        info.index = meld_in_texture(base, other, info.index, stats)?;
         new_object.occlusion_texture = Some(info);
     }
     if let Some(mut info) = new_object.emissive_texture {
-        info.index = meld_in_texture(base, other, info.index);
+        info.index = meld_in_texture(base, other, info.index, stats)?;
         new_object.emissive_texture = Some(info);
     }
     if let Some(mut info) = new_object.pbr_metallic_roughness.base_color_texture {
-        info.index = meld_in_texture(base, other, info.index);
+        info.index = meld_in_texture(base, other, info.index, stats)?;
         new_object.pbr_metallic_roughness.base_color_texture = Some(info);
     }
     if let Some(mut info) = new_object.pbr_metallic_roughness.metallic_roughness_texture {
-        info.index = meld_in_texture(base, other, info.index);
+        info.index = meld_in_texture(base, other, info.index, stats)?;
         new_object.pbr_metallic_roughness.metallic_roughness_texture = Some(info);
     }
     // end meld logic
 
-    Index::new(base.push_material(new_object, key) as u32)
+    Ok(Index::new(base.push_material(new_object, key) as u32))
+}
+
+/// The material the glTF spec says a primitive without one uses, added to *asset* if need be.
+///
+/// Returns its key and index. Should *asset* hold a material just like it already, that's used.
+fn implicit_default_material(asset: &mut WorkAsset) -> Result<(MeldKey, usize)> {
+    let material: Material = serde_json::from_str("{}")
+        .map_err(|e| format!("Internal error: Can't build default material: {}", e))?;
+    let key = material.build_meld_key(asset)?;
+    let ix = match asset.material_ix(&key) {
+        Some(ix) => ix,
+        None => asset.push_material(material, &key),
+    };
+    Ok((key, ix))
+}
+
+// whether the material the given primitive of base uses by default may vary, according to
+// `MeldOptions::material_filter`
+fn material_may_vary(base: &WorkAsset, primitive: &Primitive) -> bool {
+    let name = primitive
+        .material
+        .and_then(|ix| base.parse.materials[ix.value()].name.as_deref());
+    base.options.material_filter.allows(name.unwrap_or(""))
 }
 
 fn copy_byte_view(
     base: &mut WorkAsset,
     foreign: &WorkAsset,
     foreign_ix: Index<View>,
-) -> Index<View> {
+) -> Result<Index<View>> {
     let view = foreign.buffer_view(foreign_ix.value());
-    let slice = foreign.buffer_view_as_slice(&view);
-    let new_ix = base.push_buffer_view_from_slice(slice) as u32;
-    Index::new(new_ix)
+    let slice = foreign.buffer_view_as_slice(&view)?;
+    let new_ix = base.push_buffer_view_from_slice(slice)? as u32;
+    Ok(Index::new(new_ix))
 }
@@ -0,0 +1,48 @@
+// Copyright (c) Facebook, Inc. and its affiliates. All Rights Reserved
+//
+
+//! Approximate accounting of the memory a meld holds on to; see `MeldOptions::memory_budget`.
+
+use crate::Result;
+
+// tracks the bytes held for asset data during a meld, and enforces any budget on them
+pub(crate) struct MemoryBudget {
+    limit: Option<usize>,
+    held: usize,
+    peak: usize,
+}
+
+impl MemoryBudget {
+    pub(crate) fn new(limit: Option<usize>) -> MemoryBudget {
+        MemoryBudget::resume(limit, 0)
+    }
+
+    // pick up accounting where an earlier budget left off, with `held` bytes still in use
+    pub(crate) fn resume(limit: Option<usize>, held: usize) -> MemoryBudget {
+        MemoryBudget {
+            limit,
+            held,
+            peak: held,
+        }
+    }
+
+    // note that `bytes` more are now held, for the given purpose, unless that breaks the budget
+    pub(crate) fn hold(&mut self, bytes: usize, purpose: &str) -> Result<()> {
+        let held = self.held + bytes;
+        if let Some(limit) = self.limit {
+            if held > limit {
+                return Err(format!(
+                    "Meld aborted: {} would bring memory use to about {} bytes, over the budget of {} bytes.",
+                    purpose, held, limit
+                ));
+            }
+        }
+        self.held = held;
+        self.peak = self.peak.max(held);
+        Ok(())
+    }
+
+    pub(crate) fn peak(&self) -> usize {
+        self.peak
+    }
+}
@@ -0,0 +1,221 @@
+// Copyright (c) Facebook, Inc. and its affiliates. All Rights Reserved
+//
+
+//! Code to compress the vertex data of an exported asset with the meshoptimizer codec, using the
+//! `EXT_meshopt_compression` extension.
+//!
+//! Buffer views that hold vertex attributes are encoded in `ATTRIBUTES` mode, and their bytes
+//! moved into the BIN chunk in that form. The views themselves then point into a fallback buffer
+//! without data, so the extension is required. Index buffers are left as they are: the codec we
+//! link writes an older index format than the extension allows.
+
+use std::collections::HashSet;
+use std::convert::TryFrom;
+use std::mem::size_of;
+
+use gltf::json::accessor::GenericComponentType;
+use gltf::json::buffer::Buffer;
+use gltf::json::validation::Checked;
+use gltf::json::{Index, Root};
+
+use serde_json::{json, Value};
+
+use crate::gltfext::set_root_buffer;
+use crate::Result;
+
+use super::has_opaque_view_references;
+
+/// The name of the glTF extension for meshopt compressed buffer views.
+pub const EXT_MESHOPT_COMPRESSION: &str = "EXT_meshopt_compression";
+
+// the codec needs vertices of a size that's a multiple of 4, and we dispatch on it, below
+const MAX_STRIDE: usize = 64;
+
+/// Compresses every buffer view of vertex attributes that the codec can take, and returns the
+/// new blob, along with the `extensions` JSON to add to that of buffer views & buffers, by
+/// top-level property and index, which gltf-json has no place for.
+pub fn compress_vertex_views(
+    root: &mut Root,
+    blob: &[u8],
+) -> Result<(Vec<u8>, Vec<(&'static str, usize, Value)>)> {
+    if has_opaque_view_references(root) {
+        return Err(format!(
+            "Can't compress the buffer views of an asset whose extensions may use them unseen."
+        ));
+    }
+    let strides = vertex_view_strides(root);
+
+    let mut new_blob = vec![];
+    let mut fallback_len = 0;
+    let mut extensions = vec![];
+    for (view_ix, view) in root.buffer_views.iter_mut().enumerate() {
+        let start = view.byte_offset.unwrap_or(0) as usize;
+        let bytes = blob
+            .get(start..start + view.byte_length as usize)
+            .ok_or_else(|| format!("Buffer view {} lies beyond the blob.", view_ix))?;
+
+        let encoded = match strides[view_ix] {
+            Some(stride) => encode_vertices(bytes, stride)?.map(|encoded| (encoded, stride)),
+            None => None,
+        };
+        match encoded {
+            Some((encoded, stride)) => {
+                while new_blob.len() % 4 != 0 {
+                    new_blob.push(0x00);
+                }
+                extensions.push((
+                    "bufferViews",
+                    view_ix,
+                    json!({
+                        EXT_MESHOPT_COMPRESSION: {
+                            "buffer": 0,
+                            "byteOffset": new_blob.len(),
+                            "byteLength": encoded.len(),
+                            "byteStride": stride,
+                            "count": bytes.len() / stride,
+                            "mode": "ATTRIBUTES",
+                        }
+                    }),
+                ));
+                new_blob.extend_from_slice(&encoded);
+
+                // the view now describes the decoded data, in a buffer of its own
+                fallback_len = (fallback_len + 3) & !3;
+                view.buffer = Index::new(1);
+                view.byte_offset = Some(fallback_len as u32);
+                view.byte_stride = Some(stride as u32);
+                fallback_len += bytes.len();
+            }
+            None => {
+                // keep the view's offset modulo 4, so alignment is preserved
+                while new_blob.len() % 4 != start % 4 {
+                    new_blob.push(0x00);
+                }
+                view.byte_offset = Some(new_blob.len() as u32);
+                new_blob.extend_from_slice(bytes);
+            }
+        }
+    }
+    if extensions.is_empty() {
+        return Ok((blob.to_vec(), extensions));
+    }
+
+    set_root_buffer(&new_blob, &mut root.buffers)?;
+    root.buffers.push(Buffer {
+        byte_length: fallback_len as u32,
+        uri: None,
+        name: None,
+        extensions: None,
+        extras: None,
+    });
+    extensions.push((
+        "buffers",
+        1,
+        json!({ EXT_MESHOPT_COMPRESSION: { "fallback": true } }),
+    ));
+
+    let name = EXT_MESHOPT_COMPRESSION.to_owned();
+    if !root.extensions_used.contains(&name) {
+        root.extensions_used.push(name.clone());
+    }
+    if !root.extensions_required.contains(&name) {
+        root.extensions_required.push(name);
+    }
+    Ok((new_blob, extensions))
+}
+
+// the vertex stride of each buffer view that holds only vertex attributes, and which the codec
+// can take, i.e. whose stride is a multiple of 4 that evenly divides its length
+fn vertex_view_strides(root: &Root) -> Vec<Option<usize>> {
+    let mut attributes = HashSet::new();
+    for primitive in root.meshes.iter().flat_map(|mesh| &mesh.primitives) {
+        attributes.extend(primitive.attributes.values().map(|ix| ix.value()));
+        for target in primitive.targets.iter().flatten() {
+            let accessors = vec![target.positions, target.normals, target.tangents];
+            attributes.extend(accessors.into_iter().flatten().map(|ix| ix.value()));
+        }
+    }
+
+    let mut strides: Vec<Option<usize>> = root
+        .buffer_views
+        .iter()
+        .map(|view| view.byte_stride.map(|stride| stride as usize))
+        .collect();
+    let mut excluded = vec![false; root.buffer_views.len()];
+    for (accessor_ix, accessor) in root.accessors.iter().enumerate() {
+        if let Some(sparse) = &accessor.sparse {
+            excluded[sparse.indices.buffer_view.value()] = true;
+            excluded[sparse.values.buffer_view.value()] = true;
+        }
+        let view_ix = match accessor.buffer_view {
+            Some(view_ix) => view_ix.value(),
+            None => continue,
+        };
+        let element_size = match (&accessor.component_type, &accessor.type_) {
+            (Checked::Valid(GenericComponentType(component_type)), Checked::Valid(type_)) => {
+                component_type.size() * type_.multiplicity()
+            }
+            _ => 0,
+        };
+        if !attributes.contains(&accessor_ix) || element_size == 0 {
+            excluded[view_ix] = true;
+        } else if root.buffer_views[view_ix].byte_stride.is_none() {
+            // a view without a stride must be holding the tightly packed elements of accessors
+            // of a single size, or there's no telling what its vertices are
+            match strides[view_ix] {
+                Some(stride) if stride != element_size => excluded[view_ix] = true,
+                _ => strides[view_ix] = Some(element_size),
+            }
+        }
+    }
+    for image in &root.images {
+        if let Some(view_ix) = image.buffer_view {
+            excluded[view_ix.value()] = true;
+        }
+    }
+
+    strides
+        .into_iter()
+        .zip(root.buffer_views.iter())
+        .zip(excluded)
+        .map(|((stride, view), excluded)| match stride {
+            Some(stride)
+                if !excluded
+                    && stride % 4 == 0
+                    && stride <= MAX_STRIDE
+                    && view.byte_length as usize % stride == 0 =>
+            {
+                Some(stride)
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+// the codec takes the vertex size from the vertex type, so we pick the array type of the stride
+macro_rules! encode_with_stride {
+    ($bytes:expr, $stride:expr, $($size:literal)*) => {
+        match $stride {
+            $($size => encode_as::<[u8; $size]>($bytes).map(Some),)*
+            _ => Ok(None),
+        }
+    };
+}
+
+// the encoded bytes of the vertices of the given stride, or `None` if the stride isn't one we
+// can encode
+fn encode_vertices(bytes: &[u8], stride: usize) -> Result<Option<Vec<u8>>> {
+    encode_with_stride!(bytes, stride, 4 8 12 16 20 24 28 32 36 40 44 48 52 56 60 64)
+}
+
+fn encode_as<T>(bytes: &[u8]) -> Result<Vec<u8>>
+where
+    T: for<'a> TryFrom<&'a [u8]>,
+{
+    let vertices: Vec<T> = bytes
+        .chunks_exact(size_of::<T>())
+        .filter_map(|vertex| T::try_from(vertex).ok())
+        .collect();
+    ::meshopt::encode_vertex_buffer(&vertices)
+        .map_err(|e| format!("Couldn't encode vertices with meshopt: {}", e))
+}
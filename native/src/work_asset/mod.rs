@@ -1,22 +1,131 @@
 // Copyright (c) Facebook, Inc. and its affiliates. All Rights Reserved
 //
 
-use std::collections::HashMap;
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap};
 
-use gltf::json::{buffer::View, Image, Material, Mesh, Root};
+use gltf::json::accessor::{ComponentType, Type};
+use gltf::json::{buffer::View, Image, Index, Material, Mesh, Root};
 use gltf::json::{texture::Sampler, Texture};
+use gltf::json::{Accessor, Node, Scene};
 
-use crate::{Fingerprint, MeldKey, Result, Tag};
+use serde_json::{Map, Value};
 
-use crate::gltfext::add_buffer_view_from_slice;
+use crate::extension::ExtensionRevision;
+use crate::{Fingerprint, MeldKey, Result, Tag, TagDisplay, TagPayload};
+use crate::{VariantMaterial, VariantTable};
+
+use crate::gltfext::{add_accessor_from_slice, add_buffer_view_from_slice};
+
+pub mod cache;
+pub use cache::MeldCache;
+
+pub mod canonicalize;
+
+pub mod cartesian;
+
+#[cfg(feature = "draco-compression")]
+pub(crate) mod draco;
 
 pub mod construct;
 
+pub mod correspondence;
+pub use correspondence::{PrimitiveCorrespondence, PrimitivePair, PrimitiveRef};
+
+pub mod diagnose;
+pub use diagnose::{MeldDiagnosis, MeshCandidate, MeshMismatch, MeshProblem};
+
+pub mod estimate;
+pub use estimate::MeldEstimate;
+
 pub mod export;
 
+pub mod exporter;
+pub use exporter::{
+    ExtrasVariantExporter, SidecarVariantExporter, SwitchTableVariantExporter, VariantExporter,
+    VariantExporters,
+};
+
+pub mod importer;
+pub use importer::{ExtrasVariantImporter, VariantImporter, VariantImporters};
+
+pub(crate) mod intern;
+use intern::{plain_mapping, InternedMapping, StringTable};
+
 pub mod meld;
+pub use meld::{MeldCounts, MeldStats};
+
+pub(crate) mod memory;
+
+#[cfg(feature = "meshopt-compression")]
+pub(crate) mod meshopt;
+
+pub(crate) mod node_scope;
+
+pub mod options;
+pub use options::{
+    AssetInfoPolicy, BoundsPolicy, ColorPolicy, DracoSettings, FingerprintAlgorithm,
+    FixedPrimitive, InputLimits, MaterialFilter, MaterialFilterPolicy, MeldOptions, MeshSelector,
+    NameMergePolicy, NodeTransformPolicy, TagCase, TagPolicy, TextureEncoding,
+    INPUT_LIMIT_EXCEEDED,
+};
+
+pub mod overrides;
+pub use overrides::MaterialOverrides;
+
+pub mod remap;
+
+pub mod replace;
+
+pub mod sharing;
+pub use sharing::{NearDuplicate, SharingAnalysis};
 
-const EPS_FINGERPRINT: f64 = 1e-6;
+pub mod split;
+pub use split::tag_file_stem;
+
+pub mod textures;
+
+pub mod timings;
+pub use timings::PhaseTimings;
+
+pub(crate) mod transforms;
+
+pub(crate) mod uri;
+
+// extensions that are known not to reference buffer views, and so can't confuse blob compaction
+const VIEW_SAFE_EXTENSIONS: &[&str] = &[
+    "FB_material_variants",
+    "KHR_lights_punctual",
+    "KHR_materials_pbrSpecularGlossiness",
+    "KHR_materials_unlit",
+    "KHR_materials_variants",
+    "KHR_texture_transform",
+];
+
+// true if the asset uses extensions that may reference buffer views in ways we can't see
+fn has_opaque_view_references(root: &Root) -> bool {
+    root.extensions_used
+        .iter()
+        .any(|used| !VIEW_SAFE_EXTENSIONS.contains(&used.as_str()))
+}
+
+// extensions that are known not to reference materials, textures, images or samplers by index,
+// and so can't be confused by those being dropped or reordered; unlike the above, this leaves out
+// `KHR_materials_pbrSpecularGlossiness`, whose textures are referenced from material extensions
+const INDEX_SAFE_EXTENSIONS: &[&str] = &[
+    "FB_material_variants",
+    "KHR_lights_punctual",
+    "KHR_materials_unlit",
+    "KHR_materials_variants",
+    "KHR_texture_transform",
+];
+
+// true if the asset uses extensions that may reference materials & such in ways we can't see
+fn has_opaque_index_references(root: &Root) -> bool {
+    root.extensions_used
+        .iter()
+        .any(|used| !INDEX_SAFE_EXTENSIONS.contains(&used.as_str()))
+}
 
 /// The primary internal data structure, which enables and accelerates the melding operation.
 ///
@@ -40,11 +149,44 @@ pub struct WorkAsset {
     /// See crate-level documentation for more exhaustive information on how this happens.
     default_tag: Tag,
 
+    /// The settings this asset's meld keys were computed with.
+    options: MeldOptions,
+
+    /// The revision of `KHR_materials_variants` the asset was imported with, if it could be told.
+    extension_revision: Option<ExtensionRevision>,
+
+    /// Any top-level glTF properties that were not parsed into `parse`, but kept as raw JSON to
+    /// be written back out verbatim on export; see `MeldOptions::lenient`.
+    passthrough: Map<String, Value>,
+
+    /// Any GLB chunks beyond JSON & BIN, as magic number & bytes, to be written back out verbatim
+    /// on export; see `MeldOptions::drop_extra_chunks`.
+    extra_chunks: Vec<(u32, Vec<u8>)>,
+
+    /// Warnings about how the files of the asset's buffers & images were found as it was loaded,
+    /// e.g. only by ignoring case; see `MeldOptions::case_insensitive_uris`.
+    load_warnings: Vec<String>,
+
     /// A glTF asset's geometry is laid out in a vector of meshes, each of which consists of a
     /// vector of mesh primitives. For each mesh primitive, the variational extension adds a
     /// mapping of variant tag -> material references. That data is stored in this field, and
     /// gets used during melding & during export.
-    mesh_primitive_variants: Vec<Vec<HashMap<Tag, MeldKey>>>,
+    ///
+    /// The tags & keys are interned in `strings`, since the same few recur in every mapping.
+    mesh_primitive_variants: Vec<Vec<InternedMapping>>,
+
+    /// The shared strings of the tags & material keys of `mesh_primitive_variants`.
+    strings: StringTable,
+
+    /// The order the asset's tags were listed in by its root `KHR_materials_variants` extension
+    /// as it was imported, or were laid out in by a meld; see `get_tags_in_use()`.
+    variant_order: Vec<Tag>,
+
+    /// How to present tags to end users, for those tags it's been specified for.
+    tag_displays: HashMap<Tag, TagDisplay>,
+
+    /// Caller data attached to tags, keyed by tag and then by name.
+    tag_payloads: HashMap<Tag, BTreeMap<String, TagPayload>>,
 
     /// A `MeldKey` for each `Image`; essentially a hash of the binary contents.
     image_keys: Vec<MeldKey>,
@@ -60,43 +202,321 @@ pub struct WorkAsset {
     /// Each `Primitive` of each `Mesh` has a `Fingerprint` computed for it, and they are
     /// stored herein.
     mesh_primitive_fingerprints: Vec<Vec<Fingerprint>>,
+
+    /// How long constructing this asset took; see the `profiling` feature.
+    timings: PhaseTimings,
 }
 
 impl WorkAsset {
+    /// The parsed glTF JSON of this asset.
+    pub fn root(&self) -> &Root {
+        &self.parse
+    }
+
     /// A slice view of the entire binary blob.
     pub fn blob_slice(&self) -> &[u8] {
         &self.blob.as_slice()
     }
 
+    /// Roughly how many bytes this asset holds: its blob and its meld keys, but not its JSON.
+    pub fn approximate_size(&self) -> usize {
+        let keys = [
+            &self.image_keys,
+            &self.material_keys,
+            &self.mesh_keys,
+            &self.sampler_keys,
+            &self.texture_keys,
+        ];
+        let key_bytes: usize = keys
+            .iter()
+            .flat_map(|keys| keys.iter())
+            .map(String::len)
+            .sum();
+        let chunk_bytes: usize = self.extra_chunks.iter().map(|(_, bytes)| bytes.len()).sum();
+        self.blob.len() + chunk_bytes + key_bytes
+    }
+
+    /// The tag used to represent vanilla glTF's material references.
+    pub fn default_tag(&self) -> &Tag {
+        &self.default_tag
+    }
+
+    /// The settings this asset's meld keys were computed with.
+    pub fn options(&self) -> &MeldOptions {
+        &self.options
+    }
+
+    /// Warnings about how the files of the asset's buffers & images were found as it was loaded.
+    pub fn load_warnings(&self) -> &[String] {
+        &self.load_warnings
+    }
+
+    /// How long constructing this asset took, phase by phase; see the `profiling` feature.
+    pub fn timings(&self) -> &PhaseTimings {
+        &self.timings
+    }
+
+    /// The revision of `KHR_materials_variants` the asset was imported with, if it could be told.
+    pub fn extension_revision(&self) -> Option<ExtensionRevision> {
+        self.extension_revision
+    }
+
     /// Returns a vector of tags being used throughout the entire asset.
+    ///
+    /// Tags the asset was imported with come first, in the order its root extension listed them;
+    /// for a melded asset, that's the base's tags followed by those the meld added. Any other
+    /// tags follow in order of first use, mesh by mesh and primitive by primitive; the tags of a
+    /// single primitive are sorted.
     pub fn get_tags_in_use(&self) -> Result<Vec<Tag>> {
-        let mut tags_in_use: Vec<Tag> = Vec::new();
+        let mut first_used: Vec<Tag> = Vec::new();
         for vec_of_prims in &self.mesh_primitive_variants {
             for tag_meld_entry in vec_of_prims {
-                for tag in tag_meld_entry.keys() {
-                    if !tags_in_use.contains(tag) {
-                        tags_in_use.push(tag.clone());
+                // in a fixed order, so that exports don't depend on how the mapping is hashed
+                let mut tags: Vec<&str> = tag_meld_entry.keys().map(|tag| &**tag).collect();
+                tags.sort_unstable();
+                for tag in tags {
+                    if !first_used.iter().any(|used| used == tag) {
+                        first_used.push(tag.to_owned());
                     }
                 }
             }
         };
+        let mut tags_in_use: Vec<Tag> = self
+            .variant_order
+            .iter()
+            .filter(|tag| first_used.contains(tag))
+            .cloned()
+            .collect();
+        for tag in first_used {
+            if !tags_in_use.contains(&tag) {
+                tags_in_use.push(tag);
+            }
+        }
         Ok(tags_in_use)
     }
 
+    /// How the given tag is to be presented to end users, if that's been specified.
+    pub fn tag_display(&self, tag: &Tag) -> Option<&TagDisplay> {
+        self.tag_displays.get(tag)
+    }
+
+    /// Specifies how the given tag is to be presented to end users, replacing any earlier display.
+    ///
+    /// The tag is first normalized according to `MeldOptions::tag_policy`. Displays are exported
+    /// only for tags that are in use.
+    pub fn set_tag_display(&mut self, tag: &Tag, display: TagDisplay) -> Result<()> {
+        let tag = self.options.tag_policy.apply(tag)?;
+        self.tag_displays.insert(tag, display);
+        Ok(())
+    }
+
+    /// The payloads attached to the given tag, keyed by name, if there are any.
+    pub fn tag_payloads(&self, tag: &Tag) -> Option<&BTreeMap<String, TagPayload>> {
+        self.tag_payloads.get(tag)
+    }
+
+    /// Attaches a payload to the given tag under the given key, replacing any earlier one.
+    ///
+    /// The tag is first normalized according to `MeldOptions::tag_policy`. Payloads are exported
+    /// only for tags that are in use.
+    pub fn set_tag_payload(&mut self, tag: &Tag, key: &str, payload: TagPayload) -> Result<()> {
+        let tag = self.options.tag_policy.apply(tag)?;
+        self.tag_payloads
+            .entry(tag)
+            .or_default()
+            .insert(key.to_owned(), payload);
+        Ok(())
+    }
+
     /// The mapping of `Tag` to material `MeldKey` for a given primitive of a given mesh.
-    pub fn variant_mapping(&self, m_ix: usize, p_ix: usize) -> &HashMap<Tag, MeldKey> {
+    ///
+    /// This is a copy; the asset itself holds its tags & keys interned.
+    pub fn variant_mapping(&self, m_ix: usize, p_ix: usize) -> HashMap<Tag, MeldKey> {
+        plain_mapping(self.interned_mapping(m_ix, p_ix))
+    }
+
+    // the mapping of a given primitive of a given mesh, as we hold it
+    pub(crate) fn interned_mapping(&self, m_ix: usize, p_ix: usize) -> &InternedMapping {
         let mesh_mappings = &self.mesh_primitive_variants[m_ix];
         let primitive_mapping = &mesh_mappings[p_ix];
         primitive_mapping
     }
 
-    /// The slice of bytes that constitute the raw data of a given `Image`.
-    pub fn read_image_bytes(&self, image: &Image) -> Result<&[u8]> {
+    /// For each primitive of each mesh, the mapping of `Tag` to the material it selects.
+    ///
+    /// Unlike `variant_mapping()`, material keys are resolved to glTF indices and names, and
+    /// the default tag is included wherever a primitive has a material.
+    pub fn variant_table(&self) -> Result<VariantTable> {
+        let variant_material = |ix: usize| VariantMaterial {
+            index: ix,
+            name: self.parse.materials[ix].name.clone(),
+        };
+        let mut table = vec![];
+        for (m_ix, mesh) in self.parse.meshes.iter().enumerate() {
+            let mut primitives = vec![];
+            for (p_ix, primitive) in mesh.primitives.iter().enumerate() {
+                let mut entries = HashMap::new();
+                for (tag, material_key) in self.interned_mapping(m_ix, p_ix) {
+                    let material_ix = self.material_ix(material_key).ok_or_else(|| {
+                        format!(
+                            "Huh? {} maps tag {} to {}.",
+                            self.describe_primitive(m_ix, p_ix),
+                            tag,
+                            self.describe_material_key(material_key)
+                        )
+                    })?;
+                    entries.insert(tag.to_string(), variant_material(material_ix));
+                }
+                if let Some(material_ix) = primitive.material {
+                    entries
+                        .entry(self.default_tag.clone())
+                        .or_insert_with(|| variant_material(material_ix.value()));
+                }
+                primitives.push(entries);
+            }
+            table.push(primitives);
+        }
+        Ok(table)
+    }
+
+    /// Maps `tag` to the material with the given `MeldKey` on a given primitive of a given mesh.
+    ///
+    /// The tag is first normalized according to `MeldOptions::tag_policy`. The material key must
+    /// belong to a material already in this asset. If `tag` is this asset's default tag, the
+    /// primitive's own glTF material reference is redirected too, so that the two never disagree
+    /// at export time.
+    pub fn set_variant(
+        &mut self,
+        m_ix: usize,
+        p_ix: usize,
+        tag: &Tag,
+        material_key: &MeldKey,
+    ) -> Result<()> {
+        self.check_primitive_ix(m_ix, p_ix)?;
+        let tag = &self.options.tag_policy.apply(tag)?;
+        let material_ix = self.material_ix(material_key).ok_or_else(|| {
+            format!(
+                "Can't map tag {} on {} to {}.",
+                tag,
+                self.describe_primitive(m_ix, p_ix),
+                self.describe_material_key(material_key)
+            )
+        })?;
+
+        if *tag == self.default_tag {
+            let primitive = &mut self.parse.meshes[m_ix].primitives[p_ix];
+            primitive.material = Some(Index::new(material_ix as u32));
+        }
+        let (tag, material_key) = (self.strings.intern(tag), self.strings.intern(material_key));
+        self.mesh_primitive_variants[m_ix][p_ix].insert(tag, material_key);
+        Ok(())
+    }
+
+    /// Removes any mapping for `tag` on a given primitive of a given mesh, returning the
+    /// `MeldKey` it mapped to, if any.
+    ///
+    /// The default tag can't be cleared; it's backed by the primitive's glTF material reference.
+    pub fn clear_variant(
+        &mut self,
+        m_ix: usize,
+        p_ix: usize,
+        tag: &Tag,
+    ) -> Result<Option<MeldKey>> {
+        self.check_primitive_ix(m_ix, p_ix)?;
+        let tag = &self.options.tag_policy.apply(tag)?;
+        if *tag == self.default_tag {
+            return Err(format!(
+                "Can't clear default tag {} from {}.",
+                tag,
+                self.describe_primitive(m_ix, p_ix)
+            ));
+        }
+        let removed = self.mesh_primitive_variants[m_ix][p_ix].remove(tag.as_str());
+        Ok(removed.map(|key| key.to_string()))
+    }
+
+    fn check_primitive_ix(&self, m_ix: usize, p_ix: usize) -> Result<()> {
+        let mesh = self
+            .parse
+            .meshes
+            .get(m_ix)
+            .ok_or_else(|| format!("Mesh index {} out of range.", m_ix))?;
+        if p_ix >= mesh.primitives.len() {
+            return Err(format!(
+                "Primitive index {} out of range for {}.",
+                p_ix,
+                self.describe_mesh(m_ix)
+            ));
+        }
+        Ok(())
+    }
+
+    // whether the given primitive is one of `MeldOptions::fixed_primitives`
+    pub(crate) fn is_fixed_primitive(&self, m_ix: usize, p_ix: usize) -> bool {
+        self.options
+            .fixed_primitives
+            .iter()
+            .any(|fixed| fixed.selects(&self.parse, m_ix, p_ix))
+    }
+
+    /// Identifies the ix:th mesh in messages, by index and by name if it has one, e.g. "mesh 3
+    /// 'Body'". An artist can act on the name without opening the JSON.
+    pub fn describe_mesh(&self, ix: usize) -> String {
+        let name = self
+            .parse
+            .meshes
+            .get(ix)
+            .and_then(|mesh| mesh.name.as_ref());
+        describe_object("mesh", ix, name)
+    }
+
+    /// Identifies a primitive of a mesh in messages; see `describe_mesh()`.
+    pub fn describe_primitive(&self, m_ix: usize, p_ix: usize) -> String {
+        format!("{}, primitive {}", self.describe_mesh(m_ix), p_ix)
+    }
+
+    /// Identifies the ix:th material in messages; see `describe_mesh()`.
+    pub fn describe_material(&self, ix: usize) -> String {
+        let name = self
+            .parse
+            .materials
+            .get(ix)
+            .and_then(|material| material.name.as_ref());
+        describe_object("material", ix, name)
+    }
+
+    /// Identifies the material with the given `MeldKey` in messages. Keys are long, and mean
+    /// little to anyone; they're only ever shown abbreviated, and only if no such material exists.
+    pub fn describe_material_key(&self, key: &str) -> String {
+        match self.material_ix(key) {
+            Some(ix) => self.describe_material(ix),
+            None => format!("unknown material (key {})", abbreviate_key(key)),
+        }
+    }
+
+    /// Identifies the ix:th image in messages, by name or else by original URI; see
+    /// `describe_mesh()`.
+    pub fn describe_image(&self, ix: usize) -> String {
+        let image = self.parse.images.get(ix);
+        let name = image.and_then(|image| image.name.as_ref().or_else(|| image.uri.as_ref()));
+        describe_object("image", ix, name)
+    }
+
+    /// The bytes that constitute the raw data of a given `Image`.
+    ///
+    /// These are currently always borrowed from our blob, but callers that transform images
+    /// should not count on it; it leaves room for images to be held apart from the blob, e.g.
+    /// after recompression, without another change of signature.
+    pub fn read_image_bytes(&self, image: &Image) -> Result<Cow<[u8]>> {
         if let Some(view) = image.buffer_view {
             if let Some(view) = self.parse.get(view) {
                 let offset = view.byte_offset.unwrap_or(0) as usize;
                 let length = view.byte_length as usize;
-                return Ok(&self.blob_slice()[offset..offset + length]);
+                return self
+                    .blob
+                    .get(offset..offset + length)
+                    .map(Cow::Borrowed)
+                    .ok_or_else(|| format!("Image buffer view reaches beyond the blob."));
             }
         }
         Err(format!("Internal error: Image with a URI field?!"))
@@ -107,11 +527,21 @@ impl WorkAsset {
         &self.parse.buffer_views[ix]
     }
 
-    /// The slice of bytes underlying the given buffer view.
-    pub fn buffer_view_as_slice(&self, view: &View) -> &[u8] {
+    /// The slice of bytes underlying the given buffer view, or an error if it lies beyond the
+    /// blob.
+    pub fn buffer_view_as_slice(&self, view: &View) -> Result<&[u8]> {
         let start = view.byte_offset.unwrap_or(0) as usize;
-        let end = start + view.byte_length as usize;
-        &self.blob[start..end]
+        start
+            .checked_add(view.byte_length as usize)
+            .and_then(|end| self.blob.get(start..end))
+            .ok_or_else(|| {
+                format!(
+                    "Buffer view of {} bytes at offset {} lies beyond the blob of {} bytes.",
+                    view.byte_length,
+                    start,
+                    self.blob.len()
+                )
+            })
     }
 
     /// Clone our JSON data and blob, and create a `Gltf` wrapper around it.
@@ -127,29 +557,131 @@ impl WorkAsset {
     }
 
     /// Search the `Primitives` of a `Mesh` non-exactly for a specific `Fingerprint`.
+    ///
+    /// Fingerprints match if they're closer than `MeldOptions::fingerprint_epsilon`.
     pub fn find_almost_equal_fingerprint(
         &self,
         mesh_ix: usize,
         print: &Fingerprint,
         exclude_ix: Option<usize>,
     ) -> Option<usize> {
-        let prints = &self.mesh_primitive_fingerprints[mesh_ix];
-        for (primitive_ix, primitive_print) in prints.iter().enumerate() {
-            if let Some(exclude_ix) = exclude_ix {
-                if exclude_ix == primitive_ix {
-                    continue;
-                }
+        self.rank_fingerprints(mesh_ix, print, exclude_ix)
+            .first()
+            .filter(|(_, gap)| *gap < self.options.fingerprint_epsilon)
+            .map(|(primitive_ix, _)| *primitive_ix)
+    }
+
+    /// The `Primitives` of a `Mesh` that have a `Fingerprint`, closest to the given one first,
+    /// each with the distance between the two.
+    pub fn rank_fingerprints(
+        &self,
+        mesh_ix: usize,
+        print: &Fingerprint,
+        exclude_ix: Option<usize>,
+    ) -> Vec<(usize, f64)> {
+        let mut ranked: Vec<(usize, f64)> = self.mesh_primitive_fingerprints[mesh_ix]
+            .iter()
+            .enumerate()
+            .filter(|(primitive_ix, _)| Some(*primitive_ix) != exclude_ix)
+            .map(|(primitive_ix, primitive_print)| (primitive_ix, (primitive_print - print).abs()))
+            .collect();
+        ranked.sort_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+        ranked
+    }
+
+    // the authoring tool's ID of each mesh, as per `MeldOptions::mesh_id_key`
+    fn mesh_source_ids(&self) -> Vec<Option<String>> {
+        let id_key = match &self.options.mesh_id_key {
+            Some(id_key) => id_key,
+            None => return vec![None; self.parse.meshes.len()],
+        };
+        let id_in = |extras: Option<&str>| -> Option<String> {
+            let extras: Value = serde_json::from_str(extras?).ok()?;
+            match extras.get(id_key)? {
+                Value::String(id) => Some(id.to_owned()),
+                Value::Number(id) => Some(id.to_string()),
+                _ => None,
             }
-            if (primitive_print - print).abs() < EPS_FINGERPRINT {
-                return Some(primitive_ix);
+        };
+        let mut node_ids = vec![vec![]; self.parse.meshes.len()];
+        for node in &self.parse.nodes {
+            if let Some(mesh_ix) = node.mesh {
+                node_ids[mesh_ix.value()].push(id_in(node.extras.as_ref().map(|raw| raw.get())));
             }
         }
-        return None;
+        self.parse
+            .meshes
+            .iter()
+            .zip(node_ids)
+            .map(|(mesh, node_ids)| {
+                id_in(mesh.extras.as_ref().map(|raw| raw.get())).or_else(|| {
+                    match node_ids.as_slice() {
+                        [only] => only.clone(),
+                        _ => None,
+                    }
+                })
+            })
+            .collect()
+    }
+
+    // explain how the ranked fingerprints compare to our epsilon, and suggest one that picks out
+    // the closest primitive alone, if there is such a thing
+    fn describe_fingerprint_gaps(&self, ranked: &[(usize, f64)]) -> String {
+        let (closest_ix, gap) = match ranked.first() {
+            Some(&closest) => closest,
+            None => return format!("there are no fingerprinted primitives to compare with"),
+        };
+        let closest: Vec<String> = ranked
+            .iter()
+            .take(3)
+            .map(|(ix, gap)| format!("primitive {} at a distance of {:e}", ix, gap))
+            .collect();
+        let next_gap = ranked.get(1).map_or(std::f64::INFINITY, |&(_, gap)| gap);
+        let suggestion = if gap < next_gap {
+            let epsilon = (2.0 * gap).min((gap + next_gap) / 2.0);
+            format!(
+                "an epsilon of {:e} would match primitive {} alone",
+                epsilon, closest_ix
+            )
+        } else {
+            format!("no epsilon would match primitive {} alone", closest_ix)
+        };
+        format!(
+            "the closest are {}; the epsilon is {:e}, and {}",
+            closest.join(", "),
+            self.options.fingerprint_epsilon,
+            suggestion
+        )
     }
 
     /// Adds a new buffer view to the asset, returning its index.
-    pub fn push_buffer_view_from_slice(&mut self, bytes: &[u8]) -> usize {
-        add_buffer_view_from_slice(bytes, &mut self.parse.buffer_views, &mut self.blob).value()
+    ///
+    /// Fails if the blob would grow beyond what glTF can address.
+    pub fn push_buffer_view_from_slice(&mut self, bytes: &[u8]) -> Result<usize> {
+        add_buffer_view_from_slice(bytes, &mut self.parse.buffer_views, &mut self.blob)
+            .map(|ix| ix.value())
+    }
+
+    /// Adds a new accessor to the asset, along with a buffer view holding its data, returning
+    /// the accessor's index; see `gltfext::add_accessor_from_slice()` for details.
+    pub fn push_accessor_from_slice(
+        &mut self,
+        bytes: &[u8],
+        component_type: ComponentType,
+        type_: Type,
+        count: usize,
+    ) -> Result<usize> {
+        let root = &mut self.parse;
+        add_accessor_from_slice(
+            bytes,
+            component_type,
+            type_,
+            count,
+            &mut root.buffer_views,
+            &mut root.accessors,
+            &mut self.blob,
+        )
+        .map(|ix| ix.value())
     }
 }
 
@@ -171,7 +703,7 @@ macro_rules! impl_accessors_and_mutators {
         #[doc = " The index of the given `"]
         #[doc = $name]
         #[doc = "` key, if any."]
-        pub fn $index_of_keys(&self, key: &MeldKey) -> Option<usize> {
+        pub fn $index_of_keys(&self, key: &str) -> Option<usize> {
             self.$keys().iter().position(|k| k == key)
         }
         #[doc = " Add a new `"]
@@ -193,6 +725,31 @@ macro_rules! impl_accessors_and_mutators {
     };
 }
 
+/// Provide accessors and mutators for nodes, scenes, accessors and buffer views, which have no
+/// meld keys:
+macro_rules! impl_unkeyed_accessors_and_mutators {
+    ($ty:ty, $name:expr, $objects:ident, $push:ident) => {
+        #[doc = " This asset's `"]
+        #[doc = $name]
+        #[doc = "` glTF objects."]
+        pub fn $objects(&self) -> &Vec<$ty> {
+            &self.parse.$objects
+        }
+        #[doc = " Add a new `"]
+        #[doc = $name]
+        #[doc = "` glTF object, returning its index."]
+        pub fn $push(&mut self, item: $ty) -> usize {
+            let new_ix = self.parse.$objects.len();
+            self.parse.$objects.push(item);
+            new_ix
+        }
+    };
+
+    ($ty:ty, $objects:ident, $push:ident) => {
+        impl_unkeyed_accessors_and_mutators!($ty, stringify!($ty), $objects, $push);
+    };
+}
+
 impl WorkAsset {
     impl_accessors_and_mutators!(Image, images, image_keys, image_ix, push_image);
     impl_accessors_and_mutators!(
@@ -205,4 +762,27 @@ impl WorkAsset {
     impl_accessors_and_mutators!(Mesh, meshes, mesh_keys, mesh_ix, push_mesh);
     impl_accessors_and_mutators!(Sampler, samplers, sampler_keys, sampler_ix, push_sampler);
     impl_accessors_and_mutators!(Texture, textures, texture_keys, texture_ix, push_texture);
+
+    // in lenient mode, nodes & scenes are passed through as raw JSON, and so are empty here
+    impl_unkeyed_accessors_and_mutators!(Node, nodes, push_node);
+    impl_unkeyed_accessors_and_mutators!(Scene, scenes, push_scene);
+    impl_unkeyed_accessors_and_mutators!(Accessor, accessors, push_accessor);
+    impl_unkeyed_accessors_and_mutators!(View, buffer_views, push_buffer_view);
+}
+
+// e.g. "mesh 3 'Body'", or just "mesh 3" for an object with no name
+pub(crate) fn describe_object(kind: &str, ix: usize, name: Option<&String>) -> String {
+    match name {
+        Some(name) => format!("{} {} '{}'", kind, ix, name),
+        None => format!("{} {}", kind, ix),
+    }
+}
+
+// meld keys can run to hundreds of characters; a prefix tells keys apart well enough
+fn abbreviate_key(key: &str) -> Cow<str> {
+    const MAX_CHARS: usize = 40;
+    match key.char_indices().nth(MAX_CHARS) {
+        Some((end, _)) => Cow::Owned(format!("{}...", &key[..end])),
+        None => Cow::Borrowed(key),
+    }
 }
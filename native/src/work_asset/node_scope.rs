@@ -0,0 +1,74 @@
+// Copyright (c) Facebook, Inc. and its affiliates. All Rights Reserved
+//
+
+//! Code to give each node a mesh of its own, so that nodes that share a mesh can still take on
+//! different variants; see `MeldOptions::node_scoped_meshes`.
+
+use gltf::json::{Index, Mesh, Root};
+
+use serde_json::value::RawValue;
+use serde_json::Value;
+
+use crate::meld_keys::MESH_ID_EXTRAS_KEY;
+use crate::Result;
+
+/// Separates the name of a mesh from that of the node it's scoped to, e.g. `Wheel@FrontLeft`.
+pub const NODE_SCOPE_SEPARATOR: char = '@';
+
+/// Names the mesh of every node for both mesh & node, e.g. `Wheel@FrontLeft`, and gives each
+/// node that shares its mesh with an earlier node a copy of its own, appended to the meshes.
+///
+/// A node without a name goes by its index, e.g. `Wheel@#3`, and so does a mesh. A mesh whose
+/// name already ends in its node's is left as it is, so that an asset that was exported with
+/// scoped meshes loads the same again. Meshes that are renamed lose any ID an earlier export
+/// stamped them with, so that they're keyed by their new names.
+pub fn scope_meshes_to_nodes(root: &mut Root) -> Result<()> {
+    let originals = root.meshes.clone();
+    let mut scoped = vec![false; originals.len()];
+    for node_ix in 0..root.nodes.len() {
+        let mesh_ix = match root.nodes[node_ix].mesh {
+            Some(mesh_ix) => mesh_ix.value(),
+            None => continue,
+        };
+        let node_name = match &root.nodes[node_ix].name {
+            Some(name) => name.to_owned(),
+            None => format!("#{}", node_ix),
+        };
+        let mut mesh = originals[mesh_ix].clone();
+        let mesh_name = match &mesh.name {
+            Some(name) => name.to_owned(),
+            None => format!("#{}", mesh_ix),
+        };
+        let suffix = format!("{}{}", NODE_SCOPE_SEPARATOR, node_name);
+        if !mesh_name.ends_with(&suffix) {
+            mesh.name = Some(format!("{}{}", mesh_name, suffix));
+            unstamp_mesh_id(&mut mesh)?;
+        }
+
+        if !scoped[mesh_ix] {
+            root.meshes[mesh_ix] = mesh;
+            scoped[mesh_ix] = true;
+        } else {
+            root.nodes[node_ix].mesh = Some(Index::new(root.meshes.len() as u32));
+            root.meshes.push(mesh);
+        }
+    }
+    Ok(())
+}
+
+// remove the ID an earlier export stamped into the mesh's extras, if any
+fn unstamp_mesh_id(mesh: &mut Mesh) -> Result<()> {
+    let mut extras = match &mesh.extras {
+        Some(raw) => match serde_json::from_str(raw.get()) {
+            Ok(Value::Object(map)) => map,
+            _ => return Ok(()),
+        },
+        None => return Ok(()),
+    };
+    if extras.remove(MESH_ID_EXTRAS_KEY).is_some() {
+        let json = Value::Object(extras).to_string();
+        mesh.extras =
+            Some(RawValue::from_string(json).map_err(|e| format!("Bad mesh extras: {}", e))?);
+    }
+    Ok(())
+}
@@ -0,0 +1,688 @@
+// Copyright (c) Facebook, Inc. and its affiliates. All Rights Reserved
+//
+
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+use gltf::json::Root;
+
+use serde_json::{json, Value};
+
+use crate::axes;
+use crate::extension::ExtensionRevision;
+use crate::work_asset::exporter::VariantExporters;
+use crate::work_asset::importer::VariantImporters;
+use crate::{Result, Tag};
+
+/// By default, floating-point material factors are compared to this many decimal digits.
+pub const DEFAULT_FLOAT_PRECISION: usize = 5;
+
+/// By default, primitive fingerprints this close to each other are taken to be the same.
+pub const DEFAULT_FINGERPRINT_EPSILON: f64 = 1e-6;
+
+/// Every error due to `InputLimits` begins with this, so that callers can tell them apart from
+/// other errors, e.g. to respond to an upload with 413 rather than 400.
+pub const INPUT_LIMIT_EXCEEDED: &str = "Input limit exceeded";
+
+/// Settings that control how glTF objects are keyed, and thus which of them meld together, and
+/// how the result is exported.
+///
+/// Both sides of a meld must have been constructed with the same options, or their keys could
+/// not meaningfully be compared.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MeldOptions {
+    /// The number of decimal digits floating-point material factors are rounded to when building
+    /// meld keys, or `None` to require them to be bitwise identical.
+    ///
+    /// Different exporters routinely write e.g. `0.8` and `0.800000012` for the same value.
+    pub float_precision: Option<usize>,
+
+    /// If set, base color and emissive factors are compared per channel on a grid of this
+    /// spacing, rather than according to `float_precision`; e.g. `1.0 / 255.0` to disregard
+    /// differences below what an 8-bit channel can express.
+    ///
+    /// The tolerance is approximate: each channel is rounded to its nearest grid point, so two
+    /// colours almost a full step apart can compare equal, while two that straddle the midpoint
+    /// between grid points differ however close they are.
+    pub color_tolerance: Option<f32>,
+
+    /// When two materials are equal only by virtue of `color_tolerance`, which one's colour
+    /// factors survive in the melded result.
+    pub color_policy: ColorPolicy,
+
+    /// Whether exported assets carry their `Metadata` in the glTF root `extras`, so that they
+    /// can later be loaded by `VariationalAsset::from_slice()` without recomputing it.
+    pub embed_metadata: bool,
+
+    /// Whether to parse only those top-level glTF properties the melder works on, and to carry
+    /// all others – nodes, scenes, animations, and anything unknown to us – through as raw JSON.
+    ///
+    /// This lets assets that use glTF features newer than our `gltf` crate understands be melded,
+    /// as long as those features are confined to parts of the asset we leave untouched. Options
+    /// that work on nodes – `node_transforms`, `node_scoped_meshes`, and variant exporters that
+    /// need them – can't be combined with this, and fail the load.
+    pub lenient: bool,
+
+    /// Whether exported assets carry their variant mappings also in the form of the earlier
+    /// `FB_material_variants` draft extension, for viewers that predate `KHR_materials_variants`.
+    pub write_legacy_extension: bool,
+
+    /// Whether exported primitives leave out of their `KHR_materials_variants` mappings those
+    /// variants that select the primitive's own material, which the extension falls back to
+    /// anyway; for an asset where most tags vary few primitives, that's most of its mappings.
+    /// The default tag's entry is kept, to tell a compacted primitive from one that never varied.
+    ///
+    /// The asset renders the same, and the root extension's `extras` say that its mappings are
+    /// compacted, so that loading it again puts back what was left out, and it keeps its tags.
+    pub compact_variant_mappings: bool,
+
+    /// The revision of `KHR_materials_variants` to export, or `None` to use the same revision
+    /// the asset was imported with – or, if that can't be told, the ratified one.
+    pub extension_revision: Option<ExtensionRevision>,
+
+    /// How to re-encode at export the texture images used by each tag's materials, since
+    /// download budgets may differ e.g. between a hero variant and the long tail.
+    ///
+    /// An image is re-encoded only if every tag that uses it asks for the same encoding; tags
+    /// not listed here leave their images alone. This requires the `texture-encoding` feature.
+    pub texture_encodings: BTreeMap<Tag, TextureEncoding>,
+
+    /// If set, variant tags are also derived from the names of an imported asset's materials,
+    /// turning a single artist file into a variational asset.
+    ///
+    /// A material named e.g. `Body_red` is taken to be the `red` variant of `Body`, given a
+    /// separator of `_`; any primitive that uses it then maps `red` to it, `blue` to `Body_blue`,
+    /// and so on. Mappings already present in `KHR_materials_variants` take precedence.
+    pub material_tag_separator: Option<String>,
+
+    /// Importers of variants that source assets encode in conventions of their own, e.g. legacy
+    /// `extras` on primitives; see `VariantImporter`. Like those derived from material names,
+    /// the mappings they import give way to any in `KHR_materials_variants`.
+    pub variant_importers: VariantImporters,
+
+    /// Exporters of variants in conventions other than `KHR_materials_variants`, e.g. `extras`
+    /// on primitives or a sidecar switch table, for engines that don't read the extension; see
+    /// `VariantExporter`. The extension itself is always written.
+    pub variant_exporters: VariantExporters,
+
+    /// Rules that every tag must follow as it enters the melder, whether through a source
+    /// asset's default tag, its `KHR_materials_variants` extension, or its material names.
+    pub tag_policy: TagPolicy,
+
+    /// If set, the approximate number of bytes of asset data a meld may hold at any one time,
+    /// counting both source assets, the result, and the export of it. A meld that would exceed
+    /// it fails with an error, rather than leave the host to run out of memory.
+    ///
+    /// The accounting covers blobs and meld keys, which dominate for any asset with textures,
+    /// but not the glTF JSON; leave some headroom. See `MeldStats::peak_memory` for what a meld
+    /// actually needed.
+    pub memory_budget: Option<usize>,
+
+    /// Whether each `VariationalAsset` hangs on to the `WorkAsset` it was exported from, so that
+    /// a later meld of it with these same options needn't parse, key & fingerprint its GLB all
+    /// over again. That's a boon when melding many sources one by one, but the asset's contents
+    /// are then held twice over, for as long as it lives. Assets whose textures are re-encoded
+    /// on export are never kept, since their GLB isn't what the `WorkAsset` holds.
+    pub keep_work_assets: bool,
+
+    /// Caps on the size & complexity of source assets, enforced as they are constructed.
+    pub input_limits: InputLimits,
+
+    /// Directories to look in for the files that an asset's relative image & buffer URIs name,
+    /// when they aren't where the URI says, relative to the asset; e.g. a sibling folder of
+    /// textures. Each is tried in turn, with the URI's path and then with its file name alone.
+    pub texture_search_paths: Vec<PathBuf>,
+
+    /// Whether a file that an asset's URI names, but which isn't found as it's named, may be
+    /// found by ignoring letter case; as for assets authored on Windows, loaded on Linux. Each
+    /// such file is noted in `WorkAsset::load_warnings()`.
+    pub case_insensitive_uris: bool,
+
+    /// Whether the URIs of an asset's images & buffers must be relative paths that stay within
+    /// the asset's directory, or a search path; absolute paths, `file:` URIs and paths with `..`
+    /// are refused. For assets from untrusted parties, which shouldn't read what they please.
+    pub confine_uris: bool,
+
+    /// What to do at export about the `min` & `max` bounds of vertex position accessors, which
+    /// viewers rely on e.g. for culling, but which assets are otherwise passed through with
+    /// untouched, even when their exporter got them wrong.
+    pub position_bounds: BoundsPolicy,
+
+    /// How mesh primitives are fingerprinted, which is how the primitives of a mesh are matched
+    /// up between the two sides of a meld.
+    pub fingerprint_algorithm: FingerprintAlgorithm,
+
+    /// How close two fingerprints must be for their primitives to be taken as the same. When
+    /// matching fails, the error suggests a value that would have worked.
+    pub fingerprint_epsilon: f64,
+
+    /// Whether the transforms of the nodes that place a mesh in the scene are taken into account
+    /// when fingerprinting it, so that an asset whose exporter baked e.g. a root scale into the
+    /// vertices still melds with one that kept it on a node. Nodes are only visible to us when
+    /// the asset isn't parsed `lenient`ly, so anything but `Ignore` fails a lenient load.
+    pub node_transforms: NodeTransformPolicy,
+
+    /// Whether the mesh of each node is named for both mesh & node as the asset is loaded, e.g.
+    /// `Wheel@FrontLeft`, and each node that shares its mesh with another is given a copy of its
+    /// own; so that e.g. the four wheels of a car can take on different variants, and meld with
+    /// the namesake meshes of another asset that's loaded the same way. Nodes are only visible
+    /// to us when the asset isn't parsed `lenient`ly, so this fails a lenient load.
+    pub node_scoped_meshes: bool,
+
+    /// Whether to drop any GLB chunks beyond JSON & BIN, e.g. vendor-specific physics data, which
+    /// are otherwise carried through a meld untouched, one copy of each.
+    pub drop_extra_chunks: bool,
+
+    /// How the `copyright`, `generator` & `extras` of the two assets' `asset` properties are
+    /// combined in the melded result.
+    pub asset_info_policy: AssetInfoPolicy,
+
+    /// Which name survives when an image or texture melds with an equivalent of another name,
+    /// and whether the other names it went by are recorded in its `extras`, to trace which
+    /// source contributed what.
+    pub name_merge_policy: NameMergePolicy,
+
+    /// Whether to list `KHR_materials_variants` in `extensionsRequired` on export, for content
+    /// that makes no sense to viewers that would ignore its variants. It's always listed in
+    /// `extensionsUsed`, and other extensions are listed as the source assets list them.
+    pub require_variants_extension: bool,
+
+    /// Whether to accept source assets that require extensions we can't honor, e.g. Draco mesh
+    /// compression, passing their data through untouched. Such an asset is otherwise rejected
+    /// as it's loaded, with an error that names the extensions; and if it is accepted, it may
+    /// well not meld as expected.
+    pub allow_unsupported_required: bool,
+
+    /// A property of mesh `extras` where an authoring tool stamps a stable ID, e.g. `sourceId`.
+    /// Where a mesh has none, that of the one node that uses it is taken instead. Meshes that
+    /// both carry an ID are matched up by it rather than by name; others still go by name.
+    pub mesh_id_key: Option<String>,
+
+    /// Mesh primitives of the base asset that must never vary, e.g. a logo badge. A meld leaves
+    /// them with the base's default material, and drops any variant mappings they have; should
+    /// the melded-in asset give one a different material, that's noted in `MeldStats::warnings`.
+    pub fixed_primitives: Vec<FixedPrimitive>,
+
+    /// Which materials may vary between variants, by name, so that exporter noise can't turn a
+    /// material that should be constant into accidental variants of it.
+    pub material_filter: MaterialFilter,
+}
+
+/// Mesh primitives that must never vary; see `MeldOptions::fixed_primitives`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FixedPrimitive {
+    /// The mesh the primitives belong to.
+    pub mesh: MeshSelector,
+    /// The index of the primitive within the mesh, or `None` for all of its primitives.
+    pub primitive: Option<usize>,
+}
+
+/// A mesh of an asset, by name or by index; see `FixedPrimitive`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum MeshSelector {
+    /// Every mesh of this name.
+    Name(String),
+    /// The mesh of this index.
+    Index(usize),
+}
+
+/// Which materials may vary between variants; see `MeldOptions::material_filter`.
+///
+/// A primitive may take on a different material for a new tag in a meld only if the base's
+/// default material for it is allowed to vary: if its name matches some pattern of `allow`, or
+/// `allow` is empty, and matches no pattern of `deny`. Patterns may use `*` for any run of
+/// characters, and `?` for any one character; unnamed materials have the empty name. The
+/// default filter lets every material vary.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct MaterialFilter {
+    /// Patterns of the names of the materials that may vary; if empty, all may.
+    pub allow: Vec<String>,
+    /// Patterns of the names of the materials that may not vary, even if `allow` matches them.
+    pub deny: Vec<String>,
+    /// What a meld does with a variation of a material that may not vary.
+    pub policy: MaterialFilterPolicy,
+}
+
+/// What to do about a material that varies but may not; see `MaterialFilter::policy`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MaterialFilterPolicy {
+    /// The meld fails, with an error that names the material.
+    Error,
+    /// The variation is dropped; the primitive keeps its material for the new tag.
+    Ignore,
+}
+
+/// Caps on the size & complexity of source assets, for assets that come from untrusted parties,
+/// e.g. uploads to a web service; see `MeldOptions::input_limits`.
+///
+/// Sizes are checked before the bytes in question are parsed or copied – for external buffers &
+/// images, by the size of their files before they're read – so a vast upload is turned away
+/// cheaply. The default imposes no limits at all. See also `MeldOptions::confine_uris`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct InputLimits {
+    /// The maximum size of the glTF JSON, in bytes.
+    pub max_json_bytes: Option<usize>,
+    /// The maximum size of the binary blob, in bytes; this includes the BIN chunk of a GLB, as
+    /// well as any external buffers & images that are read in.
+    pub max_blob_bytes: Option<usize>,
+    /// The maximum number of images.
+    pub max_images: Option<usize>,
+    /// The maximum number of meshes.
+    pub max_meshes: Option<usize>,
+    /// The maximum number of mesh primitives, across all meshes.
+    pub max_primitives: Option<usize>,
+}
+
+/// Rules for tag strings, so that downstream runtimes with stricter identifier rules don't
+/// choke on what the melder emits; see `MeldOptions::tag_policy`.
+///
+/// The default policy accepts any non-empty tag as it is.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TagPolicy {
+    /// The case tags are normalized to before anything else is checked or compared.
+    pub case: TagCase,
+
+    /// If set, tags may consist only of ASCII letters & digits, and of these characters.
+    pub allowed_punctuation: Option<String>,
+
+    /// If set, the maximum length of a tag, in characters.
+    pub max_length: Option<usize>,
+
+    /// Tags that may not be used, e.g. because a runtime gives them special meaning. They are
+    /// normalized to `case` before comparison.
+    pub reserved: Vec<Tag>,
+}
+
+/// How tags are normalized with regard to letter case; see `TagPolicy::case`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TagCase {
+    /// Tags are used exactly as given.
+    Preserve,
+    /// Tags are converted to lower case.
+    Lower,
+    /// Tags are converted to upper case.
+    Upper,
+}
+
+/// A target format for re-encoded texture images; see `MeldOptions::texture_encodings`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum TextureEncoding {
+    /// Lossless PNG. Images that already are PNG are left as they are.
+    Png,
+    /// JPEG of the given quality, from 1 to 100. Images with an alpha channel are left as they
+    /// are, since JPEG can't represent it.
+    Jpeg(u8),
+}
+
+/// How vertex attributes are quantized when geometry is compressed with Draco; see
+/// `WorkAsset::export_draco()`.
+///
+/// Each setting is the number of bits floating-point attributes of its kind are quantized to,
+/// from 1 to 30; fewer bits compress better, and lose more precision.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DracoSettings {
+    /// The bits per component of vertex positions.
+    pub position_bits: u8,
+    /// The bits per component of vertex normals.
+    pub normal_bits: u8,
+    /// The bits per component of texture coordinates.
+    pub texcoord_bits: u8,
+    /// The bits per component of any other floating-point attributes, e.g. tangents and colors.
+    pub generic_bits: u8,
+}
+
+/// How mesh primitives are fingerprinted; see `MeldOptions::fingerprint_algorithm`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FingerprintAlgorithm {
+    /// A sum of skewed vertex positions & colours; see `meld_keys::build_fingerprint()`. It's
+    /// cheap and forgiving of tiny numerical differences, but distinct shapes can collide, and
+    /// larger drift between exporters breaks it.
+    Additive,
+    /// A sum of hashes of vertices quantized to a grid of the given spacing, e.g. `1e-4`; see
+    /// `meld_keys::build_quantized_fingerprint()`.
+    Quantized(f32),
+}
+
+/// How node transforms figure in a meld; see `MeldOptions::node_transforms`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NodeTransformPolicy {
+    /// Meshes are fingerprinted from their vertex data as it stands.
+    Ignore,
+    /// Meshes are fingerprinted as if the transform of the nodes that instance them were baked
+    /// into their vertices, which are themselves left untouched. A mesh that's instanced with
+    /// different transforms is fingerprinted as it stands.
+    Compare,
+    /// The transforms of the nodes are baked into the positions, normals & tangents of the
+    /// meshes as the asset is loaded, and every node's transform is reset, so that the melded
+    /// result holds the baked geometry. Assets where that would change how they render – with
+    /// animations, skins, cameras, lights, morph targets, mirroring transforms, or meshes that
+    /// are instanced with different transforms – are rejected.
+    Bake,
+}
+
+/// How accessor bounds are treated at export; see `MeldOptions::position_bounds`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BoundsPolicy {
+    /// Bounds are exported as they were imported.
+    Keep,
+    /// Bounds are checked against the data, and any discrepancy fails the export.
+    Verify,
+    /// Bounds are computed from the data, replacing whatever was there.
+    Recompute,
+}
+
+/// How the `asset` properties of two melded assets combine; see `MeldOptions::asset_info_policy`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AssetInfoPolicy {
+    /// The base asset's properties are kept unchanged, and the other's dropped.
+    KeepBase,
+    /// Distinct copyrights are joined with `"; "`, every contributing generator is listed in the
+    /// `asset.extras` property `contributingGenerators`, and other `extras` properties are
+    /// combined, the base's winning out when both have the same one.
+    Merge,
+}
+
+impl Default for AssetInfoPolicy {
+    fn default() -> Self {
+        AssetInfoPolicy::Merge
+    }
+}
+
+/// How the names of equivalent images & textures of two melded assets combine; see
+/// `MeldOptions::name_merge_policy`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NameMergePolicy {
+    /// The base's name is kept, and the other's dropped without a trace.
+    Ignore,
+    /// The base's name is kept, and any other is listed in the `extras` property `nameAliases`.
+    KeepBase,
+    /// The melded-in asset's name wins, and any other is listed in `nameAliases`.
+    KeepOther,
+}
+
+impl Default for NameMergePolicy {
+    fn default() -> Self {
+        NameMergePolicy::Ignore
+    }
+}
+
+/// Which of two tolerably-equal colours survives a meld; see `MeldOptions::color_tolerance`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ColorPolicy {
+    /// The base asset's material is kept unchanged.
+    KeepBase,
+    /// The base asset's material takes on the colour factors of the melded-in material.
+    KeepOther,
+}
+
+impl Default for ColorPolicy {
+    fn default() -> Self {
+        ColorPolicy::KeepBase
+    }
+}
+
+impl Default for MaterialFilterPolicy {
+    fn default() -> Self {
+        MaterialFilterPolicy::Error
+    }
+}
+
+impl Default for DracoSettings {
+    fn default() -> Self {
+        DracoSettings {
+            position_bits: 14,
+            normal_bits: 10,
+            texcoord_bits: 12,
+            generic_bits: 12,
+        }
+    }
+}
+
+impl Default for FingerprintAlgorithm {
+    fn default() -> Self {
+        FingerprintAlgorithm::Additive
+    }
+}
+
+impl Default for NodeTransformPolicy {
+    fn default() -> Self {
+        NodeTransformPolicy::Ignore
+    }
+}
+
+impl Default for BoundsPolicy {
+    fn default() -> Self {
+        BoundsPolicy::Keep
+    }
+}
+
+impl Default for TagCase {
+    fn default() -> Self {
+        TagCase::Preserve
+    }
+}
+
+impl TagCase {
+    fn apply(&self, tag: &str) -> Tag {
+        match self {
+            TagCase::Preserve => tag.to_owned(),
+            TagCase::Lower => tag.to_lowercase(),
+            TagCase::Upper => tag.to_uppercase(),
+        }
+    }
+}
+
+impl TagPolicy {
+    /// Normalizes the given tag according to this policy, or explains why it's not allowed.
+    ///
+    /// Structured tags, see the `axes` module, are also brought into canonical form.
+    pub fn apply(&self, tag: &str) -> Result<Tag> {
+        let tag = self.case.apply(tag);
+        if tag.is_empty() {
+            return Err(String::from("Tags may not be empty."));
+        }
+        // structured tags are put in canonical form, and may always use their own separators
+        let (tag, structural): (Tag, &[char]) = match axes::parse_axis_tag(&tag)? {
+            Some(values) => (
+                axes::axis_tag(&values),
+                &[axes::AXIS_SEPARATOR, axes::AXIS_VALUE_SEPARATOR],
+            ),
+            None => (tag, &[]),
+        };
+        if let Some(punctuation) = &self.allowed_punctuation {
+            let bad_char = tag.chars().find(|c| {
+                !c.is_ascii_alphanumeric() && !punctuation.contains(*c) && !structural.contains(c)
+            });
+            if let Some(bad_char) = bad_char {
+                return Err(format!("Tag {:?} contains disallowed {:?}.", tag, bad_char));
+            }
+        }
+        if let Some(max_length) = self.max_length {
+            if tag.chars().count() > max_length {
+                return Err(format!(
+                    "Tag {:?} is longer than {} characters.",
+                    tag, max_length
+                ));
+            }
+        }
+        if self
+            .reserved
+            .iter()
+            .any(|reserved| self.case.apply(reserved) == tag)
+        {
+            return Err(format!("Tag {:?} is reserved.", tag));
+        }
+        Ok(tag)
+    }
+}
+
+impl InputLimits {
+    /// Checks the sizes of an asset's JSON and blob, before they are parsed or copied.
+    pub fn check_sizes(&self, json_bytes: usize, blob_bytes: usize) -> Result<()> {
+        check_limit("JSON size", json_bytes, self.max_json_bytes)?;
+        check_limit("Blob size", blob_bytes, self.max_blob_bytes)
+    }
+
+    /// Checks the object counts of a parsed asset.
+    pub fn check_counts(&self, root: &Root) -> Result<()> {
+        let primitives = root.meshes.iter().map(|mesh| mesh.primitives.len()).sum();
+        check_limit("Image count", root.images.len(), self.max_images)?;
+        check_limit("Mesh count", root.meshes.len(), self.max_meshes)?;
+        check_limit("Primitive count", primitives, self.max_primitives)
+    }
+}
+
+impl FixedPrimitive {
+    /// Whether this is, or includes, the given primitive of the given mesh of the given asset.
+    pub fn selects(&self, root: &Root, m_ix: usize, p_ix: usize) -> bool {
+        let mesh = match &self.mesh {
+            MeshSelector::Name(name) => root
+                .meshes
+                .get(m_ix)
+                .map_or(false, |mesh| mesh.name.as_ref() == Some(name)),
+            MeshSelector::Index(ix) => *ix == m_ix,
+        };
+        mesh && self.primitive.map_or(true, |ix| ix == p_ix)
+    }
+}
+
+impl MaterialFilter {
+    /// Whether a material of the given name may vary between variants.
+    pub fn allows(&self, name: &str) -> bool {
+        let matches = |pattern: &String| wildcard_match(pattern, name);
+        (self.allow.is_empty() || self.allow.iter().any(matches)) && !self.deny.iter().any(matches)
+    }
+}
+
+// whether the text matches the pattern in full, with `*` & `?` as wildcards; each `*` is first
+// taken to match nothing, then one more character each time a mismatch sends us back to it, and
+// as the last `*` always covers what any earlier one could, there's never more to backtrack to
+fn wildcard_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    let (mut p, mut t) = (0, 0);
+    // the position of the last `*` seen, and of the text it's matched up to
+    let mut star: Option<(usize, usize)> = None;
+    while t < text.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p, t));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            _ => match star {
+                Some((star_p, star_t)) => {
+                    star = Some((star_p, star_t + 1));
+                    p = star_p + 1;
+                    t = star_t + 1;
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+fn check_limit(what: &str, actual: usize, limit: Option<usize>) -> Result<()> {
+    match limit {
+        Some(limit) if actual > limit => Err(format!(
+            "{}: {} is {}, over the limit of {}.",
+            INPUT_LIMIT_EXCEEDED, what, actual, limit
+        )),
+        _ => Ok(()),
+    }
+}
+
+impl Default for MeldOptions {
+    fn default() -> Self {
+        MeldOptions {
+            float_precision: Some(DEFAULT_FLOAT_PRECISION),
+            color_tolerance: None,
+            color_policy: ColorPolicy::default(),
+            embed_metadata: false,
+            lenient: false,
+            write_legacy_extension: false,
+            compact_variant_mappings: false,
+            extension_revision: None,
+            texture_encodings: BTreeMap::new(),
+            material_tag_separator: None,
+            variant_importers: VariantImporters::default(),
+            variant_exporters: VariantExporters::default(),
+            tag_policy: TagPolicy::default(),
+            memory_budget: None,
+            keep_work_assets: false,
+            input_limits: InputLimits::default(),
+            texture_search_paths: vec![],
+            case_insensitive_uris: false,
+            confine_uris: false,
+            position_bounds: BoundsPolicy::default(),
+            fingerprint_algorithm: FingerprintAlgorithm::default(),
+            fingerprint_epsilon: DEFAULT_FINGERPRINT_EPSILON,
+            node_transforms: NodeTransformPolicy::default(),
+            node_scoped_meshes: false,
+            drop_extra_chunks: false,
+            asset_info_policy: AssetInfoPolicy::default(),
+            name_merge_policy: NameMergePolicy::default(),
+            require_variants_extension: false,
+            allow_unsupported_required: false,
+            mesh_id_key: None,
+            fixed_primitives: vec![],
+            material_filter: MaterialFilter::default(),
+        }
+    }
+}
+
+impl MeldOptions {
+    /// Options that require floating-point values to match exactly.
+    pub fn exact() -> Self {
+        MeldOptions {
+            float_precision: None,
+            ..Default::default()
+        }
+    }
+
+    // the options that decide how objects are keyed & fingerprinted, and how meshes are matched
+    // up, by name, each written out as JSON; assets loaded with any of them set differently
+    // can't be melded, nor share cached keys, while the rest only matter to a meld's result
+    pub(crate) fn keying_options(&self) -> Vec<(&'static str, Value)> {
+        let fingerprint_algorithm = match self.fingerprint_algorithm {
+            FingerprintAlgorithm::Additive => json!("additive"),
+            FingerprintAlgorithm::Quantized(spacing) => json!({ "quantized": spacing }),
+        };
+        let node_transforms = match self.node_transforms {
+            NodeTransformPolicy::Ignore => "ignore",
+            NodeTransformPolicy::Compare => "compare",
+            NodeTransformPolicy::Bake => "bake",
+        };
+        vec![
+            ("float_precision", json!(self.float_precision)),
+            ("color_tolerance", json!(self.color_tolerance)),
+            ("fingerprint_algorithm", fingerprint_algorithm),
+            ("fingerprint_epsilon", json!(self.fingerprint_epsilon)),
+            ("node_transforms", json!(node_transforms)),
+            ("node_scoped_meshes", json!(self.node_scoped_meshes)),
+            ("mesh_id_key", json!(self.mesh_id_key)),
+        ]
+    }
+
+    // why assets loaded with these options & the other can't be melded, if they can't: they
+    // must agree on their keying options, and on what tags they allow
+    pub(crate) fn meld_incompatibility(&self, other: &MeldOptions) -> Option<String> {
+        let theirs = other.keying_options();
+        for ((name, ours), (_, theirs)) in self.keying_options().iter().zip(&theirs) {
+            if ours != theirs {
+                return Some(format!(
+                    "{} is {} for the base, but {} for the melded asset",
+                    name, ours, theirs
+                ));
+            }
+        }
+        if self.tag_policy != other.tag_policy {
+            return Some(String::from("their tag policies differ"));
+        }
+        None
+    }
+}
@@ -0,0 +1,229 @@
+// Copyright (c) Facebook, Inc. and its affiliates. All Rights Reserved
+//
+
+//! Code to synthesize new variants from the materials of existing ones.
+
+use std::collections::HashMap;
+
+use gltf::json::material::{NormalTexture, OcclusionTexture};
+use gltf::json::{texture, Index, Material, Texture};
+use serde::de::DeserializeOwned;
+use serde_derive::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::meld_keys::HasKeyForVariants;
+use crate::{MeldKey, Result, Tag, WorkAsset};
+
+/// Changes to make to materials when deriving one variant from another; see
+/// `WorkAsset::derive_variant()`.
+///
+/// Every field is optional, and whatever is left out is kept as it is. As JSON, this looks like
+/// `{"baseColorFactor": [0.4, 0.6, 0.9, 1.0], "roughnessFactor": 0.8}`. Texture fields swap in
+/// new images, given by index into the list of image files that accompanies the overrides.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct MaterialOverrides {
+    /// The new base colour factor, as linear RGBA.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub base_color_factor: Option<[f32; 4]>,
+    /// The new metallic factor.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub metallic_factor: Option<f32>,
+    /// The new roughness factor.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub roughness_factor: Option<f32>,
+    /// The new emissive factor, as linear RGB.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub emissive_factor: Option<[f32; 3]>,
+    /// The image to use as the new base colour texture.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub base_color_texture: Option<usize>,
+    /// The image to use as the new metallic-roughness texture.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub metallic_roughness_texture: Option<usize>,
+    /// The image to use as the new normal texture.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub normal_texture: Option<usize>,
+    /// The image to use as the new occlusion texture.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub occlusion_texture: Option<usize>,
+    /// The image to use as the new emissive texture.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub emissive_texture: Option<usize>,
+}
+
+impl MaterialOverrides {
+    /// Parses overrides from JSON.
+    pub fn from_json(json: &str) -> Result<MaterialOverrides> {
+        serde_json::from_str(json).map_err(|e| format!("Bad material overrides: {}", e))
+    }
+
+    /// Serialises these overrides as JSON.
+    pub fn to_json(&self) -> Result<String> {
+        serde_json::to_string(self)
+            .map_err(|e| format!("Couldn't serialize material overrides: {}", e))
+    }
+
+    /// Whether these overrides change nothing at all.
+    pub fn is_empty(&self) -> bool {
+        *self == MaterialOverrides::default()
+    }
+}
+
+impl WorkAsset {
+    /// Adds a new tag, mapped on every primitive to a copy of the material that tag `from` maps
+    /// to there, with *overrides* applied; returns how many primitives it was mapped on.
+    ///
+    /// This synthesizes simple variants, e.g. colourways, with no source asset to meld in. Each
+    /// material is copied once, however many primitives share it; a copy that comes out the same
+    /// as some existing material is simply that material. Texture overrides index into *images*,
+    /// the bytes of PNG or JPEG files. The new tag must not already be in use.
+    pub fn derive_variant(
+        &mut self,
+        from: &Tag,
+        tag: &Tag,
+        overrides: &MaterialOverrides,
+        images: &[Vec<u8>],
+    ) -> Result<usize> {
+        let from = &self.options.tag_policy.apply(from)?;
+        let tag = &self.options.tag_policy.apply(tag)?;
+        if self.get_tags_in_use()?.contains(tag) || *tag == self.default_tag {
+            return Err(format!("Tag {} is already in use.", tag));
+        }
+
+        let mut derived: HashMap<MeldKey, MeldKey> = HashMap::new();
+        let mut mapped = 0;
+        for m_ix in 0..self.parse.meshes.len() {
+            for p_ix in 0..self.parse.meshes[m_ix].primitives.len() {
+                let source_key = match self.interned_mapping(m_ix, p_ix).get(from.as_str()) {
+                    Some(key) => key.to_string(),
+                    None if *from == self.default_tag => {
+                        match self.parse.meshes[m_ix].primitives[p_ix].material {
+                            Some(ix) => self.material_keys[ix.value()].to_owned(),
+                            None => continue,
+                        }
+                    }
+                    None => continue,
+                };
+                let key = match derived.get(&source_key) {
+                    Some(key) => key.to_owned(),
+                    None => {
+                        let key = self.derive_material(&source_key, tag, overrides, images)?;
+                        derived.insert(source_key, key.clone());
+                        key
+                    }
+                };
+                self.set_variant(m_ix, p_ix, tag, &key)?;
+                mapped += 1;
+            }
+        }
+        if mapped == 0 {
+            return Err(format!("No primitive has a material for tag {}.", from));
+        }
+        Ok(mapped)
+    }
+
+    // copy the material with the given key, apply the overrides, and return the key of the result
+    fn derive_material(
+        &mut self,
+        source_key: &MeldKey,
+        tag: &Tag,
+        overrides: &MaterialOverrides,
+        images: &[Vec<u8>],
+    ) -> Result<MeldKey> {
+        let source_ix = self.material_ix(source_key).ok_or_else(|| {
+            format!(
+                "Can't derive from {}.",
+                self.describe_material_key(source_key)
+            )
+        })?;
+        let mut material: Material = self.materials()[source_ix].clone();
+        material.name = Some(match material.name.take() {
+            Some(name) => format!("{}_{}", name, tag),
+            None => tag.to_owned(),
+        });
+
+        let pbr = &mut material.pbr_metallic_roughness;
+        if let Some(factor) = overrides.base_color_factor {
+            pbr.base_color_factor.0 = factor;
+        }
+        if let Some(factor) = overrides.metallic_factor {
+            pbr.metallic_factor.0 = factor;
+        }
+        if let Some(factor) = overrides.roughness_factor {
+            pbr.roughness_factor.0 = factor;
+        }
+        if let Some(factor) = overrides.emissive_factor {
+            material.emissive_factor.0 = factor;
+        }
+
+        let mut texture = |image: usize| -> Result<Index<Texture>> {
+            let bytes = images.get(image).ok_or_else(|| {
+                format!(
+                    "Overrides use image {}, but only {} images were given.",
+                    image,
+                    images.len()
+                )
+            })?;
+            self.add_image_texture(bytes)
+        };
+        if let Some(image) = overrides.base_color_texture {
+            set_texture(&mut pbr.base_color_texture, texture(image)?)?;
+        }
+        if let Some(image) = overrides.metallic_roughness_texture {
+            set_texture(&mut pbr.metallic_roughness_texture, texture(image)?)?;
+        }
+        if let Some(image) = overrides.normal_texture {
+            set_texture(&mut material.normal_texture, texture(image)?)?;
+        }
+        if let Some(image) = overrides.occlusion_texture {
+            set_texture(&mut material.occlusion_texture, texture(image)?)?;
+        }
+        if let Some(image) = overrides.emissive_texture {
+            set_texture(&mut material.emissive_texture, texture(image)?)?;
+        }
+
+        let key = material.build_meld_key(self)?;
+        if self.material_ix(&key).is_none() {
+            self.push_material(material, &key);
+        }
+        Ok(key)
+    }
+}
+
+/// The texture references of a material, which we can point at a new texture.
+trait TextureReference: DeserializeOwned {
+    fn index_mut(&mut self) -> &mut Index<Texture>;
+}
+
+impl TextureReference for texture::Info {
+    fn index_mut(&mut self) -> &mut Index<Texture> {
+        &mut self.index
+    }
+}
+
+impl TextureReference for NormalTexture {
+    fn index_mut(&mut self) -> &mut Index<Texture> {
+        &mut self.index
+    }
+}
+
+impl TextureReference for OcclusionTexture {
+    fn index_mut(&mut self) -> &mut Index<Texture> {
+        &mut self.index
+    }
+}
+
+// point the given texture reference at the given texture, keeping any other attributes it has
+fn set_texture<T: TextureReference>(info: &mut Option<T>, index: Index<Texture>) -> Result<()> {
+    match info {
+        Some(info) => *info.index_mut() = index,
+        None => {
+            *info = Some(
+                serde_json::from_value(json!({ "index": index.value() }))
+                    .map_err(|e| format!("Internal error: Can't build texture reference: {}", e))?,
+            )
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,113 @@
+// Copyright (c) Facebook, Inc. and its affiliates. All Rights Reserved
+//
+
+//! Code to redirect material references in bulk, e.g. to consolidate exporter duplicates.
+
+use std::collections::{HashMap, HashSet};
+
+use gltf::json::Index;
+
+use crate::{MeldKey, Result, WorkAsset};
+
+impl WorkAsset {
+    /// Redirects every use of a material named as a key of *names* to the material named as its
+    /// value, returning how many material references were rewritten.
+    ///
+    /// This is meant for consolidating the duplicates exporters like to generate, e.g. mapping
+    /// `Body.001` and `Body.002` to `Body`; materials whose targets collide simply merge. All the
+    /// materials of a source name are redirected, but a target name must pick out a single
+    /// material. See `remap_material_keys()` for the details.
+    pub fn remap_materials(&mut self, names: &HashMap<String, String>) -> Result<usize> {
+        let mut keys = HashMap::new();
+        for (source, target) in names {
+            let target_key = self.unique_material_key(target)?;
+            let source_keys = self.material_keys_named(source);
+            if source_keys.is_empty() {
+                return Err(format!("No material named '{}'.", source));
+            }
+            for source_key in source_keys {
+                keys.insert(source_key, target_key.clone());
+            }
+        }
+        self.remap_material_keys(&keys)
+    }
+
+    /// Redirects every use of a material whose `MeldKey` is a key of *keys* to the material whose
+    /// key is its value, returning how many material references were rewritten.
+    ///
+    /// Both the default material of each primitive and its variant mappings are rewritten.
+    /// Redirects are followed, so A → B and B → C takes A to C; but every target must be a
+    /// material of this asset. The materials redirected away from are left in place, unused.
+    pub fn remap_material_keys(&mut self, keys: &HashMap<MeldKey, MeldKey>) -> Result<usize> {
+        let mut resolved = HashMap::new();
+        for source in keys.keys() {
+            let target = resolve_redirect(source, keys)?;
+            let target_ix = self.material_ix(&target).ok_or_else(|| {
+                format!("Can't redirect to {}.", self.describe_material_key(&target))
+            })?;
+            if target != *source {
+                resolved.insert(source.to_owned(), (target, target_ix));
+            }
+        }
+
+        let mut rewritten = 0;
+        for (m_ix, mesh) in self.parse.meshes.iter_mut().enumerate() {
+            for (p_ix, primitive) in mesh.primitives.iter_mut().enumerate() {
+                if let Some(material) = primitive.material {
+                    let key = &self.material_keys[material.value()];
+                    if let Some((_, target_ix)) = resolved.get(key) {
+                        primitive.material = Some(Index::new(*target_ix as u32));
+                        rewritten += 1;
+                    }
+                }
+                for key in self.mesh_primitive_variants[m_ix][p_ix].values_mut() {
+                    if let Some((target, _)) = resolved.get(&**key) {
+                        *key = self.strings.intern(target);
+                        rewritten += 1;
+                    }
+                }
+            }
+        }
+        Ok(rewritten)
+    }
+
+    // the distinct keys of the materials with the given name
+    fn material_keys_named(&self, name: &str) -> Vec<MeldKey> {
+        let mut keys: Vec<MeldKey> = self
+            .materials()
+            .iter()
+            .zip(self.material_keys())
+            .filter(|(material, _)| material.name.as_ref().map(String::as_str) == Some(name))
+            .map(|(_, key)| key.to_owned())
+            .collect();
+        keys.sort_unstable();
+        keys.dedup();
+        keys
+    }
+
+    // the key of the one material with the given name, or an error if there's no such thing
+    pub(crate) fn unique_material_key(&self, name: &str) -> Result<MeldKey> {
+        let mut keys = self.material_keys_named(name);
+        match keys.len() {
+            0 => Err(format!("No material named '{}'.", name)),
+            1 => Ok(keys.remove(0)),
+            _ => Err(format!("Ambiguous material name '{}'.", name)),
+        }
+    }
+}
+
+// follow redirects from the given key to where they end, refusing to go round in circles
+fn resolve_redirect(source: &MeldKey, keys: &HashMap<MeldKey, MeldKey>) -> Result<MeldKey> {
+    let mut seen = HashSet::new();
+    let mut key = source;
+    while let Some(target) = keys.get(key) {
+        if target == key {
+            break;
+        }
+        if !seen.insert(key) {
+            return Err(format!("Material redirects go round in circles."));
+        }
+        key = target;
+    }
+    Ok(key.to_owned())
+}
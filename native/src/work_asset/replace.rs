@@ -0,0 +1,143 @@
+// Copyright (c) Facebook, Inc. and its affiliates. All Rights Reserved
+//
+
+//! Code to swap new material definitions and image data into an asset after the fact.
+
+use std::collections::HashMap;
+
+use gltf::json::{image::MimeType, Image, Index, Material, Texture};
+use serde_json::json;
+use sha1::Sha1;
+
+use crate::gltfext::compact_blob;
+use crate::meld_keys::HasKeyForVariants;
+use crate::{MeldKey, Result, Tag, WorkAsset};
+
+use super::has_opaque_view_references;
+
+impl WorkAsset {
+    /// Maps `tag` on every primitive of the m_ix:th mesh to a new *material*, returning its key.
+    ///
+    /// The material's texture references index into *images*, the bytes of PNG or JPEG files,
+    /// each of which becomes a texture with the default sampler. The images, textures and
+    /// material are all keyed as if loaded from a source asset, so that any already present are
+    /// reused rather than duplicated. Whatever the tag mapped to before is left in place.
+    pub fn replace_material(
+        &mut self,
+        tag: &Tag,
+        m_ix: usize,
+        mut material: Material,
+        images: &[Vec<u8>],
+    ) -> Result<MeldKey> {
+        if m_ix >= self.parse.meshes.len() {
+            return Err(format!("Mesh index {} out of range.", m_ix));
+        }
+
+        let pbr = &mut material.pbr_metallic_roughness;
+        let texture_ixs = vec![
+            pbr.base_color_texture.as_mut().map(|t| &mut t.index),
+            pbr.metallic_roughness_texture
+                .as_mut()
+                .map(|t| &mut t.index),
+            material.normal_texture.as_mut().map(|t| &mut t.index),
+            material.occlusion_texture.as_mut().map(|t| &mut t.index),
+            material.emissive_texture.as_mut().map(|t| &mut t.index),
+        ];
+        for texture_ix in texture_ixs.into_iter().filter_map(|ix| ix) {
+            let bytes = images.get(texture_ix.value()).ok_or_else(|| {
+                format!(
+                    "New material uses texture {}, but only {} images were given.",
+                    texture_ix.value(),
+                    images.len()
+                )
+            })?;
+            *texture_ix = self.add_image_texture(bytes)?;
+        }
+
+        let key = material.build_meld_key(self)?;
+        if self.material_ix(&key).is_none() {
+            self.push_material(material, &key);
+        }
+        for p_ix in 0..self.parse.meshes[m_ix].primitives.len() {
+            self.set_variant(m_ix, p_ix, tag, &key)?;
+        }
+        Ok(key)
+    }
+
+    /// Replaces the file bytes of the image_ix:th *image* with `bytes`, of the given MIME type.
+    ///
+    /// The new bytes get a buffer view of their own, and the old ones are dropped from the blob
+    /// unless something else still uses them, so `approximate_size()` stays true. The keys of the
+    /// image, and of the textures & materials that depend on it, are then recomputed, and variant
+    /// mappings follow their materials to the new keys. Any `Metadata` computed from this asset
+    /// beforehand no longer reflects its texture sizes.
+    pub fn replace_image_bytes(&mut self, image_ix: usize, bytes: &[u8], mime: &str) -> Result<()> {
+        if image_ix >= self.parse.images.len() {
+            return Err(format!("Image index {} out of range.", image_ix));
+        }
+        let view_ix = self.push_buffer_view_from_slice(bytes)?;
+        let image = &mut self.parse.images[image_ix];
+        image.buffer_view = Some(Index::new(view_ix as u32));
+        image.mime_type = Some(MimeType(mime.to_owned()));
+        image.uri = None;
+
+        let keep_unreferenced = has_opaque_view_references(&self.parse);
+        self.blob = compact_blob(&mut self.parse, &self.blob, keep_unreferenced)?;
+
+        self.image_keys[image_ix] = self.parse.images[image_ix].build_meld_key(self)?;
+        self.texture_keys = self.build_meld_keys(&self.parse.textures)?;
+        let material_keys = self.build_meld_keys(&self.parse.materials)?;
+        let rekeyed: HashMap<MeldKey, MeldKey> = self
+            .material_keys
+            .drain(..)
+            .zip(material_keys.iter().cloned())
+            .filter(|(old, new)| old != new)
+            .collect();
+        self.material_keys = material_keys;
+        for mapping in self.mesh_primitive_variants.iter_mut().flatten() {
+            for material_key in mapping.values_mut() {
+                if let Some(new_key) = rekeyed.get(&**material_key) {
+                    *material_key = self.strings.intern(new_key);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // the texture, with the default sampler, of the given image file bytes, added if need be
+    pub(crate) fn add_image_texture(&mut self, bytes: &[u8]) -> Result<Index<Texture>> {
+        // this is what Image::build_meld_key() would make of the bytes
+        let image_key = Sha1::from(bytes).digest().to_string();
+        let image_ix = match self.image_ix(&image_key) {
+            Some(ix) => ix,
+            None => {
+                let mime_type = image_mime_type(bytes)?;
+                let view_ix = self.push_buffer_view_from_slice(bytes)?;
+                let image: Image =
+                    serde_json::from_value(json!({ "bufferView": view_ix, "mimeType": mime_type }))
+                        .map_err(|e| format!("Internal error: Can't build image: {}", e))?;
+                self.push_image(image, &image_key)
+            }
+        };
+
+        let texture: Texture = serde_json::from_value(json!({ "source": image_ix }))
+            .map_err(|e| format!("Internal error: Can't build texture: {}", e))?;
+        let texture_key = texture.build_meld_key(self)?;
+        let texture_ix = match self.texture_ix(&texture_key) {
+            Some(ix) => ix,
+            None => self.push_texture(texture, &texture_key),
+        };
+        Ok(Index::new(texture_ix as u32))
+    }
+}
+
+// the MIME type of the given image file bytes, going by their magic number
+fn image_mime_type(bytes: &[u8]) -> Result<&'static str> {
+    if bytes.starts_with(b"\x89PNG\r\n\x1a\n") {
+        Ok("image/png")
+    } else if bytes.starts_with(&[0xff, 0xd8, 0xff]) {
+        Ok("image/jpeg")
+    } else {
+        Err(format!("New texture images must be PNG or JPEG files."))
+    }
+}
@@ -0,0 +1,159 @@
+// Copyright (c) Facebook, Inc. and its affiliates. All Rights Reserved
+//
+
+//! Code to find texture images of different tags that are nearly alike, and so could be shared.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use serde_derive::{Deserialize, Serialize};
+
+use crate::work_asset::textures::material_images;
+use crate::{Result, Tag, WorkAsset};
+
+/// Two images, used by different tags, that look almost the same; see `SharingAnalysis`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct NearDuplicate {
+    /// The index of the one image.
+    pub image: usize,
+    /// The index of the other image.
+    pub other_image: usize,
+    /// The tags whose materials use the one image, in order.
+    pub tags: Vec<Tag>,
+    /// The tags whose materials use the other image, in order.
+    pub other_tags: Vec<Tag>,
+    /// How many bits of the two images' perceptual hashes differ, out of 64.
+    pub distance: u32,
+    /// The byte size of the smaller image, which using the larger one in its stead would save.
+    pub bytes_saved: usize,
+}
+
+/// Which images of an asset could be unified across tags, and what that would save; see
+/// `WorkAsset::analyze_sharing()`.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct SharingAnalysis {
+    /// Every pair of near-duplicate images, in order of image index.
+    pub pairs: Vec<NearDuplicate>,
+    /// The byte size that unifying each group of near-duplicates into its largest image would
+    /// save; this is less than the sum over `pairs` when an image is alike to several others.
+    pub bytes_saved: usize,
+}
+
+impl WorkAsset {
+    /// Looks for images that are used by different tags, yet are nearly alike, e.g. because an
+    /// artist exported the same texture twice with slightly different settings.
+    ///
+    /// Images are compared by a perceptual hash of their decoded pixels; those of the same
+    /// dimensions whose hashes differ in at most `max_distance` bits, out of 64, are reported
+    /// as near-duplicates. Exports that used one image for both would meld better, and this
+    /// reports by how much. It requires building with the `image` feature.
+    pub fn analyze_sharing(&self, max_distance: u32) -> Result<SharingAnalysis> {
+        // the tags that use each image
+        let mut image_tags: BTreeMap<usize, BTreeSet<Tag>> = BTreeMap::new();
+        for (m_ix, mesh) in self.meshes().iter().enumerate() {
+            for (p_ix, primitive) in mesh.primitives.iter().enumerate() {
+                let mut uses = vec![];
+                for (tag, material_key) in self.interned_mapping(m_ix, p_ix) {
+                    if let Some(material_ix) = self.material_ix(material_key) {
+                        uses.push((&**tag, material_ix));
+                    }
+                }
+                if let Some(material_ix) = primitive.material {
+                    uses.push((self.default_tag.as_str(), material_ix.value()));
+                }
+                for (tag, material_ix) in uses {
+                    let material = &self.materials()[material_ix];
+                    for image_ix in material_images(&self.parse, material) {
+                        image_tags
+                            .entry(image_ix)
+                            .or_default()
+                            .insert(tag.to_owned());
+                    }
+                }
+            }
+        }
+
+        let mut hashed = vec![];
+        for (&image_ix, tags) in &image_tags {
+            let bytes = self.read_image_bytes(&self.images()[image_ix])?;
+            let (width, height, hash) =
+                perceptual_hash(&bytes).map_err(|e| format!("Image {}: {}", image_ix, e))?;
+            hashed.push((image_ix, tags, width, height, hash, bytes.len()));
+        }
+
+        let mut analysis = SharingAnalysis::default();
+        // each near-duplicate image, and the group of them it belongs to, named for one member
+        let mut groups: BTreeMap<usize, usize> = BTreeMap::new();
+        for (ix, (image, tags, width, height, hash, size)) in hashed.iter().enumerate() {
+            for (other_image, other_tags, other_width, other_height, other_hash, other_size) in
+                &hashed[ix + 1..]
+            {
+                let distance = (hash ^ other_hash).count_ones();
+                if tags == other_tags
+                    || (width, height) != (other_width, other_height)
+                    || distance > max_distance
+                {
+                    continue;
+                }
+                let group = *groups.get(image).unwrap_or(image);
+                let other_group = *groups.get(other_image).unwrap_or(other_image);
+                for member in groups.values_mut() {
+                    if *member == other_group {
+                        *member = group;
+                    }
+                }
+                groups.insert(*image, group);
+                groups.insert(*other_image, group);
+
+                analysis.pairs.push(NearDuplicate {
+                    image: *image,
+                    other_image: *other_image,
+                    tags: tags.iter().cloned().collect(),
+                    other_tags: other_tags.iter().cloned().collect(),
+                    distance,
+                    bytes_saved: *size.min(other_size),
+                });
+            }
+        }
+
+        // each group keeps only its largest image
+        let mut group_sizes: BTreeMap<usize, Vec<usize>> = BTreeMap::new();
+        for (image, _, _, _, _, size) in &hashed {
+            if let Some(group) = groups.get(image) {
+                group_sizes.entry(*group).or_default().push(*size);
+            }
+        }
+        analysis.bytes_saved = group_sizes
+            .values()
+            .map(|sizes| sizes.iter().sum::<usize>() - sizes.iter().max().unwrap_or(&0))
+            .sum();
+        Ok(analysis)
+    }
+}
+
+// the image's dimensions, and a 64-bit difference hash of its pixels: each bit tells whether a
+// pixel of a downscaled, grey-scale copy is brighter than its right-hand neighbour
+#[cfg(feature = "image")]
+fn perceptual_hash(bytes: &[u8]) -> Result<(u32, u32, u64)> {
+    use image::imageops::FilterType;
+    use image::GenericImageView;
+
+    let decoded = image::load_from_memory(bytes)
+        .map_err(|e| format!("Couldn't decode texture image: {}", e))?;
+    let (width, height) = decoded.dimensions();
+    let small = decoded.resize_exact(9, 8, FilterType::Triangle).to_luma();
+    let mut hash = 0u64;
+    for y in 0..8 {
+        for x in 0..8 {
+            let brighter = small.get_pixel(x, y)[0] > small.get_pixel(x + 1, y)[0];
+            hash = (hash << 1) | brighter as u64;
+        }
+    }
+    Ok((width, height, hash))
+}
+
+#[cfg(not(feature = "image"))]
+fn perceptual_hash(_bytes: &[u8]) -> Result<(u32, u32, u64)> {
+    Err(format!(
+        "Texture image analysis requires building with the image feature."
+    ))
+}
@@ -0,0 +1,250 @@
+// Copyright (c) Facebook, Inc. and its affiliates. All Rights Reserved
+//
+
+//! Code to split a variational `WorkAsset` into one plain glTF asset per variant tag.
+
+use std::borrow::Cow;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use gltf::json::{Index, Material, Root, Texture};
+use sha1::Sha1;
+
+use crate::extension;
+use crate::gltfext::{compact_blob, compact_blob_spans};
+use crate::{GlbChunk, Result, Tag, WorkAsset};
+
+use super::{has_opaque_index_references, has_opaque_view_references};
+
+// the punctuation tag_file_stem() keeps, being harmless in file names everywhere
+const FILE_NAME_PUNCTUATION: &str = "-_.,+=@";
+
+// the longest tag_file_stem() gets before any hash is appended, in bytes; file systems commonly
+// allow 255, which leaves room for an extension, or a prefix
+const MAX_FILE_STEM_BYTES: usize = 160;
+
+// device names that Windows won't have as file names, whatever the extension
+const RESERVED_FILE_STEMS: &[&str] = &[
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8",
+    "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+impl WorkAsset {
+    /// Builds a plain, non-variational GLB for each tag in use; see `extract_variant()`.
+    pub fn split(&self) -> Result<BTreeMap<Tag, Vec<u8>>> {
+        let mut result = BTreeMap::new();
+        for tag in self.get_tags_in_use()? {
+            let glb = self
+                .extract_variant(&tag)
+                .map_err(|e| format!("Tag {}: {}", tag, e))?;
+            result.insert(tag, glb);
+        }
+        Ok(result)
+    }
+
+    /// Writes a plain, non-variational GLB for each tag in use into the given directory, which
+    /// is created if need be, each named for its tag by `tag_file_stem()`. Returns the number of
+    /// bytes written for each tag.
+    ///
+    /// Unlike `split()`, no GLB is ever built in memory: each is streamed to its file, with its
+    /// binary data copied straight from this asset's blob, which most variants share the bulk of.
+    pub fn split_to_dir(&self, dir: &Path) -> Result<BTreeMap<Tag, usize>> {
+        fs::create_dir_all(dir)
+            .map_err(|e| format!("Couldn't create directory {}: {}", dir.display(), e))?;
+        let mut result = BTreeMap::new();
+        for tag in self.get_tags_in_use()? {
+            let path = dir.join(format!("{}.glb", tag_file_stem(&tag)));
+            let file = File::create(&path)
+                .map_err(|e| format!("Couldn't create file {}: {}", path.display(), e))?;
+            let mut writer = BufWriter::new(file);
+            let written = self
+                .write_variant(&tag, &mut writer)
+                .map_err(|e| format!("Tag {}: {}", tag, e))?;
+            writer
+                .flush()
+                .map_err(|e| format!("Couldn't write file {}: {}", path.display(), e))?;
+            result.insert(tag, written);
+        }
+        Ok(result)
+    }
+
+    /// Builds a plain glTF asset, in GLB form, that shows the given variant tag.
+    ///
+    /// Every mesh primitive the tag maps is given the tag's material as its default, and all
+    /// `KHR_materials_variants` data is dropped, so that the result suits viewers that know
+    /// nothing of variants. Materials, textures, images & samplers the variant doesn't use are
+    /// dropped too, along with their bytes – unless the asset uses extensions that might refer to
+    /// them in ways we can't see. Textures are re-encoded just as for `export()`.
+    pub fn extract_variant(&self, tag: &Tag) -> Result<Vec<u8>> {
+        let (mut root, source_blob, keep_unreferenced) = self.variant_root(tag)?;
+        let blob = compact_blob(&mut root, &source_blob, keep_unreferenced)?;
+        self.build_glb_for_export(root, &blob)
+    }
+
+    /// Writes the GLB that `extract_variant()` builds to `writer` instead, returning the number
+    /// of bytes written. The binary data is streamed from this asset's blob, never copied whole.
+    pub fn write_variant<W: Write>(&self, tag: &Tag, writer: &mut W) -> Result<usize> {
+        let (mut root, source_blob, keep_unreferenced) = self.variant_root(tag)?;
+        let spans = compact_blob_spans(&mut root, source_blob.len(), keep_unreferenced)?;
+        let pieces: Vec<&[u8]> = spans
+            .into_iter()
+            .map(|(start, end)| &source_blob[start..end.min(source_blob.len())])
+            .collect();
+        let json = self.build_json_for_export(root)?;
+        GlbChunk::write_with_extras(
+            writer,
+            GlbChunk::JSON(&json),
+            &pieces,
+            self.extra_glb_chunks(),
+        )
+    }
+
+    // the root of the given variant, before blob compaction, along with the blob it refers to,
+    // and whether compaction must keep unreferenced buffer views
+    fn variant_root(&self, tag: &Tag) -> Result<(Root, Cow<[u8]>, bool)> {
+        if !self.get_tags_in_use()?.contains(tag) {
+            return Err(format!("Tag {} is not in use in this asset.", tag));
+        }
+        let mut root = self.parse.clone();
+        let source_blob = self.reencode_textures(&mut root)?;
+
+        for (m_ix, mesh) in root.meshes.iter_mut().enumerate() {
+            for (p_ix, primitive) in mesh.primitives.iter_mut().enumerate() {
+                if let Some(material_key) = self.interned_mapping(m_ix, p_ix).get(tag.as_str()) {
+                    let material_ix = self.material_ix(material_key).ok_or_else(|| {
+                        format!(
+                            "Huh? {} maps tag {} to {}.",
+                            self.describe_primitive(m_ix, p_ix),
+                            tag,
+                            self.describe_material_key(material_key)
+                        )
+                    })?;
+                    primitive.material = Some(Index::new(material_ix as u32));
+                }
+            }
+        }
+        extension::uninstall(&mut root);
+
+        // texture references hidden in material extensions would be left dangling
+        if !has_opaque_index_references(&root) {
+            prune_unused_materials(&mut root);
+        }
+        let keep_unreferenced = has_opaque_view_references(&root);
+        Ok((root, source_blob, keep_unreferenced))
+    }
+}
+
+/// A file name stem for the given tag that's safe on any common file system, e.g. for writing
+/// out the results of `WorkAsset::split()`.
+///
+/// Letters & digits are kept, Unicode or not, as is the punctuation in `-_.,+=@`; anything else,
+/// e.g. a space, a slash, or the `:` of a structured tag, becomes `_`. Stems that Windows
+/// reserves, or that would make hidden files, get a leading `_`, and overlong ones are cut short.
+/// If the tag had to be changed at all, a short hash of it is appended, so that distinct tags
+/// never share a file name.
+pub fn tag_file_stem(tag: &str) -> String {
+    let mut stem: String = tag
+        .chars()
+        .map(|c| {
+            if c.is_alphanumeric() || FILE_NAME_PUNCTUATION.contains(c) {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    let device = stem.split('.').next().unwrap_or("").to_uppercase();
+    if stem.is_empty() || stem.starts_with('.') || RESERVED_FILE_STEMS.contains(&device.as_str()) {
+        stem.insert(0, '_');
+    }
+    if stem.ends_with('.') {
+        stem.pop();
+        stem.push('_');
+    }
+    if stem.len() > MAX_FILE_STEM_BYTES {
+        let mut end = MAX_FILE_STEM_BYTES;
+        while !stem.is_char_boundary(end) {
+            end -= 1;
+        }
+        stem.truncate(end);
+    }
+    if stem != tag {
+        let hash = Sha1::from(tag.as_bytes()).digest().to_string();
+        stem = format!("{}-{}", stem, &hash[..8]);
+    }
+    stem
+}
+
+// drop the materials no primitive uses, then the textures, images & samplers no remaining
+// material uses, and re-index all references to those that remain
+fn prune_unused_materials(root: &mut Root) {
+    let used = root
+        .meshes
+        .iter()
+        .flat_map(|mesh| &mesh.primitives)
+        .filter_map(|primitive| primitive.material)
+        .map(|ix| ix.value())
+        .collect();
+    let new_ix = retain_used(&mut root.materials, used);
+    for primitive in root.meshes.iter_mut().flat_map(|mesh| &mut mesh.primitives) {
+        primitive.material = primitive.material.map(|ix| new_ix[&ix.value()]);
+    }
+
+    let used = root
+        .materials
+        .iter_mut()
+        .flat_map(texture_indices)
+        .map(|ix| ix.value())
+        .collect();
+    let new_ix = retain_used(&mut root.textures, used);
+    for ix in root.materials.iter_mut().flat_map(texture_indices) {
+        *ix = new_ix[&ix.value()];
+    }
+
+    let used = root.textures.iter().map(|t| t.source.value()).collect();
+    let new_ix = retain_used(&mut root.images, used);
+    for texture in &mut root.textures {
+        texture.source = new_ix[&texture.source.value()];
+    }
+
+    let used = root
+        .textures
+        .iter()
+        .filter_map(|t| t.sampler)
+        .map(|ix| ix.value())
+        .collect();
+    let new_ix = retain_used(&mut root.samplers, used);
+    for texture in &mut root.textures {
+        texture.sampler = texture.sampler.map(|ix| new_ix[&ix.value()]);
+    }
+}
+
+// keep only the objects at the given indices, returning a mapping from old index to new
+fn retain_used<T>(objects: &mut Vec<T>, used: BTreeSet<usize>) -> HashMap<usize, Index<T>> {
+    let mut new_ix = HashMap::new();
+    for (ix, object) in std::mem::replace(objects, vec![]).into_iter().enumerate() {
+        if used.contains(&ix) {
+            new_ix.insert(ix, Index::new(objects.len() as u32));
+            objects.push(object);
+        }
+    }
+    new_ix
+}
+
+// the texture indices of all the texture references of the given material
+pub(crate) fn texture_indices(material: &mut Material) -> Vec<&mut Index<Texture>> {
+    let pbr = &mut material.pbr_metallic_roughness;
+    let mut result = vec![];
+    result.extend(pbr.base_color_texture.as_mut().map(|t| &mut t.index));
+    result.extend(
+        pbr.metallic_roughness_texture
+            .as_mut()
+            .map(|t| &mut t.index),
+    );
+    result.extend(material.normal_texture.as_mut().map(|t| &mut t.index));
+    result.extend(material.occlusion_texture.as_mut().map(|t| &mut t.index));
+    result.extend(material.emissive_texture.as_mut().map(|t| &mut t.index));
+    result
+}
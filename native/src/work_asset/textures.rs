@@ -0,0 +1,166 @@
+// Copyright (c) Facebook, Inc. and its affiliates. All Rights Reserved
+//
+
+//! Code to re-encode texture images during export, as configured by `MeldOptions`, and to read
+//! their headers for metadata.
+
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashSet};
+
+use gltf::json::{image::MimeType, Material, Root};
+
+use crate::gltfext::add_buffer_view_from_slice;
+use crate::{Result, TextureEncoding, WorkAsset};
+
+impl WorkAsset {
+    /// Re-encodes images in the given export `Root` per `MeldOptions::texture_encodings`.
+    ///
+    /// Returns the blob the `Root` now refers to: if any image is re-encoded, a copy of our blob
+    /// with the new bytes appended, else our own blob, borrowed. Any old bytes are left for blob
+    /// compaction to drop. The `Root` must be a fresh clone of our own, so that its indices match
+    /// ours.
+    pub(crate) fn reencode_textures(&self, root: &mut Root) -> Result<Cow<[u8]>> {
+        let mut blob = Cow::Borrowed(self.blob.as_slice());
+        let encodings = &self.options.texture_encodings;
+        if encodings.is_empty() {
+            return Ok(blob);
+        }
+
+        // for each image, the set of encodings asked for by the tags whose materials use it
+        let mut wanted: BTreeMap<usize, HashSet<Option<TextureEncoding>>> = BTreeMap::new();
+        for (m_ix, mesh) in root.meshes.iter().enumerate() {
+            for (p_ix, primitive) in mesh.primitives.iter().enumerate() {
+                let mut uses = vec![];
+                for (tag, material_key) in self.interned_mapping(m_ix, p_ix) {
+                    if let Some(material_ix) = self.material_ix(material_key) {
+                        uses.push((&**tag, material_ix));
+                    }
+                }
+                if let Some(material_ix) = primitive.material {
+                    uses.push((self.default_tag.as_str(), material_ix.value()));
+                }
+                for (tag, material_ix) in uses {
+                    for image_ix in material_images(root, &root.materials[material_ix]) {
+                        wanted
+                            .entry(image_ix)
+                            .or_insert_with(HashSet::new)
+                            .insert(encodings.get(tag).cloned());
+                    }
+                }
+            }
+        }
+
+        for (image_ix, asked) in wanted {
+            // only re-encode if all users of the image agree on how
+            let encoding = match asked.into_iter().collect::<Vec<_>>().as_slice() {
+                [Some(encoding)] => *encoding,
+                _ => continue,
+            };
+            let image = &self.images()[image_ix];
+            let mime_type = image.mime_type.as_ref().map(|m| m.0.as_str());
+            let bytes = self.read_image_bytes(image)?;
+            if let Some((bytes, mime_type)) = encode_image(&bytes, mime_type, encoding)
+                .map_err(|e| format!("Image {}: {}", image_ix, e))?
+            {
+                let view_ix =
+                    add_buffer_view_from_slice(&bytes, &mut root.buffer_views, blob.to_mut())
+                        .map_err(|e| format!("Image {}: {}", image_ix, e))?;
+                root.images[image_ix].buffer_view = Some(view_ix);
+                root.images[image_ix].mime_type = Some(MimeType(mime_type.to_owned()));
+            }
+        }
+        Ok(blob)
+    }
+}
+
+// the indices of all the images used by the textures of the given material
+pub(crate) fn material_images(root: &Root, material: &Material) -> Vec<usize> {
+    let pbr = &material.pbr_metallic_roughness;
+    let texture_ixs = vec![
+        pbr.base_color_texture.as_ref().map(|t| t.index),
+        pbr.metallic_roughness_texture.as_ref().map(|t| t.index),
+        material.normal_texture.as_ref().map(|t| t.index),
+        material.occlusion_texture.as_ref().map(|t| t.index),
+        material.emissive_texture.as_ref().map(|t| t.index),
+    ];
+    texture_ixs
+        .into_iter()
+        .filter_map(|ix| ix)
+        .map(|ix| root.textures[ix.value()].source.value())
+        .collect()
+}
+
+// encode the image bytes as asked, returning the new bytes and MIME type, or `None` if the image
+// is best left as it is
+#[cfg(feature = "texture-encoding")]
+fn encode_image(
+    bytes: &[u8],
+    mime_type: Option<&str>,
+    encoding: TextureEncoding,
+) -> Result<Option<(Vec<u8>, &'static str)>> {
+    use image::ImageOutputFormat;
+
+    let (format, new_mime_type) = match encoding {
+        TextureEncoding::Png => (ImageOutputFormat::Png, "image/png"),
+        TextureEncoding::Jpeg(quality) => (ImageOutputFormat::Jpeg(quality), "image/jpeg"),
+    };
+    if encoding == TextureEncoding::Png && mime_type == Some(new_mime_type) {
+        return Ok(None);
+    }
+
+    let decoded = image::load_from_memory(bytes)
+        .map_err(|e| format!("Couldn't decode texture image: {}", e))?;
+    if let TextureEncoding::Jpeg(_) = encoding {
+        if decoded.color().has_alpha() {
+            return Ok(None);
+        }
+    }
+
+    let mut encoded = vec![];
+    decoded
+        .write_to(&mut encoded, format)
+        .map_err(|e| format!("Couldn't encode texture image: {}", e))?;
+    Ok(Some((encoded, new_mime_type)))
+}
+
+// the width, height, channel count & format name of the image, read from its header alone, or
+// `None` if it's in a format we don't read
+#[cfg(feature = "image")]
+pub(crate) fn read_image_header(bytes: &[u8]) -> Result<Option<(u32, u32, u8, &'static str)>> {
+    use image::jpeg::JpegDecoder;
+    use image::png::PngDecoder;
+    use image::{ImageDecoder, ImageError, ImageFormat};
+
+    fn describe<'a, D: ImageDecoder<'a>>(
+        decoder: D,
+        format: &'static str,
+    ) -> (u32, u32, u8, &'static str) {
+        let (width, height) = decoder.dimensions();
+        (width, height, decoder.color_type().channel_count(), format)
+    }
+    let bad_header = |e: ImageError| format!("Couldn't read texture image header: {}", e);
+    Ok(match image::guess_format(bytes) {
+        Ok(ImageFormat::Png) => Some(describe(PngDecoder::new(bytes).map_err(bad_header)?, "png")),
+        Ok(ImageFormat::Jpeg) => Some(describe(
+            JpegDecoder::new(bytes).map_err(bad_header)?,
+            "jpeg",
+        )),
+        _ => None,
+    })
+}
+
+#[cfg(not(feature = "image"))]
+pub(crate) fn read_image_header(_bytes: &[u8]) -> Result<Option<(u32, u32, u8, &'static str)>> {
+    Ok(None)
+}
+
+#[cfg(not(feature = "texture-encoding"))]
+fn encode_image(
+    _bytes: &[u8],
+    _mime_type: Option<&str>,
+    _encoding: TextureEncoding,
+) -> Result<Option<(Vec<u8>, &'static str)>> {
+    Err(format!(
+        "Texture re-encoding requires building with the texture-encoding feature."
+    ))
+}
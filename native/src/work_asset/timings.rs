@@ -0,0 +1,78 @@
+// Copyright (c) Facebook, Inc. and its affiliates. All Rights Reserved
+//
+
+//! Optional timing of the phases of constructing, melding & exporting assets.
+//!
+//! Timings are only collected when the crate is built with the `profiling` feature; otherwise
+//! every duration is zero, and no clock is ever read. (Notably, `std::time::Instant` panics in
+//! `wasm32-unknown-unknown` builds.)
+
+use std::time::Duration;
+
+use serde_derive::{Deserialize, Serialize};
+
+/// How long each phase of a meld took.
+///
+/// The `transform`, `keys` and `fingerprints` phases are part of `construct`, which covers the
+/// whole of parsing & indexing the source assets.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PhaseTimings {
+    /// Parsing and indexing the source assets.
+    pub construct: Duration,
+    /// Resolving external buffers & images into the blob, during construction.
+    pub transform: Duration,
+    /// Computing meld keys, during construction.
+    pub keys: Duration,
+    /// Computing mesh primitive fingerprints, during construction.
+    pub fingerprints: Duration,
+    /// The meld proper.
+    pub meld: Duration,
+    /// Exporting the melded asset.
+    pub export: Duration,
+}
+
+impl PhaseTimings {
+    /// The total time spent, across all phases.
+    pub fn total(&self) -> Duration {
+        self.construct + self.meld + self.export
+    }
+
+    /// Whether nothing was timed, e.g. because the `profiling` feature is disabled.
+    pub fn is_empty(&self) -> bool {
+        self.total() == Duration::default()
+    }
+
+    pub(crate) fn add(&mut self, other: &PhaseTimings) {
+        self.construct += other.construct;
+        self.transform += other.transform;
+        self.keys += other.keys;
+        self.fingerprints += other.fingerprints;
+        self.meld += other.meld;
+        self.export += other.export;
+    }
+}
+
+// measures the time since its creation, if the `profiling` feature is enabled
+pub(crate) struct PhaseTimer {
+    #[cfg(feature = "profiling")]
+    start: std::time::Instant,
+}
+
+impl PhaseTimer {
+    pub(crate) fn start() -> PhaseTimer {
+        PhaseTimer {
+            #[cfg(feature = "profiling")]
+            start: std::time::Instant::now(),
+        }
+    }
+
+    #[cfg(feature = "profiling")]
+    pub(crate) fn elapsed(&self) -> Duration {
+        self.start.elapsed()
+    }
+
+    #[cfg(not(feature = "profiling"))]
+    pub(crate) fn elapsed(&self) -> Duration {
+        Duration::default()
+    }
+}
@@ -0,0 +1,464 @@
+// Copyright (c) Facebook, Inc. and its affiliates. All Rights Reserved
+//
+
+//! Code to compute node transforms, and to bake them into mesh vertices; see
+//! `MeldOptions::node_transforms`.
+
+use std::collections::{HashMap, HashSet};
+
+use gltf::json::accessor::{Accessor, ComponentType, GenericComponentType, Type};
+use gltf::json::mesh::Semantic;
+use gltf::json::validation::Checked;
+use gltf::json::{Index, Node, Root};
+
+use serde_json::json;
+
+use crate::gltfext::{add_accessor_from_slice, get_slice_from_buffer_view};
+use crate::Result;
+
+use super::describe_object;
+
+/// A 4x4 matrix, in the column-major order of glTF's `node.matrix`.
+pub type Transform = [f64; 16];
+
+/// The transform that leaves everything where it is.
+pub const IDENTITY: Transform = [
+    1.0, 0.0, 0.0, 0.0, //
+    0.0, 1.0, 0.0, 0.0, //
+    0.0, 0.0, 1.0, 0.0, //
+    0.0, 0.0, 0.0, 1.0,
+];
+
+/// The one world transform with which the nodes of the asset's scenes instance each mesh, if
+/// there is such a thing; `None` for a mesh that no node in a scene instances, or that several
+/// do with different transforms.
+pub fn mesh_world_transforms(root: &Root) -> Result<Vec<Option<Transform>>> {
+    Ok(mesh_instance_transforms(root)?
+        .iter()
+        .map(|instances| shared_transform(instances))
+        .collect())
+}
+
+/// Bakes the world transform of the nodes that instance each mesh into its positions, normals
+/// & tangents, and resets the transform of every node, so that the asset renders as before.
+///
+/// Attributes are baked in place where no other mesh uses their bytes, and into new accessors
+/// where one does. Assets that can't be baked without changing how they render are rejected;
+/// see `NodeTransformPolicy::Bake`.
+pub fn bake_node_transforms(root: &mut Root, blob: &mut Vec<u8>) -> Result<()> {
+    let instances = mesh_instance_transforms(root)?;
+    if instances
+        .iter()
+        .flatten()
+        .all(|transform| *transform == IDENTITY)
+    {
+        return Ok(());
+    }
+    check_bakeable(root)?;
+    let shared = shared_accessors(root);
+
+    for (mesh_ix, instances) in instances.iter().enumerate() {
+        let transform = match (instances.first(), shared_transform(instances)) {
+            (None, _) => continue,
+            (Some(_), Some(transform)) => transform,
+            (Some(_), None) => {
+                return Err(format!(
+                    "Can't bake node transforms: {} is instanced with different transforms.",
+                    describe_object("mesh", mesh_ix, root.meshes[mesh_ix].name.as_ref())
+                ));
+            }
+        };
+        if transform == IDENTITY {
+            continue;
+        }
+        if determinant(&transform) <= 0.0 {
+            return Err(format!(
+                "Can't bake node transforms: {} is mirrored or flattened by its node.",
+                describe_object("mesh", mesh_ix, root.meshes[mesh_ix].name.as_ref())
+            ));
+        }
+
+        // an accessor shared by several primitives of the mesh is baked only once
+        let mut baked: HashMap<(usize, Semantic), Index<Accessor>> = HashMap::new();
+        for p_ix in 0..root.meshes[mesh_ix].primitives.len() {
+            if root.meshes[mesh_ix].primitives[p_ix].targets.is_some() {
+                return Err(format!(
+                    "Can't bake node transforms into the morph targets of {}.",
+                    describe_object("mesh", mesh_ix, root.meshes[mesh_ix].name.as_ref())
+                ));
+            }
+            for semantic in vec![Semantic::Positions, Semantic::Normals, Semantic::Tangents] {
+                let attribute = Checked::Valid(semantic.clone());
+                let primitive = &root.meshes[mesh_ix].primitives[p_ix];
+                let accessor_ix = match primitive.attributes.get(&attribute) {
+                    Some(&accessor_ix) => accessor_ix,
+                    None => continue,
+                };
+                let key = (accessor_ix.value(), semantic.clone());
+                let baked_ix = match baked.get(&key) {
+                    Some(&baked_ix) => baked_ix,
+                    None => {
+                        let exclusive = !shared.contains(&accessor_ix.value());
+                        let baked_ix = bake_accessor(
+                            root,
+                            blob,
+                            accessor_ix,
+                            &semantic,
+                            &transform,
+                            exclusive,
+                        )?;
+                        baked.insert(key, baked_ix);
+                        baked_ix
+                    }
+                };
+                let primitive = &mut root.meshes[mesh_ix].primitives[p_ix];
+                primitive.attributes.insert(attribute, baked_ix);
+            }
+        }
+    }
+
+    for node in &mut root.nodes {
+        node.matrix = None;
+        node.translation = None;
+        node.rotation = None;
+        node.scale = None;
+    }
+    Ok(())
+}
+
+/// Applies a transform to a point.
+pub fn transform_point(transform: &Transform, point: [f32; 3]) -> [f32; 3] {
+    let m = transform;
+    let (x, y, z) = (point[0] as f64, point[1] as f64, point[2] as f64);
+    [
+        (m[0] * x + m[4] * y + m[8] * z + m[12]) as f32,
+        (m[1] * x + m[5] * y + m[9] * z + m[13]) as f32,
+        (m[2] * x + m[6] * y + m[10] * z + m[14]) as f32,
+    ]
+}
+
+// the world transforms with which the nodes of the asset's scenes instance each mesh
+fn mesh_instance_transforms(root: &Root) -> Result<Vec<Vec<Transform>>> {
+    let mut result = vec![vec![]; root.meshes.len()];
+    for (node, world) in root.nodes.iter().zip(node_world_transforms(root)?) {
+        if let (Some(mesh_ix), Some(world)) = (node.mesh, world) {
+            result
+                .get_mut(mesh_ix.value())
+                .ok_or_else(|| format!("Node references non-existent mesh {}.", mesh_ix))?
+                .push(world);
+        }
+    }
+    Ok(result)
+}
+
+// the world transform of each node that's part of a scene, or None for any that isn't
+fn node_world_transforms(root: &Root) -> Result<Vec<Option<Transform>>> {
+    let mut world = vec![None; root.nodes.len()];
+    let mut pending: Vec<(Index<Node>, Transform)> = vec![];
+    for scene in &root.scenes {
+        for &node_ix in &scene.nodes {
+            pending.push((node_ix, IDENTITY));
+        }
+    }
+    while let Some((node_ix, parent)) = pending.pop() {
+        let node = root
+            .nodes
+            .get(node_ix.value())
+            .ok_or_else(|| format!("Reference to non-existent node {}.", node_ix))?;
+        // a node is only ever visited once, which also keeps a cyclic hierarchy from hanging us
+        if world[node_ix.value()].is_some() {
+            continue;
+        }
+        let transform = multiply(&parent, &local_transform(node));
+        world[node_ix.value()] = Some(transform);
+        for &child_ix in node.children.iter().flatten() {
+            pending.push((child_ix, transform));
+        }
+    }
+    Ok(world)
+}
+
+// the transform of a node relative to its parent
+fn local_transform(node: &Node) -> Transform {
+    if let Some(matrix) = node.matrix {
+        let mut transform = IDENTITY;
+        for (to, from) in transform.iter_mut().zip(matrix.iter()) {
+            *to = *from as f64;
+        }
+        return transform;
+    }
+    let [tx, ty, tz] = node.translation.unwrap_or([0.0, 0.0, 0.0]);
+    let [x, y, z, w] = node.rotation.as_ref().map_or([0.0, 0.0, 0.0, 1.0], |q| q.0);
+    let [sx, sy, sz] = node.scale.unwrap_or([1.0, 1.0, 1.0]);
+    let (x, y, z, w) = (x as f64, y as f64, z as f64, w as f64);
+    let (sx, sy, sz) = (sx as f64, sy as f64, sz as f64);
+
+    // translation * rotation * scale, column by column
+    [
+        (1.0 - 2.0 * (y * y + z * z)) * sx,
+        (2.0 * (x * y + z * w)) * sx,
+        (2.0 * (x * z - y * w)) * sx,
+        0.0,
+        (2.0 * (x * y - z * w)) * sy,
+        (1.0 - 2.0 * (x * x + z * z)) * sy,
+        (2.0 * (y * z + x * w)) * sy,
+        0.0,
+        (2.0 * (x * z + y * w)) * sz,
+        (2.0 * (y * z - x * w)) * sz,
+        (1.0 - 2.0 * (x * x + y * y)) * sz,
+        0.0,
+        tx as f64,
+        ty as f64,
+        tz as f64,
+        1.0,
+    ]
+}
+
+fn multiply(a: &Transform, b: &Transform) -> Transform {
+    let mut product = [0.0; 16];
+    for col in 0..4 {
+        for row in 0..4 {
+            product[col * 4 + row] = (0..4).map(|k| a[k * 4 + row] * b[col * 4 + k]).sum();
+        }
+    }
+    product
+}
+
+// the cofactors of the upper-left 3x3 of a transform, which is its inverse transpose scaled by
+// its determinant; this is what normals are transformed with, since we normalize them anyway
+fn cofactors(transform: &Transform) -> Transform {
+    let a = |row: usize, col: usize| transform[(col % 3) * 4 + row % 3];
+    let mut result = IDENTITY;
+    for col in 0..3 {
+        for row in 0..3 {
+            result[col * 4 + row] = a(row + 1, col + 1) * a(row + 2, col + 2)
+                - a(row + 1, col + 2) * a(row + 2, col + 1);
+        }
+    }
+    result
+}
+
+fn determinant(transform: &Transform) -> f64 {
+    let cofactors = cofactors(transform);
+    (0..3)
+        .map(|col| transform[col * 4] * cofactors[col * 4])
+        .sum()
+}
+
+// a direction, transformed by the upper-left 3x3 of a transform and then normalized
+fn transform_direction(transform: &Transform, direction: [f32; 3]) -> [f32; 3] {
+    let m = transform;
+    let (x, y, z) = (
+        direction[0] as f64,
+        direction[1] as f64,
+        direction[2] as f64,
+    );
+    let v = [
+        m[0] * x + m[4] * y + m[8] * z,
+        m[1] * x + m[5] * y + m[9] * z,
+        m[2] * x + m[6] * y + m[10] * z,
+    ];
+    let length = (v[0] * v[0] + v[1] * v[1] + v[2] * v[2]).sqrt();
+    if length > 0.0 {
+        [
+            (v[0] / length) as f32,
+            (v[1] / length) as f32,
+            (v[2] / length) as f32,
+        ]
+    } else {
+        direction
+    }
+}
+
+fn shared_transform(instances: &[Transform]) -> Option<Transform> {
+    let (first, rest) = instances.split_first()?;
+    if rest.iter().all(|transform| transform == first) {
+        Some(*first)
+    } else {
+        None
+    }
+}
+
+// reject assets where resetting node transforms would move more than the vertices we bake
+fn check_bakeable(root: &Root) -> Result<()> {
+    if !root.animations.is_empty() {
+        return Err(format!(
+            "Can't bake node transforms into an asset with animations."
+        ));
+    }
+    if !root.skins.is_empty() {
+        return Err(format!(
+            "Can't bake node transforms into an asset with skins."
+        ));
+    }
+    for (node_ix, node) in root.nodes.iter().enumerate() {
+        if node.camera.is_some() || node.extensions.is_some() {
+            return Err(format!(
+                "Can't bake node transforms: {} carries a camera or extension it would move.",
+                describe_object("node", node_ix, node.name.as_ref())
+            ));
+        }
+    }
+    Ok(())
+}
+
+// accessors whose bytes are used by more than one mesh, or shared with other accessors or images
+// through their buffer view, and which therefore can't be baked in place
+fn shared_accessors(root: &Root) -> HashSet<usize> {
+    let mut meshes_using: HashMap<usize, HashSet<usize>> = HashMap::new();
+    for (mesh_ix, mesh) in root.meshes.iter().enumerate() {
+        for primitive in &mesh.primitives {
+            let target_accessors = primitive
+                .targets
+                .iter()
+                .flatten()
+                .flat_map(|target| vec![target.positions, target.normals, target.tangents])
+                .flatten();
+            let accessors = primitive
+                .attributes
+                .values()
+                .cloned()
+                .chain(primitive.indices)
+                .chain(target_accessors);
+            for accessor_ix in accessors {
+                let users = meshes_using.entry(accessor_ix.value()).or_default();
+                users.insert(mesh_ix);
+            }
+        }
+    }
+    let mut view_users: HashMap<usize, usize> = HashMap::new();
+    let accessor_views = root
+        .accessors
+        .iter()
+        .filter_map(|accessor| accessor.buffer_view);
+    let image_views = root.images.iter().filter_map(|image| image.buffer_view);
+    for view_ix in accessor_views.chain(image_views) {
+        *view_users.entry(view_ix.value()).or_default() += 1;
+    }
+
+    let shared = |accessor_ix: usize, accessor: &Accessor| {
+        meshes_using.get(&accessor_ix).map_or(0, HashSet::len) > 1
+            || accessor
+                .buffer_view
+                .map_or(true, |view_ix| view_users[&view_ix.value()] > 1)
+    };
+    root.accessors
+        .iter()
+        .enumerate()
+        .filter(|(accessor_ix, accessor)| shared(*accessor_ix, accessor))
+        .map(|(accessor_ix, _)| accessor_ix)
+        .collect()
+}
+
+// a positions, normals or tangents accessor, baked; the original one is overwritten in place if
+// it's `exclusive` to the mesh being baked, or else a transformed copy of it is added
+fn bake_accessor(
+    root: &mut Root,
+    blob: &mut Vec<u8>,
+    accessor_ix: Index<Accessor>,
+    semantic: &Semantic,
+    transform: &Transform,
+    exclusive: bool,
+) -> Result<Index<Accessor>> {
+    let (type_, components) = match semantic {
+        Semantic::Tangents => (Type::Vec4, 4),
+        _ => (Type::Vec3, 3),
+    };
+    let element_size = 4 * components;
+    let normal_transform = cofactors(transform);
+
+    // the byte offset of each element in the blob, and its baked value
+    let mut elements: Vec<(usize, [f32; 4])> = vec![];
+    {
+        let accessor = root.accessors.get(accessor_ix.value()).ok_or_else(|| {
+            format!(
+                "Primitive references non-existent accessor {}.",
+                accessor_ix
+            )
+        })?;
+        match (&accessor.component_type, &accessor.type_) {
+            (Checked::Valid(GenericComponentType(ComponentType::F32)), Checked::Valid(t))
+                if *t == type_ => {}
+            _ => {
+                return Err(format!(
+                    "Can't bake node transforms into accessor {}: it doesn't hold {:?} floats.",
+                    accessor_ix, type_
+                ))
+            }
+        }
+        if accessor.sparse.is_some() {
+            return Err(format!(
+                "Can't bake node transforms into sparse accessor {}.",
+                accessor_ix
+            ));
+        }
+        let view = accessor
+            .buffer_view
+            .and_then(|view_ix| root.buffer_views.get(view_ix.value()))
+            .ok_or_else(|| format!("Accessor {} lacks a valid buffer view.", accessor_ix))?;
+        let bytes = get_slice_from_buffer_view(view, blob)?;
+        let stride = view
+            .byte_stride
+            .map_or(element_size, |stride| stride as usize);
+
+        for element in 0..accessor.count as usize {
+            let start = accessor.byte_offset as usize + element * stride;
+            let element = bytes.get(start..start + element_size).ok_or_else(|| {
+                format!("Accessor {} reaches beyond its buffer view.", accessor_ix)
+            })?;
+            let mut value = [0.0; 4];
+            for (component, b) in element.chunks(4).enumerate() {
+                value[component] = f32::from_le_bytes([b[0], b[1], b[2], b[3]]);
+            }
+            let xyz = [value[0], value[1], value[2]];
+            let xyz = match semantic {
+                Semantic::Positions => transform_point(transform, xyz),
+                Semantic::Normals => transform_direction(&normal_transform, xyz),
+                _ => transform_direction(transform, xyz),
+            };
+            value[..3].copy_from_slice(&xyz);
+            elements.push((view.byte_offset.unwrap_or(0) as usize + start, value));
+        }
+    }
+
+    let baked_ix = if exclusive {
+        for (offset, value) in &elements {
+            for (component, value) in value[..components].iter().enumerate() {
+                let at = offset + 4 * component;
+                blob[at..at + 4].copy_from_slice(&value.to_le_bytes());
+            }
+        }
+        accessor_ix
+    } else {
+        let mut bytes = Vec::with_capacity(elements.len() * element_size);
+        for (_, value) in &elements {
+            for value in &value[..components] {
+                bytes.extend_from_slice(&value.to_le_bytes());
+            }
+        }
+        add_accessor_from_slice(
+            &bytes,
+            ComponentType::F32,
+            type_,
+            elements.len(),
+            &mut root.buffer_views,
+            &mut root.accessors,
+            blob,
+        )?
+    };
+
+    if *semantic == Semantic::Positions && !elements.is_empty() {
+        let mut min = [f32::INFINITY; 3];
+        let mut max = [f32::NEG_INFINITY; 3];
+        for (_, value) in &elements {
+            for component in 0..3 {
+                min[component] = min[component].min(value[component]);
+                max[component] = max[component].max(value[component]);
+            }
+        }
+        let accessor = &mut root.accessors[baked_ix.value()];
+        accessor.min = Some(json!(min));
+        accessor.max = Some(json!(max));
+    }
+    Ok(baked_ix)
+}
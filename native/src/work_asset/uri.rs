@@ -0,0 +1,223 @@
+// Copyright (c) Facebook, Inc. and its affiliates. All Rights Reserved
+//
+
+//! Code to find and read the files that the URIs of an asset's buffers & images name.
+
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use crate::{InputLimits, MeldOptions, Result};
+
+/// Reads the files that the URIs of an asset's buffers & images name, taking into account the
+/// search paths & case sensitivity of the `MeldOptions`, and noting anything worth a warning.
+pub struct UriResolver<'a> {
+    file_base: Option<&'a Path>,
+    search_paths: &'a [PathBuf],
+    case_insensitive: bool,
+    confined: bool,
+    limits: &'a InputLimits,
+    warnings: Vec<String>,
+    file_stamps: Vec<FileStamp>,
+}
+
+/// What cheaply tells one version of a file read by a `UriResolver` from another: its path,
+/// size, and time of modification, as seconds & nanoseconds since the epoch, if known.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FileStamp {
+    pub path: PathBuf,
+    pub len: u64,
+    pub modified: Option<(u64, u32)>,
+}
+
+impl<'a> UriResolver<'a> {
+    /// A resolver of URIs relative to `file_base`, per the given options.
+    pub fn new(file_base: Option<&'a Path>, options: &'a MeldOptions) -> UriResolver<'a> {
+        UriResolver {
+            file_base,
+            search_paths: &options.texture_search_paths,
+            case_insensitive: options.case_insensitive_uris,
+            confined: options.confine_uris,
+            limits: &options.input_limits,
+            warnings: vec![],
+            file_stamps: vec![],
+        }
+    }
+
+    /// Reads the file a URI names.
+    ///
+    /// Percent-escapes in the URI are decoded, though a file whose name is the URI as it is
+    /// will do too. A relative path that isn't found relative to `file_base` is looked for in
+    /// each of the search paths in turn, both as it is and by its file name alone; and failing
+    /// all that, the same places are searched without regard to letter case, if so configured.
+    ///
+    /// The file is only read if adding it to a blob of `blob_len` bytes keeps that within the
+    /// blob size limit; and, if URIs are confined, only if the URI is a relative path that doesn't
+    /// climb out of its directory.
+    pub fn read(&mut self, uri: &str, blob_len: usize) -> Result<Vec<u8>> {
+        if self.confined {
+            check_confined(uri)?;
+        }
+        // this is very temporary, lifted lifted from gltf::import.rs
+        let path = if uri.contains(":") {
+            if uri.starts_with("file://") {
+                &uri["file://".len()..]
+            } else if uri.starts_with("file:") {
+                &uri["file:".len()..]
+            } else {
+                return Err(format!("Can't read {}: only file URIs are supported.", uri));
+            }
+        } else {
+            &uri[..]
+        };
+        let path = self.resolve(uri, path);
+        let read_error =
+            |e: std::io::Error| format!("Error reading file {}: {}", path.display(), e.to_string());
+        let metadata = fs::metadata(&path).map_err(read_error)?;
+        let len = metadata.len().min(usize::MAX as u64) as usize;
+        self.limits.check_sizes(0, blob_len.saturating_add(len))?;
+        let modified = metadata
+            .modified()
+            .ok()
+            .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+            .map(|since| (since.as_secs(), since.subsec_nanos()));
+        self.file_stamps.push(FileStamp {
+            path: path.clone(),
+            len: metadata.len(),
+            modified,
+        });
+        fs::read(path.as_path()).map_err(read_error)
+    }
+
+    /// The stamps of the files read so far, in the order they were read.
+    pub fn file_stamps(&self) -> &[FileStamp] {
+        &self.file_stamps
+    }
+
+    /// The warnings that came up as URIs were resolved, e.g. because a file was found only by
+    /// ignoring case.
+    pub fn into_warnings(self) -> Vec<String> {
+        self.warnings
+    }
+
+    // the file the path of a URI names, if it can be found; or else where it should have been
+    fn resolve(&mut self, uri: &str, path: &str) -> PathBuf {
+        let decoded = percent_decode(path);
+        let mut paths = vec![PathBuf::from(&decoded)];
+        if decoded != path {
+            paths.push(PathBuf::from(path));
+        }
+        let mut candidates: Vec<PathBuf> = paths
+            .iter()
+            .map(|path| match self.file_base {
+                Some(file_base) => file_base.join(path),
+                None => path.clone(),
+            })
+            .collect();
+        let expected = candidates[0].clone();
+        if paths[0].is_relative() {
+            for dir in self.search_paths {
+                candidates.extend(paths.iter().map(|path| dir.join(path)));
+                candidates.extend(paths[0].file_name().map(|name| dir.join(name)));
+            }
+        }
+
+        if let Some(found) = candidates.iter().find(|c| c.is_file()) {
+            return found.clone();
+        }
+        if self.case_insensitive {
+            if let Some(found) = candidates.iter().find_map(|c| find_ignoring_case(c)) {
+                self.warnings.push(format!(
+                    "The file of URI {} was found only by ignoring case, as {}.",
+                    uri,
+                    found.display()
+                ));
+                return found;
+            }
+        }
+        expected
+    }
+}
+
+// refuse a URI that isn't a relative path, or one that climbs out of its directory, however it's
+// spelled; see `MeldOptions::confine_uris`
+fn check_confined(uri: &str) -> Result<()> {
+    let escapes = |path: &str| {
+        path.contains(':')
+            || path.starts_with('/')
+            || path.starts_with('\\')
+            || path.split(&['/', '\\'][..]).any(|part| part == "..")
+    };
+    if escapes(uri) || escapes(&percent_decode(uri)) {
+        return Err(format!(
+            "Can't read {}: only relative paths within the asset's directory are allowed.",
+            uri
+        ));
+    }
+    Ok(())
+}
+
+// the path with its %-escapes decoded, or as it is if they don't decode to UTF-8
+fn percent_decode(path: &str) -> String {
+    let bytes = path.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut ix = 0;
+    while ix < bytes.len() {
+        let escaped = match bytes.get(ix + 1..ix + 3) {
+            Some(hex) if bytes[ix] == b'%' && hex.iter().all(u8::is_ascii_hexdigit) => {
+                std::str::from_utf8(hex)
+                    .ok()
+                    .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+            }
+            _ => None,
+        };
+        match escaped {
+            Some(byte) => {
+                decoded.push(byte);
+                ix += 3;
+            }
+            None => {
+                decoded.push(bytes[ix]);
+                ix += 1;
+            }
+        }
+    }
+    String::from_utf8(decoded).unwrap_or_else(|_| path.to_owned())
+}
+
+// the file the path names, if there is one when the case of each component is disregarded; if
+// several match, the first by name is taken
+fn find_ignoring_case(path: &Path) -> Option<PathBuf> {
+    let mut found = PathBuf::new();
+    for component in path.components() {
+        let name = match component {
+            Component::Normal(name) => name,
+            other => {
+                found.push(other.as_os_str());
+                continue;
+            }
+        };
+        if found.join(name).exists() {
+            found.push(name);
+            continue;
+        }
+        let dir = if found.as_os_str().is_empty() {
+            Path::new(".")
+        } else {
+            found.as_path()
+        };
+        let wanted = name.to_str()?.to_lowercase();
+        let matched = fs::read_dir(dir)
+            .ok()?
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.file_name())
+            .filter(|entry| entry.to_str().map_or(false, |n| n.to_lowercase() == wanted))
+            .min()?;
+        found.push(matched);
+    }
+    if found.is_file() {
+        Some(found)
+    } else {
+        None
+    }
+}
@@ -10,9 +10,12 @@ use spectral::prelude::*;
 
 use assets::*;
 
+use gltf::json::{Index, Root};
 use gltf::Gltf;
 
-use gltf_variant_meld::{Tag, VariationalAsset};
+use gltf_variant_meld::{
+    remove_buffer_view, replace_buffer_view_bytes, GlbChunk, MeldOptions, Tag, VariationalAsset,
+};
 
 #[test]
 fn test_tiny_parse() {
@@ -72,3 +75,163 @@ fn test_pinecone_comparison() {
         assert_that!(pbr.roughness_factor()).is_equal_to(test.3);
     }
 }
+
+#[test]
+fn test_unsupported_versions() {
+    let load = |gltf: &[u8]| VariationalAsset::from_slice(gltf, None, None);
+
+    let json = r#"{ "asset": { "version": "1.0" }, "meshes": {} }"#;
+    let err = load(json.as_bytes()).expect_err("glTF 1.0 not rejected");
+    assert_that!(err.contains("Unsupported glTF version 1.0")).is_true();
+
+    let json = r#"{ "asset": { "version": "2.1", "minVersion": "2.1" } }"#;
+    let err = load(json.as_bytes()).expect_err("glTF 2.1 minVersion not rejected");
+    assert_that!(err.contains("requires glTF 2.1 or later")).is_true();
+
+    // a newer minor version is fine, so long as it doesn't insist on being understood
+    let json = r#"{ "asset": { "version": "2.1", "minVersion": "2.0" } }"#;
+    assert_that!(load(json.as_bytes())).is_ok();
+
+    // glTF 1.0 binaries have a GLB version of 1
+    let mut glb =
+        GlbChunk::to_bytes(GlbChunk::JSON(json.as_bytes()), None).expect("GLB creation failure");
+    glb[4] = 1;
+    let err = load(&glb).expect_err("GLB version 1 not rejected");
+    assert_that!(err.contains("Unsupported GLB version 1")).is_true();
+}
+
+#[test]
+fn test_unsupported_required_extensions() {
+    let tag = Tag::from("tag");
+    let json = r#"
+    {
+        "asset": { "version": "2.0" },
+        "extensionsUsed": [ "KHR_draco_mesh_compression", "KHR_texture_transform" ],
+        "extensionsRequired": [ "KHR_draco_mesh_compression", "KHR_texture_transform" ]
+    }
+    "#;
+    let err = VariationalAsset::from_slice(json.as_bytes(), Some(&tag), None)
+        .expect_err("Draco requirement not rejected");
+    assert_that!(err.contains("unsupported extensions: KHR_draco_mesh_compression.")).is_true();
+
+    let options = &MeldOptions {
+        allow_unsupported_required: true,
+        ..Default::default()
+    };
+    let asset =
+        VariationalAsset::from_slice_with_options(json.as_bytes(), Some(&tag), None, options)
+            .expect("VariationalAsset::from_slice_with_options() failure");
+    assert_that!(asset.unsupported_required_extensions())
+        .is_ok()
+        .is_equal_to(vec!["KHR_draco_mesh_compression".to_owned()]);
+}
+
+#[test]
+fn test_blob_compaction() {
+    // one referenced buffer view, one unreferenced, and a few bytes not covered by any view
+    let json = r#"
+    {
+        "asset": { "version": "2.0" },
+        "buffers": [ { "byteLength": 24 } ],
+        "bufferViews": [
+            { "buffer": 0, "byteOffset": 4, "byteLength": 4 },
+            { "buffer": 0, "byteOffset": 12, "byteLength": 8 }
+        ],
+        "images": [ { "bufferView": 1, "mimeType": "image/png" } ]
+    }
+    "#;
+    let bin: Vec<u8> = (0..24).collect();
+    let glb = GlbChunk::to_bytes(GlbChunk::JSON(json.as_bytes()), Some(GlbChunk::BIN(&bin)))
+        .expect("GLB creation failure");
+
+    let asset = VariationalAsset::from_slice(&glb, Some(&Tag::from("tag")), None)
+        .expect("glTF parse failure");
+    assert_that!(asset.metadata().blob_bytes_saved()).is_equal_to(16);
+
+    let asset = Gltf::from_slice(asset.glb())
+        .or_else(|e| Err(e.to_string()))
+        .expect("glTF re-parse failure");
+    assert_that!(Vec::from_iter(asset.views())).has_length(1);
+    assert_that!(asset.blob.as_ref().map(Vec::as_slice)).is_equal_to(Some(&bin[12..20]));
+}
+
+#[test]
+fn test_buffer_view_dedupe() {
+    // two buffer views over identical bytes, each referenced by an image
+    let json = r#"
+    {
+        "asset": { "version": "2.0" },
+        "buffers": [ { "byteLength": 16 } ],
+        "bufferViews": [
+            { "buffer": 0, "byteOffset": 0, "byteLength": 8 },
+            { "buffer": 0, "byteOffset": 8, "byteLength": 8 }
+        ],
+        "images": [
+            { "bufferView": 0, "mimeType": "image/png" },
+            { "bufferView": 1, "mimeType": "image/png" }
+        ]
+    }
+    "#;
+    let bin: Vec<u8> = (0..8).chain(0..8).collect();
+    let glb = GlbChunk::to_bytes(GlbChunk::JSON(json.as_bytes()), Some(GlbChunk::BIN(&bin)))
+        .expect("GLB creation failure");
+
+    let asset = VariationalAsset::from_slice(&glb, Some(&Tag::from("tag")), None)
+        .expect("glTF parse failure");
+    let asset = Gltf::from_slice(asset.glb())
+        .or_else(|e| Err(e.to_string()))
+        .expect("glTF re-parse failure");
+
+    assert_that!(Vec::from_iter(asset.views())).has_length(1);
+    assert_that!(asset.blob.as_ref().map(Vec::len)).is_equal_to(Some(8));
+}
+
+#[test]
+fn test_buffer_view_removal_and_replacement() {
+    // three buffer views, the middle one unreferenced
+    let json = r#"
+    {
+        "asset": { "version": "2.0" },
+        "buffers": [ { "byteLength": 16 } ],
+        "bufferViews": [
+            { "buffer": 0, "byteOffset": 0, "byteLength": 4 },
+            { "buffer": 0, "byteOffset": 4, "byteLength": 8 },
+            { "buffer": 0, "byteOffset": 12, "byteLength": 4 }
+        ],
+        "images": [
+            { "bufferView": 0, "mimeType": "image/png" },
+            { "bufferView": 2, "mimeType": "image/png" }
+        ]
+    }
+    "#;
+    let mut root: Root = serde_json::from_str(json).expect("glTF parse failure");
+    let bin: Vec<u8> = (0..16).collect();
+
+    assert_that!(remove_buffer_view(&mut root, &bin, Index::new(0))).is_err();
+    let bin = remove_buffer_view(&mut root, &bin, Index::new(1)).expect("removal failure");
+    assert_that!(root.buffer_views).has_length(2);
+    assert_that!(root.images[1].buffer_view.map(|view| view.value())).is_equal_to(Some(1));
+    assert_that!(bin).is_equal_to(vec![0, 1, 2, 3, 12, 13, 14, 15]);
+
+    // grow the first view; its old bytes go, and the new ones land at the end
+    let bin = replace_buffer_view_bytes(&mut root, &bin, Index::new(0), &[9; 6])
+        .expect("replacement failure");
+    assert_that!(bin).is_equal_to(vec![12, 13, 14, 15, 9, 9, 9, 9, 9, 9, 0, 0]);
+    assert_that!(root.buffer_views[0].byte_offset).is_equal_to(Some(4));
+    assert_that!(root.buffer_views[0].byte_length).is_equal_to(6);
+    assert_that!(root.buffer_views[1].byte_offset).is_equal_to(Some(0));
+    assert_that!(root.buffers[0].byte_length).is_equal_to(12);
+}
+
+#[test]
+fn test_glb_length_header() {
+    // the file length must survive intact beyond 16 MB, i.e. in all four bytes of the header
+    let json = r#"{ "asset": { "version": "2.0" } }"#;
+    let bin = vec![0u8; 0x0100_0004];
+    let glb = GlbChunk::to_bytes(GlbChunk::JSON(json.as_bytes()), Some(GlbChunk::BIN(&bin)))
+        .expect("GLB creation failure");
+
+    let mut length = [0u8; 4];
+    length.copy_from_slice(&glb[0x08..0x0C]);
+    assert_that!(u32::from_le_bytes(length) as usize).is_equal_to(glb.len());
+}
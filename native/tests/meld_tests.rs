@@ -3,12 +3,28 @@
 
 extern crate assets;
 extern crate gltf_variant_meld;
+extern crate serde_json;
+
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
 
 use spectral::prelude::*;
 
 use assets::*;
 
-use gltf_variant_meld::{Tag, VariationalAsset};
+use gltf_variant_meld::{
+    tag_file_stem, AssetInfoPolicy, AxisValues, ExtrasVariantExporter, ExtrasVariantImporter,
+    FingerprintAlgorithm, FixedPrimitive, GlbChunk, MaterialFilter, MaterialFilterPolicy,
+    MeldCache, MeldOptions, MeshMismatch, MeshSelector, Metadata, NameMergePolicy,
+    NodeTransformPolicy, PrimitiveCorrespondence, SidecarVariantExporter,
+    SwitchTableVariantExporter, Tag, VariantExporter, VariantExporters, VariantImporters,
+    VariationalAsset, WorkAsset, MESH_ID_EXTRAS_KEY,
+};
 
 #[test]
 fn test_pinecone_meld() {
@@ -18,7 +34,9 @@ fn test_pinecone_meld() {
         VariationalAsset::from_file(path, Some(tag)).expect("VariationalAsset::from_file() failure")
     };
     let meld_assets = |base, other| {
-        VariationalAsset::meld(base, other).expect("VariationalAsset::meld() failure")
+        let (melded, _) =
+            VariationalAsset::meld(base, other).expect("VariationalAsset::meld() failure");
+        melded
     };
 
     // helper lambdas
@@ -26,9 +44,15 @@ fn test_pinecone_meld() {
         assert_that!(asset.default_tag()).is_equal_to(default_tag);
         assert_that!(asset.metadata().tags().iter()).contains_all_of(&tags);
         assert_that!(asset.metadata().tags().iter().count()).is_equal_to(tags.len());
+
+        let counts = asset.metadata().counts();
+        assert_that!(counts.materials).is_equal_to(tags.len());
+        assert_that!(counts.variational_materials).is_equal_to(tags.len());
     };
 
     let matte_pinecone = load_asset(ASSET_PINECONE_MATTE(), &matte);
+    assert_that!(matte_pinecone.metadata().counts().materials).is_equal_to(1);
+    assert_that!(matte_pinecone.metadata().counts().variational_materials).is_equal_to(0);
     let shiny_pinecone = load_asset(ASSET_PINECONE_SHINY(), &shiny);
     let matte_shiny_pinecone = meld_assets(&matte_pinecone, &shiny_pinecone);
     test(&matte_shiny_pinecone, &matte, vec![&matte, &shiny]);
@@ -60,7 +84,8 @@ fn test_teapot_meld() {
     };
 
     let meld_and_test = |base, meld, ts| {
-        let melded = VariationalAsset::meld(base, meld).expect("VariationalAsset::meld() failure");
+        let (melded, _) =
+            VariationalAsset::meld(base, meld).expect("VariationalAsset::meld() failure");
         let metadata = melded.metadata();
         assert_that!(metadata.total_sizes().texture_bytes()).is_equal_to(ts);
         assert_that!(metadata.variational_sizes().texture_bytes()).is_equal_to(ts);
@@ -86,6 +111,22 @@ fn test_teapot_meld() {
     test_tag(&melded, &camo_pink_bronze, 227318);
     test_tag(&melded, &camo_pink_silver, 227318);
     test_tag(&melded, &green_pink_silver, 337020);
+    let exclusive = |tag| {
+        melded
+            .metadata()
+            .tag_exclusive_sizes(tag)
+            .unwrap()
+            .texture_bytes()
+    };
+    assert_that!(exclusive(&camo_pink_bronze)).is_equal_to(0);
+    assert_that!(exclusive(&green_pink_silver)).is_equal_to(337020);
+
+    // the camo texture is shared by two tags, the green one used by just the one
+    let dedupe = melded.metadata().dedupe();
+    assert_that!(dedupe.shared_bytes).is_equal_to(227318);
+    assert_that!(dedupe.exclusive_bytes).is_equal_to(337020);
+    assert_that!(dedupe.duplicated_bytes).is_equal_to(0);
+    assert_that!(dedupe.bytes_by_tag_count.get(&2)).is_equal_to(Some(&227318));
 
     // finally a fourth variant that should add no new texture
     let base_pot = melded;
@@ -95,4 +136,1586 @@ fn test_teapot_meld() {
     test_tag(&melded, &camo_pink_silver, 227318);
     test_tag(&melded, &green_pink_silver, 337020);
     test_tag(&melded, &green_pink_bronze, 337020);
+    let exclusive = |tag| {
+        melded
+            .metadata()
+            .tag_exclusive_sizes(tag)
+            .unwrap()
+            .texture_bytes()
+    };
+    assert_that!(exclusive(&green_pink_silver)).is_equal_to(0);
+    assert_that!(melded.metadata().dedupe().exclusive_bytes).is_equal_to(0);
+}
+
+#[test]
+fn test_meld_stats() {
+    let (matte, shiny) = (Tag::from("matte"), Tag::from("shiny"));
+
+    let matte_pinecone = VariationalAsset::from_file(ASSET_PINECONE_MATTE(), Some(&matte))
+        .expect("VariationalAsset::from_file() failure");
+    let shiny_pinecone = VariationalAsset::from_file(ASSET_PINECONE_SHINY(), Some(&shiny))
+        .expect("VariationalAsset::from_file() failure");
+
+    let (_, stats) = VariationalAsset::meld(&matte_pinecone, &shiny_pinecone)
+        .expect("VariationalAsset::meld() failure");
+    assert_that!(stats.tags_added).is_equal_to(vec![shiny.clone()]);
+    assert_that!(stats.primitives_touched).is_greater_than(0);
+    assert_that!(stats.materials.copied).is_greater_than(0);
+    assert_that!(stats.variants_root_json_added).is_greater_than(0);
+    assert_that!(stats.variants_mapping_json_added).is_greater_than(0);
+
+    // melding an asset with a tag the base already has should add nothing
+    let (_, stats) = VariationalAsset::meld(&matte_pinecone, &matte_pinecone)
+        .expect("VariationalAsset::meld() failure");
+    assert_that!(stats.tags_added).has_length(0);
+    assert_that!(stats.blob_bytes_appended).is_equal_to(0);
+    assert_that!(stats.variants_root_json_added).is_equal_to(0);
+    assert_that!(stats.variants_mapping_json_added).is_equal_to(0);
+}
+
+#[test]
+fn test_idempotent_meld() {
+    let (matte, shiny, tinted) = (Tag::from("matte"), Tag::from("shiny"), Tag::from("tinted"));
+    let load_asset = |path, tag| {
+        VariationalAsset::from_file(path, Some(tag)).expect("VariationalAsset::from_file() failure")
+    };
+    let matte_pinecone = load_asset(ASSET_PINECONE_MATTE(), &matte);
+    let shiny_pinecone = load_asset(ASSET_PINECONE_SHINY(), &shiny);
+    let tinted_pinecone = load_asset(ASSET_PINECONE_TINTED(), &tinted);
+    let (melded, _) = VariationalAsset::meld(&matte_pinecone, &shiny_pinecone)
+        .expect("VariationalAsset::meld() failure");
+    let (melded, _) = VariationalAsset::meld(&melded, &tinted_pinecone)
+        .expect("VariationalAsset::meld() failure");
+
+    // melding an asset with itself, or its own GLB, over and over, should change nothing
+    let reparsed = VariationalAsset::from_slice(melded.glb(), Some(&matte), None)
+        .expect("VariationalAsset::from_slice() failure");
+    let mut remelded = melded.clone();
+    for other in &[&melded, &reparsed, &melded] {
+        let (result, stats) =
+            VariationalAsset::meld(&remelded, other).expect("VariationalAsset::meld() failure");
+        assert_that!(stats.tags_added).has_length(0);
+        assert_that!(stats.primitives_touched).is_equal_to(0);
+        assert_that!(stats.blob_bytes_appended).is_equal_to(0);
+        assert_that!(result.glb()).is_equal_to(melded.glb());
+        remelded = result;
+    }
+}
+
+#[test]
+fn test_keep_work_assets() {
+    let (matte, shiny, tinted) = (Tag::from("matte"), Tag::from("shiny"), Tag::from("tinted"));
+    let options = &MeldOptions {
+        keep_work_assets: true,
+        ..Default::default()
+    };
+    let load_asset = |path, tag| {
+        VariationalAsset::from_file_with_options(path, Some(tag), None, options)
+            .expect("VariationalAsset::from_file_with_options() failure")
+    };
+    let tinted_pinecone = load_asset(ASSET_PINECONE_TINTED(), &tinted);
+    let (melded, _) = VariationalAsset::meld_with_options(
+        &load_asset(ASSET_PINECONE_MATTE(), &matte),
+        &load_asset(ASSET_PINECONE_SHINY(), &shiny),
+        options,
+    )
+    .expect("VariationalAsset::meld_with_options() failure");
+
+    // melding the kept asset comes out just as melding one parsed afresh from its GLB
+    let (kept, _) = VariationalAsset::meld_with_options(&melded, &tinted_pinecone, options)
+        .expect("VariationalAsset::meld_with_options() failure");
+    let reparsed = VariationalAsset::from_slice(melded.glb(), Some(&matte), None)
+        .expect("VariationalAsset::from_slice() failure");
+    let (remelded, _) = VariationalAsset::meld_with_options(&reparsed, &tinted_pinecone, options)
+        .expect("VariationalAsset::meld_with_options() failure");
+    assert_that!(kept.glb()).is_equal_to(remelded.glb());
+    assert_that!(kept.metadata().tags()).is_equal_to(remelded.metadata().tags());
+}
+
+#[test]
+fn test_canonical_form() {
+    let (camo_pink_bronze, camo_pink_silver, green_pink_silver) = (
+        Tag::from("camo_pink_bronze"),
+        Tag::from("camo_pink_silver"),
+        Tag::from("green_pink_silver"),
+    );
+    let load_asset = |path, tag| {
+        VariationalAsset::from_file(path, Some(tag)).expect("VariationalAsset::from_file() failure")
+    };
+    let meld_assets = |base, other| {
+        let (melded, _) =
+            VariationalAsset::meld(base, other).expect("VariationalAsset::meld() failure");
+        melded
+    };
+    let base = load_asset(ASSET_TEAPOT_CAMO_PINK_BRONZE(), &camo_pink_bronze);
+    let silver = load_asset(ASSET_TEAPOT_CAMO_PINK_SILVER(), &camo_pink_silver);
+    let green = load_asset(ASSET_TEAPOT_GREEN_PINK_SILVER(), &green_pink_silver);
+
+    // the same sources melded in different orders lay out their materials & images differently
+    let silver_first = meld_assets(&meld_assets(&base, &silver), &green);
+    let green_first = meld_assets(&meld_assets(&base, &green), &silver);
+    assert_that!(silver_first.glb()).is_not_equal_to(green_first.glb());
+
+    // but they have the one canonical form, which is its own canonical form in turn
+    let canonicalize =
+        |asset: &VariationalAsset| asset.canonicalize().expect("canonicalize() failure");
+    let canonical = canonicalize(&silver_first);
+    assert_that!(canonicalize(&green_first).glb()).is_equal_to(canonical.glb());
+    assert_that!(canonicalize(&canonical).glb()).is_equal_to(canonical.glb());
+    assert_that!(canonical.metadata().tags()).is_equal_to(silver_first.metadata().tags());
+
+    let equal = |a, b| VariationalAsset::semantically_equal(a, b).expect("comparison failure");
+    assert_that!(equal(&silver_first, &green_first)).is_true();
+    assert_that!(equal(&silver_first, &meld_assets(&base, &silver))).is_false();
+
+    // a different default tag makes for a different asset
+    let silver_based = meld_assets(&silver, &meld_assets(&base, &green));
+    assert_that!(equal(&silver_first, &silver_based)).is_false();
+}
+
+#[test]
+fn test_extra_glb_chunks() {
+    let (matte, shiny) = (Tag::from("matte"), Tag::from("shiny"));
+    let physics = GlbChunk::Other(0x5359_4850, b"physics!");
+
+    // add a vendor-specific chunk to the exported matte pinecone
+    let matte_pinecone = VariationalAsset::from_file(ASSET_PINECONE_MATTE(), Some(&matte))
+        .expect("VariationalAsset::from_file() failure");
+    let glb = match GlbChunk::from_bytes(matte_pinecone.glb()) {
+        Ok(Some((json, bin))) => GlbChunk::to_bytes_with_extras(json, bin, vec![physics]),
+        _ => panic!("GLB parse failure"),
+    }
+    .expect("GLB creation failure");
+    let matte_pinecone = VariationalAsset::from_slice(&glb, Some(&matte), None)
+        .expect("VariationalAsset::from_slice() failure");
+    let shiny_pinecone = VariationalAsset::from_file(ASSET_PINECONE_SHINY(), Some(&shiny))
+        .expect("VariationalAsset::from_file() failure");
+
+    let extra_chunks = |asset: &VariationalAsset| -> Vec<(u32, Vec<u8>)> {
+        match GlbChunk::from_bytes_with_extras(asset.glb()) {
+            Ok(Some((_, _, extras))) => extras
+                .into_iter()
+                .filter_map(|chunk| match chunk {
+                    GlbChunk::Other(magic, bytes) => Some((magic, bytes.to_vec())),
+                    _ => None,
+                })
+                .collect(),
+            _ => panic!("GLB parse failure"),
+        }
+    };
+
+    // the chunk survives melding, on either side, just the once
+    let (melded, _) = VariationalAsset::meld(&shiny_pinecone, &matte_pinecone)
+        .expect("VariationalAsset::meld() failure");
+    let (melded, _) =
+        VariationalAsset::meld(&melded, &matte_pinecone).expect("VariationalAsset::meld() failure");
+    assert_that!(extra_chunks(&melded)).is_equal_to(vec![(0x5359_4850, b"physics!".to_vec())]);
+
+    // unless we ask for it to be dropped
+    let options = &MeldOptions {
+        drop_extra_chunks: true,
+        ..Default::default()
+    };
+    let (melded, _) =
+        VariationalAsset::meld_with_options(&matte_pinecone, &shiny_pinecone, options)
+            .expect("VariationalAsset::meld_with_options() failure");
+    assert_that!(extra_chunks(&melded)).is_equal_to(vec![]);
+}
+
+#[test]
+fn test_stable_mesh_ids() {
+    let (matte, shiny, tinted) = (Tag::from("matte"), Tag::from("shiny"), Tag::from("tinted"));
+    let load =
+        |path, tag| VariationalAsset::from_file(path, Some(tag)).expect("glTF import failure");
+    let (melded, _) = VariationalAsset::meld(
+        &load(ASSET_PINECONE_MATTE(), &matte),
+        &load(ASSET_PINECONE_SHINY(), &shiny),
+    )
+    .expect("VariationalAsset::meld() failure");
+
+    // the exported mesh is stamped with its original name...
+    let (json, bin) = match GlbChunk::from_bytes(melded.glb()) {
+        Ok(Some((GlbChunk::JSON(json), bin))) => (json, bin),
+        _ => panic!("GLB parse failure"),
+    };
+    let mut json: serde_json::Value = serde_json::from_slice(json).expect("JSON parse failure");
+    assert_that!(json["meshes"][0]["extras"][MESH_ID_EXTRAS_KEY])
+        .is_equal_to(serde_json::Value::from("Pinecone"));
+
+    // ... which still matches it up with new sources after it's been renamed
+    json["meshes"][0]["name"] = "Renamed".into();
+    let json = serde_json::to_vec(&json).expect("JSON serialization failure");
+    let glb = GlbChunk::to_bytes(GlbChunk::JSON(&json), bin).expect("GLB creation failure");
+    let renamed = VariationalAsset::from_slice(&glb, None, None)
+        .expect("VariationalAsset::from_slice() failure");
+    let (melded, stats) = VariationalAsset::meld(&renamed, &load(ASSET_PINECONE_TINTED(), &tinted))
+        .expect("VariationalAsset::meld() failure");
+    assert_that!(stats.tags_added).is_equal_to(vec![tinted]);
+    let asset = WorkAsset::from_slice(melded.glb(), None, None).expect("glTF import failure");
+    assert_that!(asset.meshes()[0].name).is_equal_to(Some(String::from("Renamed")));
+}
+
+#[test]
+fn test_mesh_id_key() {
+    let (matte, shiny) = (Tag::from("matte"), Tag::from("shiny"));
+    let options = &MeldOptions {
+        mesh_id_key: Some(String::from("sourceId")),
+        ..Default::default()
+    };
+    let load = |path: &Path, tag: &Tag, name: &str, id: &str, options: &MeldOptions| {
+        let bytes = fs::read(path).expect("Couldn't read asset file");
+        let mut json: serde_json::Value =
+            serde_json::from_slice(&bytes).expect("JSON parse failure");
+        json["meshes"][0]["name"] = name.into();
+        json["meshes"][0]["extras"] = serde_json::json!({ "sourceId": id });
+        let bytes = serde_json::to_vec(&json).expect("JSON serialization failure");
+        WorkAsset::from_slice_with_options(&bytes, Some(tag), path.parent(), options)
+            .expect("glTF import failure")
+    };
+
+    // differently named meshes match by ID, but only when asked to
+    let base = load(ASSET_PINECONE_MATTE(), &matte, "Pinecone", "abc", options);
+    let other = load(ASSET_PINECONE_SHINY(), &shiny, "Renamed", "abc", options);
+    assert_that!(WorkAsset::meld(&base, &other)).is_ok();
+    let plain = &MeldOptions::default();
+    let base = load(ASSET_PINECONE_MATTE(), &matte, "Pinecone", "abc", plain);
+    let other = load(ASSET_PINECONE_SHINY(), &shiny, "Renamed", "abc", plain);
+    assert_that!(WorkAsset::meld(&base, &other)).is_err();
+
+    // and once both sides carry IDs, names no longer count
+    let base = load(ASSET_PINECONE_MATTE(), &matte, "Pinecone", "abc", options);
+    let other = load(ASSET_PINECONE_SHINY(), &shiny, "Pinecone", "xyz", options);
+    assert_that!(WorkAsset::meld(&base, &other)).is_err();
+}
+
+#[test]
+fn test_fixed_primitives() {
+    let (matte, shiny) = (Tag::from("matte"), Tag::from("shiny"));
+    let load = |path, tag, options: &MeldOptions| {
+        WorkAsset::from_file_with_options(path, Some(tag), None, options)
+            .expect("glTF import failure")
+    };
+    let fixed = |mesh, primitive| MeldOptions {
+        fixed_primitives: vec![FixedPrimitive { mesh, primitive }],
+        ..Default::default()
+    };
+
+    // a fixed primitive keeps the base's material, with a warning that the other side varies it
+    let options = &fixed(MeshSelector::Name(String::from("Pinecone")), None);
+    let base = load(ASSET_PINECONE_MATTE(), &matte, options);
+    let (melded, stats) = WorkAsset::meld(&base, &load(ASSET_PINECONE_SHINY(), &shiny, options))
+        .expect("meld() failure");
+    assert_that!(stats.tags_added).has_length(0);
+    assert_that!(stats.warnings).has_length(1);
+    assert_that!(melded.get_tags_in_use().unwrap()).has_length(0);
+    assert_that!(melded.meshes()[0].primitives[0].material)
+        .is_equal_to(base.meshes()[0].primitives[0].material);
+
+    // but there's nothing to warn of if the other side agrees
+    let (_, stats) = WorkAsset::meld(&base, &load(ASSET_PINECONE_MATTE(), &shiny, options))
+        .expect("meld() failure");
+    assert_that!(stats.warnings).has_length(0);
+
+    // primitives the option doesn't select vary as usual
+    let options = &fixed(MeshSelector::Index(0), Some(1));
+    let base = load(ASSET_PINECONE_MATTE(), &matte, options);
+    let (melded, stats) = WorkAsset::meld(&base, &load(ASSET_PINECONE_SHINY(), &shiny, options))
+        .expect("meld() failure");
+    assert_that!(stats.tags_added).is_equal_to(vec![shiny.clone()]);
+    assert_that!(stats.warnings).has_length(0);
+    assert_that!(melded.get_tags_in_use().unwrap()).is_equal_to(vec![matte, shiny]);
+}
+
+#[test]
+fn test_meld_options_mismatch() {
+    let (matte, shiny) = (Tag::from("matte"), Tag::from("shiny"));
+    let load = |path, tag, options: &MeldOptions| {
+        WorkAsset::from_file_with_options(path, Some(tag), None, options)
+            .expect("glTF import failure")
+    };
+    let base = load(ASSET_PINECONE_MATTE(), &matte, &MeldOptions::default());
+
+    // options that only matter to the result, or to export, needn't agree
+    let unkeyed = &MeldOptions {
+        memory_budget: Some(1 << 30),
+        keep_work_assets: true,
+        write_legacy_extension: true,
+        ..Default::default()
+    };
+    let other = load(ASSET_PINECONE_SHINY(), &shiny, unkeyed);
+    assert_that!(WorkAsset::meld(&base, &other)).is_ok();
+
+    // but keying options must, and the error names the one that doesn't
+    let keyed = &MeldOptions {
+        float_precision: Some(3),
+        ..Default::default()
+    };
+    let other = load(ASSET_PINECONE_SHINY(), &shiny, keyed);
+    let err = WorkAsset::meld(&base, &other).expect_err("differently keyed assets melded");
+    assert_that!(err.contains("float_precision is 5 for the base, but 3")).is_true();
+    let err = WorkAsset::estimate_meld(&base, &other).expect_err("differently keyed assets");
+    assert_that!(err.contains("float_precision is 5 for the base, but 3")).is_true();
+}
+
+#[test]
+fn test_material_filter() {
+    let (matte, shiny) = (Tag::from("matte"), Tag::from("shiny"));
+    let meld = |filter: MaterialFilter| {
+        let options = &MeldOptions {
+            material_filter: filter,
+            ..Default::default()
+        };
+        let load = |path, tag| {
+            WorkAsset::from_file_with_options(path, Some(tag), None, options)
+                .expect("glTF import failure")
+        };
+        let base = load(ASSET_PINECONE_MATTE(), &matte);
+        let other = load(ASSET_PINECONE_SHINY(), &shiny);
+        WorkAsset::meld(&base, &other).map(|(_, stats)| stats.tags_added)
+    };
+    let patterns = |patterns: &[&str]| patterns.iter().map(|p| p.to_string()).collect::<Vec<_>>();
+
+    // the pinecone's one material is named 'lambert1'
+    let tags_added = meld(MaterialFilter::default()).expect("meld() failure");
+    assert_that!(tags_added).is_equal_to(vec![shiny.clone()]);
+    let allowed = MaterialFilter {
+        allow: patterns(&["lambert?", "Body*"]),
+        ..Default::default()
+    };
+    let tags_added = meld(allowed).expect("meld() failure");
+    assert_that!(tags_added).is_equal_to(vec![shiny.clone()]);
+
+    // a material that may not vary fails the meld, or keeps its material for the new tag
+    let not_allowed = MaterialFilter {
+        allow: patterns(&["Body*"]),
+        ..Default::default()
+    };
+    assert_that!(meld(not_allowed)).is_err();
+    let denied = MaterialFilter {
+        deny: patterns(&["*bert*"]),
+        ..Default::default()
+    };
+    assert_that!(meld(denied.clone())).is_err();
+    let ignored = MaterialFilter {
+        policy: MaterialFilterPolicy::Ignore,
+        ..denied
+    };
+    let tags_added = meld(ignored).expect("meld() failure");
+    assert_that!(tags_added).has_length(0);
+
+    // many stars against a long near-miss must not backtrack exponentially
+    let starry = MaterialFilter {
+        allow: patterns(&["*a*a*a*a*a*a*a*a*a*a*a*a*b", "a?*"]),
+        ..Default::default()
+    };
+    assert_that!(starry.allows(&"a".repeat(64))).is_true();
+    assert_that!(starry.allows(&"b".repeat(64))).is_false();
+    assert_that!(starry.allows(&format!("{}c", "a".repeat(64)))).is_true();
+    let stars_only = MaterialFilter {
+        allow: patterns(&["*a*a*a*a*a*a*a*a*a*a*a*a*b"]),
+        ..Default::default()
+    };
+    assert_that!(stars_only.allows(&"a".repeat(64))).is_false();
+    assert_that!(stars_only.allows(&format!("{}b", "a".repeat(64)))).is_true();
+}
+
+#[test]
+fn test_node_scoped_meshes() {
+    let (matte, shiny) = (Tag::from("matte"), Tag::from("shiny"));
+    let options = &MeldOptions {
+        node_scoped_meshes: true,
+        ..Default::default()
+    };
+    // the pinecone's one mesh is instanced by its node 1, 'Pinecone'
+    let load = |path: &Path, tag: &Tag, edit: &dyn Fn(&mut serde_json::Value)| {
+        let bytes = fs::read(path).expect("Couldn't read asset file");
+        let mut json: serde_json::Value =
+            serde_json::from_slice(&bytes).expect("JSON parse failure");
+        edit(&mut json);
+        let bytes = serde_json::to_vec(&json).expect("JSON serialization failure");
+        WorkAsset::from_slice_with_options(&bytes, Some(tag), path.parent(), options)
+            .expect("glTF import failure")
+    };
+
+    // a base where a second node shares the mesh, which each node gets a copy of...
+    let base = load(ASSET_PINECONE_MATTE(), &matte, &|json| {
+        let twin = serde_json::json!({ "name": "Twin", "mesh": 0 });
+        json["nodes"].as_array_mut().unwrap().push(twin);
+        json["nodes"][0]["children"] = serde_json::json!([1, 2]);
+    });
+    let names: Vec<_> = base.meshes().iter().map(|mesh| mesh.name.clone()).collect();
+    assert_that!(names).is_equal_to(vec![
+        Some(String::from("Pinecone@Pinecone")),
+        Some(String::from("Pinecone@Twin")),
+    ]);
+
+    // ... so that one of them can vary on its own
+    let other = load(ASSET_PINECONE_SHINY(), &shiny, &|json| {
+        json["nodes"][1]["name"] = "Twin".into();
+    });
+    let (melded, stats) = WorkAsset::meld(&base, &other).expect("meld() failure");
+    assert_that!(stats.tags_added).is_equal_to(vec![shiny.clone()]);
+    assert_that!(melded.variant_mapping(0, 0).contains_key(&shiny)).is_false();
+    assert_that!(melded.variant_mapping(1, 0).contains_key(&shiny)).is_true();
+
+    // and the result loads the same again
+    let exported = melded.export().expect("export failure");
+    let reloaded = WorkAsset::from_slice_with_options(exported.glb(), Some(&matte), None, options)
+        .expect("glTF import failure");
+    assert_that!(reloaded.meshes()).has_length(2);
+    let (_, stats) = WorkAsset::meld(&reloaded, &other).expect("meld() failure");
+    assert_that!(stats.tags_added).has_length(0);
+}
+
+#[test]
+fn test_variant_importers() {
+    let (matte, red) = (Tag::from("matte"), Tag::from("red"));
+    let importers = VariantImporters(vec![Arc::new(ExtrasVariantImporter::default())]);
+    let options = &MeldOptions {
+        variant_importers: importers,
+        ..Default::default()
+    };
+    // a legacy pinecone that names its red variant in its primitive's extras
+    let load = |variant: serde_json::Value, options: &MeldOptions| {
+        let path = ASSET_PINECONE_MATTE();
+        let bytes = fs::read(path).expect("Couldn't read asset file");
+        let mut json: serde_json::Value =
+            serde_json::from_slice(&bytes).expect("JSON parse failure");
+        let mut red_material = json["materials"][0].clone();
+        red_material["name"] = "lambert1_red".into();
+        red_material["pbrMetallicRoughness"]["metallicFactor"] = 0.9.into();
+        json["materials"].as_array_mut().unwrap().push(red_material);
+        json["meshes"][0]["primitives"][0]["extras"] =
+            serde_json::json!({ "variant_red": variant, "author": "pinecone" });
+        let bytes = serde_json::to_vec(&json).expect("JSON serialization failure");
+        WorkAsset::from_slice_with_options(&bytes, Some(&matte), path.parent(), options)
+    };
+
+    // the importer turns the extras property into a mapping, and takes it out of the asset
+    let asset = load(1.into(), options).expect("glTF import failure");
+    assert_that!(asset.variant_mapping(0, 0).contains_key(&red)).is_true();
+    let extras = asset.meshes()[0].primitives[0].extras.as_ref();
+    assert_that!(extras.map(|raw| raw.get())).is_equal_to(Some(r#"{"author":"pinecone"}"#));
+
+    // from which point on the variant lives in KHR_materials_variants
+    let exported = asset.export().expect("export failure");
+    let reloaded =
+        WorkAsset::from_slice(exported.glb(), Some(&matte), None).expect("glTF import failure");
+    assert_that!(reloaded.variant_mapping(0, 0).contains_key(&red)).is_true();
+
+    // without importers, the extras are none of our business
+    let asset = load(1.into(), &MeldOptions::default()).expect("glTF import failure");
+    assert_that!(asset.variant_mapping(0, 0).contains_key(&red)).is_false();
+
+    // but an importer will have material indices that exist
+    assert_that!(load(5.into(), options)).is_err();
+    assert_that!(load("lambert1_red".into(), options)).is_err();
+}
+
+#[test]
+fn test_variant_exporters() {
+    let (matte, shiny) = (Tag::from("matte"), Tag::from("shiny"));
+    let meld = |options: &MeldOptions| {
+        let load = |path, tag| {
+            WorkAsset::from_file_with_options(path, Some(tag), None, options)
+                .expect("glTF import failure")
+        };
+        let base = load(ASSET_PINECONE_MATTE(), &matte);
+        let other = load(ASSET_PINECONE_SHINY(), &shiny);
+        let (melded, _) = WorkAsset::meld(&base, &other).expect("meld() failure");
+        melded.export().expect("export failure")
+    };
+
+    // by default, there's only KHR_materials_variants
+    let exported = meld(&MeldOptions::default());
+    assert_that!(exported.sidecars().len()).is_equal_to(0);
+
+    let exporters: Vec<Arc<dyn VariantExporter>> = vec![
+        Arc::new(ExtrasVariantExporter::default()),
+        Arc::new(SidecarVariantExporter {
+            file_name: String::from("variants.json"),
+        }),
+    ];
+    let options = &MeldOptions {
+        variant_exporters: VariantExporters(exporters),
+        ..Default::default()
+    };
+    let exported = meld(options);
+
+    // the switch table lists both variants for the pinecone's one primitive
+    let table = &exported.sidecars()["variants.json"];
+    let table: serde_json::Value = serde_json::from_slice(table).expect("JSON parse failure");
+    assert_that!(table["variants"]).is_equal_to(serde_json::json!(["matte", "shiny"]));
+    let mapping = table["meshes"][0]["primitives"][0].as_object().unwrap();
+    let tags: Vec<&str> = mapping.keys().map(String::as_str).collect();
+    assert_that!(tags).is_equal_to(vec!["matte", "shiny"]);
+
+    // and the primitive's extras say the same, as the importer reads them back
+    let importers = VariantImporters(vec![Arc::new(ExtrasVariantImporter::default())]);
+    let options = &MeldOptions {
+        variant_importers: importers,
+        ..Default::default()
+    };
+    let reloaded = WorkAsset::from_slice_with_options(exported.glb(), Some(&matte), None, options)
+        .expect("glTF import failure");
+    assert_that!(reloaded.meshes()[0].primitives[0].extras).is_none();
+    assert_that!(reloaded.variant_mapping(0, 0).contains_key(&shiny)).is_true();
+}
+
+#[test]
+fn test_switch_table() {
+    let (matte, shiny) = (Tag::from("matte"), Tag::from("shiny"));
+    let exporter = SwitchTableVariantExporter {
+        file_name: String::from("switches.json"),
+    };
+    let options = &MeldOptions {
+        variant_exporters: VariantExporters(vec![Arc::new(exporter)]),
+        ..Default::default()
+    };
+    let load = |path, tag| {
+        WorkAsset::from_file_with_options(path, Some(tag), None, options)
+            .expect("glTF import failure")
+    };
+    let base = load(ASSET_PINECONE_MATTE(), &matte);
+    let other = load(ASSET_PINECONE_SHINY(), &shiny);
+    let (melded, _) = WorkAsset::meld(&base, &other).expect("meld() failure");
+    let exported = melded.export().expect("export failure");
+
+    let table = &exported.sidecars()["switches.json"];
+    let table: serde_json::Value = serde_json::from_slice(table).expect("JSON parse failure");
+    assert_that!(table["nodes"]).is_equal_to(serde_json::json!(["RootNode", "Pinecone"]));
+
+    // the root node has no mesh, and the pinecone's one primitive switches material
+    let (matte_nodes, shiny_nodes) = (&table["variants"]["matte"], &table["variants"]["shiny"]);
+    assert_that!(matte_nodes[0]).is_equal_to(serde_json::Value::Null);
+    assert_that!(shiny_nodes[0]).is_equal_to(serde_json::Value::Null);
+    assert_that!(matte_nodes[1]).is_not_equal_to(&shiny_nodes[1]);
+
+    // and the default tag selects what the glTF itself does
+    let reloaded =
+        WorkAsset::from_slice(exported.glb(), Some(&matte), None).expect("glTF import failure");
+    let primitive = &reloaded.meshes()[0].primitives[0];
+    let material = primitive.material.map(|ix| ix.value());
+    assert_that!(matte_nodes[1]).is_equal_to(serde_json::json!([material]));
+}
+
+#[test]
+fn test_remap_materials() {
+    let (matte, shiny) = (Tag::from("matte"), Tag::from("shiny"));
+
+    // the shiny pinecone's material arrives under an exporter-mangled name
+    let bytes = fs::read(ASSET_PINECONE_SHINY()).expect("Couldn't read asset file");
+    let mut json: serde_json::Value = serde_json::from_slice(&bytes).expect("JSON parse failure");
+    json["materials"][0]["name"] = "lambert1.001".into();
+    let bytes = serde_json::to_vec(&json).expect("JSON serialization failure");
+    let shiny_pinecone =
+        WorkAsset::from_slice(&bytes, Some(&shiny), ASSET_PINECONE_SHINY().parent())
+            .expect("glTF import failure");
+    let matte_pinecone =
+        WorkAsset::from_file(ASSET_PINECONE_MATTE(), Some(&matte)).expect("glTF import failure");
+
+    let (mut melded, _) =
+        WorkAsset::meld(&matte_pinecone, &shiny_pinecone).expect("WorkAsset::meld() failure");
+    let mapping = melded.variant_mapping(0, 0);
+    assert_that!(mapping[&matte]).is_not_equal_to(&mapping[&shiny]);
+
+    let names = |pairs: &[(&str, &str)]| -> HashMap<String, String> {
+        pairs
+            .iter()
+            .map(|&(from, to)| (from.to_owned(), to.to_owned()))
+            .collect()
+    };
+    assert_that!(melded.remap_materials(&names(&[("lambert2", "lambert1")]))).is_err();
+    assert_that!(melded.remap_materials(&names(&[("lambert1", "lambert2")]))).is_err();
+
+    let rewritten = melded
+        .remap_materials(&names(&[("lambert1.001", "lambert1")]))
+        .expect("WorkAsset::remap_materials() failure");
+    assert_that!(rewritten).is_equal_to(1);
+    let mapping = melded.variant_mapping(0, 0);
+    assert_that!(mapping[&matte]).is_equal_to(&mapping[&shiny]);
+    assert_that!(melded.export()).is_ok();
+}
+
+#[test]
+fn test_implicit_default_material() {
+    let (matte, shiny) = (Tag::from("matte"), Tag::from("shiny"));
+
+    // the shiny pinecone leaves its primitive to the spec's default material
+    let bytes = fs::read(ASSET_PINECONE_SHINY()).expect("Couldn't read asset file");
+    let mut json: serde_json::Value = serde_json::from_slice(&bytes).expect("JSON parse failure");
+    json["meshes"][0]["primitives"][0]
+        .as_object_mut()
+        .expect("primitive isn't an object")
+        .remove("material");
+    let bytes = serde_json::to_vec(&json).expect("JSON serialization failure");
+    let bare_pinecone =
+        WorkAsset::from_slice(&bytes, Some(&shiny), ASSET_PINECONE_SHINY().parent())
+            .expect("glTF import failure");
+    let matte_pinecone =
+        WorkAsset::from_file(ASSET_PINECONE_MATTE(), Some(&matte)).expect("glTF import failure");
+
+    // either way round, both tags end up explicitly mapped, to different materials
+    for (base, other) in vec![
+        (&matte_pinecone, &bare_pinecone),
+        (&bare_pinecone, &matte_pinecone),
+    ] {
+        let (melded, _) = WorkAsset::meld(base, other).expect("WorkAsset::meld() failure");
+        assert_that!(melded.meshes()[0].primitives[0].material).is_some();
+        let table = melded
+            .variant_table()
+            .expect("Failed to build variant table.");
+        let mapping = &table[0][0];
+        assert_that!(mapping.keys()).contains_all_of(&vec![&matte, &shiny]);
+        assert_that!(mapping[&matte].index).is_not_equal_to(mapping[&shiny].index);
+        assert_that!(melded.export()).is_ok();
+    }
+}
+
+#[test]
+fn test_unicode_tags() {
+    let (matte, shiny) = (Tag::from("mattë"), Tag::from("光沢 / shiny"));
+
+    let matte_pinecone = VariationalAsset::from_file(ASSET_PINECONE_MATTE(), Some(&matte))
+        .expect("VariationalAsset::from_file() failure");
+    let shiny_pinecone = VariationalAsset::from_file(ASSET_PINECONE_SHINY(), Some(&shiny))
+        .expect("VariationalAsset::from_file() failure");
+    let (melded, _) = VariationalAsset::meld(&matte_pinecone, &shiny_pinecone)
+        .expect("VariationalAsset::meld() failure");
+
+    // the tags survive a round trip through the GLB's JSON untouched
+    let reparsed = VariationalAsset::from_slice(melded.glb(), None, None)
+        .expect("VariationalAsset::from_slice() failure");
+    assert_that!(reparsed.metadata().tags().iter()).contains_all_of(&vec![&matte, &shiny]);
+    let split = reparsed.split().expect("VariationalAsset::split() failure");
+    assert_that!(split.keys()).contains_all_of(&vec![&matte, &shiny]);
+
+    // but only those that are safe as they are make file names as they are
+    assert_that!(tag_file_stem(&matte)).is_equal_to(matte.clone());
+    let stem = tag_file_stem(&shiny);
+    assert_that!(stem.as_str()).starts_with("光沢___shiny-");
+    assert_that!(stem).is_not_equal_to(tag_file_stem("光沢 _ shiny"));
+    assert_that!(tag_file_stem("color:red,size:L").contains(':')).is_false();
+    assert_that!(tag_file_stem("con").as_str()).starts_with("_con-");
+    assert_that!(tag_file_stem("..").as_str()).starts_with("_._-");
+}
+
+#[test]
+fn test_concurrent_melds() {
+    let (matte, shiny) = (Tag::from("matte"), Tag::from("shiny"));
+    let dir = env::temp_dir().join("gltf_variant_meld_test_concurrent_melds");
+    let cache = Arc::new(MeldCache::new(&dir).expect("Couldn't create cache."));
+    let options = MeldOptions::default();
+
+    let matte_pinecone = Arc::new(
+        VariationalAsset::from_file(ASSET_PINECONE_MATTE(), Some(&matte))
+            .expect("VariationalAsset::from_file() failure"),
+    );
+    let shiny_pinecone = VariationalAsset::from_file(ASSET_PINECONE_SHINY(), Some(&shiny))
+        .expect("VariationalAsset::from_file() failure");
+    let (expected, _) = VariationalAsset::meld(&matte_pinecone, &shiny_pinecone)
+        .expect("VariationalAsset::meld() failure");
+
+    // many threads share one base asset and one cache, and each melds the same thing repeatedly
+    let threads: Vec<_> = (0..8)
+        .map(|_| {
+            let (matte_pinecone, cache) = (matte_pinecone.clone(), cache.clone());
+            let (shiny, options) = (shiny.clone(), options.clone());
+            thread::spawn(move || {
+                (0..4)
+                    .map(|_| {
+                        let shiny_pinecone = VariationalAsset::from_file_with_options(
+                            ASSET_PINECONE_SHINY(),
+                            Some(&shiny),
+                            Some(cache.as_ref()),
+                            &options,
+                        )?;
+                        let (melded, _) = VariationalAsset::meld_with_options(
+                            &matte_pinecone,
+                            &shiny_pinecone,
+                            &options,
+                        )?;
+                        Ok(melded)
+                    })
+                    .collect::<Result<Vec<_>, String>>()
+            })
+        })
+        .collect();
+    for thread in threads {
+        let melds = thread
+            .join()
+            .expect("Meld thread panicked.")
+            .expect("Concurrent meld failure");
+        for melded in melds {
+            assert_that!(melded.glb().len()).is_equal_to(expected.glb().len());
+            assert_that!(melded.metadata().tags()).is_equal_to(expected.metadata().tags());
+        }
+    }
+}
+
+#[test]
+fn test_asset_info_merge() {
+    let (matte, shiny) = (Tag::from("matte"), Tag::from("shiny"));
+
+    // give the two pinecones distinct attribution
+    let load = |path: &std::path::Path, tag, asset_info: serde_json::Value| {
+        let bytes = fs::read(path).expect("Couldn't read asset file");
+        let mut json: serde_json::Value =
+            serde_json::from_slice(&bytes).expect("JSON parse failure");
+        json["asset"] = asset_info;
+        let bytes = serde_json::to_vec(&json).expect("JSON serialization failure");
+        VariationalAsset::from_slice(&bytes, Some(tag), path.parent())
+            .expect("VariationalAsset::from_slice() failure")
+    };
+    let matte_pinecone = load(
+        ASSET_PINECONE_MATTE(),
+        &matte,
+        serde_json::json!({ "version": "2.0", "generator": "FBX2glTF", "copyright": "Matte Inc" }),
+    );
+    let shiny_pinecone = load(
+        ASSET_PINECONE_SHINY(),
+        &shiny,
+        serde_json::json!({
+            "version": "2.0",
+            "generator": "Blender",
+            "copyright": "Shiny Co",
+            "extras": { "artist": "Pat" }
+        }),
+    );
+    let asset_info = |asset: &VariationalAsset| match GlbChunk::from_bytes(asset.glb()) {
+        Ok(Some((GlbChunk::JSON(json), _))) => {
+            let json: serde_json::Value = serde_json::from_slice(json).expect("JSON parse failure");
+            json["asset"].clone()
+        }
+        _ => panic!("GLB parse failure"),
+    };
+
+    let (melded, _) = VariationalAsset::meld(&matte_pinecone, &shiny_pinecone)
+        .expect("VariationalAsset::meld() failure");
+    let (melded, _) =
+        VariationalAsset::meld(&melded, &shiny_pinecone).expect("VariationalAsset::meld() failure");
+    let info = asset_info(&melded);
+    assert_that!(info["copyright"]).is_equal_to(serde_json::json!("Matte Inc; Shiny Co"));
+    assert_that!(info["generator"]).is_equal_to(serde_json::json!("FBX2glTF"));
+    assert_that!(info["extras"]).is_equal_to(serde_json::json!({
+        "artist": "Pat",
+        "contributingGenerators": ["FBX2glTF", "Blender"]
+    }));
+
+    // a meld that already credits both shouldn't credit either again
+    let (remelded, _) =
+        VariationalAsset::meld(&melded, &melded).expect("VariationalAsset::meld() failure");
+    assert_that!(asset_info(&remelded)).is_equal_to(info);
+
+    let options = &MeldOptions {
+        asset_info_policy: AssetInfoPolicy::KeepBase,
+        ..Default::default()
+    };
+    let (melded, _) =
+        VariationalAsset::meld_with_options(&matte_pinecone, &shiny_pinecone, options)
+            .expect("VariationalAsset::meld_with_options() failure");
+    assert_that!(asset_info(&melded)["copyright"]).is_equal_to(serde_json::json!("Matte Inc"));
+    assert_that!(asset_info(&melded)["extras"]).is_equal_to(serde_json::Value::Null);
+}
+
+#[test]
+fn test_name_merge_policy() {
+    let (bronze, silver) = (Tag::from("bronze"), Tag::from("silver"));
+
+    // the two teapots share their camouflage image & texture, but name them differently
+    let load = |path: &std::path::Path, tag, image_name: &str, texture_name: Option<&str>| {
+        let bytes = fs::read(path).expect("Couldn't read asset file");
+        let mut json: serde_json::Value =
+            serde_json::from_slice(&bytes).expect("JSON parse failure");
+        json["images"][0]["name"] = serde_json::json!(image_name);
+        if let Some(texture_name) = texture_name {
+            json["textures"][0]["name"] = serde_json::json!(texture_name);
+        }
+        let bytes = serde_json::to_vec(&json).expect("JSON serialization failure");
+        VariationalAsset::from_slice(&bytes, Some(tag), path.parent())
+            .expect("VariationalAsset::from_slice() failure")
+    };
+    let bronze_pot = load(
+        ASSET_TEAPOT_CAMO_PINK_BRONZE(),
+        &bronze,
+        "Camo",
+        Some("CamoTex"),
+    );
+    let silver_pot = load(ASSET_TEAPOT_CAMO_PINK_SILVER(), &silver, "camouflage", None);
+    let parse_json = |asset: &VariationalAsset| match GlbChunk::from_bytes(asset.glb()) {
+        Ok(Some((GlbChunk::JSON(json), _))) => {
+            serde_json::from_slice::<serde_json::Value>(json).expect("JSON parse failure")
+        }
+        _ => panic!("GLB parse failure"),
+    };
+    let meld = |base, other, name_merge_policy| {
+        let options = &MeldOptions {
+            name_merge_policy,
+            ..Default::default()
+        };
+        let (melded, _) = VariationalAsset::meld_with_options(base, other, options)
+            .expect("VariationalAsset::meld_with_options() failure");
+        melded
+    };
+
+    // by default, the base's names win without a trace of the others
+    let json = parse_json(&meld(&bronze_pot, &silver_pot, NameMergePolicy::Ignore));
+    assert_that!(json["images"].as_array().unwrap()).has_length(1);
+    assert_that!(json["images"][0]["name"]).is_equal_to(serde_json::json!("Camo"));
+    assert_that!(json["images"][0]["extras"]).is_equal_to(serde_json::Value::Null);
+
+    let json = parse_json(&meld(&bronze_pot, &silver_pot, NameMergePolicy::KeepBase));
+    assert_that!(json["images"][0]["name"]).is_equal_to(serde_json::json!("Camo"));
+    assert_that!(json["images"][0]["extras"])
+        .is_equal_to(serde_json::json!({ "nameAliases": ["camouflage"] }));
+    // a name on one side only is simply kept, with nothing to alias
+    assert_that!(json["textures"][0]["name"]).is_equal_to(serde_json::json!("CamoTex"));
+    assert_that!(json["textures"][0]["extras"]).is_equal_to(serde_json::Value::Null);
+
+    let melded = meld(&bronze_pot, &silver_pot, NameMergePolicy::KeepOther);
+    let json = parse_json(&melded);
+    assert_that!(json["images"][0]["name"]).is_equal_to(serde_json::json!("camouflage"));
+    assert_that!(json["images"][0]["extras"])
+        .is_equal_to(serde_json::json!({ "nameAliases": ["Camo"] }));
+    assert_that!(json["textures"][0]["name"]).is_equal_to(serde_json::json!("CamoTex"));
+
+    // aliases carry through later melds, and a meld with itself changes nothing
+    let remelded = meld(&melded, &melded, NameMergePolicy::KeepOther);
+    assert_that!(parse_json(&remelded)["images"]).is_equal_to(json["images"].clone());
+    let third_pot = load(ASSET_TEAPOT_CAMO_PINK_BRONZE(), &bronze, "Pattern", None);
+    let json = parse_json(&meld(&melded, &third_pot, NameMergePolicy::KeepBase));
+    assert_that!(json["images"][0]["name"]).is_equal_to(serde_json::json!("camouflage"));
+    assert_that!(json["images"][0]["extras"])
+        .is_equal_to(serde_json::json!({ "nameAliases": ["Camo", "Pattern"] }));
+}
+
+#[test]
+fn test_extension_declarations() {
+    let (matte, shiny) = (Tag::from("matte"), Tag::from("shiny"));
+
+    // have each pinecone declare extensions of its own
+    let load = |path: &std::path::Path, tag, used: &[&str], required: &[&str]| {
+        let bytes = fs::read(path).expect("Couldn't read asset file");
+        let mut json: serde_json::Value =
+            serde_json::from_slice(&bytes).expect("JSON parse failure");
+        json["extensionsUsed"] = serde_json::json!(used);
+        json["extensionsRequired"] = serde_json::json!(required);
+        let bytes = serde_json::to_vec(&json).expect("JSON serialization failure");
+        VariationalAsset::from_slice(&bytes, Some(tag), path.parent())
+            .expect("VariationalAsset::from_slice() failure")
+    };
+    let matte_pinecone = load(
+        ASSET_PINECONE_MATTE(),
+        &matte,
+        &["KHR_texture_transform"],
+        &[],
+    );
+    let shiny_pinecone = load(
+        ASSET_PINECONE_SHINY(),
+        &shiny,
+        &["KHR_materials_unlit"],
+        &["KHR_materials_unlit"],
+    );
+    let declarations = |asset: &VariationalAsset| match GlbChunk::from_bytes(asset.glb()) {
+        Ok(Some((GlbChunk::JSON(json), _))) => {
+            let json: serde_json::Value = serde_json::from_slice(json).expect("JSON parse failure");
+            let sorted = |list: &serde_json::Value| {
+                let mut list: Vec<String> =
+                    serde_json::from_value(list.clone()).unwrap_or_default();
+                list.sort();
+                list
+            };
+            (
+                sorted(&json["extensionsUsed"]),
+                sorted(&json["extensionsRequired"]),
+            )
+        }
+        _ => panic!("GLB parse failure"),
+    };
+
+    let (melded, _) = VariationalAsset::meld(&matte_pinecone, &shiny_pinecone)
+        .expect("VariationalAsset::meld() failure");
+    let (used, required) = declarations(&melded);
+    assert_that!(used).is_equal_to(vec![
+        "KHR_materials_unlit".to_owned(),
+        "KHR_materials_variants".to_owned(),
+        "KHR_texture_transform".to_owned(),
+    ]);
+    assert_that!(required).is_equal_to(vec!["KHR_materials_unlit".to_owned()]);
+
+    let options = &MeldOptions {
+        require_variants_extension: true,
+        ..Default::default()
+    };
+    let (melded, _) =
+        VariationalAsset::meld_with_options(&matte_pinecone, &shiny_pinecone, options)
+            .expect("VariationalAsset::meld_with_options() failure");
+    let (_, required) = declarations(&melded);
+    assert_that!(required).is_equal_to(vec![
+        "KHR_materials_unlit".to_owned(),
+        "KHR_materials_variants".to_owned(),
+    ]);
+}
+
+#[test]
+fn test_quantized_fingerprints() {
+    let (matte, shiny) = (Tag::from("matte"), Tag::from("shiny"));
+
+    let matte_pinecone = VariationalAsset::from_file(ASSET_PINECONE_MATTE(), Some(&matte))
+        .expect("VariationalAsset::from_file() failure");
+    let shiny_pinecone = VariationalAsset::from_file(ASSET_PINECONE_SHINY(), Some(&shiny))
+        .expect("VariationalAsset::from_file() failure");
+
+    let (_, additive) = VariationalAsset::meld(&matte_pinecone, &shiny_pinecone)
+        .expect("VariationalAsset::meld() failure");
+
+    let options = &MeldOptions {
+        fingerprint_algorithm: FingerprintAlgorithm::Quantized(1e-4),
+        ..Default::default()
+    };
+    let (_, quantized) =
+        VariationalAsset::meld_with_options(&matte_pinecone, &shiny_pinecone, options)
+            .expect("VariationalAsset::meld_with_options() failure");
+    assert_that!(quantized.primitives_touched).is_equal_to(additive.primitives_touched);
+    assert_that!(quantized.materials).is_equal_to(additive.materials);
+
+    let options = &MeldOptions {
+        fingerprint_algorithm: FingerprintAlgorithm::Quantized(0.0),
+        ..Default::default()
+    };
+    let bytes = fs::read(ASSET_PINECONE_MATTE()).expect("Couldn't read asset file");
+    let base_dir = ASSET_PINECONE_MATTE().parent();
+    let asset = WorkAsset::from_slice_with_options(&bytes, Some(&matte), base_dir, options);
+    assert_that!(asset).is_err();
+}
+
+#[test]
+fn test_node_transforms() {
+    let (matte, shiny) = (Tag::from("matte"), Tag::from("shiny"));
+    let scaled = |path: &Path| {
+        let bytes = fs::read(path).expect("Couldn't read asset file");
+        let mut json: serde_json::Value =
+            serde_json::from_slice(&bytes).expect("JSON parse failure");
+        json["nodes"][0]["scale"] = serde_json::json!([2.0, 2.0, 2.0]);
+        serde_json::to_vec(&json).expect("JSON serialization failure")
+    };
+    let options = |node_transforms| MeldOptions {
+        node_transforms,
+        ..Default::default()
+    };
+
+    // the matte pinecone keeps its scale on a node, where the shiny one's exporter baked it in
+    let matte_pinecone = VariationalAsset::from_slice(
+        &scaled(ASSET_PINECONE_MATTE()),
+        Some(&matte),
+        ASSET_PINECONE_MATTE().parent(),
+    )
+    .expect("VariationalAsset::from_slice() failure");
+    let shiny_pinecone = VariationalAsset::from_slice_with_options(
+        &scaled(ASSET_PINECONE_SHINY()),
+        Some(&shiny),
+        ASSET_PINECONE_SHINY().parent(),
+        &options(NodeTransformPolicy::Bake),
+    )
+    .expect("VariationalAsset::from_slice_with_options() failure");
+    let json = match GlbChunk::from_bytes(shiny_pinecone.glb()) {
+        Ok(Some((GlbChunk::JSON(json), _))) => json,
+        _ => panic!("GLB parse failure"),
+    };
+    let json: serde_json::Value = serde_json::from_slice(json).expect("JSON parse failure");
+    assert_that!(json["nodes"][0]["scale"]).is_equal_to(serde_json::Value::Null);
+
+    assert_that!(VariationalAsset::meld(&matte_pinecone, &shiny_pinecone)).is_err();
+    let options = options(NodeTransformPolicy::Compare);
+    let (melded, stats) =
+        VariationalAsset::meld_with_options(&matte_pinecone, &shiny_pinecone, &options)
+            .expect("VariationalAsset::meld_with_options() failure");
+    assert_that!(stats.tags_added).is_equal_to(vec![shiny]);
+    assert_that!(melded.metadata().tags().len()).is_equal_to(2);
+}
+
+#[test]
+fn test_fingerprint_diagnostics() {
+    let matte = Tag::from("matte");
+    let bytes = fs::read(ASSET_PINECONE_MATTE()).expect("Couldn't read asset file");
+    let base_dir = ASSET_PINECONE_MATTE().parent();
+
+    // a second copy of the pinecone's only primitive can't be told apart from the first
+    let mut json: serde_json::Value = serde_json::from_slice(&bytes).expect("JSON parse failure");
+    let primitive = json["meshes"][0]["primitives"][0].clone();
+    json["meshes"][0]["primitives"]
+        .as_array_mut()
+        .expect("mesh lacks primitives")
+        .push(primitive);
+    let bytes = serde_json::to_vec(&json).expect("JSON serialization failure");
+
+    let err = WorkAsset::from_slice(&bytes, Some(&matte), base_dir)
+        .expect_err("identical primitives not detected");
+    assert_that!(err.contains("primitives 0 and 1 of mesh 0")).is_true();
+    assert_that!(err.contains("within the epsilon of 1e-6")).is_true();
+    assert_that!(err.contains("no epsilon can tell them apart")).is_true();
+}
+
+#[test]
+fn test_diagnose_meld() {
+    let (matte, shiny) = (Tag::from("matte"), Tag::from("shiny"));
+    let matte_pinecone = VariationalAsset::from_file(ASSET_PINECONE_MATTE(), Some(&matte))
+        .expect("glTF import failure");
+    let shiny_pinecone = VariationalAsset::from_file(ASSET_PINECONE_SHINY(), Some(&shiny))
+        .expect("glTF import failure");
+    let options = &MeldOptions::default();
+
+    let diagnosis = VariationalAsset::diagnose_meld(&matte_pinecone, &shiny_pinecone, options)
+        .expect("Failed to diagnose meld.");
+    assert_that!(diagnosis.is_empty()).is_true();
+
+    // with its mesh renamed, the shiny pinecone no longer melds, but the diagnosis knows better
+    let bytes = fs::read(ASSET_PINECONE_SHINY()).expect("Couldn't read asset file");
+    let mut json: serde_json::Value = serde_json::from_slice(&bytes).expect("JSON parse failure");
+    json["meshes"][0]["name"] = serde_json::Value::from("renamed");
+    let bytes = serde_json::to_vec(&json).expect("JSON serialization failure");
+    let renamed_pinecone =
+        VariationalAsset::from_slice(&bytes, Some(&shiny), ASSET_PINECONE_SHINY().parent())
+            .expect("glTF import failure");
+    assert_that!(VariationalAsset::meld(&matte_pinecone, &renamed_pinecone)).is_err();
+
+    let diagnosis = VariationalAsset::diagnose_meld(&matte_pinecone, &renamed_pinecone, options)
+        .expect("Failed to diagnose meld.");
+    assert_that!(diagnosis.problems).has_length(1);
+    let problem = &diagnosis.problems[0];
+    assert_that!(problem.name).is_equal_to(Some(String::from("renamed")));
+    assert_that!(problem.mismatch).is_equal_to(MeshMismatch::NoSuchName);
+    assert_that!(problem.candidates).has_length(1);
+    assert_that!(problem.candidates[0].base_mesh).is_equal_to(0);
+    assert_that!(problem.candidates[0].fingerprint_distance).is_some();
+}
+
+#[test]
+fn test_primitive_correspondence() {
+    let (matte, shiny) = (Tag::from("matte"), Tag::from("shiny"));
+    let base =
+        WorkAsset::from_file(ASSET_PINECONE_MATTE(), Some(&matte)).expect("glTF import failure");
+
+    // a renamed mesh won't meld...
+    let bytes = fs::read(ASSET_PINECONE_SHINY()).expect("Couldn't read asset file");
+    let mut json: serde_json::Value = serde_json::from_slice(&bytes).expect("JSON parse failure");
+    json["meshes"][0]["name"] = "Renamed".into();
+    let bytes = serde_json::to_vec(&json).expect("JSON serialization failure");
+    let other = WorkAsset::from_slice(&bytes, Some(&shiny), ASSET_PINECONE_SHINY().parent())
+        .expect("glTF import failure");
+    assert_that!(WorkAsset::meld(&base, &other)).is_err();
+
+    // ... unless matched up explicitly, as the template guesses
+    let template =
+        WorkAsset::correspondence_template(&base, &other).expect("Failed to build template.");
+    let json = template.to_json().expect("Failed to serialize template.");
+    let correspondence =
+        PrimitiveCorrespondence::from_json(&json).expect("Failed to parse template.");
+    assert_that!(correspondence.primitives).has_length(1);
+    assert_that!(correspondence.primitives[0].other.mesh_name)
+        .is_equal_to(Some(String::from("Renamed")));
+    let (_, stats) = WorkAsset::meld_with_correspondence(&base, &other, &correspondence)
+        .expect("Failed to meld.");
+    assert_that!(stats.tags_added).is_equal_to(vec![shiny]);
+
+    // entries must name primitives that exist
+    let mut broken = correspondence.clone();
+    broken.primitives[0].other.primitive = 7;
+    assert_that!(WorkAsset::meld_with_correspondence(&base, &other, &broken)).is_err();
+}
+
+#[test]
+fn test_estimate_meld_size() {
+    let (camo_pink_bronze, camo_pink_silver, green_pink_silver) = (
+        Tag::from("camo_pink_bronze"),
+        Tag::from("camo_pink_silver"),
+        Tag::from("green_pink_silver"),
+    );
+    let load =
+        |path, tag| VariationalAsset::from_file(path, Some(tag)).expect("glTF import failure");
+    let base = load(ASSET_TEAPOT_CAMO_PINK_BRONZE(), &camo_pink_bronze);
+
+    // the camo texture is already in the base
+    let other = load(ASSET_TEAPOT_CAMO_PINK_SILVER(), &camo_pink_silver);
+    let estimate =
+        VariationalAsset::estimate_meld_size(&base, &other).expect("Failed to estimate meld.");
+    assert_that!(estimate.tags_added).is_equal_to(vec![camo_pink_silver]);
+    assert_that!(estimate.shared_texture_bytes).is_equal_to(227318);
+    assert_that!(estimate.new_texture_bytes).is_equal_to(0);
+
+    // the green texture isn't, and the estimate should be close to what the meld does
+    let other = load(ASSET_TEAPOT_GREEN_PINK_SILVER(), &green_pink_silver);
+    let estimate =
+        VariationalAsset::estimate_meld_size(&base, &other).expect("Failed to estimate meld.");
+    let (melded, stats) = VariationalAsset::meld(&base, &other).expect("Failed to meld.");
+    assert_that!(estimate.tags_added).is_equal_to(stats.tags_added);
+    assert_that!(estimate.new_texture_bytes).is_equal_to(stats.blob_bytes_appended);
+    let (estimated, actual) = (estimate.output_bytes as f64, melded.glb().len() as f64);
+    assert_that!((estimated - actual).abs() / actual).is_less_than(0.05);
+}
+
+#[test]
+fn test_estimate_mismatched_primitives() {
+    let (bronze, silver) = (Tag::from("camo_pink_bronze"), Tag::from("camo_pink_silver"));
+    let base = VariationalAsset::from_file(ASSET_TEAPOT_CAMO_PINK_BRONZE(), Some(&bronze))
+        .expect("glTF import failure");
+
+    // give the first mesh of the other teapot a second primitive, borrowed from the second
+    let path = ASSET_TEAPOT_CAMO_PINK_SILVER();
+    let bytes = fs::read(path).expect("Couldn't read asset file");
+    let mut json: serde_json::Value = serde_json::from_slice(&bytes).expect("JSON parse failure");
+    let primitive = json["meshes"][1]["primitives"][0].clone();
+    json["meshes"][0]["primitives"]
+        .as_array_mut()
+        .expect("mesh lacks primitives")
+        .push(primitive);
+    let bytes = serde_json::to_vec(&json).expect("JSON serialization failure");
+    let other = VariationalAsset::from_slice(&bytes, Some(&silver), path.parent())
+        .expect("glTF import failure");
+
+    // the estimate fails just as the meld does, rather than panicking
+    let err = VariationalAsset::meld(&base, &other).expect_err("mismatch not detected");
+    assert_that!(err.contains("has 1 primitives, but its melded counterpart has 2")).is_true();
+    let err =
+        VariationalAsset::estimate_meld_size(&base, &other).expect_err("mismatch not detected");
+    assert_that!(err.contains("has 1 primitives, but its melded counterpart has 2")).is_true();
+}
+
+#[test]
+fn test_phase_timings() {
+    let (matte, shiny) = (Tag::from("matte"), Tag::from("shiny"));
+
+    let matte_pinecone = VariationalAsset::from_file(ASSET_PINECONE_MATTE(), Some(&matte))
+        .expect("VariationalAsset::from_file() failure");
+    let shiny_pinecone = VariationalAsset::from_file(ASSET_PINECONE_SHINY(), Some(&shiny))
+        .expect("VariationalAsset::from_file() failure");
+
+    let (_, stats) = VariationalAsset::meld(&matte_pinecone, &shiny_pinecone)
+        .expect("VariationalAsset::meld() failure");
+    let timings = stats.timings;
+    if cfg!(feature = "profiling") {
+        assert_that!(timings.construct).is_greater_than(timings.keys + timings.fingerprints);
+        assert_that!(timings.meld).is_greater_than(Duration::default());
+        assert_that!(timings.export).is_greater_than(Duration::default());
+    } else {
+        assert_that!(timings.is_empty()).is_true();
+    }
+}
+
+#[test]
+fn test_memory_budget() {
+    let (matte, shiny) = (Tag::from("matte"), Tag::from("shiny"));
+
+    let matte_pinecone = VariationalAsset::from_file(ASSET_PINECONE_MATTE(), Some(&matte))
+        .expect("VariationalAsset::from_file() failure");
+    let shiny_pinecone = VariationalAsset::from_file(ASSET_PINECONE_SHINY(), Some(&shiny))
+        .expect("VariationalAsset::from_file() failure");
+
+    // the peak counts at least both sources, and a copy of the base
+    let options = &MeldOptions {
+        memory_budget: Some(usize::max_value()),
+        ..Default::default()
+    };
+    let (_, stats) = VariationalAsset::meld_with_options(&matte_pinecone, &shiny_pinecone, options)
+        .expect("VariationalAsset::meld_with_options() failure");
+    let sources = 2 * matte_pinecone.glb().len() + shiny_pinecone.glb().len();
+    assert_that!(stats.peak_memory).is_greater_than(sources / 2);
+
+    // a budget of exactly the peak is fine, but one byte less is not
+    let options = &MeldOptions {
+        memory_budget: Some(stats.peak_memory),
+        ..Default::default()
+    };
+    VariationalAsset::meld_with_options(&matte_pinecone, &shiny_pinecone, options)
+        .expect("VariationalAsset::meld_with_options() failure");
+
+    let options = &MeldOptions {
+        memory_budget: Some(stats.peak_memory - 1),
+        ..Default::default()
+    };
+    let err = VariationalAsset::meld_with_options(&matte_pinecone, &shiny_pinecone, options)
+        .expect_err("meld should exceed its memory budget");
+    assert_that!(err.contains("over the budget")).is_true();
+}
+
+#[test]
+fn test_teapot_append() {
+    let (camo_pink_bronze, camo_pink_silver, green_pink_silver) = (
+        Tag::from("camo_pink_bronze"),
+        Tag::from("camo_pink_silver"),
+        Tag::from("green_pink_silver"),
+    );
+    let load_asset = |path, tag| {
+        VariationalAsset::from_file(path, Some(tag)).expect("VariationalAsset::from_file() failure")
+    };
+
+    let base = load_asset(ASSET_TEAPOT_CAMO_PINK_BRONZE(), &camo_pink_bronze);
+    let silver = load_asset(ASSET_TEAPOT_CAMO_PINK_SILVER(), &camo_pink_silver);
+    let green = load_asset(ASSET_TEAPOT_GREEN_PINK_SILVER(), &green_pink_silver);
+
+    let (melded, _) = VariationalAsset::meld(&base, &silver).expect("meld() failure");
+    let (melded, _) = VariationalAsset::meld(&melded, &green).expect("meld() failure");
+
+    let options = &MeldOptions::default();
+    let appended = base.append(&silver, options).expect("append() failure");
+    let appended = appended.append(&green, options).expect("append() failure");
+
+    // appending should give the same result as melding
+    assert_that!(appended.metadata().tags()).is_equal_to(melded.metadata().tags());
+    assert_that!(appended.metadata().total_sizes().texture_bytes()).is_equal_to(564338);
+    assert_that!(appended.glb().len()).is_equal_to(melded.glb().len());
+}
+
+#[test]
+fn test_meld_of_reparsed_asset() {
+    let (camo_pink_bronze, camo_pink_silver, green_pink_silver) = (
+        Tag::from("camo_pink_bronze"),
+        Tag::from("camo_pink_silver"),
+        Tag::from("green_pink_silver"),
+    );
+    let load_asset = |path, tag| {
+        VariationalAsset::from_file(path, Some(tag)).expect("VariationalAsset::from_file() failure")
+    };
+
+    let base = load_asset(ASSET_TEAPOT_CAMO_PINK_BRONZE(), &camo_pink_bronze);
+    let silver = load_asset(ASSET_TEAPOT_CAMO_PINK_SILVER(), &camo_pink_silver);
+    let green = load_asset(ASSET_TEAPOT_GREEN_PINK_SILVER(), &green_pink_silver);
+
+    // melding a meld result directly should give the same result as melding it from its GLB
+    let (melded, _) = VariationalAsset::meld(&base, &silver).expect("meld() failure");
+    let reparsed = VariationalAsset::from_slice(melded.glb(), Some(&camo_pink_bronze), None)
+        .expect("VariationalAsset::from_slice() failure");
+
+    let (direct, _) = VariationalAsset::meld(&melded, &green).expect("meld() failure");
+    let (indirect, _) = VariationalAsset::meld(&reparsed, &green).expect("meld() failure");
+
+    assert_that!(direct.metadata().tags()).is_equal_to(indirect.metadata().tags());
+    assert_that!(direct.glb().len()).is_equal_to(indirect.glb().len());
+    assert_that!(direct.variant_table().unwrap()).is_equal_to(indirect.variant_table().unwrap());
+}
+
+#[test]
+fn test_variational_melds() {
+    let (camo_pink_bronze, camo_pink_silver, green_pink_bronze, green_pink_silver) = (
+        Tag::from("camo_pink_bronze"),
+        Tag::from("camo_pink_silver"),
+        Tag::from("green_pink_bronze"),
+        Tag::from("green_pink_silver"),
+    );
+    let load_asset = |path, tag| {
+        VariationalAsset::from_file(path, Some(tag)).expect("VariationalAsset::from_file() failure")
+    };
+    let camo_bronze = load_asset(ASSET_TEAPOT_CAMO_PINK_BRONZE(), &camo_pink_bronze);
+    let camo_silver = load_asset(ASSET_TEAPOT_CAMO_PINK_SILVER(), &camo_pink_silver);
+    let green_bronze = load_asset(ASSET_TEAPOT_GREEN_PINK_BRONZE(), &green_pink_bronze);
+    let green_silver = load_asset(ASSET_TEAPOT_GREEN_PINK_SILVER(), &green_pink_silver);
+
+    // two variational assets that share a tag, each with a tag of its own
+    let (base, _) = VariationalAsset::meld(&camo_bronze, &camo_silver).expect("meld() failure");
+    let (other, _) = VariationalAsset::meld(&green_silver, &camo_silver).expect("meld() failure");
+    let (melded, stats) = VariationalAsset::meld(&base, &other).expect("meld() failure");
+    assert_that!(stats.tags_added).is_equal_to(vec![green_pink_silver.clone()]);
+    assert_that!(melded.default_tag()).is_equal_to(&camo_pink_bronze);
+
+    // the base's tags keep their places, and the other side's new tags follow
+    let tags_in_order = |asset: &VariationalAsset| {
+        WorkAsset::from_slice(asset.glb(), Some(asset.default_tag()), None)
+            .expect("glTF import failure")
+            .get_tags_in_use()
+            .expect("get_tags_in_use() failure")
+    };
+    assert_that!(tags_in_order(&melded)).is_equal_to(vec![
+        camo_pink_bronze.clone(),
+        camo_pink_silver.clone(),
+        green_pink_silver.clone(),
+    ]);
+    let (reversed, _) = VariationalAsset::meld(&other, &base).expect("meld() failure");
+    assert_that!(tags_in_order(&reversed)).is_equal_to(vec![
+        green_pink_silver.clone(),
+        camo_pink_silver.clone(),
+        camo_pink_bronze.clone(),
+    ]);
+    assert_that!(reversed.metadata().tags()).is_equal_to(melded.metadata().tags());
+
+    // melding two more tags in at once is the same as melding them in one by one
+    let (more, _) = VariationalAsset::meld(&green_bronze, &green_silver).expect("meld() failure");
+    let (all_at_once, stats) = VariationalAsset::meld(&base, &more).expect("meld() failure");
+    assert_that!(stats.tags_added).has_length(2);
+    let (one_by_one, _) = VariationalAsset::meld(&base, &green_silver).expect("meld() failure");
+    let (one_by_one, _) =
+        VariationalAsset::meld(&one_by_one, &green_bronze).expect("meld() failure");
+    let equal = VariationalAsset::semantically_equal(&all_at_once, &one_by_one);
+    assert_that!(equal.expect("comparison failure")).is_true();
+
+    // a default tag is like any other tag, and may not select a different material than the
+    // same tag does on the other side
+    let impostor = load_asset(ASSET_TEAPOT_GREEN_PINK_SILVER(), &camo_pink_silver);
+    let (impostor, _) = VariationalAsset::meld(&impostor, &green_bronze).expect("meld() failure");
+    assert_that!(VariationalAsset::meld(&base, &impostor)).is_err();
+}
+
+#[test]
+fn test_embedded_metadata() {
+    let (matte, shiny) = (Tag::from("matte"), Tag::from("shiny"));
+    let matte_pinecone = VariationalAsset::from_file(ASSET_PINECONE_MATTE(), Some(&matte))
+        .expect("VariationalAsset::from_file() failure");
+    let shiny_pinecone = VariationalAsset::from_file(ASSET_PINECONE_SHINY(), Some(&shiny))
+        .expect("VariationalAsset::from_file() failure");
+
+    let options = &MeldOptions {
+        embed_metadata: true,
+        ..Default::default()
+    };
+    let (melded, _) =
+        VariationalAsset::meld_with_options(&matte_pinecone, &shiny_pinecone, options)
+            .expect("VariationalAsset::meld_with_options() failure");
+
+    let loaded =
+        VariationalAsset::from_slice(melded.glb(), None, None).expect("from_slice() failure");
+    assert_that!(loaded.default_tag()).is_equal_to(&matte);
+    assert_that!(loaded.glb()).is_equal_to(melded.glb());
+    assert_that!(loaded.metadata().tags()).is_equal_to(melded.metadata().tags());
+    assert_that!(loaded.metadata().total_sizes().texture_bytes())
+        .is_equal_to(melded.metadata().total_sizes().texture_bytes());
+    assert_that!(loaded.metadata().counts().materials).is_equal_to(2);
+
+    // other options could make for a different asset, so then the metadata isn't trusted
+    let options = &MeldOptions {
+        drop_extra_chunks: true,
+        ..Default::default()
+    };
+    let reparsed = VariationalAsset::from_slice_with_options(melded.glb(), None, None, options)
+        .expect("from_slice_with_options() failure");
+    assert_that!(reparsed.glb() == melded.glb()).is_false();
+
+    // nor is it once another tool has edited the asset, but kept its extras
+    let (json, bin) = match GlbChunk::from_bytes(melded.glb()) {
+        Ok(Some((GlbChunk::JSON(json), bin))) => (json, bin),
+        _ => panic!("GLB parse failure"),
+    };
+    let mut json: serde_json::Value = serde_json::from_slice(json).expect("JSON parse failure");
+    json["samplers"] = serde_json::json!([{}]);
+    let json = serde_json::to_vec(&json).expect("JSON serialization failure");
+    let edited = GlbChunk::to_bytes(GlbChunk::JSON(&json), bin).expect("GLB creation failure");
+    let reparsed = VariationalAsset::from_slice(&edited, None, None).expect("from_slice() failure");
+    assert_that!(reparsed.glb() == edited.as_slice()).is_false();
+
+    // melding the self-describing asset again recomputes its metadata from scratch
+    let (remelded, _) =
+        VariationalAsset::meld(&loaded, &shiny_pinecone).expect("VariationalAsset::meld() failure");
+    let reloaded =
+        VariationalAsset::from_slice(remelded.glb(), Some(&matte), None).expect("from_slice()");
+    assert_that!(reloaded.metadata().tags()).is_equal_to(melded.metadata().tags());
+}
+
+#[test]
+fn test_metadata_json_order() {
+    let (matte, shiny) = (Tag::from("matte"), Tag::from("shiny"));
+    let matte_pinecone = VariationalAsset::from_file(ASSET_PINECONE_MATTE(), Some(&matte))
+        .expect("VariationalAsset::from_file() failure");
+    let shiny_pinecone = VariationalAsset::from_file(ASSET_PINECONE_SHINY(), Some(&shiny))
+        .expect("VariationalAsset::from_file() failure");
+
+    // tags come out sorted, whatever order they were melded in
+    let (melded, _) = VariationalAsset::meld(&shiny_pinecone, &matte_pinecone)
+        .expect("VariationalAsset::meld() failure");
+    let metadata = melded.metadata();
+    assert_that!(metadata.wasm_tags()).is_equal_to(r#"["matte","shiny"]"#.to_owned());
+    let sizes: Vec<String> = metadata
+        .tags()
+        .iter()
+        .map(|tag| format!("{:?}:{}", tag, serde_json::json!(metadata.tag_sizes(tag))))
+        .collect();
+    assert_that!(metadata.wasm_tag_sizes()).is_equal_to(format!("{{{}}}", sizes.join(",")));
+}
+
+#[test]
+fn test_metadata_diff() {
+    let (matte, shiny, tinted) = (Tag::from("matte"), Tag::from("shiny"), Tag::from("tinted"));
+    let load_asset = |path, tag| {
+        VariationalAsset::from_file(path, Some(tag)).expect("VariationalAsset::from_file() failure")
+    };
+    let meld_assets = |base, other| {
+        let (melded, _) =
+            VariationalAsset::meld(base, other).expect("VariationalAsset::meld() failure");
+        melded
+    };
+    let matte_shiny = meld_assets(
+        &load_asset(ASSET_PINECONE_MATTE(), &matte),
+        &load_asset(ASSET_PINECONE_SHINY(), &shiny),
+    );
+    let matte_shiny_tinted =
+        meld_assets(&matte_shiny, &load_asset(ASSET_PINECONE_TINTED(), &tinted));
+
+    let (before, after) = (matte_shiny.metadata(), matte_shiny_tinted.metadata());
+    let delta = after.diff(before);
+    assert_that!(delta.tags_added).is_equal_to(vec![tinted.clone()]);
+    assert_that!(delta.tags_removed).has_length(0);
+    let bytes = |metadata: &Metadata| metadata.total_sizes().texture_bytes as i64;
+    assert_that!(delta.texture_bytes).is_equal_to(bytes(after) - bytes(before));
+    let tinted_bytes = after
+        .tag_sizes(&tinted)
+        .map_or(0, |sizes| sizes.texture_bytes);
+    assert_that!(delta.per_tag_texture_bytes.get(&tinted))
+        .is_equal_to(Some(&(tinted_bytes as i64)));
+
+    // and the other way around
+    let reverse = before.diff(after);
+    assert_that!(reverse.tags_removed).is_equal_to(vec![tinted]);
+    assert_that!(reverse.texture_bytes).is_equal_to(-delta.texture_bytes);
+
+    assert_that!(after.diff(after).is_empty()).is_true();
+}
+
+#[test]
+fn test_exclusive_resources() {
+    let (matte, shiny) = (Tag::from("matte"), Tag::from("shiny"));
+    let matte_pinecone = VariationalAsset::from_file(ASSET_PINECONE_MATTE(), Some(&matte))
+        .expect("VariationalAsset::from_file() failure");
+    let shiny_pinecone = VariationalAsset::from_file(ASSET_PINECONE_SHINY(), Some(&shiny))
+        .expect("VariationalAsset::from_file() failure");
+    let (melded, _) = VariationalAsset::meld(&matte_pinecone, &shiny_pinecone)
+        .expect("VariationalAsset::meld() failure");
+    let metadata = melded.metadata();
+
+    // each pinecone brings a material of its own
+    let matte_materials = metadata
+        .exclusive_materials(&matte)
+        .expect("no matte materials");
+    let shiny_materials = metadata
+        .exclusive_materials(&shiny)
+        .expect("no shiny materials");
+    assert_that!(matte_materials).has_length(1);
+    assert_that!(shiny_materials).has_length(1);
+    assert_that!(matte_materials).is_not_equal_to(shiny_materials);
+
+    // and whatever images only it uses are exactly what its exclusive size counts
+    for tag in &[&matte, &shiny] {
+        let images = metadata.exclusive_images(tag).expect("no exclusive images");
+        let bytes = metadata
+            .tag_exclusive_sizes(tag)
+            .map_or(0, |s| s.texture_bytes);
+        assert_that!(images.is_empty()).is_equal_to(bytes == 0);
+    }
+    assert_that!(metadata.exclusive_materials(&Tag::from("tinted"))).is_none();
+}
+
+#[test]
+fn test_export_verified() {
+    let (matte, shiny) = (Tag::from("matte"), Tag::from("shiny"));
+    let matte_pinecone = WorkAsset::from_file(ASSET_PINECONE_MATTE(), Some(&matte))
+        .expect("WorkAsset::from_file() failure");
+    let shiny_pinecone = WorkAsset::from_file(ASSET_PINECONE_SHINY(), Some(&shiny))
+        .expect("WorkAsset::from_file() failure");
+
+    let (melded, _) =
+        WorkAsset::meld(&matte_pinecone, &shiny_pinecone).expect("WorkAsset::meld() failure");
+    let verified = melded
+        .export_verified()
+        .expect("export verification failure");
+    assert_that!(verified.metadata().tags().len()).is_equal_to(2);
+
+    // verification holds up also for an asset whose mapping holds the redundant default tag
+    let mut asset = matte_pinecone.clone();
+    let material_key = asset.material_keys()[0].clone();
+    asset
+        .set_variant(0, 0, &matte, &material_key)
+        .expect("set_variant() failure");
+    assert_that!(asset.export_verified()).is_ok();
+}
+
+#[test]
+fn test_axis_tags() {
+    let load_asset = |path, tag: &str| {
+        VariationalAsset::from_file(path, Some(&Tag::from(tag)))
+            .expect("VariationalAsset::from_file() failure")
+    };
+    let meld_assets = |base, meld| {
+        let (melded, _) =
+            VariationalAsset::meld(base, meld).expect("VariationalAsset::meld() failure");
+        melded
+    };
+
+    // axis order doesn't matter; tags are brought into canonical form
+    let result = load_asset(ASSET_TEAPOT_CAMO_PINK_BRONZE(), "body:camo,metal:bronze");
+    assert_that!(result.default_tag()).is_equal_to(&Tag::from("body:camo,metal:bronze"));
+    let result = meld_assets(
+        &result,
+        &load_asset(ASSET_TEAPOT_CAMO_PINK_SILVER(), "metal:silver,body:camo"),
+    );
+    let result = meld_assets(
+        &result,
+        &load_asset(ASSET_TEAPOT_GREEN_PINK_SILVER(), "body:green,metal:silver"),
+    );
+
+    let metadata = result.metadata();
+    assert_that!(metadata.axes().len()).is_equal_to(2);
+    assert_that!(metadata.axes()["body"].iter())
+        .contains_all_of(&vec![&"camo".to_owned(), &"green".to_owned()]);
+    assert_that!(metadata.axes()["metal"].len()).is_equal_to(2);
+
+    let mut choice = AxisValues::new();
+    choice.insert("body".to_owned(), "camo".to_owned());
+    choice.insert("metal".to_owned(), "silver".to_owned());
+    assert_that!(metadata.axis_tag(&choice))
+        .is_equal_to(Some(&Tag::from("body:camo,metal:silver")));
+    // not every combination need exist
+    choice.insert("body".to_owned(), "green".to_owned());
+    choice.insert("metal".to_owned(), "bronze".to_owned());
+    assert_that!(metadata.axis_tag(&choice)).is_none();
+
+    // malformed structured tags are rejected
+    let bad = VariationalAsset::from_file(ASSET_PINECONE_MATTE(), Some(&Tag::from("color:")));
+    assert_that!(bad).is_err();
+}
+
+#[test]
+fn test_meld_axes() {
+    let load_asset = |path, tag: &str| {
+        VariationalAsset::from_file(path, Some(&Tag::from(tag)))
+            .expect("VariationalAsset::from_file() failure")
+    };
+    let meld_assets = |base, meld| {
+        let (melded, _) =
+            VariationalAsset::meld(base, meld).expect("VariationalAsset::meld() failure");
+        melded
+    };
+
+    // one asset varies only the body, the other only the metal trim
+    let bodies = meld_assets(
+        &load_asset(ASSET_TEAPOT_CAMO_PINK_BRONZE(), "body:camo"),
+        &load_asset(ASSET_TEAPOT_GREEN_PINK_BRONZE(), "body:green"),
+    );
+    let metals = meld_assets(
+        &load_asset(ASSET_TEAPOT_CAMO_PINK_BRONZE(), "metal:bronze"),
+        &load_asset(ASSET_TEAPOT_CAMO_PINK_SILVER(), "metal:silver"),
+    );
+    let combined = VariationalAsset::meld_axes(&[&bodies, &metals])
+        .expect("VariationalAsset::meld_axes() failure");
+
+    let green_silver = Tag::from("body:green,metal:silver");
+    assert_that!(combined.default_tag()).is_equal_to(&Tag::from("body:camo,metal:bronze"));
+    assert_that!(combined.metadata().tags().len()).is_equal_to(4);
+    assert_that!(combined.metadata().tags().contains(&green_silver)).is_true();
+
+    // the combination no source file was melded in for matches the source file that has it
+    let combined = WorkAsset::from_slice(combined.glb(), Some(combined.default_tag()), None)
+        .expect("glTF import failure");
+    let reference = WorkAsset::from_file(ASSET_TEAPOT_GREEN_PINK_SILVER(), Some(&green_silver))
+        .expect("glTF import failure");
+    let table = combined.variant_table().expect("variant table failure");
+    for (m_ix, mesh) in reference.meshes().iter().enumerate() {
+        for (p_ix, primitive) in mesh.primitives.iter().enumerate() {
+            if let Some(material) = primitive.material {
+                let combined_ix = table[m_ix][p_ix][&green_silver].index;
+                assert_that!(combined.material_keys()[combined_ix])
+                    .is_equal_to(&reference.material_keys()[material.value()]);
+            }
+        }
+    }
+
+    // each asset must vary along a single axis of its own
+    assert_that!(VariationalAsset::meld_axes(&[&bodies, &bodies])).is_err();
+    let plain = load_asset(ASSET_TEAPOT_CAMO_PINK_BRONZE(), "camo");
+    assert_that!(VariationalAsset::meld_axes(&[&bodies, &plain])).is_err();
 }
@@ -2,13 +2,23 @@
 //
 
 extern crate assets;
+extern crate gltf;
 extern crate gltf_variant_meld;
+extern crate serde_json;
 
 use std::collections::{HashMap};
+use std::env;
+use std::fs;
+
+use gltf::Gltf;
 
 use spectral::prelude::*;
 
-use gltf_variant_meld::{Tag, VariationalAsset, WorkAsset};
+use gltf_variant_meld::{
+    extension, tag_file_stem, validate_glb, ExtensionRevision, FixedPrimitive, GlbChunk,
+    MaterialOverrides, MeldOptions, MeshSelector, Tag, TagDisplay, TagPayload, VariationalAsset,
+    WorkAsset,
+};
 
 use assets::*;
 
@@ -42,3 +52,642 @@ fn test_parse_simple_variational() {
     assert_that!(extracted_map).has_length(2);
     assert_that!(extracted_map.keys()).contains_all_of(&vec![&tag_1, &tag_2]);
 }
+
+#[test]
+fn test_extension_document() {
+    let (tag_1, tag_2) = (Tag::from("tag_1"), Tag::from("tag_2"));
+    let asset = VariationalAsset::from_file(ASSET_PINECONE_VARIATIONAL(), Some(&tag_1))
+        .expect("glTF import failure");
+    let mut root = Gltf::from_slice(asset.glb())
+        .or_else(|e| Err(e.to_string()))
+        .expect("glTF re-parse failure")
+        .document
+        .into_json();
+
+    let document = extension::read(&root).expect("Failed to read extension.");
+    assert_that!(document.variants.iter()).contains_all_of(&vec![&tag_1, &tag_2]);
+    assert_that!(document.revision).is_equal_to(Some(ExtensionRevision::Ratified));
+    assert_that!(document.mappings[0][0]).has_length(2);
+
+    // swap the materials of the two variants, and read back what was written
+    let mut swapped = document.clone();
+    let mapping = &mut swapped.mappings[0][0];
+    let (ix_1, ix_2) = (mapping[&tag_1], mapping[&tag_2]);
+    mapping.insert(tag_1.clone(), ix_2);
+    mapping.insert(tag_2.clone(), ix_1);
+    extension::write(&mut root, &swapped).expect("Failed to write extension.");
+    let reread = extension::read(&root).expect("Failed to re-read extension.");
+    assert_that!(reread).is_equal_to(&swapped);
+
+    let mut broken = document.clone();
+    broken.mappings[0][0].insert(Tag::from("tag_3"), 0);
+    assert_that!(extension::write(&mut root, &broken)).is_err();
+    let mut broken = document.clone();
+    broken.mappings.push(vec![]);
+    assert_that!(extension::write(&mut root, &broken)).is_err();
+}
+
+#[test]
+fn test_assign_variant_by_name() {
+    let (matte, shiny) = (Tag::from("matte"), Tag::from("shiny"));
+
+    let asset = VariationalAsset::from_file(ASSET_PINECONE_MATTE(), Some(&matte))
+        .expect("glTF import failure");
+    let asset = asset
+        .assign_variant(&shiny, "Pinecone", "lambert1")
+        .expect("Failed to assign variant.");
+
+    assert_that!(asset.metadata().tags().iter()).contains_all_of(&vec![&matte, &shiny]);
+
+    assert_that!(asset.assign_variant(&shiny, "Pineapple", "lambert1")).is_err();
+    assert_that!(asset.assign_variant(&shiny, "Pinecone", "lambert2")).is_err();
+}
+
+#[test]
+fn test_replace_material() {
+    let (matte, shiny) = (Tag::from("matte"), Tag::from("shiny"));
+
+    let asset = VariationalAsset::from_file(ASSET_PINECONE_MATTE(), Some(&matte))
+        .expect("glTF import failure");
+    let material = r#"{ "name": "rougher", "pbrMetallicRoughness": { "roughnessFactor": 0.9 } }"#;
+    let asset = asset
+        .replace_material(&matte, "Pinecone", material, &[])
+        .expect("Failed to replace material.");
+
+    let table = asset
+        .variant_table()
+        .expect("Failed to build variant table.");
+    assert_that!(table[0][0][&matte].name).is_equal_to(Some(String::from("rougher")));
+
+    assert_that!(asset.replace_material(&shiny, "Pinecone", material, &[])).is_err();
+    assert_that!(asset.replace_material(&matte, "Pineapple", material, &[])).is_err();
+    assert_that!(asset.replace_material(&matte, "Pinecone", "{ nope", &[])).is_err();
+    let textured = r#"{ "pbrMetallicRoughness": { "baseColorTexture": { "index": 0 } } }"#;
+    assert_that!(asset.replace_material(&matte, "Pinecone", textured, &[])).is_err();
+}
+
+#[test]
+fn test_derive_variant() {
+    let (matte, sky_blue) = (Tag::from("matte"), Tag::from("sky_blue"));
+
+    let asset = VariationalAsset::from_file(ASSET_PINECONE_MATTE(), Some(&matte))
+        .expect("glTF import failure");
+    let overrides = MaterialOverrides::from_json(r#"{ "baseColorFactor": [0.4, 0.6, 0.9, 1.0] }"#)
+        .expect("Failed to parse overrides.");
+    let derived = asset
+        .derive_variant(&matte, &sky_blue, &overrides, &[])
+        .expect("Failed to derive variant.");
+
+    assert_that!(derived.metadata().tags().iter()).contains_all_of(&vec![&matte, &sky_blue]);
+    let table = derived
+        .variant_table()
+        .expect("Failed to build variant table.");
+    assert_that!(table[0][0][&sky_blue].name).is_equal_to(Some(String::from("lambert1_sky_blue")));
+    assert_that!(table[0][0][&sky_blue].index).is_not_equal_to(table[0][0][&matte].index);
+
+    assert_that!(derived.derive_variant(&matte, &sky_blue, &overrides, &[])).is_err();
+    assert_that!(asset.derive_variant(&sky_blue, &matte, &overrides, &[])).is_err();
+    let textured = MaterialOverrides {
+        base_color_texture: Some(0),
+        ..Default::default()
+    };
+    assert_that!(asset.derive_variant(&matte, &sky_blue, &textured, &[])).is_err();
+    let misspelt = r#"{ "baseColour": [1, 1, 1, 1] }"#;
+    assert_that!(MaterialOverrides::from_json(misspelt)).is_err();
+}
+
+#[test]
+fn test_variant_table() {
+    let (tag_1, tag_2) = (Tag::from("tag_1"), Tag::from("tag_2"));
+
+    let asset = VariationalAsset::from_file(ASSET_PINECONE_VARIATIONAL(), Some(&tag_1))
+        .expect("glTF import failure");
+    let table = asset
+        .variant_table()
+        .expect("Failed to build variant table.");
+
+    assert_that!(table).has_length(1);
+    assert_that!(table[0]).has_length(1);
+    let primitive_table = &table[0][0];
+    assert_that!(primitive_table.keys()).contains_all_of(&vec![&tag_1, &tag_2]);
+    assert_that!(primitive_table[&tag_2].index).is_equal_to(0);
+    assert_that!(primitive_table[&tag_2].name).is_equal_to(Some(String::from("lambert1")));
+}
+
+#[test]
+fn test_from_gltf() {
+    let matte = Tag::from("matte");
+
+    let bytes = fs::read(ASSET_PINECONE_MATTE()).expect("Couldn't read asset file");
+    let gltf = Gltf::from_slice(&bytes).expect("glTF parse failure");
+
+    let base_dir = ASSET_PINECONE_MATTE().parent();
+    let from_gltf = VariationalAsset::from_gltf(gltf, Some(&matte), base_dir)
+        .expect("VariationalAsset::from_gltf() failure");
+    let from_file = VariationalAsset::from_file(ASSET_PINECONE_MATTE(), Some(&matte))
+        .expect("VariationalAsset::from_file() failure");
+
+    assert_that!(from_gltf.default_tag()).is_equal_to(&matte);
+    assert_that!(from_gltf.glb().len()).is_equal_to(from_file.glb().len());
+    assert_that!(from_gltf.metadata().total_sizes().texture_bytes())
+        .is_equal_to(from_file.metadata().total_sizes().texture_bytes());
+}
+
+#[test]
+fn test_legacy_upgrade() {
+    let (matte, shiny) = (Tag::from("matte"), Tag::from("shiny"));
+
+    // dress up a plain asset in the earlier FB_material_variants draft extension
+    let bytes = fs::read(ASSET_PINECONE_MATTE()).expect("Couldn't read asset file");
+    let mut json: serde_json::Value = serde_json::from_slice(&bytes).expect("JSON parse failure");
+    json["extensionsUsed"] = serde_json::json!(["FB_material_variants"]);
+    json["extensions"] = serde_json::json!({
+        "FB_material_variants": { "default_tag": "matte" }
+    });
+    json["meshes"][0]["primitives"][0]["extensions"] = serde_json::json!({
+        "FB_material_variants": {
+            "mapping": [ { "material": 0, "tags": [ "matte", "shiny" ] } ]
+        }
+    });
+    let bytes = serde_json::to_vec(&json).expect("JSON serialization failure");
+
+    let base_dir = ASSET_PINECONE_MATTE().parent();
+    let asset = WorkAsset::from_slice(&bytes, None, base_dir).expect("legacy parse failure");
+    assert_that!(asset.default_tag()).is_equal_to(&matte);
+    assert_that!(asset.variant_mapping(0, 0).get(&shiny))
+        .is_equal_to(Some(&asset.material_keys()[0]));
+    assert_that!(asset.get_tags_in_use().unwrap()).has_length(2);
+
+    // the legacy default tag must agree with any explicitly given one
+    assert_that!(WorkAsset::from_slice(&bytes, Some(&shiny), base_dir)).is_err();
+
+    let exported = asset.export().expect("export failure");
+    let reloaded =
+        WorkAsset::from_slice(exported.glb(), Some(&matte), None).expect("re-parse failure");
+    assert_that!(reloaded.variant_mapping(0, 0).get(&shiny)).is_some();
+}
+
+#[test]
+fn test_legacy_dual_write() {
+    let (tag_1, tag_2) = (Tag::from("tag_1"), Tag::from("tag_2"));
+    let options = &MeldOptions {
+        write_legacy_extension: true,
+        ..Default::default()
+    };
+
+    let base_dir = ASSET_PINECONE_VARIATIONAL().parent();
+    let bytes = fs::read(ASSET_PINECONE_VARIATIONAL()).expect("Couldn't read asset file");
+    let asset = WorkAsset::from_slice_with_options(&bytes, Some(&tag_1), base_dir, options)
+        .expect("glTF import failure");
+    let exported = asset.export().expect("export failure");
+
+    let json = match GlbChunk::from_bytes(exported.glb()) {
+        Ok(Some((GlbChunk::JSON(json), _))) => json,
+        _ => panic!("Exported asset is not GLB"),
+    };
+    let json: serde_json::Value = serde_json::from_slice(json).expect("JSON parse failure");
+    assert_that!(json["extensions"]["FB_material_variants"]["default_tag"])
+        .is_equal_to(&serde_json::json!("tag_1"));
+    assert_that!(json["extensions"]["KHR_materials_variants"])
+        .is_not_equal_to(&serde_json::Value::Null);
+    let legacy_extension =
+        &json["meshes"][0]["primitives"][0]["extensions"]["FB_material_variants"];
+    assert_that!(legacy_extension["mapping"][0]["tags"])
+        .is_equal_to(&serde_json::json!(["tag_1", "tag_2"]));
+
+    // both forms describe the same mapping, so reading it back is uneventful
+    let reloaded =
+        WorkAsset::from_slice(exported.glb(), Some(&tag_1), None).expect("re-parse failure");
+    assert_that!(reloaded.variant_mapping(0, 0).get(&tag_2)).is_some();
+}
+
+#[test]
+fn test_extension_revision() {
+    let (tag_1, tag_2) = (Tag::from("tag_1"), Tag::from("tag_2"));
+    let draft = &MeldOptions {
+        extension_revision: Some(ExtensionRevision::Draft),
+        ..Default::default()
+    };
+
+    let bytes = fs::read(ASSET_PINECONE_VARIATIONAL()).expect("Couldn't read asset file");
+    let base_dir = ASSET_PINECONE_VARIATIONAL().parent();
+    let asset = WorkAsset::from_slice_with_options(&bytes, Some(&tag_1), base_dir, draft)
+        .expect("glTF import failure");
+    assert_that!(asset.extension_revision()).is_equal_to(Some(ExtensionRevision::Ratified));
+
+    let exported = asset.export().expect("export failure");
+    let json = match GlbChunk::from_bytes(exported.glb()) {
+        Ok(Some((GlbChunk::JSON(json), _))) => json,
+        _ => panic!("Exported asset is not GLB"),
+    };
+    let json: serde_json::Value = serde_json::from_slice(json).expect("JSON parse failure");
+    let extension = &json["meshes"][0]["primitives"][0]["extensions"]["KHR_materials_variants"];
+    assert_that!(extension["mapping"]).is_not_equal_to(&serde_json::Value::Null);
+    assert_that!(extension["mappings"]).is_equal_to(&serde_json::Value::Null);
+
+    // the draft revision is detected, read, and by default written back out again
+    let reloaded =
+        WorkAsset::from_slice(exported.glb(), Some(&tag_1), None).expect("re-parse failure");
+    assert_that!(reloaded.extension_revision()).is_equal_to(Some(ExtensionRevision::Draft));
+    assert_that!(reloaded.variant_mapping(0, 0).get(&tag_2)).is_some();
+
+    let reexported = reloaded.export().expect("export failure");
+    let reparsed =
+        WorkAsset::from_slice(reexported.glb(), Some(&tag_1), None).expect("re-parse failure");
+    assert_that!(reparsed.extension_revision()).is_equal_to(Some(ExtensionRevision::Draft));
+}
+
+#[test]
+fn test_compact_variant_mappings() {
+    let (bronze, silver) = (Tag::from("camo_pink_bronze"), Tag::from("camo_pink_silver"));
+    let load = |path, tag| VariationalAsset::from_file(path, Some(tag)).expect("import failure");
+    let base = load(ASSET_TEAPOT_CAMO_PINK_BRONZE(), &bronze);
+    let other = load(ASSET_TEAPOT_CAMO_PINK_SILVER(), &silver);
+    let compact = &MeldOptions {
+        compact_variant_mappings: true,
+        ..Default::default()
+    };
+    let (full, _) = VariationalAsset::meld(&base, &other).expect("meld failure");
+    let (compacted, _) =
+        VariationalAsset::meld_with_options(&base, &other, compact).expect("meld failure");
+    assert_that!(compacted.glb().len()).is_less_than(full.glb().len());
+    assert_that!(compacted.metadata().tags()).is_equal_to(full.metadata().tags());
+
+    // every tag left out of a primitive's mappings selects its own material anyway, and a
+    // reload puts it back
+    let table = |asset: &VariationalAsset| {
+        WorkAsset::from_slice(asset.glb(), Some(&bronze), None)
+            .expect("re-parse failure")
+            .variant_table()
+            .expect("variant_table() failure")
+    };
+    assert_that!(table(&compacted)).is_equal_to(table(&full));
+
+    // a tag that selects every primitive's own material is left out everywhere, but survives
+    let again = Tag::from("camo_pink_bronze_again");
+    let again_pot = load(ASSET_TEAPOT_CAMO_PINK_BRONZE(), &again);
+    let (compacted, _) =
+        VariationalAsset::meld_with_options(&base, &again_pot, compact).expect("meld failure");
+    let reloaded = VariationalAsset::from_slice(compacted.glb(), Some(&bronze), None)
+        .expect("re-parse failure");
+    let tags: Vec<&Tag> = reloaded.metadata().tags().iter().collect();
+    assert_that!(tags).is_equal_to(vec![&bronze, &again]);
+    let sizes = |asset: &VariationalAsset| {
+        let sizes = asset.metadata().tag_sizes(&again);
+        sizes.map(|sizes| sizes.texture_bytes)
+    };
+    assert_that!(sizes(&reloaded)).is_equal_to(sizes(&compacted));
+
+    // a primitive that never varied has no mapping, and a reload mustn't make one up for it
+    let fixed = &MeldOptions {
+        fixed_primitives: vec![FixedPrimitive {
+            mesh: MeshSelector::Index(0),
+            primitive: None,
+        }],
+        ..Default::default()
+    };
+    let compact_fixed = &MeldOptions {
+        compact_variant_mappings: true,
+        ..fixed.clone()
+    };
+    let (full, _) =
+        VariationalAsset::meld_with_options(&base, &other, fixed).expect("meld failure");
+    let (compacted, _) =
+        VariationalAsset::meld_with_options(&base, &other, compact_fixed).expect("meld failure");
+    let (full, compacted) = (table(&full), table(&compacted));
+    assert_that!(compacted[0][0].len()).is_equal_to(1);
+    assert_that!(compacted[1][0].len()).is_equal_to(2);
+    assert_that!(compacted).is_equal_to(full);
+}
+
+#[test]
+fn test_variant_extension_validation() {
+    let tag_1 = Tag::from("tag_1");
+    let bytes = fs::read(ASSET_PINECONE_VARIATIONAL()).expect("Couldn't read asset file");
+    let base_dir = ASSET_PINECONE_VARIATIONAL().parent();
+    let json: serde_json::Value = serde_json::from_slice(&bytes).expect("JSON parse failure");
+
+    let load_broken = |breakage: &dyn Fn(&mut serde_json::Value)| {
+        let mut json = json.clone();
+        breakage(&mut json);
+        let bytes = serde_json::to_vec(&json).expect("JSON serialization failure");
+        match WorkAsset::from_slice(&bytes, Some(&tag_1), base_dir) {
+            Ok(_) => panic!("Broken asset loaded without complaint"),
+            Err(e) => e,
+        }
+    };
+
+    let err = load_broken(&|json| {
+        json["extensions"]["KHR_materials_variants"]["variants"][1]["name"] = "tag_1".into();
+    });
+    assert_that!(err.contains("Variant 1 has the same name (tag_1) as variant 0")).is_true();
+
+    let err = load_broken(&|json| {
+        let extension = &mut json["meshes"][0]["primitives"][0]["extensions"];
+        extension["KHR_materials_variants"]["mappings"][0]["variants"][1] = 7.into();
+    });
+    assert_that!(err.contains("In mesh 0 'Pinecone', primitive 0")).is_true();
+    assert_that!(err.contains("references variant 7")).is_true();
+
+    let err = load_broken(&|json| {
+        let extension = &mut json["meshes"][0]["primitives"][0]["extensions"];
+        extension["KHR_materials_variants"]["mappings"][0]["material"] = 7.into();
+    });
+    assert_that!(err.contains("references material 7")).is_true();
+}
+
+#[test]
+fn test_tags_from_material_names() {
+    let (matte, shiny) = (Tag::from("matte"), Tag::from("shiny"));
+
+    // one artist file, holding both variants of the pinecone material
+    let bytes = fs::read(ASSET_PINECONE_MATTE()).expect("Couldn't read asset file");
+    let mut json: serde_json::Value = serde_json::from_slice(&bytes).expect("JSON parse failure");
+    json["materials"][0]["name"] = serde_json::json!("Pinecone_matte");
+    let mut shiny_material = json["materials"][0].clone();
+    shiny_material["name"] = serde_json::json!("Pinecone_shiny");
+    shiny_material["pbrMetallicRoughness"]["metallicFactor"] = serde_json::json!(0.9);
+    json["materials"]
+        .as_array_mut()
+        .unwrap()
+        .push(shiny_material);
+    let bytes = serde_json::to_vec(&json).expect("JSON write failure");
+
+    let options = &MeldOptions {
+        material_tag_separator: Some(String::from("_")),
+        ..Default::default()
+    };
+    let base_dir = ASSET_PINECONE_MATTE().parent();
+    let asset = WorkAsset::from_slice_with_options(&bytes, Some(&matte), base_dir, options)
+        .expect("WorkAsset::from_slice_with_options() failure");
+
+    // the default tag is backed by the primitive's own material reference
+    let mapping = asset.variant_mapping(0, 0);
+    assert_that!(mapping).has_length(1);
+    assert_that!(mapping.get(&shiny)).is_equal_to(Some(&asset.material_keys()[1]));
+
+    let exported = asset.export().expect("export failure");
+    assert_that!(exported.metadata().tags().contains(&shiny)).is_true();
+    assert_that!(exported.metadata().counts().variational_materials).is_equal_to(2);
+
+    // without the option, the material names mean nothing
+    let plain = WorkAsset::from_slice(&bytes, Some(&matte), base_dir).expect("parse failure");
+    assert_that!(plain.variant_mapping(0, 0).get(&shiny)).is_none();
+}
+
+#[test]
+fn test_tag_display() {
+    let (matte, shiny, tinted) = (Tag::from("matte"), Tag::from("shiny"), Tag::from("tinted"));
+    let load = |path, tag| {
+        VariationalAsset::from_file(path, Some(tag)).expect("VariationalAsset::from_file() failure")
+    };
+    let (melded, _) = VariationalAsset::meld(
+        &load(ASSET_PINECONE_MATTE(), &matte),
+        &load(ASSET_PINECONE_SHINY(), &shiny),
+    )
+    .expect("VariationalAsset::meld() failure");
+
+    let mut display = TagDisplay {
+        name: Some(String::from("Shiny")),
+        ..Default::default()
+    };
+    display
+        .localized_names
+        .insert(String::from("fr"), String::from("Brillant"));
+    let displayed = melded
+        .with_tag_display(&shiny, display.clone())
+        .expect("with_tag_display() failure");
+    assert_that!(displayed.metadata().tag_display(&shiny)).is_equal_to(Some(&display));
+    assert_that!(displayed.metadata().tag_display(&matte)).is_none();
+    assert_that!(display.localized_name("fr")).is_equal_to(Some(&String::from("Brillant")));
+    assert_that!(display.localized_name("de")).is_equal_to(Some(&String::from("Shiny")));
+
+    // the display travels inside the asset, and survives further melds
+    let reloaded = VariationalAsset::from_slice(displayed.glb(), Some(&matte), None)
+        .expect("VariationalAsset::from_slice() failure");
+    assert_that!(reloaded.metadata().tag_display(&shiny)).is_equal_to(Some(&display));
+    let (remelded, _) = VariationalAsset::meld(&reloaded, &load(ASSET_PINECONE_TINTED(), &tinted))
+        .expect("VariationalAsset::meld() failure");
+    assert_that!(remelded.metadata().tag_display(&shiny)).is_equal_to(Some(&display));
+
+    assert_that!(melded.with_tag_display(&tinted, display)).is_err();
+}
+
+#[test]
+fn test_tag_payloads() {
+    let (matte, shiny, tinted) = (Tag::from("matte"), Tag::from("shiny"), Tag::from("tinted"));
+    let load = |path, tag| {
+        VariationalAsset::from_file(path, Some(tag)).expect("VariationalAsset::from_file() failure")
+    };
+    let (melded, _) = VariationalAsset::meld(
+        &load(ASSET_PINECONE_MATTE(), &matte),
+        &load(ASSET_PINECONE_SHINY(), &shiny),
+    )
+    .expect("VariationalAsset::meld() failure");
+
+    let sku = TagPayload::Json(serde_json::json!({ "sku": "PC-0002" }));
+    let thumbnail = TagPayload::Binary {
+        mime_type: Some(String::from("image/png")),
+        bytes: vec![0x89, 0x50, 0x4e, 0x47, 0x0d],
+    };
+    let with_payloads = melded
+        .with_tag_payload(&shiny, "sku", sku.clone())
+        .and_then(|asset| asset.with_tag_payload(&shiny, "thumbnail", thumbnail.clone()))
+        .expect("with_tag_payload() failure");
+
+    let payloads = with_payloads
+        .tag_payloads(&shiny)
+        .expect("tag_payloads() failure");
+    assert_that!(payloads.len()).is_equal_to(2);
+    assert_that!(payloads.get("sku")).is_equal_to(Some(&sku));
+    assert_that!(payloads.get("thumbnail")).is_equal_to(Some(&thumbnail));
+    assert_that!(with_payloads.tag_payloads(&matte).unwrap().is_empty()).is_true();
+
+    // binary payloads aren't duplicated on re-export, and survive further melds
+    let again = with_payloads
+        .with_tag_payload(&shiny, "sku", sku.clone())
+        .expect("with_tag_payload() failure");
+    assert_that!(again.glb().len()).is_equal_to(with_payloads.glb().len());
+    let (remelded, _) = VariationalAsset::meld(&again, &load(ASSET_PINECONE_TINTED(), &tinted))
+        .expect("VariationalAsset::meld() failure");
+    let payloads = remelded
+        .tag_payloads(&shiny)
+        .expect("tag_payloads() failure");
+    assert_that!(payloads.get("thumbnail")).is_equal_to(Some(&thumbnail));
+}
+
+#[test]
+fn test_shared_tag_payloads() {
+    let (matte, shiny) = (Tag::from("matte"), Tag::from("shiny"));
+    let load = |path, tag| {
+        VariationalAsset::from_file(path, Some(tag)).expect("VariationalAsset::from_file() failure")
+    };
+    let (melded, _) = VariationalAsset::meld(
+        &load(ASSET_PINECONE_MATTE(), &matte),
+        &load(ASSET_PINECONE_SHINY(), &shiny),
+    )
+    .expect("VariationalAsset::meld() failure");
+
+    // the same thumbnail on both tags lands in two identical buffer views, which a reload
+    // collapses into one
+    let thumbnail = TagPayload::Binary {
+        mime_type: Some(String::from("image/png")),
+        bytes: vec![0x89, 0x50, 0x4e, 0x47, 0x0d, 0x0a, 0x1a, 0x0a],
+    };
+    let with_payloads = melded
+        .with_tag_payload(&matte, "thumbnail", thumbnail.clone())
+        .and_then(|asset| asset.with_tag_payload(&shiny, "thumbnail", thumbnail.clone()))
+        .expect("with_tag_payload() failure");
+
+    let reloaded = VariationalAsset::from_slice(with_payloads.glb(), None, None)
+        .expect("VariationalAsset::from_slice() failure");
+    for tag in &[&matte, &shiny] {
+        let payloads = reloaded.tag_payloads(tag).expect("tag_payloads() failure");
+        assert_that!(payloads.get("thumbnail")).is_equal_to(Some(&thumbnail));
+    }
+}
+
+#[test]
+fn test_split() {
+    let (tag_1, tag_2) = (Tag::from("tag_1"), Tag::from("tag_2"));
+
+    let asset = VariationalAsset::from_file(ASSET_PINECONE_VARIATIONAL(), Some(&tag_1))
+        .expect("glTF import failure");
+    let table = asset
+        .variant_table()
+        .expect("Failed to build variant table.");
+    let split = asset.split().expect("Failed to split asset.");
+    assert_that!(split.keys().collect::<Vec<_>>()).is_equal_to(vec![&tag_1, &tag_2]);
+
+    for (tag, glb) in &split {
+        let gltf = Gltf::from_slice(glb)
+            .or_else(|e| Err(e.to_string()))
+            .expect("glTF re-parse failure");
+        let root = gltf.document.into_json();
+        let extension = String::from("KHR_materials_variants");
+        assert_that!(root.extensions_used.contains(&extension)).is_false();
+
+        // only the variant's own material is left, and it's the primitive's default
+        assert_that!(root.materials).has_length(1);
+        assert_that!(root.materials[0].name).is_equal_to(&table[0][0][tag].name);
+        assert_that!(root.meshes[0].primitives[0].material.map(|ix| ix.value()))
+            .is_equal_to(Some(0));
+    }
+
+    let asset = WorkAsset::from_slice(asset.glb(), None, None).expect("glTF re-parse failure");
+    assert_that!(asset.extract_variant(&Tag::from("nope"))).is_err();
+}
+
+#[test]
+fn test_split_spec_gloss() {
+    let (bronze, silver) = (Tag::from("bronze"), Tag::from("silver"));
+
+    // move the camouflage texture of the teapots into the spec-gloss extension, where only the
+    // extension's own schema knows to look for it
+    let load = |path: &std::path::Path, tag| {
+        let bytes = fs::read(path).expect("Couldn't read asset file");
+        let mut json: serde_json::Value =
+            serde_json::from_slice(&bytes).expect("JSON parse failure");
+        let material = &mut json["materials"][0];
+        material["pbrMetallicRoughness"] = serde_json::json!({});
+        material["extensions"] = serde_json::json!({
+            "KHR_materials_pbrSpecularGlossiness": { "diffuseTexture": { "index": 0 } }
+        });
+        json["extensionsUsed"] = serde_json::json!(["KHR_materials_pbrSpecularGlossiness"]);
+        let bytes = serde_json::to_vec(&json).expect("JSON serialization failure");
+        VariationalAsset::from_slice(&bytes, Some(tag), path.parent())
+            .expect("VariationalAsset::from_slice() failure")
+    };
+    let (melded, _) = VariationalAsset::meld(
+        &load(ASSET_TEAPOT_CAMO_PINK_BRONZE(), &bronze),
+        &load(ASSET_TEAPOT_CAMO_PINK_SILVER(), &silver),
+    )
+    .expect("VariationalAsset::meld() failure");
+
+    let split = melded.split().expect("Failed to split asset.");
+    assert_that!(split.keys().collect::<Vec<_>>()).is_equal_to(vec![&bronze, &silver]);
+    for glb in split.values() {
+        let json = match GlbChunk::from_bytes(glb) {
+            Ok(Some((GlbChunk::JSON(json), _))) => json,
+            _ => panic!("GLB parse failure"),
+        };
+        let json: serde_json::Value = serde_json::from_slice(json).expect("JSON parse failure");
+        let textures = json["textures"]
+            .as_array()
+            .expect("split lost its textures");
+        let spec_gloss = json["materials"]
+            .as_array()
+            .expect("split lost its materials")
+            .iter()
+            .filter_map(|m| m["extensions"]["KHR_materials_pbrSpecularGlossiness"].as_object())
+            .collect::<Vec<_>>();
+        assert_that!(spec_gloss).has_length(1);
+        let texture_ix = spec_gloss[0]["diffuseTexture"]["index"].as_u64().unwrap();
+        assert_that!((texture_ix as usize) < textures.len()).is_true();
+    }
+}
+
+#[test]
+fn test_split_to_dir() {
+    let tag_1 = Tag::from("tag_1");
+    let asset = VariationalAsset::from_file(ASSET_PINECONE_VARIATIONAL(), Some(&tag_1))
+        .expect("glTF import failure");
+    let split = asset.split().expect("Failed to split asset.");
+
+    // streaming each variant to a file gives the very bytes that building it in memory does
+    let dir = env::temp_dir().join("gltf_variant_meld_test_split_to_dir");
+    let written = asset
+        .split_to_dir(&dir, &MeldOptions::default())
+        .expect("Failed to split asset to directory.");
+    assert_that!(written.keys().collect::<Vec<_>>()).is_equal_to(split.keys().collect::<Vec<_>>());
+    for (tag, glb) in &split {
+        let path = dir.join(format!("{}.glb", tag_file_stem(tag)));
+        let streamed = fs::read(path).expect("Couldn't read split file");
+        assert_that!(written[tag]).is_equal_to(glb.len());
+        assert_that!(&streamed).is_equal_to(glb);
+    }
+}
+
+#[test]
+fn test_validate_glb() {
+    let tag_1 = Tag::from("tag_1");
+    let asset = VariationalAsset::from_file(ASSET_PINECONE_VARIATIONAL(), Some(&tag_1))
+        .expect("glTF import failure");
+    assert_that!(validate_glb(asset.glb())).is_empty();
+
+    let (json, bin) = match GlbChunk::from_bytes(asset.glb()) {
+        Ok(Some((GlbChunk::JSON(json), Some(GlbChunk::BIN(bin))))) => (json, bin),
+        _ => panic!("Exported asset isn't GLB"),
+    };
+    let json: serde_json::Value = serde_json::from_slice(json).expect("JSON parse failure");
+    let validate_broken = |breakage: &dyn Fn(&mut serde_json::Value)| {
+        let mut json = json.clone();
+        breakage(&mut json);
+        let json = serde_json::to_vec(&json).expect("JSON serialization failure");
+        let glb = GlbChunk::to_bytes(GlbChunk::JSON(&json), Some(GlbChunk::BIN(bin)))
+            .expect("GLB creation failure");
+        validate_glb(&glb).join("\n")
+    };
+
+    let errors = validate_broken(&|json| json["accessors"][0]["count"] = 1000000.into());
+    assert_that!(errors.contains("Accessor 0 ends at byte")).is_true();
+
+    // on 32-bit targets, this would overflow the arithmetic; it must still just be an error
+    let errors = validate_broken(&|json| {
+        json["accessors"][0]["count"] = u32::MAX.into();
+        json["accessors"][0]["byteOffset"] = u32::MAX.into();
+    });
+    assert_that!(errors.contains("Accessor 0 ends")).is_true();
+
+    let errors = validate_broken(&|json| json["meshes"][0]["primitives"][0]["material"] = 7.into());
+    assert_that!(errors.contains("Index out of bounds")).is_true();
+
+    let errors = validate_broken(&|json| {
+        let extension = &mut json["meshes"][0]["primitives"][0]["extensions"];
+        extension["KHR_materials_variants"]["mappings"][0]["material"] = 7.into();
+    });
+    assert_that!(errors.contains("maps to non-existent material 7")).is_true();
+
+    let truncated = &asset.glb()[..asset.glb().len() / 2];
+    assert_that!(validate_glb(truncated)).has_length(1);
+}
@@ -0,0 +1,797 @@
+// Copyright (c) Facebook, Inc. and its affiliates. All Rights Reserved
+//
+
+extern crate assets;
+extern crate gltf;
+extern crate gltf_variant_meld;
+extern crate serde_json;
+
+use spectral::prelude::*;
+
+use std::env;
+use std::fs;
+use std::path::Path;
+
+use gltf::json::accessor::{ComponentType, Type};
+use gltf::json::Root;
+
+use gltf_variant_meld::{
+    BoundsPolicy, GlbChunk, InputLimits, MeldCache, MeldOptions, Tag, TagCase, TagPolicy,
+    VariationalAsset, WorkAsset, INPUT_LIMIT_EXCEEDED,
+};
+
+use assets::*;
+
+#[test]
+fn test_edit_variant_mapping() {
+    let (matte, shiny) = (Tag::from("matte"), Tag::from("shiny"));
+
+    let mut asset =
+        WorkAsset::from_file(ASSET_PINECONE_MATTE(), Some(&matte)).expect("glTF import failure");
+    let material_key = asset.material_keys()[0].clone();
+
+    assert_that!(asset.set_variant(0, 0, &shiny, &material_key)).is_ok();
+    assert_that!(asset.variant_mapping(0, 0).get(&shiny)).is_equal_to(Some(&material_key));
+    assert_that!(asset.get_tags_in_use().unwrap().contains(&shiny)).is_true();
+
+    // bad indices and unknown material keys are rejected
+    assert_that!(asset.set_variant(1, 0, &shiny, &material_key)).is_err();
+    assert_that!(asset.set_variant(0, 1, &shiny, &material_key)).is_err();
+    assert_that!(asset.set_variant(0, 0, &shiny, &String::from("nope"))).is_err();
+
+    // the default tag can't be cleared, but other tags can
+    assert_that!(asset.clear_variant(0, 0, &matte)).is_err();
+    assert_that!(asset.clear_variant(0, 0, &shiny)).is_equal_to(Ok(Some(material_key)));
+    assert_that!(asset.variant_mapping(0, 0).get(&shiny)).is_none();
+}
+
+#[test]
+fn test_unkeyed_accessors() {
+    let tag = Tag::from("matte");
+    let mut asset =
+        WorkAsset::from_file(ASSET_PINECONE_MATTE(), Some(&tag)).expect("glTF import failure");
+    let root = asset.root().clone();
+    assert_that!(asset.nodes()).has_length(root.nodes.len());
+    assert_that!(asset.scenes()).has_length(root.scenes.len());
+    assert_that!(asset.accessors()).has_length(root.accessors.len());
+    assert_that!(asset.buffer_views()).has_length(root.buffer_views.len());
+
+    let node = asset.nodes()[0].clone();
+    assert_that!(asset.push_node(node)).is_equal_to(root.nodes.len());
+    assert_that!(asset.nodes()).has_length(root.nodes.len() + 1);
+}
+
+#[test]
+fn test_push_accessor_from_slice() {
+    let tag = Tag::from("matte");
+    let mut asset =
+        WorkAsset::from_file(ASSET_PINECONE_MATTE(), Some(&tag)).expect("glTF import failure");
+    let (accessor_count, blob_len) = (asset.accessors().len(), asset.blob_slice().len());
+
+    let positions: Vec<u8> = [0.0f32, 1.0, 2.0, 3.0, 4.0, 5.0]
+        .iter()
+        .flat_map(|f| f.to_le_bytes().to_vec())
+        .collect();
+
+    // the byte count must match the element count
+    let result = asset.push_accessor_from_slice(&positions, ComponentType::F32, Type::Vec3, 3);
+    assert_that!(result).is_err();
+    assert_that!(asset.accessors()).has_length(accessor_count);
+    assert_that!(asset.blob_slice().len()).is_equal_to(blob_len);
+
+    let accessor_ix = asset
+        .push_accessor_from_slice(&positions, ComponentType::F32, Type::Vec3, 2)
+        .expect("push_accessor_from_slice() failure");
+    assert_that!(accessor_ix).is_equal_to(accessor_count);
+    let accessor = &asset.accessors()[accessor_ix];
+    assert_that!(accessor.count).is_equal_to(2);
+    let view = asset.buffer_view(accessor.buffer_view.expect("accessor lacks view").value());
+    assert_that!(view.byte_offset.unwrap_or(0) % 4).is_equal_to(0);
+    let slice = asset
+        .buffer_view_as_slice(view)
+        .expect("buffer view beyond blob");
+    assert_that!(slice).is_equal_to(&positions[..]);
+
+    // a view that lies beyond the blob is an error, not a panic
+    let mut beyond = view.clone();
+    beyond.byte_offset = Some(u32::MAX);
+    assert_that!(asset.buffer_view_as_slice(&beyond)).is_err();
+}
+
+#[test]
+fn test_meld_cache() {
+    let tag = Tag::from("tag");
+    let dir = env::temp_dir().join("gltf_variant_meld_test_meld_cache");
+    let _ = fs::remove_dir_all(&dir);
+    let cache = MeldCache::new(&dir).expect("Couldn't create cache.");
+
+    let uncached =
+        WorkAsset::from_file(ASSET_TEAPOT_CAMO_PINK_BRONZE(), Some(&tag)).expect("import failure");
+    // the first cached load populates the cache, the second reads from it
+    for _ in 0..2 {
+        let cached = WorkAsset::from_file_with_cache(
+            ASSET_TEAPOT_CAMO_PINK_BRONZE(),
+            Some(&tag),
+            Some(&cache),
+        )
+        .expect("cached import failure");
+        assert_that!(cached.image_keys()).is_equal_to(uncached.image_keys());
+        assert_that!(cached.material_keys()).is_equal_to(uncached.material_keys());
+        assert_that!(cached.mesh_keys()).is_equal_to(uncached.mesh_keys());
+    }
+
+    // options that don't affect keys share the entry; those that do make one of their own
+    let entries = || fs::read_dir(&dir).expect("Couldn't list cache.").count();
+    assert_that!(entries()).is_equal_to(1);
+    let load = |options: &MeldOptions| {
+        WorkAsset::from_file_with_options(
+            ASSET_TEAPOT_CAMO_PINK_BRONZE(),
+            Some(&tag),
+            Some(&cache),
+            options,
+        )
+        .expect("cached import failure")
+    };
+    load(&MeldOptions {
+        memory_budget: Some(1 << 30),
+        ..Default::default()
+    });
+    assert_that!(entries()).is_equal_to(1);
+    let cached = load(&MeldOptions::exact());
+    assert_that!(entries()).is_equal_to(2);
+    let exact = WorkAsset::from_file_with_options(
+        ASSET_TEAPOT_CAMO_PINK_BRONZE(),
+        Some(&tag),
+        None,
+        &MeldOptions::exact(),
+    )
+    .expect("import failure");
+    assert_that!(cached.material_keys()).is_equal_to(exact.material_keys());
+}
+
+#[test]
+fn test_replace_image_bytes() {
+    let tag = Tag::from("tag");
+    let mut asset = WorkAsset::from_file(ASSET_TEAPOT_CAMO_PINK_BRONZE(), Some(&tag))
+        .expect("glTF import failure");
+    let expected = WorkAsset::from_file(ASSET_TEAPOT_GREEN_PINK_BRONZE(), Some(&tag))
+        .expect("glTF import failure");
+    let old_material_keys = asset.material_keys().clone();
+
+    // the two teapots differ only in the file of their one image
+    let green = ASSET_TEAPOT_GREEN_PINK_BRONZE()
+        .parent()
+        .unwrap()
+        .join("green-brushed.jpg");
+    let bytes = fs::read(green).expect("Couldn't read image file");
+    asset
+        .replace_image_bytes(0, &bytes, "image/jpeg")
+        .expect("image replacement failure");
+
+    assert_that!(asset.image_keys()).is_equal_to(expected.image_keys());
+    assert_that!(asset.texture_keys()).is_equal_to(expected.texture_keys());
+    assert_that!(asset.material_keys()).is_equal_to(expected.material_keys());
+    assert_that!(asset.material_keys()).is_not_equal_to(&old_material_keys);
+    assert_that!(asset.read_image_bytes(&asset.images()[0]).unwrap().as_ref())
+        .is_equal_to(bytes.as_slice());
+
+    // variant mappings follow their materials to the new keys
+    for (m_ix, mesh) in asset.meshes().iter().enumerate() {
+        for p_ix in 0..mesh.primitives.len() {
+            for material_key in asset.variant_mapping(m_ix, p_ix).values() {
+                assert_that!(asset.material_ix(material_key)).is_some();
+            }
+        }
+    }
+
+    assert_that!(asset.replace_image_bytes(1, &bytes, "image/jpeg")).is_err();
+}
+
+#[test]
+fn test_texture_search_paths() {
+    let tag = Tag::from("tag");
+    let path = ASSET_TEAPOT_CAMO_PINK_BRONZE();
+    let bytes = fs::read(path).expect("Couldn't read asset file");
+    let elsewhere = env::temp_dir();
+
+    // the asset's images & buffers aren't found relative to the wrong base directory...
+    let result = WorkAsset::from_slice(&bytes, Some(&tag), Some(&elsewhere));
+    assert_that!(result).is_err();
+
+    // ...unless we're told where else to look
+    let options = MeldOptions {
+        texture_search_paths: vec![path.parent().unwrap().to_path_buf()],
+        ..Default::default()
+    };
+    let found = WorkAsset::from_slice_with_options(&bytes, Some(&tag), Some(&elsewhere), &options)
+        .expect("glTF import failure");
+    let expected = WorkAsset::from_file(path, Some(&tag)).expect("glTF import failure");
+    assert_that!(found.image_keys()).is_equal_to(expected.image_keys());
+}
+
+#[test]
+fn test_uri_resolution() {
+    let tag = Tag::from("tag");
+    let path = ASSET_PINECONE_MATTE();
+    let mut json: serde_json::Value =
+        serde_json::from_slice(&fs::read(path).expect("Couldn't read asset file")).unwrap();
+
+    // the buffer's file is in a subdirectory whose name needs escaping in a URI
+    let dir = env::temp_dir().join("gltf_variant_meld_test_uri_resolution");
+    fs::create_dir_all(dir.join("Data Files")).unwrap();
+    let data = path.parent().unwrap().join("Pinecone_data.bin");
+    fs::copy(&data, dir.join("Data Files").join("pinecone_DATA.bin")).unwrap();
+
+    json["buffers"][0]["uri"] = serde_json::json!("Data%20Files/pinecone_DATA.bin");
+    let bytes = serde_json::to_vec(&json).unwrap();
+    let asset = WorkAsset::from_slice(&bytes, Some(&tag), Some(&dir)).expect("import failure");
+    assert_that!(asset.load_warnings()).has_length(0);
+
+    // a URI that differs in case is found only when asked to ignore case, and then warned of
+    json["buffers"][0]["uri"] = serde_json::json!("data%20files/Pinecone_data.bin");
+    let bytes = serde_json::to_vec(&json).unwrap();
+    let case_sensitive = !dir.join("data files").exists();
+    if case_sensitive {
+        assert_that!(WorkAsset::from_slice(&bytes, Some(&tag), Some(&dir))).is_err();
+    }
+    let options = MeldOptions {
+        case_insensitive_uris: true,
+        ..Default::default()
+    };
+    let asset = WorkAsset::from_slice_with_options(&bytes, Some(&tag), Some(&dir), &options)
+        .expect("case-insensitive import failure");
+    if case_sensitive {
+        assert_that!(asset.load_warnings()).has_length(1);
+    }
+}
+
+#[test]
+fn test_sampler_normalization() {
+    // two identical samplers, and three textures with the same image: one of them without a
+    // sampler, i.e. using the default one, and two with explicit default samplers
+    let json = r#"
+    {
+        "asset": { "version": "2.0" },
+        "buffers": [ { "byteLength": 8 } ],
+        "bufferViews": [ { "buffer": 0, "byteLength": 8 } ],
+        "images": [ { "bufferView": 0, "mimeType": "image/png" } ],
+        "samplers": [ { "name": "one", "wrapS": 10497 }, { "name": "two" } ],
+        "textures": [ { "source": 0 }, { "source": 0, "sampler": 0 }, { "source": 0, "sampler": 1 } ]
+    }
+    "#;
+    let bin: Vec<u8> = (0..8).collect();
+    let glb = GlbChunk::to_bytes(GlbChunk::JSON(json.as_bytes()), Some(GlbChunk::BIN(&bin)))
+        .expect("GLB creation failure");
+
+    let asset = WorkAsset::from_slice(&glb, Some(&Tag::from("tag")), None).expect("parse failure");
+    assert_that!(asset.samplers()).has_length(1);
+
+    let texture_keys = asset.texture_keys();
+    assert_that!(texture_keys[0]).is_equal_to(&texture_keys[1]);
+    assert_that!(texture_keys[1]).is_equal_to(&texture_keys[2]);
+}
+
+#[test]
+fn test_material_canonicalization() {
+    // these materials differ only in ways that have no effect on their appearance
+    let json = r#"
+    {
+        "asset": { "version": "2.0" },
+        "materials": [
+            { "pbrMetallicRoughness": { "metallicFactor": 1.0 }, "alphaCutoff": 0.2 },
+            { "emissiveFactor": [ -0.0, 0.0, 0.0 ] },
+            { "alphaMode": "MASK", "alphaCutoff": 0.2 }
+        ]
+    }
+    "#;
+    let asset = WorkAsset::from_slice(json.as_bytes(), Some(&Tag::from("tag")), None)
+        .expect("parse failure");
+
+    let material_keys = asset.material_keys();
+    assert_that!(material_keys[0]).is_equal_to(&material_keys[1]);
+    assert_that!(material_keys[1]).is_not_equal_to(&material_keys[2]);
+}
+
+#[test]
+fn test_material_float_precision() {
+    let json = r#"
+    {
+        "asset": { "version": "2.0" },
+        "materials": [
+            { "pbrMetallicRoughness": { "metallicFactor": 0.8 } },
+            { "pbrMetallicRoughness": { "metallicFactor": 0.8000001 } },
+            { "pbrMetallicRoughness": { "metallicFactor": 0.81 } }
+        ]
+    }
+    "#;
+    let tag = Tag::from("tag");
+
+    let asset = WorkAsset::from_slice(json.as_bytes(), Some(&tag), None).expect("parse failure");
+    let material_keys = asset.material_keys();
+    assert_that!(material_keys[0]).is_equal_to(&material_keys[1]);
+    assert_that!(material_keys[1]).is_not_equal_to(&material_keys[2]);
+
+    let exact = &MeldOptions::exact();
+    let asset = WorkAsset::from_slice_with_options(json.as_bytes(), Some(&tag), None, exact)
+        .expect("parse failure");
+    let material_keys = asset.material_keys();
+    assert_that!(material_keys[0]).is_not_equal_to(&material_keys[1]);
+}
+
+#[test]
+fn test_material_color_tolerance() {
+    let json = r#"
+    {
+        "asset": { "version": "2.0" },
+        "materials": [
+            { "pbrMetallicRoughness": { "baseColorFactor": [ 0.5, 0.5, 0.5, 1.0 ] } },
+            { "pbrMetallicRoughness": { "baseColorFactor": [ 0.501, 0.5, 0.5, 1.0 ] } },
+            { "pbrMetallicRoughness": { "baseColorFactor": [ 0.51, 0.5, 0.5, 1.0 ] } }
+        ]
+    }
+    "#;
+    let tag = Tag::from("tag");
+
+    let asset = WorkAsset::from_slice(json.as_bytes(), Some(&tag), None).expect("parse failure");
+    let material_keys = asset.material_keys();
+    assert_that!(material_keys[0]).is_not_equal_to(&material_keys[1]);
+
+    let options = &MeldOptions {
+        color_tolerance: Some(1.0 / 255.0),
+        ..Default::default()
+    };
+    let asset = WorkAsset::from_slice_with_options(json.as_bytes(), Some(&tag), None, options)
+        .expect("parse failure");
+    let material_keys = asset.material_keys();
+    assert_that!(material_keys[0]).is_equal_to(&material_keys[1]);
+    assert_that!(material_keys[1]).is_not_equal_to(&material_keys[2]);
+}
+
+#[test]
+fn test_material_color_tolerance_boundary() {
+    // on a grid of 0.1, the first two straddle the boundary at 0.15; the last two share a cell
+    let json = r#"
+    {
+        "asset": { "version": "2.0" },
+        "materials": [
+            { "pbrMetallicRoughness": { "baseColorFactor": [ 0.149, 0.5, 0.5, 1.0 ] } },
+            { "pbrMetallicRoughness": { "baseColorFactor": [ 0.151, 0.5, 0.5, 1.0 ] } },
+            { "pbrMetallicRoughness": { "baseColorFactor": [ 0.249, 0.5, 0.5, 1.0 ] } }
+        ]
+    }
+    "#;
+    let tag = Tag::from("tag");
+    let options = &MeldOptions {
+        color_tolerance: Some(0.1),
+        ..Default::default()
+    };
+    let asset = WorkAsset::from_slice_with_options(json.as_bytes(), Some(&tag), None, options)
+        .expect("parse failure");
+    let material_keys = asset.material_keys();
+    // the tolerance is approximate: closer than it, yet apart; nearly a whole step, yet equal
+    assert_that!(material_keys[0]).is_not_equal_to(&material_keys[1]);
+    assert_that!(material_keys[1]).is_equal_to(&material_keys[2]);
+}
+
+#[test]
+fn test_from_root() {
+    let json = r#"
+    {
+        "asset": { "version": "2.0" },
+        "materials": [ { "name": "red", "pbrMetallicRoughness": { "metallicFactor": 0.5 } } ],
+        "samplers": [ {}, {} ]
+    }
+    "#;
+    let tag = Tag::from("tag");
+    let root: Root = serde_json::from_str(json).expect("JSON parse failure");
+
+    let asset = WorkAsset::from_root(root, vec![], Some(&tag), None, &MeldOptions::default())
+        .expect("WorkAsset::from_root() failure");
+    let parsed = WorkAsset::from_slice(json.as_bytes(), Some(&tag), None).expect("parse failure");
+
+    assert_that!(asset.default_tag()).is_equal_to(&tag);
+    assert_that!(asset.material_keys()).is_equal_to(parsed.material_keys());
+    // the same transformations apply, e.g. sampler deduplication
+    assert_that!(asset.samplers()).has_length(1);
+}
+
+#[test]
+fn test_lenient_parse() {
+    // the animation path is from KHR_animation_pointer, which our glTF parser doesn't know
+    let json = r#"
+    {
+        "asset": { "version": "2.0" },
+        "materials": [ { "name": "red" } ],
+        "animations": [
+            {
+                "channels": [ { "sampler": 0, "target": { "path": "pointer" } } ],
+                "samplers": [ { "input": 0, "output": 1 } ]
+            }
+        ],
+        "futureProperty": { "answer": 42 }
+    }
+    "#;
+    let tag = Tag::from("tag");
+    let options = &MeldOptions {
+        lenient: true,
+        ..Default::default()
+    };
+
+    assert_that!(WorkAsset::from_slice(json.as_bytes(), Some(&tag), None)).is_err();
+
+    let asset = WorkAsset::from_slice_with_options(json.as_bytes(), Some(&tag), None, options)
+        .expect("lenient parse failure");
+    assert_that!(asset.materials()).has_length(1);
+
+    let exported = asset.export().expect("export failure");
+    let json = match GlbChunk::from_bytes(exported.glb()) {
+        Ok(Some((GlbChunk::JSON(json), _))) => json,
+        _ => panic!("Exported asset is not GLB"),
+    };
+    let json: serde_json::Value = serde_json::from_slice(json).expect("JSON parse failure");
+    assert_that!(json["animations"][0]["channels"][0]["target"]["path"])
+        .is_equal_to(&serde_json::json!("pointer"));
+    assert_that!(json["futureProperty"]["answer"]).is_equal_to(&serde_json::json!(42));
+
+    // the exported asset can itself be loaded leniently
+    let reloaded = WorkAsset::from_slice_with_options(exported.glb(), Some(&tag), None, options);
+    assert_that!(reloaded).is_ok();
+
+    // required extensions are parsed, so what export adds to them isn't overwritten
+    let json = r#"
+    {
+        "asset": { "version": "2.0" },
+        "materials": [ { "name": "red" } ],
+        "extensionsRequired": []
+    }
+    "#;
+    let required = &MeldOptions {
+        require_variants_extension: true,
+        ..options.clone()
+    };
+    let asset = WorkAsset::from_slice_with_options(json.as_bytes(), Some(&tag), None, required)
+        .expect("lenient parse failure");
+    let exported = asset.export().expect("export failure");
+    let json = match GlbChunk::from_bytes(exported.glb()) {
+        Ok(Some((GlbChunk::JSON(json), _))) => json,
+        _ => panic!("Exported asset is not GLB"),
+    };
+    let json: serde_json::Value = serde_json::from_slice(json).expect("JSON parse failure");
+    assert_that!(json["extensionsRequired"])
+        .is_equal_to(&serde_json::json!(["KHR_materials_variants"]));
+
+    // options that work on nodes can't see them in a lenient parse, and so are refused
+    let scoped = &MeldOptions {
+        node_scoped_meshes: true,
+        ..options.clone()
+    };
+    let err = WorkAsset::from_slice_with_options(exported.glb(), Some(&tag), None, scoped)
+        .expect_err("node-scoped meshes allowed in a lenient parse");
+    assert_that!(err.contains("Can't use node-scoped meshes with a lenient parse")).is_true();
+}
+
+#[cfg(feature = "texture-encoding")]
+#[test]
+fn test_texture_encoding() {
+    use gltf_variant_meld::TextureEncoding;
+
+    let tag = Tag::from("camo_pink_bronze");
+    let path = ASSET_TEAPOT_CAMO_PINK_BRONZE();
+    let bytes = fs::read(path).expect("Couldn't read asset file");
+
+    let mut options = MeldOptions::default();
+    options
+        .texture_encodings
+        .insert(tag.clone(), TextureEncoding::Png);
+    let asset = WorkAsset::from_slice_with_options(&bytes, Some(&tag), path.parent(), &options)
+        .expect("glTF import failure");
+    let original = asset.images()[0].mime_type.clone().map(|m| m.0);
+    assert_that!(original).is_equal_to(Some(String::from("image/jpeg")));
+
+    let exported = asset.export_verified().expect("export failure");
+    let reparsed =
+        WorkAsset::from_slice(exported.glb(), Some(&tag), None).expect("re-parse failure");
+    let encoded = reparsed.images()[0].mime_type.clone().map(|m| m.0);
+    assert_that!(encoded).is_equal_to(Some(String::from("image/png")));
+
+    // the metadata reflects the size of the re-encoded image
+    let image_bytes = reparsed.read_image_bytes(&reparsed.images()[0]).unwrap();
+    assert_that!(exported.metadata().total_sizes().texture_bytes()).is_equal_to(image_bytes.len());
+}
+
+#[cfg(feature = "draco-compression")]
+#[test]
+fn test_export_draco() {
+    use gltf_variant_meld::DracoSettings;
+
+    let tag = Tag::from("matte");
+    let asset =
+        WorkAsset::from_file(ASSET_PINECONE_MATTE(), Some(&tag)).expect("glTF import failure");
+    let plain = asset.export().expect("export failure");
+    let compressed = asset
+        .export_draco(&DracoSettings::default())
+        .expect("Draco export failure");
+    assert_that!(compressed.len()).is_less_than(plain.glb().len());
+
+    let (json, bin) = match GlbChunk::from_bytes(&compressed) {
+        Ok(Some((GlbChunk::JSON(json), Some(GlbChunk::BIN(bin))))) => (json, bin),
+        _ => panic!("Exported asset is not GLB"),
+    };
+    let json: serde_json::Value = serde_json::from_slice(json).expect("JSON parse failure");
+    assert_that!(json["extensionsRequired"].as_array().unwrap())
+        .contains(&serde_json::json!("KHR_draco_mesh_compression"));
+
+    // the primitive's accessors are left without data of their own, in favour of the Draco view
+    let primitive = &json["meshes"][0]["primitives"][0];
+    let draco = &primitive["extensions"]["KHR_draco_mesh_compression"];
+    let position_ix = primitive["attributes"]["POSITION"].as_u64().unwrap() as usize;
+    assert_that!(json["accessors"][position_ix]["bufferView"].is_null()).is_true();
+    assert_that!(draco["attributes"]["POSITION"].is_u64()).is_true();
+
+    let view = &json["bufferViews"][draco["bufferView"].as_u64().unwrap() as usize];
+    let offset = view["byteOffset"].as_u64().unwrap_or(0) as usize;
+    assert_that!(&bin[offset..offset + 5]).is_equal_to(&b"DRACO"[..]);
+}
+
+#[cfg(feature = "meshopt-compression")]
+#[test]
+fn test_export_meshopt() {
+    let tag = Tag::from("matte");
+    let asset =
+        WorkAsset::from_file(ASSET_PINECONE_MATTE(), Some(&tag)).expect("glTF import failure");
+    let compressed = asset.export_meshopt().expect("meshopt export failure");
+
+    let json = match GlbChunk::from_bytes(&compressed) {
+        Ok(Some((GlbChunk::JSON(json), _))) => json,
+        _ => panic!("Exported asset is not GLB"),
+    };
+    let json: serde_json::Value = serde_json::from_slice(json).expect("JSON parse failure");
+    assert_that!(json["extensionsRequired"].as_array().unwrap())
+        .contains(&serde_json::json!("EXT_meshopt_compression"));
+    assert_that!(json["buffers"][1]["extensions"]["EXT_meshopt_compression"]["fallback"])
+        .is_equal_to(&serde_json::json!(true));
+
+    // the positions are decoded into the fallback buffer from a compressed copy in the blob
+    let primitive = &json["meshes"][0]["primitives"][0];
+    let position_ix = primitive["attributes"]["POSITION"].as_u64().unwrap() as usize;
+    let view_ix = json["accessors"][position_ix]["bufferView"]
+        .as_u64()
+        .unwrap() as usize;
+    let view = &json["bufferViews"][view_ix];
+    assert_that!(view["buffer"]).is_equal_to(&serde_json::json!(1));
+    let extension = &view["extensions"]["EXT_meshopt_compression"];
+    assert_that!(extension["mode"]).is_equal_to(&serde_json::json!("ATTRIBUTES"));
+    assert_that!(extension["byteLength"].as_u64().unwrap())
+        .is_less_than(view["byteLength"].as_u64().unwrap());
+}
+
+#[cfg(feature = "image")]
+#[test]
+fn test_image_info() {
+    let tag = Tag::from("camo_pink_bronze");
+    let asset = VariationalAsset::from_file(ASSET_TEAPOT_CAMO_PINK_BRONZE(), Some(&tag))
+        .expect("glTF import failure");
+
+    let images = asset.metadata().images();
+    assert_that!(images).has_length(1);
+    let info = &images[0];
+    assert_that!(info.format.as_str()).is_equal_to("jpeg");
+    assert_that!(info.width).is_greater_than(0);
+    assert_that!(info.height).is_greater_than(0);
+    assert_that!(info.channels).is_greater_than(0);
+    assert_that!(info.bytes).is_equal_to(asset.metadata().total_sizes().texture_bytes());
+}
+
+#[cfg(feature = "image")]
+#[test]
+fn test_sharing_analysis() {
+    let (bronze, silver) = (Tag::from("camo_pink_bronze"), Tag::from("camo_pink_silver"));
+    let bronze_teapot = VariationalAsset::from_file(ASSET_TEAPOT_CAMO_PINK_BRONZE(), Some(&bronze))
+        .expect("glTF import failure");
+    let silver_teapot = VariationalAsset::from_file(ASSET_TEAPOT_CAMO_PINK_SILVER(), Some(&silver))
+        .expect("glTF import failure");
+
+    // a single tag has nothing to share with
+    let analysis = bronze_teapot.analyze_sharing(64).expect("analysis failure");
+    assert_that!(analysis.pairs).has_length(0);
+    assert_that!(analysis.bytes_saved).is_equal_to(0);
+
+    let (melded, _) = VariationalAsset::meld(&bronze_teapot, &silver_teapot).expect("meld failure");
+    let analysis = melded.analyze_sharing(64).expect("analysis failure");
+    for pair in &analysis.pairs {
+        assert_that!(pair.tags).is_not_equal_to(&pair.other_tags);
+        assert_that!(pair.distance).is_less_than_or_equal_to(64);
+    }
+    let pair_savings: usize = analysis.pairs.iter().map(|pair| pair.bytes_saved).sum();
+    assert_that!(analysis.bytes_saved).is_less_than_or_equal_to(pair_savings);
+
+    // a stricter threshold only ever finds fewer pairs
+    let strict = melded.analyze_sharing(0).expect("analysis failure");
+    assert_that!(strict.pairs.len()).is_less_than_or_equal_to(analysis.pairs.len());
+}
+
+#[test]
+fn test_tag_policy() {
+    let options = &MeldOptions {
+        tag_policy: TagPolicy {
+            case: TagCase::Lower,
+            allowed_punctuation: Some(String::from("_-")),
+            max_length: Some(8),
+            reserved: vec![Tag::from("None")],
+        },
+        ..Default::default()
+    };
+    let load = |path: &Path, tag: &str| {
+        let bytes = fs::read(path).expect("Couldn't read asset file");
+        WorkAsset::from_slice_with_options(&bytes, Some(&Tag::from(tag)), path.parent(), options)
+    };
+
+    // tags are normalized on ingest, including those in KHR_materials_variants
+    let mut asset = load(ASSET_PINECONE_VARIATIONAL(), "Tag_1").expect("glTF import failure");
+    assert_that!(asset.default_tag()).is_equal_to(&Tag::from("tag_1"));
+    assert_that!(asset
+        .get_tags_in_use()
+        .unwrap()
+        .contains(&Tag::from("tag_2")))
+    .is_true();
+
+    // and anything else is rejected, with an explanation
+    assert_that!(load(ASSET_PINECONE_MATTE(), "too-long-tag")).is_err();
+    assert_that!(load(ASSET_PINECONE_MATTE(), "NONE")).is_err();
+    let material_key = asset.material_keys()[0].clone();
+    let err = asset
+        .set_variant(0, 0, &Tag::from("a b"), &material_key)
+        .expect_err("disallowed tag accepted");
+    assert_that!(err.contains("contains disallowed ' '")).is_true();
+    assert_that!(asset.set_variant(0, 0, &Tag::from("A-b"), &material_key)).is_ok();
+    assert_that!(asset.variant_mapping(0, 0).get(&Tag::from("a-b"))).is_some();
+}
+
+#[test]
+fn test_input_limits() {
+    let tag = Tag::from("camo");
+    let glb = WorkAsset::from_file(ASSET_TEAPOT_CAMO_PINK_BRONZE(), Some(&tag))
+        .and_then(|asset| asset.export())
+        .expect("glTF import failure")
+        .glb()
+        .to_vec();
+    let with_limits = |input_limits| MeldOptions {
+        input_limits,
+        ..Default::default()
+    };
+    let load =
+        |options| VariationalAsset::from_slice_with_options(&glb, Some(&tag), None, &options);
+
+    assert_that!(load(with_limits(InputLimits::default()))).is_ok();
+    assert_that!(load(with_limits(InputLimits {
+        max_json_bytes: Some(glb.len()),
+        max_blob_bytes: Some(glb.len()),
+        max_images: Some(100),
+        max_meshes: Some(100),
+        max_primitives: Some(100),
+    })))
+    .is_ok();
+
+    let tiny_limits = vec![
+        InputLimits {
+            max_json_bytes: Some(10),
+            ..Default::default()
+        },
+        InputLimits {
+            max_blob_bytes: Some(10),
+            ..Default::default()
+        },
+        InputLimits {
+            max_images: Some(0),
+            ..Default::default()
+        },
+        InputLimits {
+            max_primitives: Some(0),
+            ..Default::default()
+        },
+    ];
+    for limits in tiny_limits {
+        let err = load(with_limits(limits)).expect_err("input limit not enforced");
+        assert_that!(err.starts_with(INPUT_LIMIT_EXCEEDED)).is_true();
+    }
+}
+
+#[test]
+fn test_external_files() {
+    let tag = Tag::from("camo");
+    let path = ASSET_TEAPOT_CAMO_PINK_BRONZE();
+    let bytes = fs::read(path).expect("Couldn't read asset file");
+    let json: serde_json::Value = serde_json::from_slice(&bytes).expect("JSON parse failure");
+    let load = |uri: &str, options: &MeldOptions| {
+        let mut json = json.clone();
+        json["buffers"][0]["uri"] = uri.into();
+        let bytes = serde_json::to_vec(&json).expect("JSON serialization failure");
+        WorkAsset::from_slice_with_options(&bytes, Some(&tag), path.parent(), options)
+    };
+
+    // URIs that would reach beyond the asset's directory may be refused
+    let confined = &MeldOptions {
+        confine_uris: true,
+        ..Default::default()
+    };
+    let climbing = "../tank_teapots/teapot_data.bin";
+    assert_that!(load("teapot_data.bin", confined)).is_ok();
+    assert_that!(load(climbing, &MeldOptions::default())).is_ok();
+    let escaping = [
+        climbing,
+        "%2E%2E/tank_teapots/teapot_data.bin",
+        "/etc/hosts",
+        "file:teapot_data.bin",
+    ];
+    for uri in &escaping {
+        let err = load(uri, confined).expect_err("URI not confined");
+        assert_that!(err.contains("only relative paths within the asset's directory")).is_true();
+    }
+
+    // a file that would take the blob over its limit is turned away by its size, unread
+    let limited = &MeldOptions {
+        input_limits: InputLimits {
+            max_blob_bytes: Some(1000),
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let err = load("teapot_data.bin", limited).expect_err("input limit not enforced");
+    assert_that!(err.starts_with(INPUT_LIMIT_EXCEEDED)).is_true();
+}
+
+#[test]
+fn test_position_bounds() {
+    let tag = Tag::from("matte");
+    let glb = WorkAsset::from_file(ASSET_PINECONE_MATTE(), Some(&tag))
+        .and_then(|asset| asset.export())
+        .expect("glTF import failure")
+        .glb()
+        .to_vec();
+    let json_of = |glb: &[u8]| -> serde_json::Value {
+        match GlbChunk::from_bytes(glb) {
+            Ok(Some((GlbChunk::JSON(json), _))) => {
+                serde_json::from_slice(json).expect("JSON parse failure")
+            }
+            _ => panic!("Exported asset is not GLB"),
+        }
+    };
+    let bin = match GlbChunk::from_bytes(&glb) {
+        Ok(Some((_, Some(GlbChunk::BIN(bin))))) => bin,
+        _ => panic!("Exported asset has no BIN chunk"),
+    };
+
+    // break the bounds of the first primitive's positions
+    let mut json = json_of(&glb);
+    let position_ix = json["meshes"][0]["primitives"][0]["attributes"]["POSITION"]
+        .as_u64()
+        .expect("primitive lacks positions") as usize;
+    let max = json["accessors"][position_ix]["max"].clone();
+    json["accessors"][position_ix]["max"] = serde_json::json!([0.0, 0.0, 0.0]);
+    let json = serde_json::to_vec(&json).expect("JSON serialization failure");
+    let broken = GlbChunk::to_bytes(GlbChunk::JSON(&json), Some(GlbChunk::BIN(bin)))
+        .expect("GLB creation failure");
+
+    let export = |position_bounds| {
+        let options = MeldOptions {
+            position_bounds,
+            ..Default::default()
+        };
+        WorkAsset::from_slice_with_options(&broken, Some(&tag), None, &options)
+            .and_then(|asset| asset.export())
+            .map(|exported| json_of(exported.glb())["accessors"][position_ix]["max"].clone())
+    };
+
+    let kept = export(BoundsPolicy::Keep).expect("export failure");
+    assert_that!(kept).is_equal_to(serde_json::json!([0.0, 0.0, 0.0]));
+
+    let err = export(BoundsPolicy::Verify).expect_err("wrong bounds not detected");
+    assert_that!(err.contains("don't match their data")).is_true();
+
+    let recomputed = export(BoundsPolicy::Recompute).expect("export failure");
+    let (recomputed, max) = (recomputed.as_array().unwrap(), max.as_array().unwrap());
+    for (recomputed, original) in recomputed.iter().zip(max) {
+        let (recomputed, original) = (recomputed.as_f64().unwrap(), original.as_f64().unwrap());
+        assert_that!((recomputed - original).abs() < 1e-4).is_true();
+    }
+}